@@ -92,14 +92,25 @@ Add this bot to a Group as Admin, and give it ability to Delete Messages.
 });
 
 gen_checker!(check_senders, {
-    let users = &crate::env::ENV.get().unwrap().telegram_user.users;
+    if !crate::handlers::utils::message::is_allowed_sender(&message) {
+        return Ok(());
+    }
+});
 
-    if let Some(sender) = message.sender() {
-        if let Some(username) = sender.username() {
-            if !users.is_empty() && !users.contains(&username.to_string()) {
-                return Ok(());
-            }
-        }
+// commands affecting the whole instance are kept to tg_user_name in multi-tenant mode
+gen_checker!(check_admin, {
+    if crate::env::ENV.get().unwrap().multi_tenant
+        && !crate::handlers::utils::message::is_admin(&message)
+    {
+        const CHECK_ADMIN_FAILED: &str =
+            "This command is only available to the admins of this instance.";
+
+        message
+            .respond(CHECK_ADMIN_FAILED)
+            .await
+            .context(CHECK_ADMIN_FAILED)?;
+
+        return Ok(());
     }
 });
 
@@ -107,23 +118,45 @@ gen_checker!(check_tg_login, {
     let is_authorized = state.telegram_user.is_authorized().await?;
 
     if !is_authorized {
+        // the telegram account is shared by the instance in multi-tenant mode
+        if crate::env::ENV.get().unwrap().multi_tenant
+            && !crate::handlers::utils::message::is_admin(&message)
+        {
+            return Err(anyhow::anyhow!(
+                "telegram account of this instance is not logged in"
+            ))
+            .context("ask the admins of this instance to login with /auth");
+        }
+
         let response = "You haven't logged in to Telegram.";
         message.respond(response).await.context(response)?;
 
-        let (rx, _, _server_abort_handle) = crate::auth_server::spawn().await?;
-        crate::handlers::auth::login_to_telegram(message.clone(), state.clone(), rx).await?;
+        let (rx, _, token, _server_abort_handle) =
+            crate::auth_server::spawn(message.clone()).await?;
+        crate::handlers::auth::login_to_telegram(message.clone(), state.clone(), &token, rx)
+            .await?;
     }
 });
 
 gen_checker!(check_od_login, {
-    let is_authorized = state.onedrive.is_authorized().await;
+    let onedrive = state
+        .get_onedrive(crate::handlers::utils::message::get_tenant_id(&message))
+        .await?;
+    let is_authorized = onedrive.is_authorized().await;
 
     if !is_authorized {
         let response = "You haven't authorize OneDrive.";
         message.respond(response).await.context(response)?;
 
-        let (_, rx, _server_abort_handle) = crate::auth_server::spawn().await?;
-        crate::handlers::auth::authorize_onedrive(message.clone(), state.clone(), false, rx)
-            .await?;
+        let (_, rx, token, _server_abort_handle) =
+            crate::auth_server::spawn(message.clone()).await?;
+        crate::handlers::auth::authorize_onedrive(
+            message.clone(),
+            state.clone(),
+            false,
+            &token,
+            rx,
+        )
+        .await?;
     }
 });
@@ -0,0 +1,211 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    client::OneDriveClient, env::ENV, error::ResultExt, features::Feature, state::AppState,
+    trash::TRASH_FOLDER, verify::move_upload_records,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Utc};
+use serde_json::Value;
+
+const ARCHIVE_FOLDER: &str = "Archive";
+
+// files in the directory of each account older than archive_days are moved into Archive/$year in it,
+// those in folders like directories of chats keep their folders under it
+pub async fn archive_old_files(state: &AppState) {
+    let Some(archive_days) = ENV.get().unwrap().archive_days else {
+        return;
+    };
+
+    if !Feature::Archive.is_enabled() {
+        return;
+    }
+
+    for (tenant_id, onedrive) in state.get_loaded_onedrives().await {
+        archive_dir(&onedrive, tenant_id, archive_days, state)
+            .await
+            .context("failed to archive old files")
+            .trace();
+    }
+}
+
+async fn archive_dir(
+    onedrive: &OneDriveClient,
+    tenant_id: Option<i64>,
+    archive_days: u64,
+    state: &AppState,
+) -> Result<()> {
+    if onedrive.get_current_username().await?.is_none() {
+        return Ok(());
+    }
+
+    let root_path = onedrive.get_account_root_path().await;
+
+    // the directory is created on the first upload
+    if onedrive.get_item_by_path(&root_path).await?.is_none() {
+        return Ok(());
+    }
+
+    let root_path = root_path.trim_end_matches('/');
+    let now = Utc::now();
+
+    // folders relative to the directory, walked depth first
+    let mut dirs = vec![String::new()];
+
+    let mut archived = 0;
+    while let Some(dir) = dirs.pop() {
+        let dir_path = join_dir(root_path, &dir);
+
+        let children = onedrive.list_children(&dir_path).await?;
+
+        for item in children.iter() {
+            if let Some(name) = get_subfolder_name(item, &dir) {
+                dirs.push(join_relative(&dir, name));
+                continue;
+            }
+
+            let Some((name, year)) = get_archive_year(item, now, archive_days) else {
+                continue;
+            };
+
+            let archive_path = get_archive_folder(onedrive, root_path, year, &dir).await?;
+
+            let path = format!("{}/{}", dir_path, name);
+
+            // a file with the same name may have been archived already, it's kept in place then
+            match onedrive.move_item(&path, &archive_path).await {
+                Ok(new_path) => {
+                    move_upload_records(state, tenant_id, onedrive, &path, &new_path).await;
+
+                    archived += 1;
+                }
+                Err(e) => tracing::warn!("failed to archive {}: {:#}", path, e),
+            }
+        }
+    }
+
+    if archived > 0 {
+        tracing::info!("archived {} files in {}", archived, root_path);
+    }
+
+    Ok(())
+}
+
+// like /TG/Archive/2024/$dir, created if missing
+async fn get_archive_folder(
+    onedrive: &OneDriveClient,
+    root_path: &str,
+    year: i32,
+    dir: &str,
+) -> Result<String> {
+    let mut path = root_path.to_string();
+
+    let year = year.to_string();
+    let names = [ARCHIVE_FOLDER, year.as_str()]
+        .into_iter()
+        .chain(dir.split('/').filter(|name| !name.is_empty()));
+
+    for name in names {
+        let folder_path = format!("{}/{}", path, name);
+
+        if onedrive.get_item_by_path(&folder_path).await?.is_none() {
+            onedrive.create_folder(&path, name).await?;
+        }
+
+        path = folder_path;
+    }
+
+    Ok(path)
+}
+
+// folders to walk into, the archive and the trash in the directory are skipped
+fn get_subfolder_name<'a>(item: &'a Value, dir: &str) -> Option<&'a str> {
+    item.get("folder")?;
+
+    let name = item.get("name").and_then(Value::as_str)?;
+
+    (!dir.is_empty() || (name != ARCHIVE_FOLDER && name != TRASH_FOLDER)).then_some(name)
+}
+
+fn join_dir(root_path: &str, dir: &str) -> String {
+    if dir.is_empty() {
+        root_path.to_string()
+    } else {
+        format!("{}/{}", root_path, dir)
+    }
+}
+
+fn join_relative(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+// the name and the year of a file last modified before the cutoff, folders are walked instead
+fn get_archive_year(item: &Value, now: DateTime<Utc>, archive_days: u64) -> Option<(&str, i32)> {
+    if item.get("folder").is_some() {
+        return None;
+    }
+
+    let name = item.get("name").and_then(Value::as_str)?;
+
+    let modified = item
+        .get("lastModifiedDateTime")
+        .and_then(Value::as_str)
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())?
+        .with_timezone(&Utc);
+
+    let age_days = (now - modified).num_days();
+
+    (age_days >= archive_days as i64).then_some((name, modified.year()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_get_archive_year() {
+        let now = DateTime::parse_from_rfc3339("2025-01-10T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let old_file = json!({
+            "name": "a.mp4",
+            "file": {},
+            "lastModifiedDateTime": "2024-12-01T12:00:00Z",
+        });
+        assert_eq!(get_archive_year(&old_file, now, 30), Some(("a.mp4", 2024)));
+        assert!(get_archive_year(&old_file, now, 60).is_none());
+
+        let folder = json!({
+            "name": "Archive",
+            "folder": { "childCount": 3 },
+            "lastModifiedDateTime": "2020-01-01T12:00:00Z",
+        });
+        assert!(get_archive_year(&folder, now, 30).is_none());
+    }
+
+    #[test]
+    fn test_get_subfolder_name() {
+        let folder = |name: &str| json!({ "name": name, "folder": { "childCount": 1 } });
+
+        assert_eq!(get_subfolder_name(&folder("chat"), ""), Some("chat"));
+        assert!(get_subfolder_name(&folder("Archive"), "").is_none());
+        assert!(get_subfolder_name(&folder(".trash"), "").is_none());
+        // only the ones in the directory itself are skipped
+        assert_eq!(
+            get_subfolder_name(&folder("Archive"), "chat"),
+            Some("Archive")
+        );
+        assert!(get_subfolder_name(&json!({ "name": "a.mp4", "file": {} }), "").is_none());
+    }
+}
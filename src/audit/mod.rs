@@ -0,0 +1,101 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod records;
+
+use crate::utils::get_current_timestamp;
+use anyhow::{Context, Result};
+pub use records::Model as AuditRecord;
+use sea_orm::{
+    ActiveValue, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait, QueryOrder,
+    QuerySelect, Schema, Set,
+};
+
+// number of records shown in /audit
+const AUDIT_LIMIT: u64 = 20;
+
+// administrative actions, kept across restarts and included in backups
+pub struct AuditSession {
+    connection: DatabaseConnection,
+}
+
+impl AuditSession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self { connection })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to audit session")?;
+
+        Self::create_table_if_not_exists(&connection).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        if !Self::is_table_exists(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement =
+                Schema::new(backend).create_table_from_entity(records::Entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!(
+                    "failed to create table {}",
+                    records::Entity.table_name()
+                ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
+        let result = records::Entity::find().all(connection).await;
+
+        result.is_ok()
+    }
+
+    pub async fn insert(
+        &self,
+        chat_id: i64,
+        actor: Option<String>,
+        actor_id: Option<i64>,
+        action: &str,
+    ) -> Result<()> {
+        let insert_item = records::ActiveModel {
+            id: ActiveValue::default(),
+            chat_id: Set(chat_id),
+            actor: Set(actor),
+            actor_id: Set(actor_id),
+            action: Set(action.to_string()),
+            created_at: Set(get_current_timestamp()),
+        };
+
+        records::Entity::insert(insert_item)
+            .exec(&self.connection)
+            .await
+            .context("failed to insert audit record")?;
+
+        Ok(())
+    }
+
+    // latest records first
+    pub async fn get_records(&self) -> Result<Vec<AuditRecord>> {
+        records::Entity::find()
+            .order_by_desc(records::Column::Id)
+            .limit(AUDIT_LIMIT)
+            .all(&self.connection)
+            .await
+            .context("failed to get audit records")
+    }
+}
@@ -5,37 +5,122 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use anyhow::{Context, Result};
+use crate::{env::ENV, error::ErrorExt};
+use anyhow::{anyhow, Context, Result};
 use axum_server::tls_rustls::RustlsConfig;
 use rcgen::{generate_simple_self_signed, CertifiedKey};
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
-pub async fn get_rustls_config() -> Result<RustlsConfig> {
-    let cert_path = {
-        let path = Path::new("ssl/server.crt");
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
 
-        if path.exists() {
-            path
-        } else {
-            Path::new("/telegram-onedrive/server/ssl/server.crt")
+// file names used by certbot and by the docker volumes in readme
+const CERT_FILE_NAMES: [(&str, &str); 2] = [
+    ("fullchain.pem", "privkey.pem"),
+    ("server.crt", "server.key"),
+];
+
+const DEFAULT_SSL_DIRS: [&str; 2] = ["ssl", "/telegram-onedrive/server/ssl"];
+
+#[derive(Clone)]
+pub struct CertPaths {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl CertPaths {
+    fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self {
+            cert: cert.into(),
+            key: key.into(),
         }
-    };
-    let key_path = {
-        let path = Path::new("ssl/server.key");
+    }
+
+    fn exists(&self) -> bool {
+        self.cert.exists() && self.key.exists()
+    }
+
+    fn from_dir(dir: &Path) -> Option<Self> {
+        CERT_FILE_NAMES
+            .iter()
+            .map(|(cert, key)| Self::new(dir.join(cert), dir.join(key)))
+            .find(Self::exists)
+    }
+
+    async fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let cert = tokio::fs::metadata(&self.cert)
+            .await
+            .ok()?
+            .modified()
+            .ok()?;
+        let key = tokio::fs::metadata(&self.key).await.ok()?.modified().ok()?;
 
-        if path.exists() {
-            path
-        } else {
-            Path::new("/telegram-onedrive/server/ssl/server.key")
+        Some((cert, key))
+    }
+}
+
+// explicit paths first, then the cert dir, then the default paths,
+// a configured cert that can't be found is an error instead of falling back to a self signed one
+fn get_cert_paths() -> Result<Option<CertPaths>> {
+    let env = ENV.get().unwrap();
+
+    match (&env.ssl_cert_path, &env.ssl_key_path) {
+        (Some(cert), Some(key)) => {
+            let cert_paths = CertPaths::new(cert, key);
+
+            if !cert_paths.exists() {
+                return Err(anyhow!("ssl_cert {} or ssl_key {} not found", cert, key));
+            }
+
+            return Ok(Some(cert_paths));
         }
-    };
+        (Some(_), None) | (None, Some(_)) => {
+            return Err(anyhow!("ssl_cert and ssl_key should be set together"));
+        }
+        (None, None) => {}
+    }
 
-    let config = if cert_path.exists() && key_path.exists() {
-        tracing::debug!("auth server uses cert from file");
+    if let Some(dir) = &env.ssl_dir {
+        return CertPaths::from_dir(Path::new(dir)).map(Some).ok_or_else(|| {
+            anyhow!(
+                "no cert found in ssl_dir {}, expected fullchain.pem and privkey.pem, or server.crt and server.key",
+                dir
+            )
+        });
+    }
+
+    Ok(DEFAULT_SSL_DIRS
+        .iter()
+        .find_map(|dir| CertPaths::from_dir(Path::new(dir))))
+}
 
-        RustlsConfig::from_pem_file(cert_path, key_path)
+// checked at startup, instead of when the auth server is started by /auth
+pub fn check_cert_paths() -> Result<()> {
+    // tls is terminated by the reverse proxy
+    if ENV.get().unwrap().use_reverse_proxy {
+        return Ok(());
+    }
+
+    get_cert_paths().map(|_| ())
+}
+
+pub async fn get_rustls_config() -> Result<(RustlsConfig, Option<CertPaths>)> {
+    let cert_paths = get_cert_paths()?;
+
+    let config = if let Some(cert_paths) = &cert_paths {
+        tracing::debug!(
+            "auth server uses cert from file: {}",
+            cert_paths.cert.display()
+        );
+
+        RustlsConfig::from_pem_file(&cert_paths.cert, &cert_paths.key)
             .await
-            .context("failed to create rustls config from pem file")?
+            .context(format!(
+                "failed to create rustls config from pem file {}",
+                cert_paths.cert.display()
+            ))?
     } else {
         tracing::debug!("auth server uses self signed cert");
 
@@ -46,7 +131,40 @@ pub async fn get_rustls_config() -> Result<RustlsConfig> {
             .context("failed to create self signed rustls config")?
     };
 
-    Ok(config)
+    Ok((config, cert_paths))
+}
+
+// reload the cert when it is renewed, so that the auth server doesn't have to be restarted
+pub async fn watch_cert(config: RustlsConfig, cert_paths: Option<CertPaths>) {
+    let Some(cert_paths) = cert_paths else {
+        return std::future::pending().await;
+    };
+
+    let mut last_modified = cert_paths.modified().await;
+
+    loop {
+        tokio::time::sleep(RELOAD_INTERVAL).await;
+
+        let modified = cert_paths.modified().await;
+
+        if modified.is_none() || modified == last_modified {
+            continue;
+        }
+
+        // keep the old cert if the new one is broken or only half written
+        match config
+            .reload_from_pem_file(&cert_paths.cert, &cert_paths.key)
+            .await
+            .context("failed to reload cert")
+        {
+            Ok(()) => {
+                tracing::info!("auth server cert reloaded");
+
+                last_modified = modified;
+            }
+            Err(e) => e.trace(),
+        }
+    }
 }
 
 fn gen_self_signed_cert() -> Result<(Vec<u8>, Vec<u8>)> {
@@ -8,3 +8,191 @@
 mod models;
 pub mod onedrive;
 pub mod telegram;
+
+use super::{limiter::RateLimiter, AuditChat, AuthToken};
+use crate::{
+    env::ENV,
+    error::{HttpError, ResultExt},
+    handlers::utils::message::get_tenant_id,
+    utils::constant_time_eq,
+};
+use anyhow::{Context, Result};
+use axum::http::{HeaderMap, StatusCode};
+use std::net::{IpAddr, SocketAddr};
+
+// the real ip is in headers when behind reverse proxy, which are only trusted from the configured proxies
+fn get_client_ip(headers: &HeaderMap, addr: SocketAddr) -> IpAddr {
+    resolve_client_ip(headers, addr.ip(), &ENV.get().unwrap().trusted_proxies)
+}
+
+// anyone can put hops on the left of X-Forwarded-For, so it's read from the right,
+// skipping the ones added by the trusted proxies
+fn resolve_client_ip(headers: &HeaderMap, peer_ip: IpAddr, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer_ip) {
+        return peer_ip;
+    }
+
+    let forwarded_ips = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+
+    for ip in forwarded_ips.into_iter().rev() {
+        match ip {
+            Some(ip) if trusted_proxies.contains(&ip) => {}
+            Some(ip) => return ip,
+            // not a hop that can be trusted
+            None => return peer_ip,
+        }
+    }
+
+    headers
+        .get("x-real-ip")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<IpAddr>().ok())
+        .unwrap_or(peer_ip)
+}
+
+// like 127.0.0.1,::1
+pub fn parse_trusted_proxies(s: &str) -> Result<Vec<IpAddr>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .map(|ip| {
+            ip.parse::<IpAddr>()
+                .context(format!("invalid ip of trusted proxy: {}", ip))
+        })
+        .collect()
+}
+
+// limit code submissions of each ip, and report them to the chat where the authorization is started
+async fn check_submission(
+    limiter: &RateLimiter,
+    AuditChat(message): &AuditChat,
+    auth_token: &AuthToken,
+    name: &str,
+    ip: IpAddr,
+    token: &str,
+    is_last_code: bool,
+) -> Result<(), HttpError> {
+    if let Err(locked_out) = limiter.attempt(ip) {
+        tracing::warn!("{} code submission from {} is rate limited", name, ip);
+
+        if locked_out.just_locked {
+            let response = format!(
+                "Too many {} code submissions from {}, locked for {} minutes.",
+                name,
+                ip,
+                locked_out.remaining.as_secs() / 60
+            );
+            message.respond(response.as_str()).await.trace();
+        }
+
+        return Err(HttpError::with_status(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "Too many attempts, please retry after {} seconds.",
+                locked_out.remaining.as_secs()
+            ),
+        ));
+    }
+
+    let verified = if is_last_code {
+        take_token(auth_token, token)
+    } else {
+        verify_token(auth_token, token)
+    };
+
+    if let Err(e) = verified {
+        let response = format!(
+            "Rejected {} code submission from {}: invalid token.",
+            name, ip
+        );
+        message.respond(response.as_str()).await.trace();
+
+        return Err(e);
+    }
+
+    tracing::info!("received {} code submission from {}", name, ip);
+
+    let mut response = format!("Received {} code submission from {}", name, ip);
+    if let Some(tenant_id) = get_tenant_id(message) {
+        response.push_str(&format!(" for user {}", tenant_id));
+    }
+    response.push('.');
+
+    message.respond(response.as_str()).await.trace();
+
+    Ok(())
+}
+
+fn verify_token(AuthToken(expected): &AuthToken, token: &str) -> Result<(), HttpError> {
+    check_token(expected.lock().unwrap().as_deref(), token)
+}
+
+// later submissions with the same token are rejected
+fn take_token(AuthToken(expected): &AuthToken, token: &str) -> Result<(), HttpError> {
+    let mut expected = expected.lock().unwrap();
+
+    check_token(expected.as_deref(), token)?;
+    *expected = None;
+
+    Ok(())
+}
+
+fn check_token(expected: Option<&str>, token: &str) -> Result<(), HttpError> {
+    match expected {
+        Some(expected) if constant_time_eq(expected, token) => Ok(()),
+        _ => {
+            tracing::warn!("auth server received an invalid token");
+
+            Err(HttpError::with_status(
+                StatusCode::FORBIDDEN,
+                "Invalid token, please use the url sent by the bot.",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let proxy = "10.0.0.1".parse::<IpAddr>().unwrap();
+        let client = "203.0.113.7".parse::<IpAddr>().unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "1.2.3.4, 203.0.113.7, 10.0.0.1".parse().unwrap(),
+        );
+
+        // a forged left-most hop is ignored
+        assert_eq!(resolve_client_ip(&headers, proxy, &[proxy]), client);
+        // headers from anyone else aren't trusted at all
+        assert_eq!(resolve_client_ip(&headers, client, &[proxy]), client);
+        assert_eq!(resolve_client_ip(&headers, proxy, &[]), proxy);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "203.0.113.7".parse().unwrap());
+        assert_eq!(resolve_client_ip(&headers, proxy, &[proxy]), client);
+    }
+
+    #[test]
+    fn test_take_token() {
+        let auth_token = AuthToken::new("token".to_string());
+
+        assert!(verify_token(&auth_token, "other").is_err());
+        assert!(verify_token(&auth_token, "token").is_ok());
+        assert!(take_token(&auth_token, "token").is_ok());
+
+        // rejected once the onedrive code is accepted
+        assert!(take_token(&auth_token, "token").is_err());
+        assert!(verify_token(&auth_token, "token").is_err());
+    }
+}
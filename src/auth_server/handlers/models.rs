@@ -8,6 +8,19 @@
 use serde::Deserialize;
 
 #[derive(Deserialize)]
-pub struct CodeParams {
+pub struct TokenParams {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct TelegramCodeParams {
+    pub code: String,
+    pub token: String,
+}
+
+// the token is passed back by onedrive as state
+#[derive(Deserialize)]
+pub struct OneDriveCodeParams {
     pub code: String,
+    pub state: String,
 }
@@ -5,17 +5,45 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::models::CodeParams;
-use crate::{auth_server::SenderOD, error::HttpError};
-use axum::{debug_handler, extract::Query, response::Result, Extension};
+use super::{check_submission, get_client_ip, models::OneDriveCodeParams};
+use crate::{
+    auth_server::{limiter::RateLimiter, AuditChat, AuthToken, SenderOD},
+    error::HttpError,
+};
+use axum::{
+    debug_handler,
+    extract::{ConnectInfo, Query},
+    http::HeaderMap,
+    response::Result,
+    Extension,
+};
+use std::net::SocketAddr;
 
 pub const CODE_PATH: &str = "/auth";
 
 #[debug_handler]
 pub async fn code_handler(
     Extension(SenderOD(tx)): Extension<SenderOD>,
-    Query(CodeParams { code }): Query<CodeParams>,
+    Extension(auth_token): Extension<AuthToken>,
+    Extension(audit_chat): Extension<AuditChat>,
+    Extension(limiter): Extension<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(OneDriveCodeParams { code, state }): Query<OneDriveCodeParams>,
 ) -> Result<String> {
+    let ip = get_client_ip(&headers, addr);
+
+    check_submission(
+        &limiter,
+        &audit_chat,
+        &auth_token,
+        "onedrive",
+        ip,
+        &state,
+        true,
+    )
+    .await?;
+
     tracing::debug!("received od auth code: {}", code);
 
     tx.send(code).await.map_err(HttpError::new)?;
@@ -5,21 +5,35 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::models::CodeParams;
-use crate::{auth_server::SenderTG, error::HttpError};
+use super::{
+    check_submission, get_client_ip,
+    models::{TelegramCodeParams, TokenParams},
+    verify_token,
+};
+use crate::{
+    auth_server::{limiter::RateLimiter, AuditChat, AuthToken, SenderTG},
+    error::HttpError,
+};
 use anyhow::Context;
 use axum::{
     debug_handler,
-    http::StatusCode,
+    extract::{ConnectInfo, Query},
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse, Response, Result},
     Extension, Json,
 };
+use std::net::SocketAddr;
 use tokio::fs;
 
 pub const INDEX_PATH: &str = "/";
 
 #[debug_handler]
-pub async fn index_handler() -> Result<Html<String>> {
+pub async fn index_handler(
+    Extension(auth_token): Extension<AuthToken>,
+    Query(TokenParams { token }): Query<TokenParams>,
+) -> Result<Html<String>> {
+    verify_token(&auth_token, &token)?;
+
     let html = fs::read_to_string("./index.html")
         .await
         .context("failed to read index.html")
@@ -33,8 +47,27 @@ pub const CODE_PATH: &str = "/tg";
 #[debug_handler]
 pub async fn code_handler(
     Extension(SenderTG(tx)): Extension<SenderTG>,
-    Json(CodeParams { code }): Json<CodeParams>,
+    Extension(auth_token): Extension<AuthToken>,
+    Extension(audit_chat): Extension<AuditChat>,
+    Extension(limiter): Extension<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(TelegramCodeParams { code, token }): Json<TelegramCodeParams>,
 ) -> Result<Response> {
+    let ip = get_client_ip(&headers, addr);
+
+    // an invalid code can be submitted again, and the onedrive code may follow
+    check_submission(
+        &limiter,
+        &audit_chat,
+        &auth_token,
+        "telegram",
+        ip,
+        &token,
+        false,
+    )
+    .await?;
+
     tracing::debug!("received tg auth code: {}", code);
 
     tx.send(code)
@@ -0,0 +1,114 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// submissions allowed for each ip in a window
+const MAX_ATTEMPTS: u32 = 5;
+const WINDOW: Duration = Duration::from_secs(60);
+const LOCKOUT: Duration = Duration::from_secs(600);
+
+pub struct LockedOut {
+    pub remaining: Duration,
+    // whether the ip is locked by this attempt, used to avoid reporting the same lockout repeatedly
+    pub just_locked: bool,
+}
+
+struct AttemptRecord {
+    count: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl AttemptRecord {
+    const fn new(now: Instant) -> Self {
+        Self {
+            count: 0,
+            window_start: now,
+            locked_until: None,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    records: Arc<Mutex<HashMap<IpAddr, AttemptRecord>>>,
+}
+
+impl RateLimiter {
+    pub fn attempt(&self, ip: IpAddr) -> Result<(), LockedOut> {
+        self.attempt_at(ip, Instant::now())
+    }
+
+    fn attempt_at(&self, ip: IpAddr, now: Instant) -> Result<(), LockedOut> {
+        let mut records = self.records.lock().unwrap();
+
+        let record = records.entry(ip).or_insert_with(|| AttemptRecord::new(now));
+
+        if let Some(locked_until) = record.locked_until {
+            if now < locked_until {
+                return Err(LockedOut {
+                    remaining: locked_until - now,
+                    just_locked: false,
+                });
+            }
+
+            *record = AttemptRecord::new(now);
+        }
+
+        if now.duration_since(record.window_start) >= WINDOW {
+            *record = AttemptRecord::new(now);
+        }
+
+        record.count += 1;
+
+        if record.count > MAX_ATTEMPTS {
+            record.locked_until = Some(now + LOCKOUT);
+
+            return Err(LockedOut {
+                remaining: LOCKOUT,
+                just_locked: true,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::default();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let now = Instant::now();
+
+        for _ in 0..MAX_ATTEMPTS {
+            assert!(limiter.attempt_at(ip, now).is_ok());
+        }
+
+        let locked_out = limiter.attempt_at(ip, now).unwrap_err();
+        assert!(locked_out.just_locked);
+
+        let locked_out = limiter.attempt_at(ip, now + WINDOW).unwrap_err();
+        assert!(!locked_out.just_locked);
+        assert_eq!(locked_out.remaining, LOCKOUT - WINDOW);
+
+        assert!(limiter.attempt_at(other_ip, now).is_ok());
+
+        assert!(limiter.attempt_at(ip, now + LOCKOUT).is_ok());
+    }
+}
@@ -6,12 +6,15 @@
 */
 
 mod auto_abort;
-mod cert;
+pub mod cert;
 mod handlers;
+mod limiter;
 
 use crate::{
     env::{ENV, Env},
     error::ResultExt,
+    handlers::utils::message::get_tenant_id,
+    message::TelegramMessage,
 };
 use anyhow::{Context, Result};
 use auto_abort::AutoAbortHandle;
@@ -20,9 +23,15 @@ use axum::{
     routing::{get, post},
 };
 use axum_server::Handle;
-use cert::get_rustls_config;
+use cert::{get_rustls_config, watch_cert};
+pub use handlers::parse_trusted_proxies;
 use handlers::{onedrive, telegram};
-use std::net::TcpListener;
+use limiter::RateLimiter;
+use rand::{Rng, distributions::Alphanumeric};
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+};
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tower_http::services::ServeDir;
 
@@ -32,7 +41,26 @@ struct SenderTG(Sender<String>);
 #[derive(Clone)]
 struct SenderOD(Sender<String>);
 
-pub async fn spawn() -> Result<(Receiver<String>, Receiver<String>, AutoAbortHandle)> {
+// generated for each spawn and only sent to telegram, so that visitors can't submit codes,
+// taken once the onedrive code is accepted, which is the last code of an authorization
+#[derive(Clone)]
+struct AuthToken(Arc<Mutex<Option<String>>>);
+
+impl AuthToken {
+    fn new(token: String) -> Self {
+        Self(Arc::new(Mutex::new(Some(token))))
+    }
+}
+
+const TOKEN_LENGTH: usize = 32;
+
+// the chat where the authorization is started, code submissions are reported to it
+#[derive(Clone)]
+struct AuditChat(TelegramMessage);
+
+pub async fn spawn(
+    message: TelegramMessage,
+) -> Result<(Receiver<String>, Receiver<String>, String, AutoAbortHandle)> {
     tracing::debug!("spawning auth server");
 
     let Env {
@@ -44,13 +72,27 @@ pub async fn spawn() -> Result<(Receiver<String>, Receiver<String>, AutoAbortHan
     let (tx_tg, rx_tg) = mpsc::channel(1);
     let (tx_od, rx_od) = mpsc::channel(1);
 
+    let mut token = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect::<String>();
+
+    // passed as state in multi-tenant mode, binding the authorization to the requesting user
+    if let Some(tenant_id) = get_tenant_id(&message) {
+        token = format!("{}-{}", tenant_id, token);
+    }
+
     let router = Router::new()
         .nest_service("/static", ServeDir::new("./static"))
         .route(telegram::INDEX_PATH, get(telegram::index_handler))
         .route(telegram::CODE_PATH, post(telegram::code_handler))
         .route(onedrive::CODE_PATH, get(onedrive::code_handler))
         .layer(Extension(SenderTG(tx_tg)))
-        .layer(Extension(SenderOD(tx_od)));
+        .layer(Extension(SenderOD(tx_od)))
+        .layer(Extension(AuthToken::new(token.clone())))
+        .layer(Extension(AuditChat(message)))
+        .layer(Extension(RateLimiter::default()));
 
     let server =
         TcpListener::bind(format!("0.0.0.0:{}", port)).context("failed to create tcp listener")?;
@@ -64,29 +106,31 @@ pub async fn spawn() -> Result<(Receiver<String>, Receiver<String>, AutoAbortHan
         tokio::spawn(async move {
             axum_server::from_tcp(server)
                 .handle(shutdown_handle_clone)
-                .serve(router.into_make_service())
+                .serve(router.into_make_service_with_connect_info::<SocketAddr>())
                 .await
                 .context("auth server failed to serve")
                 .trace();
         })
         .abort_handle()
     } else {
-        let config = get_rustls_config().await?;
+        let (config, cert_paths) = get_rustls_config().await?;
 
         tracing::info!("auth server listening on https://0.0.0.0:{}", port);
 
         tokio::spawn(async move {
-            axum_server::from_tcp_rustls(server, config)
+            let serve = axum_server::from_tcp_rustls(server, config.clone())
                 .handle(shutdown_handle_clone)
-                .serve(router.into_make_service())
-                .await
-                .context("auth server failed to serve")
-                .trace();
+                .serve(router.into_make_service_with_connect_info::<SocketAddr>());
+
+            tokio::select! {
+                result = serve => result.context("auth server failed to serve").trace(),
+                () = watch_cert(config, cert_paths) => {}
+            }
         })
         .abort_handle()
     };
 
     let auto_abort_handle = AutoAbortHandle::new(abort_handle, shutdown_handle);
 
-    Ok((rx_tg, rx_od, auto_abort_handle))
+    Ok((rx_tg, rx_od, token, auto_abort_handle))
 }
@@ -0,0 +1,166 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    crypto::{decrypt, encrypt},
+    env::ENV,
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use async_zip::{
+    base::{read::mem::ZipFileReader, write::ZipFileWriter},
+    Compression, ZipEntryBuilder,
+};
+use chrono::Utc;
+use path_slash::PathBufExt;
+use std::{ffi::OsStr, path::Path};
+use tokio::fs;
+
+pub const BACKUP_DIR: &str = "/telegram-onedrive/backups";
+
+// bot state worth carrying over to a new instance, the task queue and message cache are transient
+fn backup_paths() -> [&'static str; 6] {
+    let env = ENV.get().unwrap();
+
+    [
+        &env.onedrive.session_path,
+        &env.telegram_session_path,
+        &env.settings_session_path,
+        &env.usage_session_path,
+        &env.history_session_path,
+        &env.audit_session_path,
+    ]
+}
+
+// the archive contains sessions of all accounts, so it's never stored unencrypted
+fn get_backup_key() -> Result<&'static str> {
+    ENV.get()
+        .unwrap()
+        .session_key
+        .as_deref()
+        .ok_or_else(|| anyhow!("session_key is required to encrypt and decrypt backups"))
+}
+
+pub async fn create_backup() -> Result<Vec<u8>> {
+    let key = get_backup_key()?;
+
+    let mut writer = ZipFileWriter::new(Vec::new());
+
+    for path in backup_paths() {
+        let path = Path::new(path);
+
+        if !path.exists() {
+            continue;
+        }
+
+        let filename = path
+            .file_name()
+            .ok_or_else(|| anyhow!("invalid session path"))?
+            .to_string_lossy()
+            .to_string();
+
+        let data = fs::read(path)
+            .await
+            .context(format!("failed to read {} for backup", filename))?;
+
+        writer
+            .write_entry_whole(
+                ZipEntryBuilder::new(filename.clone().into(), Compression::Stored),
+                &data,
+            )
+            .await
+            .context(format!("failed to write {} to backup", filename))?;
+    }
+
+    let archive = writer
+        .close()
+        .await
+        .context("failed to close backup archive")?;
+
+    encrypt(key, &archive).context("failed to encrypt backup archive")
+}
+
+// returns the path of the uploaded backup
+pub async fn upload_backup(data: Vec<u8>, state: AppState) -> Result<String> {
+    let filename = format!(
+        "telegram-onedrive-{}.backup",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    );
+
+    let filename = state
+        .onedrive
+        .upload_bytes(None, BACKUP_DIR, &filename, &data)
+        .await
+        .context("failed to upload backup")?;
+
+    tracing::info!("uploaded backup {} size: {}", filename, data.len());
+
+    Ok(Path::new(BACKUP_DIR)
+        .join(filename)
+        .to_slash_lossy()
+        .to_string())
+}
+
+// run before the clients start, existing files are kept as *.bak
+pub async fn restore_backup(archive_path: &str) -> Result<()> {
+    let key = get_backup_key()?;
+
+    let data = fs::read(archive_path)
+        .await
+        .context(format!("failed to read backup {}", archive_path))?;
+
+    let archive = decrypt(key, &data).context("failed to decrypt backup archive")?;
+
+    let reader = ZipFileReader::new(archive)
+        .await
+        .context("failed to read backup archive")?;
+
+    let paths = backup_paths();
+
+    for index in 0..reader.file().entries().len() {
+        let mut entry_reader = reader
+            .reader_with_entry(index)
+            .await
+            .context("failed to read backup entry")?;
+
+        let filename = entry_reader
+            .entry()
+            .filename()
+            .as_str()
+            .context("invalid backup entry name")?
+            .to_string();
+
+        let Some(path) = paths
+            .iter()
+            .find(|path| Path::new(path).file_name() == Some(OsStr::new(&filename)))
+        else {
+            tracing::warn!("skipped unknown backup entry {}", filename);
+
+            continue;
+        };
+
+        let mut buffer = Vec::new();
+        entry_reader
+            .read_to_end_checked(&mut buffer)
+            .await
+            .context(format!("failed to read {} from backup", filename))?;
+
+        if Path::new(path).exists() {
+            fs::rename(path, format!("{}.bak", path))
+                .await
+                .context(format!("failed to back up existing {}", path))?;
+        }
+
+        fs::write(path, buffer)
+            .await
+            .context(format!("failed to restore {}", path))?;
+
+        tracing::info!("restored {}", path);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,265 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod records;
+
+use crate::env::ENV;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
+    EntityName, EntityTrait, QueryFilter, Schema, Set,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+// usages are written to the db in batches, not once per chunk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccountType {
+    Telegram,
+    OneDrive,
+}
+
+impl AccountType {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Telegram => "telegram",
+            Self::OneDrive => "onedrive",
+        }
+    }
+}
+
+pub struct AccountUsage {
+    pub account_type: String,
+    pub account: String,
+    pub daily_bytes: u64,
+    pub monthly_bytes: u64,
+}
+
+struct PendingUsages {
+    bytes: HashMap<(AccountType, String), u64>,
+    flushed_at: Instant,
+}
+
+// bytes transferred by each account per day and per month, in utc
+pub struct UsageSession {
+    connection: DatabaseConnection,
+    // also avoid inserting the same record twice by parallel tasks
+    pending: Mutex<PendingUsages>,
+}
+
+impl UsageSession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self {
+            connection,
+            pending: Mutex::new(PendingUsages {
+                bytes: HashMap::new(),
+                flushed_at: Instant::now(),
+            }),
+        })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to usage session")?;
+
+        Self::create_table_if_not_exists(&connection).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        if !Self::is_table_exists(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement =
+                Schema::new(backend).create_table_from_entity(records::Entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!(
+                    "failed to create table {}",
+                    records::Entity.table_name()
+                ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
+        let result = records::Entity::find().all(connection).await;
+
+        result.is_ok()
+    }
+
+    pub async fn add_usage(
+        &self,
+        account_type: AccountType,
+        account: &str,
+        bytes: u64,
+    ) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+
+        *pending
+            .bytes
+            .entry((account_type, account.to_string()))
+            .or_default() += bytes;
+
+        if pending.flushed_at.elapsed() >= FLUSH_INTERVAL {
+            self.flush_pending(&mut pending).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn flush(&self) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+
+        self.flush_pending(&mut pending).await
+    }
+
+    async fn flush_pending(&self, pending: &mut PendingUsages) -> Result<()> {
+        pending.flushed_at = Instant::now();
+
+        // an account is only dropped from the pending ones once it's written
+        let keys = pending.bytes.keys().cloned().collect::<Vec<_>>();
+
+        for key in keys {
+            let (account_type, account) = &key;
+            let bytes = pending.bytes[&key];
+
+            self.write_usage(*account_type, account, bytes).await?;
+
+            pending.bytes.remove(&key);
+        }
+
+        Ok(())
+    }
+
+    async fn write_usage(
+        &self,
+        account_type: AccountType,
+        account: &str,
+        bytes: u64,
+    ) -> Result<()> {
+        for period in [get_daily_period(), get_monthly_period()] {
+            let update_result = records::Entity::update_many()
+                .col_expr(
+                    records::Column::Bytes,
+                    Expr::col(records::Column::Bytes).add(bytes as i64),
+                )
+                .filter(records::Column::AccountType.eq(account_type.as_str()))
+                .filter(records::Column::Account.eq(account))
+                .filter(records::Column::Period.eq(&period))
+                .exec(&self.connection)
+                .await
+                .context("failed to update usage")?;
+
+            if update_result.rows_affected == 0 {
+                let insert_item = records::ActiveModel {
+                    id: ActiveValue::default(),
+                    account_type: Set(account_type.as_str().to_string()),
+                    account: Set(account.to_string()),
+                    period: Set(period),
+                    bytes: Set(bytes as i64),
+                };
+
+                records::Entity::insert(insert_item)
+                    .exec(&self.connection)
+                    .await
+                    .context("failed to insert usage")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_current_usages(&self) -> Result<Vec<AccountUsage>> {
+        self.flush().await?;
+
+        let daily_period = get_daily_period();
+
+        let records = records::Entity::find()
+            .filter(
+                Condition::any()
+                    .add(records::Column::Period.eq(&daily_period))
+                    .add(records::Column::Period.eq(get_monthly_period())),
+            )
+            .all(&self.connection)
+            .await
+            .context("failed to get current usages")?;
+
+        let mut usages = BTreeMap::new();
+
+        for record in records {
+            let usage = usages
+                .entry((record.account_type.clone(), record.account.clone()))
+                .or_insert_with(|| AccountUsage {
+                    account_type: record.account_type,
+                    account: record.account,
+                    daily_bytes: 0,
+                    monthly_bytes: 0,
+                });
+
+            if record.period == daily_period {
+                usage.daily_bytes = record.bytes as u64;
+            } else {
+                usage.monthly_bytes = record.bytes as u64;
+            }
+        }
+
+        Ok(usages.into_values().collect())
+    }
+
+    // the reason if any account exceeds the soft caps, the queue should be paused
+    pub async fn get_exceeded_cap(&self) -> Result<Option<String>> {
+        let env = ENV.get().unwrap();
+
+        if env.daily_cap.is_none() && env.monthly_cap.is_none() {
+            return Ok(None);
+        }
+
+        for usage in self.get_current_usages().await? {
+            if let Some(daily_cap) = env.daily_cap {
+                if usage.daily_bytes >= daily_cap * 1024 * 1024 {
+                    return Ok(Some(format!(
+                        "{} account {} exceeded the daily cap {}MB",
+                        usage.account_type, usage.account, daily_cap
+                    )));
+                }
+            }
+
+            if let Some(monthly_cap) = env.monthly_cap {
+                if usage.monthly_bytes >= monthly_cap * 1024 * 1024 {
+                    return Ok(Some(format!(
+                        "{} account {} exceeded the monthly cap {}MB",
+                        usage.account_type, usage.account, monthly_cap
+                    )));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn get_daily_period() -> String {
+    Utc::now().format("%Y-%m-%d").to_string()
+}
+
+fn get_monthly_period() -> String {
+    Utc::now().format("%Y-%m").to_string()
+}
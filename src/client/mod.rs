@@ -6,8 +6,8 @@
 */
 
 pub mod onedrive;
-mod telegram;
+pub mod telegram;
 pub mod utils;
 
-pub use onedrive::OneDriveClient;
+pub use onedrive::{AppTarget, DriveTarget, NationalCloud, OneDriveClient, OneDriveScope};
 pub use telegram::TelegramClient;
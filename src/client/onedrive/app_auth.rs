@@ -0,0 +1,259 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::NationalCloud;
+use crate::{env::OneDriveAppEnv, utils::get_current_timestamp};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use onedrive_api::{DriveId, DriveLocation};
+use rand::{distributions::Alphanumeric, Rng};
+use ring::{
+    digest::{digest, SHA256},
+    rand::SystemRandom,
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::{fmt::Display, str::FromStr};
+
+// the client assertion is only used once, a short lifetime is enough
+const ASSERTION_LIFETIME_SECS: i64 = 600;
+
+// the drive accessed in app-only mode, as there is no signed-in user
+#[derive(Clone)]
+pub enum AppTarget {
+    // user id or user principal name
+    User(String),
+    // site id
+    Site(String),
+    // any document library, like a library of a site other than the default one
+    Drive(String),
+}
+
+impl FromStr for AppTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("user", user)) if !user.is_empty() => Ok(Self::User(user.to_string())),
+            Some(("site", site)) if !site.is_empty() => Ok(Self::Site(site.to_string())),
+            Some(("drive", drive)) if !drive.is_empty() => Ok(Self::Drive(drive.to_string())),
+            _ => Err(anyhow!(
+                "onedrive app target should be user:$user_principal_name, site:$site_id or drive:$drive_id"
+            )),
+        }
+    }
+}
+
+// also used as the username of the session
+impl Display for AppTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::User(user) => write!(f, "user:{}", user),
+            Self::Site(site) => write!(f, "site:{}", site),
+            Self::Drive(drive) => write!(f, "drive:{}", drive),
+        }
+    }
+}
+
+impl AppTarget {
+    pub fn drive_location(&self) -> DriveLocation {
+        match self {
+            Self::User(user) => DriveLocation::from_user(user.clone()),
+            Self::Site(site) => DriveLocation::from_site(site.clone()),
+            Self::Drive(drive) => DriveLocation::from_id(DriveId(drive.clone())),
+        }
+    }
+
+    // path of the drive in graph api
+    pub fn drive_path(&self) -> String {
+        match self {
+            Self::User(user) => format!("users/{}/drive", user),
+            Self::Site(site) => format!("sites/{}/drive", site),
+            Self::Drive(drive) => format!("drives/{}", drive),
+        }
+    }
+}
+
+enum AppCredential {
+    Secret(String),
+    Certificate {
+        key_pair: RsaKeyPair,
+        // base64url encoded sha256 of the certificate
+        thumbprint: String,
+    },
+}
+
+pub struct AppAuth {
+    tenant_id: String,
+    client_id: String,
+    credential: AppCredential,
+    cloud: NationalCloud,
+    pub target: AppTarget,
+}
+
+#[derive(Deserialize)]
+pub struct AppTokenResponse {
+    pub access_token: String,
+    #[serde(rename = "expires_in")]
+    pub expires_in_secs: u64,
+}
+
+impl AppAuth {
+    pub fn new(
+        client_id: &str,
+        client_secret: &str,
+        cloud: NationalCloud,
+        OneDriveAppEnv {
+            tenant_id,
+            target,
+            client_cert_path,
+            client_key_path,
+        }: &OneDriveAppEnv,
+    ) -> Result<Self> {
+        let credential = match (client_cert_path, client_key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                tracing::debug!("onedrive app-only mode uses certificate credential");
+
+                load_certificate(cert_path, key_path)?
+            }
+            _ => {
+                tracing::debug!("onedrive app-only mode uses secret credential");
+
+                AppCredential::Secret(client_secret.to_string())
+            }
+        };
+
+        Ok(Self {
+            tenant_id: tenant_id.clone(),
+            client_id: client_id.to_string(),
+            credential,
+            cloud,
+            target: target.clone(),
+        })
+    }
+
+    fn token_url(&self) -> String {
+        self.cloud.token_url(&self.tenant_id)
+    }
+
+    pub async fn request_token(&self) -> Result<AppTokenResponse> {
+        let mut params = vec![
+            ("grant_type", "client_credentials".to_string()),
+            ("client_id", self.client_id.clone()),
+            ("scope", format!("{}/.default", self.cloud.graph_origin())),
+        ];
+
+        match &self.credential {
+            AppCredential::Secret(secret) => params.push(("client_secret", secret.clone())),
+            AppCredential::Certificate {
+                key_pair,
+                thumbprint,
+            } => {
+                params.push((
+                    "client_assertion_type",
+                    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer".to_string(),
+                ));
+                params.push((
+                    "client_assertion",
+                    self.build_client_assertion(key_pair, thumbprint)?,
+                ));
+            }
+        }
+
+        let response = reqwest::Client::new()
+            .post(self.token_url())
+            .form(&params)
+            .send()
+            .await
+            .context("failed to send request for onedrive app-only token")?;
+
+        let status = response.status();
+
+        let content = response
+            .text()
+            .await
+            .context("failed to get response text for onedrive app-only token")?;
+
+        if !status.is_success() {
+            return Err(anyhow!(
+                "failed to get onedrive app-only token: {}",
+                content
+            ));
+        }
+
+        serde_json::from_str::<AppTokenResponse>(&content)
+            .context("failed to deserialize onedrive app-only token response")
+    }
+
+    // a jwt signed by the private key of the certificate uploaded to the app registration
+    fn build_client_assertion(&self, key_pair: &RsaKeyPair, thumbprint: &str) -> Result<String> {
+        let now = get_current_timestamp();
+
+        let jti = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect::<String>();
+
+        let header = json!({
+            "alg": "RS256",
+            "typ": "JWT",
+            "x5t#S256": thumbprint,
+        });
+
+        let claims = json!({
+            "aud": self.token_url(),
+            "iss": self.client_id,
+            "sub": self.client_id,
+            "jti": jti,
+            "nbf": now,
+            "exp": now + ASSERTION_LIFETIME_SECS,
+        });
+
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+
+        let mut signature = vec![0; key_pair.public().modulus_len()];
+        key_pair
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .map_err(|_| anyhow!("failed to sign onedrive client assertion"))?;
+
+        Ok(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)))
+    }
+}
+
+fn load_certificate(cert_path: &str, key_path: &str) -> Result<AppCredential> {
+    let cert_pem = std::fs::read(cert_path).context("failed to read onedrive client cert")?;
+    let cert = pem::parse(cert_pem).context("failed to parse onedrive client cert")?;
+
+    let thumbprint = URL_SAFE_NO_PAD.encode(digest(&SHA256, cert.contents()));
+
+    let key_pem = std::fs::read(key_path).context("failed to read onedrive client key")?;
+    let key = pem::parse(key_pem).context("failed to parse onedrive client key")?;
+
+    // both pkcs8 and pkcs1 keys are accepted
+    let key_pair = match key.tag() {
+        "PRIVATE KEY" => RsaKeyPair::from_pkcs8(key.contents()),
+        "RSA PRIVATE KEY" => RsaKeyPair::from_der(key.contents()),
+        tag => return Err(anyhow!("unsupported onedrive client key type: {}", tag)),
+    }
+    .map_err(|e| anyhow!("invalid onedrive client key: {}", e))?;
+
+    Ok(AppCredential::Certificate {
+        key_pair,
+        thumbprint,
+    })
+}
@@ -0,0 +1,90 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use anyhow::{anyhow, Error, Result};
+use std::str::FromStr;
+
+// the cloud the account lives in, set by od_cloud
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NationalCloud {
+    Global,
+    // operated by 21Vianet
+    China,
+    // GCC High
+    UsGov,
+    UsGovDod,
+    Germany,
+}
+
+impl FromStr for NationalCloud {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "global" => Ok(Self::Global),
+            "china" => Ok(Self::China),
+            "us_gov" => Ok(Self::UsGov),
+            "us_gov_dod" => Ok(Self::UsGovDod),
+            "germany" => Ok(Self::Germany),
+            _ => Err(anyhow!(
+                "onedrive cloud should be global, china, us_gov, us_gov_dod or germany"
+            )),
+        }
+    }
+}
+
+impl NationalCloud {
+    // also the resource of app-only tokens
+    pub const fn graph_origin(self) -> &'static str {
+        match self {
+            Self::Global => "https://graph.microsoft.com",
+            Self::China => "https://microsoftgraph.chinacloudapi.cn",
+            Self::UsGov => "https://graph.microsoft.us",
+            Self::UsGovDod => "https://dod-graph.microsoft.us",
+            Self::Germany => "https://graph.microsoft.de",
+        }
+    }
+
+    pub const fn login_host(self) -> &'static str {
+        match self {
+            Self::Global => "login.microsoftonline.com",
+            Self::China => "login.chinacloudapi.cn",
+            Self::UsGov | Self::UsGovDod => "login.microsoftonline.us",
+            Self::Germany => "login.microsoftonline.de",
+        }
+    }
+
+    // like https://graph.microsoft.com/v1.0
+    pub fn api_url(self) -> String {
+        format!("{}/v1.0", self.graph_origin())
+    }
+
+    pub fn token_url(self, tenant: &str) -> String {
+        format!("https://{}/{}/oauth2/v2.0/token", self.login_host(), tenant)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cloud() {
+        assert!("China".parse::<NationalCloud>().unwrap() == NationalCloud::China);
+        assert!("us_gov_dod".parse::<NationalCloud>().unwrap() == NationalCloud::UsGovDod);
+        assert!("azure".parse::<NationalCloud>().is_err());
+
+        assert_eq!(
+            NationalCloud::China.token_url("common"),
+            "https://login.chinacloudapi.cn/common/oauth2/v2.0/token"
+        );
+        assert_eq!(
+            NationalCloud::UsGov.api_url(),
+            "https://graph.microsoft.us/v1.0"
+        );
+    }
+}
@@ -37,6 +37,11 @@ impl OneDriveClient {
         Ok(root_path)
     }
 
+    // the directory of the current account, ignoring a temporary one
+    pub async fn get_account_root_path(&self) -> String {
+        self.session.read().await.root_path.clone()
+    }
+
     pub async fn does_temp_root_path_exist(&self) -> bool {
         let is_exist = !self.temp_root_path.read().await.is_empty();
 
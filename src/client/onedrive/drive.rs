@@ -5,9 +5,11 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::OneDriveClient;
-use anyhow::Result;
-use onedrive_api::{DriveLocation, OneDrive};
+use super::{graph::GraphRequest, target::SharedFolder, DriveTarget, OneDriveClient};
+use anyhow::{anyhow, Context, Result};
+use onedrive_api::OneDrive;
+
+const MAX_SHARED_ITEMS: usize = 1000;
 
 impl OneDriveClient {
     pub async fn get_usernames(&self) -> Result<Vec<String>> {
@@ -23,11 +25,62 @@ impl OneDriveClient {
 
         session.change_session(username).await?;
 
-        *self.client.write().await =
-            OneDrive::new(session.access_token.clone(), DriveLocation::me());
+        *self.client.write().await = OneDrive::new(
+            session.access_token.clone(),
+            self.drive_location(&session.drive_target),
+        );
 
         tracing::debug!("change account to {}", username);
 
         Ok(())
     }
+
+    pub async fn get_drive_target(&self) -> DriveTarget {
+        self.session.read().await.drive_target.clone()
+    }
+
+    // checked before saving, returns the name of the drive
+    pub async fn set_drive_target(&self, drive_target: DriveTarget) -> Result<String> {
+        if self.app_auth.is_some() {
+            return Err(anyhow!("drive target can't be changed in app-only mode"))
+                .context("set od_app_target instead");
+        }
+
+        self.refresh_access_token().await?;
+
+        let mut session = self.session.write().await;
+
+        let client = OneDrive::new(
+            session.access_token.clone(),
+            self.drive_location(&drive_target),
+        );
+
+        let drive_name = self
+            .check_drive(&client, &drive_target)
+            .await
+            .context("failed to access the drive, check the id and od_scope")?;
+
+        session.set_drive_target(drive_target).await?;
+
+        *self.client.write().await = client;
+        self.quota_cache.write().await.clear();
+        self.clear_items();
+
+        Ok(drive_name)
+    }
+
+    // folders shared with the current account, which can be set as drive targets
+    pub async fn list_shared_folders(&self) -> Result<Vec<SharedFolder>> {
+        let graph = self.get_current_graph().await?;
+
+        let items = graph
+            .get_pages(
+                GraphRequest::list_shared_with_me(&self.cloud.api_url()),
+                MAX_SHARED_ITEMS,
+            )
+            .await
+            .context("failed to list items shared with the account, check od_scope")?;
+
+        Ok(items.iter().filter_map(SharedFolder::from_item).collect())
+    }
 }
@@ -0,0 +1,473 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::PATH_ENCODE_SET;
+use anyhow::{anyhow, Context, Result};
+use percent_encoding::utf8_percent_encode;
+use reqwest::{header, Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{fmt::Display, future::Future};
+
+// a request to graph api, built apart from sending it so that it can be checked without network
+#[derive(Debug, PartialEq, Eq)]
+pub struct GraphRequest {
+    pub method: Method,
+    pub url: String,
+    pub body: Option<Value>,
+    // raw bytes of a file, sent instead of the json body
+    pub content: Option<Vec<u8>>,
+}
+
+impl GraphRequest {
+    const fn new(method: Method, url: String) -> Self {
+        Self {
+            method,
+            url,
+            body: None,
+            content: None,
+        }
+    }
+
+    fn with_body(mut self, body: Value) -> Self {
+        self.body = Some(body);
+
+        self
+    }
+
+    fn with_content(mut self, content: Vec<u8>) -> Self {
+        self.content = Some(content);
+
+        self
+    }
+
+    // the profile of the account, not bound to any drive
+    pub fn get_me(api_url: &str) -> Self {
+        Self::new(Method::GET, format!("{}/me/", api_url))
+    }
+
+    // refresh tokens issued to the account are invalidated, not supported by personal accounts
+    pub fn revoke_sign_in_sessions(api_url: &str) -> Self {
+        Self::new(Method::POST, format!("{}/me/revokeSignInSessions", api_url))
+    }
+
+    // items shared with the account, in the drives of their owners
+    pub fn list_shared_with_me(api_url: &str) -> Self {
+        Self::new(Method::GET, format!("{}/me/drive/sharedWithMe", api_url))
+    }
+}
+
+pub struct GraphResponse {
+    pub status: StatusCode,
+    // seconds from the Retry-After header, sent with 429 and 503
+    pub retry_after: Option<u32>,
+    pub content: String,
+}
+
+// a failed status, kept so that callers can tell throttling from other errors
+#[derive(Debug)]
+pub struct GraphStatusError {
+    pub status: StatusCode,
+    pub retry_after: Option<u32>,
+    pub content: String,
+}
+
+impl std::error::Error for GraphStatusError {}
+
+impl Display for GraphStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.status, self.content)
+    }
+}
+
+impl GraphResponse {
+    // none if not found, failed statuses keep the content for the reason
+    pub fn content(self) -> Result<Option<String>> {
+        if self.status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !self.status.is_success() {
+            return Err(GraphStatusError {
+                status: self.status,
+                retry_after: self.retry_after,
+                content: self.content,
+            }
+            .into());
+        }
+
+        Ok(Some(self.content))
+    }
+
+    pub fn json<T: DeserializeOwned>(self) -> Result<Option<T>> {
+        self.content()?
+            .map(|content| serde_json::from_str(&content))
+            .transpose()
+            .context("failed to deserialize graph response")
+    }
+}
+
+// sends requests with the access token, tests pass a fake one instead of the http client
+pub trait Transport: Sync {
+    fn send(
+        &self,
+        request: GraphRequest,
+        access_token: &str,
+    ) -> impl Future<Output = Result<GraphResponse>> + Send;
+}
+
+impl Transport for reqwest::Client {
+    async fn send(&self, request: GraphRequest, access_token: &str) -> Result<GraphResponse> {
+        let mut builder = self
+            .request(request.method, &request.url)
+            .header(header::AUTHORIZATION, format!("Bearer {}", access_token));
+
+        if let Some(body) = request.body {
+            builder = builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+
+        if let Some(content) = request.content {
+            builder = builder
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(content);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .context("failed to send request to graph api")?;
+
+        let status = response.status();
+
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+
+        let content = response
+            .text()
+            .await
+            .context("failed to get response text from graph api")?;
+
+        Ok(GraphResponse {
+            status,
+            retry_after,
+            content,
+        })
+    }
+}
+
+// urls of a drive, like https://graph.microsoft.com/v1.0/me/drive, urls of onedrive_api only reach the global cloud
+pub struct DriveApi {
+    api_url: String,
+    drive_path: String,
+    // root, or items/$item_id of a shared folder
+    root_item: String,
+}
+
+impl DriveApi {
+    pub const fn new(api_url: String, drive_path: String, root_item: String) -> Self {
+        Self {
+            api_url,
+            drive_path,
+            root_item,
+        }
+    }
+
+    fn drive_url(&self) -> String {
+        format!("{}/{}", self.api_url, self.drive_path)
+    }
+
+    // like https://graph.microsoft.com/v1.0/me/drive/root:/a.mp4:
+    fn item_url(&self, path: &str) -> String {
+        let item = if path == "/" {
+            self.root_item.clone()
+        } else {
+            format!(
+                "{}:{}:",
+                self.root_item,
+                utf8_percent_encode(path, PATH_ENCODE_SET)
+            )
+        };
+
+        format!("{}/{}", self.drive_url(), item)
+    }
+
+    pub fn get_drive(&self) -> GraphRequest {
+        GraphRequest::new(Method::GET, self.drive_url())
+    }
+
+    pub fn get_item(&self, path: &str) -> GraphRequest {
+        GraphRequest::new(Method::GET, self.item_url(path))
+    }
+
+    // the first page, the following ones are in @odata.nextLink
+    pub fn list_children(&self, path: &str) -> GraphRequest {
+        GraphRequest::new(
+            Method::GET,
+            format!(
+                "{}/children?$expand=thumbnails&$select=name,size,folder,file,image,webUrl,lastModifiedDateTime&$top=200",
+                self.item_url(path)
+            ),
+        )
+    }
+
+    // fails if an item with the same name exists
+    pub fn create_folder(&self, parent_path: &str, name: &str) -> GraphRequest {
+        GraphRequest::new(
+            Method::POST,
+            format!("{}/children", self.item_url(parent_path)),
+        )
+        .with_body(json!({
+            "name": name,
+            "folder": {},
+            "@microsoft.graph.conflictBehavior": "fail",
+        }))
+    }
+
+    // fields like description or fileSystemInfo
+    pub fn update_item(&self, path: &str, fields: Value) -> GraphRequest {
+        GraphRequest::new(Method::PATCH, self.item_url(path)).with_body(fields)
+    }
+
+    // fails if an item with the same name exists in the destination
+    pub fn move_item(&self, path: &str, parent_id: &str, name: &str) -> GraphRequest {
+        self.update_item(
+            path,
+            json!({
+                "parentReference": { "id": parent_id },
+                "name": name,
+            }),
+        )
+    }
+
+    pub fn delete_item(&self, path: &str) -> GraphRequest {
+        GraphRequest::new(Method::DELETE, self.item_url(path))
+    }
+
+    // conflict behavior is one of rename, replace and fail
+    pub fn create_upload_session(&self, path: &str, conflict_behavior: &str) -> GraphRequest {
+        GraphRequest::new(
+            Method::POST,
+            format!("{}/createUploadSession", self.item_url(path)),
+        )
+        .with_body(json!({
+            "item": {
+                "@microsoft.graph.conflictBehavior": conflict_behavior,
+            }
+        }))
+    }
+
+    // a file in a single request instead of an upload session, only for small files
+    pub fn upload_content(
+        &self,
+        path: &str,
+        conflict_behavior: &str,
+        content: Vec<u8>,
+    ) -> GraphRequest {
+        GraphRequest::new(
+            Method::PUT,
+            format!(
+                "{}/content?@microsoft.graph.conflictBehavior={}",
+                self.item_url(path),
+                conflict_behavior
+            ),
+        )
+        .with_content(content)
+    }
+
+    // a view-only link, scope is anonymous or organization
+    pub fn create_link(&self, path: &str, scope: &str) -> GraphRequest {
+        GraphRequest::new(Method::POST, format!("{}/createLink", self.item_url(path)))
+            .with_body(json!({ "type": "view", "scope": scope }))
+    }
+}
+
+// a drive of an account with its access token
+pub struct GraphClient<T: Transport = reqwest::Client> {
+    transport: T,
+    access_token: String,
+    pub drive: DriveApi,
+}
+
+impl<T: Transport> GraphClient<T> {
+    pub const fn new(transport: T, access_token: String, drive: DriveApi) -> Self {
+        Self {
+            transport,
+            access_token,
+            drive,
+        }
+    }
+
+    pub async fn send(&self, request: GraphRequest) -> Result<GraphResponse> {
+        self.transport.send(request, &self.access_token).await
+    }
+
+    // values of all pages up to max, the request is resumed from @odata.nextLink of each page
+    pub async fn get_pages(&self, request: GraphRequest, max: usize) -> Result<Vec<Value>> {
+        let mut values = Vec::new();
+        let mut next_request = Some(request);
+
+        while let Some(request) = next_request.take() {
+            let mut page = self
+                .send(request)
+                .await?
+                .json::<Value>()?
+                .ok_or_else(|| anyhow!("not found"))?;
+
+            let items = page
+                .get_mut("value")
+                .and_then(Value::as_array_mut)
+                .ok_or_else(|| anyhow!("field value not found in page"))?;
+
+            values.append(items);
+
+            if values.len() >= max {
+                values.truncate(max);
+
+                break;
+            }
+
+            next_request = page
+                .get("@odata.nextLink")
+                .and_then(Value::as_str)
+                .map(|url| GraphRequest::new(Method::GET, url.to_string()));
+        }
+
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn drive() -> DriveApi {
+        DriveApi::new(
+            "https://graph.microsoft.com/v1.0".to_string(),
+            "me/drive".to_string(),
+            "root".to_string(),
+        )
+    }
+
+    // responds with the pages in order, and keeps the urls requested
+    struct FakeTransport {
+        pages: Mutex<Vec<Value>>,
+        urls: Mutex<Vec<String>>,
+    }
+
+    impl Transport for FakeTransport {
+        async fn send(&self, request: GraphRequest, _: &str) -> Result<GraphResponse> {
+            self.urls.lock().unwrap().push(request.url);
+
+            let page = self.pages.lock().unwrap().remove(0);
+
+            Ok(GraphResponse {
+                status: StatusCode::OK,
+                retry_after: None,
+                content: page.to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_item_requests() {
+        let drive = drive();
+
+        assert_eq!(
+            drive.get_item("/").url,
+            "https://graph.microsoft.com/v1.0/me/drive/root"
+        );
+        assert_eq!(
+            drive.delete_item("/Movies/a b#1.mp4"),
+            GraphRequest::new(
+                Method::DELETE,
+                "https://graph.microsoft.com/v1.0/me/drive/root:/Movies/a%20b%231.mp4:".to_string()
+            )
+        );
+        assert_eq!(
+            drive.move_item("/a.mp4", "123", "b.mp4").body,
+            Some(json!({ "parentReference": { "id": "123" }, "name": "b.mp4" }))
+        );
+        assert_eq!(
+            drive.create_upload_session("/a.mp4", "fail").url,
+            "https://graph.microsoft.com/v1.0/me/drive/root:/a.mp4:/createUploadSession"
+        );
+        assert_eq!(
+            drive.upload_content("/a.jpg", "rename", vec![1, 2]).url,
+            "https://graph.microsoft.com/v1.0/me/drive/root:/a.jpg:/content?@microsoft.graph.conflictBehavior=rename"
+        );
+
+        let shared = DriveApi::new(
+            "https://graph.microsoft.com/v1.0".to_string(),
+            "drives/b!abc".to_string(),
+            "items/01XYZ".to_string(),
+        );
+        assert_eq!(
+            shared.get_item("/").url,
+            "https://graph.microsoft.com/v1.0/drives/b!abc/items/01XYZ"
+        );
+        assert_eq!(
+            shared.get_item("/TG/a.mp4").url,
+            "https://graph.microsoft.com/v1.0/drives/b!abc/items/01XYZ:/TG/a.mp4:"
+        );
+    }
+
+    #[test]
+    fn test_graph_response() {
+        let response = |status, content: &str| GraphResponse {
+            status,
+            retry_after: None,
+            content: content.to_string(),
+        };
+
+        assert!(response(StatusCode::NOT_FOUND, "")
+            .content()
+            .unwrap()
+            .is_none());
+        assert!(response(StatusCode::CONFLICT, "nameAlreadyExists")
+            .content()
+            .is_err_and(|e| e.to_string().contains("nameAlreadyExists")));
+        assert!(GraphResponse {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            retry_after: Some(30),
+            content: String::new(),
+        }
+        .content()
+        .is_err_and(|e| e
+            .downcast_ref::<GraphStatusError>()
+            .is_some_and(|e| e.retry_after == Some(30))));
+        assert_eq!(
+            response(StatusCode::OK, r#"{"name":"a.mp4"}"#)
+                .json::<Value>()
+                .unwrap(),
+            Some(json!({ "name": "a.mp4" }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_pages() {
+        let transport = FakeTransport {
+            pages: Mutex::new(vec![
+                json!({ "value": [1, 2], "@odata.nextLink": "https://next/2" }),
+                json!({ "value": [3, 4], "@odata.nextLink": "https://next/3" }),
+            ]),
+            urls: Mutex::new(Vec::new()),
+        };
+        let graph = GraphClient::new(transport, "token".to_string(), drive());
+
+        let request = graph.drive.list_children("/");
+        let values = graph.get_pages(request, 3).await.unwrap();
+
+        assert_eq!(values, vec![json!(1), json!(2), json!(3)]);
+        assert_eq!(graph.transport.urls.lock().unwrap()[1], "https://next/2");
+    }
+}
@@ -0,0 +1,387 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::OneDriveClient;
+use anyhow::{anyhow, Context, Result};
+use onedrive_api::resource::DriveItem;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// folders are browsed page by page and paths are checked repeatedly, so lookups are cached
+const ITEM_CACHE_TTL: Duration = Duration::from_secs(60);
+
+// avoid fetching too many pages for huge folders
+const MAX_CHILDREN: usize = 1000;
+
+// username of the account and the path of the item
+type ItemKey = (String, String);
+
+struct Cached<T> {
+    value: T,
+    fetched_at: Instant,
+}
+
+impl<T: Clone> Cached<T> {
+    fn get(&self) -> Option<T> {
+        (self.fetched_at.elapsed() < ITEM_CACHE_TTL).then(|| self.value.clone())
+    }
+}
+
+#[derive(Default)]
+pub struct ItemCache {
+    children: Mutex<HashMap<ItemKey, Cached<Arc<Vec<Value>>>>>,
+    // none if the item doesn't exist
+    items: Mutex<HashMap<ItemKey, Cached<Option<DriveItem>>>>,
+}
+
+impl ItemCache {
+    // the item is uploaded, moved or deleted, so its parents and children are outdated too
+    fn invalidate(&self, path: &str) {
+        let path = normalize_path(path);
+
+        self.children
+            .lock()
+            .unwrap()
+            .retain(|(_, cached_path), _| !is_affected(cached_path, &path));
+        self.items
+            .lock()
+            .unwrap()
+            .retain(|(_, cached_path), _| !is_affected(cached_path, &path));
+    }
+
+    fn clear(&self) {
+        self.children.lock().unwrap().clear();
+        self.items.lock().unwrap().clear();
+    }
+}
+
+impl OneDriveClient {
+    // raw items in the folder, with thumbnails expanded
+    pub async fn list_children(&self, path: &str) -> Result<Arc<Vec<Value>>> {
+        let path = normalize_path(path);
+        let key = (self.get_cache_username().await?, path.clone());
+
+        let cached = self
+            .item_cache
+            .children
+            .lock()
+            .unwrap()
+            .get(&key)
+            .and_then(Cached::get);
+
+        if let Some(children) = cached {
+            return Ok(children);
+        }
+
+        let graph = self.get_current_graph().await?;
+
+        let children = graph
+            .get_pages(graph.drive.list_children(&path), MAX_CHILDREN)
+            .await
+            .context(format!("failed to list children of {}", path))?;
+
+        tracing::debug!("got {} children in {}", children.len(), path);
+
+        let children = Arc::new(children);
+
+        self.item_cache.children.lock().unwrap().insert(
+            key,
+            Cached {
+                value: children.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+
+        Ok(children)
+    }
+
+    // none if the item doesn't exist
+    pub async fn get_item_by_path(&self, path: &str) -> Result<Option<DriveItem>> {
+        let path = normalize_path(path);
+        let key = (self.get_cache_username().await?, path.clone());
+
+        let cached = self
+            .item_cache
+            .items
+            .lock()
+            .unwrap()
+            .get(&key)
+            .and_then(Cached::get);
+
+        if let Some(item) = cached {
+            return Ok(item);
+        }
+
+        let graph = self.get_current_graph().await?;
+
+        let item = graph
+            .send(graph.drive.get_item(&path))
+            .await?
+            .json::<DriveItem>()
+            .context(format!("failed to get item {}", path))?;
+
+        self.item_cache.items.lock().unwrap().insert(
+            key,
+            Cached {
+                value: item.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+
+        Ok(item)
+    }
+
+    // not cached for accounts other than the current one, like the one a task is pinned to
+    pub async fn get_account_item_by_path(
+        &self,
+        account: Option<&str>,
+        path: &str,
+    ) -> Result<Option<DriveItem>> {
+        if account.is_none() || account == self.get_current_username().await?.as_deref() {
+            return self.get_item_by_path(path).await;
+        }
+
+        let path = normalize_path(path);
+
+        let graph = self.get_account_graph(account).await?;
+
+        graph
+            .send(graph.drive.get_item(&path))
+            .await?
+            .json::<DriveItem>()
+            .context(format!("failed to get item {}", path))
+    }
+
+    // folders are created when uploading, so only an existing file is rejected
+    pub async fn check_dir(&self, path: &str) -> Result<()> {
+        match self.get_item_by_path(path).await? {
+            Some(item) if item.folder.is_none() => {
+                Err(anyhow!("{} is a file, not a directory", path))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // fails if an item with the same name exists, returns the path of the new folder
+    pub async fn create_folder(&self, parent_path: &str, name: &str) -> Result<String> {
+        let parent_path = normalize_path(parent_path);
+
+        if name.is_empty() || name.contains('/') {
+            return Err(anyhow!("invalid folder name: {}", name));
+        }
+
+        let graph = self.get_current_graph().await?;
+
+        graph
+            .send(graph.drive.create_folder(&parent_path, name))
+            .await?
+            .content()
+            .and_then(|content| content.ok_or_else(|| anyhow!("{} not found", parent_path)))
+            .context(format!(
+                "failed to create folder {} in {}",
+                name, parent_path
+            ))?;
+
+        self.invalidate_items(&parent_path);
+
+        Ok(normalize_path(&format!("{}/{}", parent_path, name)))
+    }
+
+    // into the folder if the destination is one, otherwise to the path, returns the new path
+    pub async fn move_item(&self, source_path: &str, dest_path: &str) -> Result<String> {
+        let source_path = normalize_path(source_path);
+        let mut dest_path = normalize_path(dest_path);
+
+        let (_, source_name) =
+            split_path(&source_path).ok_or_else(|| anyhow!("the root can't be moved"))?;
+
+        if self.get_item_by_path(&source_path).await?.is_none() {
+            return Err(anyhow!("{} not found", source_path));
+        }
+
+        if self
+            .get_item_by_path(&dest_path)
+            .await?
+            .is_some_and(|item| item.folder.is_some())
+        {
+            dest_path = normalize_path(&format!("{}/{}", dest_path, source_name));
+        }
+
+        let (parent_path, name) =
+            split_path(&dest_path).ok_or_else(|| anyhow!("can't move to the root itself"))?;
+
+        if name.is_empty() {
+            return Err(anyhow!("invalid name: {}", name));
+        }
+
+        let parent_id = self
+            .get_item_by_path(parent_path)
+            .await?
+            .and_then(|item| item.id)
+            .ok_or_else(|| anyhow!("{} not found", parent_path))?;
+
+        let graph = self.get_current_graph().await?;
+
+        // fails if an item with the same name exists in the destination
+        graph
+            .send(graph.drive.move_item(&source_path, &parent_id, name))
+            .await?
+            .content()
+            .and_then(|content| content.ok_or_else(|| anyhow!("{} not found", source_path)))
+            .context(format!("failed to move {} to {}", source_path, dest_path))?;
+
+        self.invalidate_items(&source_path);
+        self.invalidate_items(&dest_path);
+
+        Ok(dest_path)
+    }
+
+    // in the same folder, returns the new path
+    pub async fn rename_item(&self, path: &str, name: &str) -> Result<String> {
+        if name.contains('/') {
+            return Err(anyhow!(
+                "new name should not contain /, use /mv to move items"
+            ));
+        }
+
+        let path = normalize_path(path);
+        let (parent_path, _) =
+            split_path(&path).ok_or_else(|| anyhow!("the root can't be renamed"))?;
+
+        self.move_item(&path, &format!("{}/{}", parent_path, name))
+            .await
+    }
+
+    // items go to the recycle bin of the drive
+    pub async fn delete_item(&self, path: &str) -> Result<()> {
+        let path = normalize_path(path);
+
+        if path == "/" {
+            return Err(anyhow!("the root can't be deleted"));
+        }
+
+        let graph = self.get_current_graph().await?;
+
+        graph
+            .send(graph.drive.delete_item(&path))
+            .await?
+            .content()
+            .and_then(|content| content.ok_or_else(|| anyhow!("{} not found", path)))
+            .context(format!("failed to delete {}", path))?;
+
+        self.invalidate_items(&path);
+
+        Ok(())
+    }
+
+    // a short-lived url to download the file without authorization, and its size, none if not found
+    pub async fn get_download_url(
+        &self,
+        account: Option<&str>,
+        path: &str,
+    ) -> Result<Option<(String, u64)>> {
+        let path = normalize_path(path);
+
+        let graph = self.get_account_graph(account).await?;
+
+        let Some(item) = graph
+            .send(graph.drive.get_item(&path))
+            .await?
+            .json::<Value>()
+            .context(format!("failed to get {}", path))?
+        else {
+            return Ok(None);
+        };
+
+        if item.get("folder").is_some() {
+            return Err(anyhow!("{} is a folder, only files can be fetched", path));
+        }
+
+        let url = item
+            .get("@microsoft.graph.downloadUrl")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("download url of {} not found", path))?;
+
+        let size = item.get("size").and_then(Value::as_u64).unwrap_or_default();
+
+        Ok(Some((url.to_string(), size)))
+    }
+
+    // for changes made by any account of this client
+    pub fn invalidate_items(&self, path: &str) {
+        self.item_cache.invalidate(path);
+    }
+
+    pub(super) fn clear_items(&self) {
+        self.item_cache.clear();
+    }
+
+    async fn get_cache_username(&self) -> Result<String> {
+        Ok(self.get_current_username().await?.unwrap_or_default())
+    }
+}
+
+// like /a/b, or / for the root
+fn normalize_path(path: &str) -> String {
+    format!("/{}", path.trim_matches('/'))
+}
+
+// the parent and the name, none for the root
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    match path.rsplit_once('/')? {
+        (_, "") => None,
+        ("", name) => Some(("/", name)),
+        (parent, name) => Some((parent, name)),
+    }
+}
+
+// the path itself, its parents and its children
+fn is_affected(cached_path: &str, path: &str) -> bool {
+    let is_parent_or_self = |parent: &str, child: &str| {
+        parent == "/"
+            || parent == child
+            || child
+                .strip_prefix(parent)
+                .is_some_and(|rest| rest.starts_with('/'))
+    };
+
+    is_parent_or_self(cached_path, path) || is_parent_or_self(path, cached_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_affected() {
+        let path = normalize_path("/Movies/2024/a.mp4");
+
+        assert!(is_affected("/", &path));
+        assert!(is_affected("/Movies", &path));
+        assert!(is_affected("/Movies/2024", &path));
+        assert!(is_affected("/Movies/2024/a.mp4", &path));
+
+        assert!(!is_affected("/Movies/2023", &path));
+        assert!(!is_affected("/Movies/2024/a.mp4.part", &path));
+        assert!(!is_affected("/Docs", &path));
+
+        // children of a moved or deleted folder
+        assert!(is_affected("/Movies/2024/a", "/Movies/2024"));
+        assert!(!is_affected("/Movies/20245", "/Movies/2024"));
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(split_path("/Movies/a.mp4"), Some(("/Movies", "a.mp4")));
+        assert_eq!(split_path("/a.mp4"), Some(("/", "a.mp4")));
+        assert_eq!(split_path("/"), None);
+    }
+}
@@ -5,64 +5,110 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod app_auth;
+mod cloud;
 mod dir;
 mod drive;
+mod graph;
 pub mod invalid_name;
+mod items;
+mod quota;
+mod rate_limit;
+mod scope;
 mod session;
+mod share;
+mod target;
+mod tenant;
+mod throttle;
+mod thumbnail;
 mod upload;
 mod utils;
 
 use crate::{
     env::{Env, OneDriveEnv, ENV},
+    error::ResultExt,
     message::TelegramMessage,
 };
 use anyhow::{anyhow, Context, Result};
+use app_auth::AppAuth;
+pub use app_auth::AppTarget;
+pub use cloud::NationalCloud;
+pub use graph::GraphStatusError;
+use graph::{DriveApi, GraphClient};
+use items::ItemCache;
 use onedrive_api::{
-    Auth, ClientCredential, DriveLocation, OneDrive as Client, Permission, Tenant, TokenResponse,
+    resource::{Drive, DriveItem},
+    Auth, DriveLocation, OneDrive as Client, Tenant, TokenResponse,
 };
 use path_slash::PathBufExt;
+use quota::QuotaCache;
+pub use rate_limit::{format_rate, parse_rate, UPLOAD_LIMIT};
+pub use scope::OneDriveScope;
 use session::OneDriveSession;
 use std::{collections::HashMap, path::Path};
+pub use target::DriveTarget;
+pub use throttle::THROTTLE;
 use tokio::sync::{mpsc::Receiver, RwLock};
+pub use upload::is_small_file;
+
+// access tokens last about an hour, refreshed this long ahead so that no request meets the expiry
+const REFRESH_AHEAD_SECS: i64 = 10 * 60;
 
 pub struct OneDriveClient {
     client: RwLock<Client>,
     session: RwLock<OneDriveSession>,
     auth_provider: Auth,
+    scope: OneDriveScope,
+    cloud: NationalCloud,
+    // set in app-only mode
+    app_auth: Option<AppAuth>,
+    client_id: String,
     client_secret: String,
+    redirect_uri: String,
     session_path: String,
     pub default_root_path: String,
     temp_root_path: RwLock<String>,
+    quota_cache: RwLock<HashMap<String, QuotaCache>>,
+    item_cache: ItemCache,
 }
 
 impl OneDriveClient {
     pub async fn new() -> Result<Self> {
+        Self::with_session_path(&ENV.get().unwrap().onedrive.session_path).await
+    }
+
+    async fn with_session_path(session_path: &str) -> Result<Self> {
         let Env {
             onedrive:
                 OneDriveEnv {
                     client_id,
                     client_secret,
-                    session_path,
                     root_path,
+                    scope,
+                    cloud,
+                    app,
                     ..
                 },
             server_uri,
             ..
         } = ENV.get().unwrap();
 
+        let app_auth = app
+            .as_ref()
+            .map(|app| AppAuth::new(client_id, client_secret, *cloud, app))
+            .transpose()?;
+
         let client = RwLock::new(Client::new("", DriveLocation::me()));
         let session = RwLock::new(
             OneDriveSession::default()
                 .set_connection(session_path)
                 .await?,
         );
+        let redirect_uri = Path::new(server_uri).join("auth").to_slash_lossy();
         let auth_provider = Auth::new(
             client_id,
-            Permission::new_read()
-                .write(true)
-                .access_shared(true)
-                .offline_access(true),
-            Path::new(server_uri).join("auth").to_slash_lossy(),
+            scope.permission(),
+            redirect_uri.clone(),
             Tenant::Common,
         );
 
@@ -70,10 +116,17 @@ impl OneDriveClient {
             client,
             session,
             auth_provider,
+            scope: *scope,
+            cloud: *cloud,
+            app_auth,
+            client_id: client_id.clone(),
             client_secret: client_secret.clone(),
-            session_path: session_path.clone(),
+            redirect_uri: redirect_uri.to_string(),
+            session_path: session_path.to_string(),
             default_root_path: root_path.to_string(),
             temp_root_path: RwLock::new(String::new()),
+            quota_cache: RwLock::new(HashMap::new()),
+            item_cache: ItemCache::default(),
         };
 
         let _ = onedrive_client.auto_login().await;
@@ -85,10 +138,17 @@ impl OneDriveClient {
         &self,
         message: TelegramMessage,
         should_add: bool,
+        token: &str,
         mut rx: Receiver<String>,
     ) -> Result<()> {
         tracing::info!("logging in to onedrive");
 
+        if let Some(app_auth) = &self.app_auth {
+            tracing::info!("onedrive app-only mode, login with client credentials");
+
+            return self.app_login(app_auth).await;
+        }
+
         if !should_add {
             tracing::debug!("onedrive account should not be added");
 
@@ -111,7 +171,7 @@ impl OneDriveClient {
 
         let response = format!(
             "Here are the authorization url of OneDrive:\n\n{}",
-            self.get_auth_url()
+            self.get_auth_url(token)
         );
         message.respond(response.as_str()).await.context(response)?;
 
@@ -131,28 +191,63 @@ impl OneDriveClient {
         tracing::info!("onedrive authorizing");
 
         let TokenResponse {
+            scope,
             expires_in_secs,
             access_token,
             refresh_token,
             ..
         } = self
-            .auth_provider
-            .login_with_code(&code, &ClientCredential::Secret(self.client_secret.clone()))
+            .request_token(&[("grant_type", "authorization_code"), ("code", &code)])
             .await
             .context("failed to get onedrive token response when login with code")?;
 
+        // fail early instead of failing on the first request which needs the missing scope
+        self.scope.check_granted(&scope)?;
+
         let refresh_token = refresh_token.ok_or_else(|| {
             anyhow!("failed to receive onedrive refresh token when login with code")
         })?;
 
-        let client = Client::new(&access_token, DriveLocation::me());
+        let client = Client::new(&access_token, self.drive_location(&DriveTarget::Me));
 
         tracing::info!("onedrive authorized");
 
+        self.add_session(client, expires_in_secs, &refresh_token)
+            .await?;
+
+        Ok(())
+    }
+
+    // like a newly authorized account, used to import tokens of the python version
+    pub async fn login_with_refresh_token(&self, refresh_token: &str) -> Result<String> {
+        let TokenResponse {
+            expires_in_secs,
+            access_token,
+            refresh_token,
+            ..
+        } = self.get_token_using_refresh_token(refresh_token).await?;
+
+        let refresh_token = refresh_token.ok_or_else(|| {
+            anyhow!("failed to receive onedrive refresh token when login with refresh token")
+        })?;
+
+        let client = Client::new(&access_token, self.drive_location(&DriveTarget::Me));
+
+        self.add_session(client, expires_in_secs, &refresh_token)
+            .await
+    }
+
+    // the new account becomes the current one if no account is logged in, returns its username
+    async fn add_session(
+        &self,
+        client: Client,
+        expires_in_secs: u64,
+        refresh_token: &str,
+    ) -> Result<String> {
         let session = OneDriveSession::new(
             &client,
             expires_in_secs,
-            &refresh_token,
+            refresh_token,
             &self.session_path,
             &self.default_root_path,
         )
@@ -160,8 +255,16 @@ impl OneDriveClient {
 
         session.save().await?;
 
-        if let Some(username) = self.get_current_username().await? {
-            if username == session.username {
+        let username = session.username.clone();
+
+        // the account may have been logged in before with another drive target
+        let client = Client::new(
+            session.access_token.clone(),
+            self.drive_location(&session.drive_target),
+        );
+
+        if let Some(current_username) = self.get_current_username().await? {
+            if current_username == username {
                 self.session.write().await.overwrite(session);
                 *self.client.write().await = client;
             }
@@ -171,53 +274,217 @@ impl OneDriveClient {
             *self.client.write().await = client;
         }
 
-        Ok(())
+        Ok(username)
     }
 
     async fn auto_login(&self) -> Result<()> {
+        if let Some(app_auth) = &self.app_auth {
+            return self.app_login(app_auth).await;
+        }
+
         let mut session = OneDriveSession::load(&self.session_path).await?;
 
-        let token_response = self
-            .get_token_using_refresh_token(&session.refresh_token)
-            .await?;
+        self.refresh_session(&mut session).await?;
 
-        let access_token = token_response.access_token;
-        *self.client.write().await = Client::new(&access_token, DriveLocation::me());
+        *self.client.write().await = Client::new(
+            session.access_token.clone(),
+            self.drive_location(&session.drive_target),
+        );
 
-        session.refresh_token = token_response.refresh_token.ok_or_else(|| {
-            anyhow!("failed to receive onedrive refresh token when login with refresh token")
-        })?;
-        session.access_token = access_token;
+        self.session.write().await.overwrite(session);
+
+        Ok(())
+    }
+
+    // there is no refresh token in app-only mode, a new token is requested each time
+    async fn app_login(&self, app_auth: &AppAuth) -> Result<()> {
+        let token_response = app_auth.request_token().await?;
+
+        let username = app_auth.target.to_string();
+
+        let mut session = self.session.write().await;
+
+        // keep the root path if the target has been used before
+        if session.change_session(&username).await.is_err() {
+            session.username = username;
+            session.root_path.clone_from(&self.default_root_path);
+        }
+
+        session.access_token = token_response.access_token;
+        session.refresh_token = String::new();
         session.set_expiration_timestamp(token_response.expires_in_secs);
         session.save().await?;
+        session.set_current_user().await?;
 
-        self.session.write().await.overwrite(session);
+        *self.client.write().await = Client::new(
+            session.access_token.clone(),
+            self.drive_location(&session.drive_target),
+        );
+
+        tracing::info!("onedrive app-only token received for {}", session.username);
 
         Ok(())
     }
 
+    // the drive target of the account is ignored in app-only mode
+    fn drive_location(&self, drive_target: &DriveTarget) -> DriveLocation {
+        self.app_auth.as_ref().map_or_else(
+            || drive_target.drive_location(),
+            |app_auth| app_auth.target.drive_location(),
+        )
+    }
+
+    // path of the drive in graph api
+    fn drive_path(&self, drive_target: &DriveTarget) -> String {
+        self.app_auth.as_ref().map_or_else(
+            || drive_target.drive_path(),
+            |app_auth| app_auth.target.drive_path(),
+        )
+    }
+
+    // item paths in graph api are relative to it
+    fn root_item(&self, drive_target: &DriveTarget) -> String {
+        self.app_auth
+            .as_ref()
+            .map_or_else(|| drive_target.root_item(), |_| "root".to_string())
+    }
+
+    // requests to the drive with the token of the client
+    fn graph(&self, client: &Client, drive_target: &DriveTarget) -> GraphClient {
+        GraphClient::new(
+            client.client().clone(),
+            client.access_token().to_string(),
+            DriveApi::new(
+                self.cloud.api_url(),
+                self.drive_path(drive_target),
+                self.root_item(drive_target),
+            ),
+        )
+    }
+
+    // the current account, refreshed if needed
+    async fn get_current_graph(&self) -> Result<GraphClient> {
+        self.refresh_access_token().await?;
+
+        let client = self.client.read().await;
+        let session = self.session.read().await;
+
+        Ok(self.graph(&client, &session.drive_target))
+    }
+
+    // account is none for the current one
+    async fn get_account_graph(&self, account: Option<&str>) -> Result<GraphClient> {
+        let (client, drive_target) = self.get_account_client(account).await?;
+
+        Ok(self.graph(&client, &drive_target))
+    }
+
+    // a shared folder in the drive of its owner
+    fn is_shared_folder(&self, drive_target: &DriveTarget) -> bool {
+        self.app_auth.is_none() && matches!(drive_target, DriveTarget::Shared { .. })
+    }
+
+    // the name of the drive, or of the folder if it's shared, since the drive of its owner may not be accessible
+    async fn check_drive(&self, client: &Client, drive_target: &DriveTarget) -> Result<String> {
+        if !self.is_shared_folder(drive_target) {
+            let drive = self.get_drive(client, drive_target).await?;
+
+            return Ok(drive.name.unwrap_or_default());
+        }
+
+        let graph = self.graph(client, drive_target);
+
+        let folder = graph
+            .send(graph.drive.get_item("/"))
+            .await?
+            .json::<DriveItem>()
+            .context("failed to get shared folder")?
+            .ok_or_else(|| anyhow!("shared folder not found"))?;
+
+        if folder.folder.is_none() {
+            return Err(anyhow!("shared item is not a folder"));
+        }
+
+        Ok(folder.name.unwrap_or_default())
+    }
+
+    async fn get_drive(&self, client: &Client, drive_target: &DriveTarget) -> Result<Drive> {
+        let graph = self.graph(client, drive_target);
+
+        graph
+            .send(graph.drive.get_drive())
+            .await?
+            .json::<Drive>()
+            .context("failed to get drive")?
+            .ok_or_else(|| anyhow!("drive not found"))
+    }
+
     pub async fn get_token_using_refresh_token(
         &self,
         refresh_token: &str,
     ) -> Result<TokenResponse> {
-        self.auth_provider
-            .login_with_refresh_token(
-                refresh_token,
-                &ClientCredential::Secret(self.client_secret.clone()),
-            )
+        self.request_token(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])
+        .await
+        .context("failed to get refresh token response when login with refresh token")
+    }
+
+    // the token endpoint of auth_provider is fixed to the global cloud
+    async fn request_token(&self, grant: &[(&str, &str)]) -> Result<TokenResponse> {
+        let scope = self.scope.scope_string(self.cloud.graph_origin());
+
+        let mut params = vec![
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("scope", scope.as_str()),
+        ];
+        params.extend_from_slice(grant);
+
+        let response = reqwest::Client::new()
+            .post(self.cloud.token_url("common"))
+            .form(&params)
+            .send()
+            .await
+            .context("failed to send request for onedrive token")?;
+
+        let status = response.status();
+
+        let content = response
+            .text()
             .await
-            .context("failed to get refresh token response when login with refresh token")
+            .context("failed to get response text for onedrive token")?;
+
+        if !status.is_success() {
+            return Err(anyhow!(
+                "failed to request onedrive token: {}: {}",
+                status,
+                content
+            ));
+        }
+
+        serde_json::from_str(&content).context("failed to deserialize onedrive token response")
     }
 
-    pub fn get_auth_url(&self) -> String {
+    // token is passed as state, and will be sent back with the code
+    pub fn get_auth_url(&self, token: &str) -> String {
         let mut url = self.auth_provider.code_auth_url();
 
+        // auth_provider always points to the global cloud
+        url.set_host(Some(self.cloud.login_host())).unwrap();
+
         let mut query_pairs = url
             .query_pairs()
             .into_owned()
             .collect::<HashMap<String, String>>();
 
-        query_pairs.get_mut("scope").unwrap().push_str(" user.read");
+        query_pairs.insert(
+            "scope".to_string(),
+            self.scope.scope_string(self.cloud.graph_origin()),
+        );
+        query_pairs.insert("state".to_string(), token.to_string());
 
         url.query_pairs_mut().clear().extend_pairs(query_pairs);
 
@@ -235,7 +502,11 @@ impl OneDriveClient {
             self.refresh_access_token().await.ok();
         }
 
-        self.client.read().await.get_drive().await.is_ok()
+        let drive_target = self.get_drive_target().await;
+
+        self.check_drive(&*self.client.read().await, &drive_target)
+            .await
+            .is_ok()
     }
 
     pub async fn set_current_user(&self) -> Result<()> {
@@ -246,7 +517,10 @@ impl OneDriveClient {
         let mut session = self.session.write().await;
         session.remove_user(username).await?;
 
-        *self.client.write().await = Client::new(&session.access_token, DriveLocation::me());
+        *self.client.write().await = Client::new(
+            &session.access_token,
+            self.drive_location(&session.drive_target),
+        );
 
         Ok(())
     }
@@ -255,24 +529,154 @@ impl OneDriveClient {
         let is_expired = { self.session.read().await.is_expired() };
 
         if is_expired {
-            let mut session = self.session.write().await;
+            self.refresh_current_session().await?;
+        }
+
+        Ok(())
+    }
+
+    // called periodically, so that tokens of all accounts are renewed before they are needed
+    pub async fn refresh_access_tokens_ahead(&self) -> Result<()> {
+        let (username, is_expiring) = {
+            let session = self.session.read().await;
+
+            (
+                session.username.clone(),
+                session.expires_within(REFRESH_AHEAD_SECS),
+            )
+        };
+
+        // not logged in yet
+        if username.is_empty() {
+            return Ok(());
+        }
+
+        if is_expiring {
+            tracing::debug!("onedrive token of {} refreshed ahead", username);
+
+            self.refresh_current_session().await?;
+        }
+
+        // there is only one target in app-only mode
+        if self.app_auth.is_some() {
+            return Ok(());
+        }
+
+        let usernames = self.session.read().await.get_usernames().await?;
 
-            let token_response = self
-                .get_token_using_refresh_token(&session.refresh_token)
+        for other_username in usernames {
+            if other_username == username {
+                continue;
+            }
+
+            let mut account_session = self
+                .session
+                .read()
+                .await
+                .get_user_session(&other_username)
                 .await?;
 
-            session.access_token = token_response.access_token;
-            session.refresh_token = token_response.refresh_token.ok_or_else(|| {
-                anyhow!("failed to receive onedrive refresh token when login with refresh token")
-            })?;
-            session.set_expiration_timestamp(token_response.expires_in_secs);
+            if account_session.expires_within(REFRESH_AHEAD_SECS) {
+                tracing::debug!("onedrive token of {} refreshed ahead", other_username);
+
+                self.refresh_session(&mut account_session)
+                    .await
+                    .context(format!(
+                        "failed to refresh onedrive token of {}",
+                        other_username
+                    ))
+                    .trace();
+            }
+        }
+
+        Ok(())
+    }
+
+    // at startup, so that the first task doesn't wait for refreshing the token and connecting to graph,
+    // the directory is looked up to open the connection and cache it
+    pub async fn warm_up(&self) -> Result<()> {
+        if self.get_current_username().await?.is_none() {
+            return Ok(());
+        }
+
+        self.refresh_access_tokens_ahead().await?;
 
-            session.save().await?;
+        let root_path = self.get_account_root_path().await;
 
-            *self.client.write().await =
-                Client::new(session.access_token.clone(), DriveLocation::me());
+        if self.get_item_by_path(&root_path).await?.is_none() {
+            tracing::debug!("onedrive directory {} is not created yet", root_path);
         }
 
         Ok(())
     }
+
+    async fn refresh_current_session(&self) -> Result<()> {
+        if let Some(app_auth) = &self.app_auth {
+            return self.app_login(app_auth).await;
+        }
+
+        let mut session = self.session.write().await;
+
+        self.refresh_session(&mut session).await?;
+
+        *self.client.write().await = Client::new(
+            session.access_token.clone(),
+            self.drive_location(&session.drive_target),
+        );
+
+        Ok(())
+    }
+
+    // the refresh token is rotated as well, the old one may be revoked
+    async fn refresh_session(&self, session: &mut OneDriveSession) -> Result<()> {
+        let token_response = self
+            .get_token_using_refresh_token(&session.refresh_token)
+            .await?;
+
+        session.access_token = token_response.access_token;
+        session.refresh_token = token_response.refresh_token.ok_or_else(|| {
+            anyhow!("failed to receive onedrive refresh token when login with refresh token")
+        })?;
+        session.set_expiration_timestamp(token_response.expires_in_secs);
+
+        session.save().await
+    }
+
+    // tasks keep uploading to the account they were queued with, even if the current one changes
+    // the drive target is returned for requests not covered by the client
+    async fn get_account_client(&self, account: Option<&str>) -> Result<(Client, DriveTarget)> {
+        self.refresh_access_token().await?;
+
+        let mut account_session = {
+            let session = self.session.read().await;
+
+            match account {
+                // there is only one target in app-only mode
+                Some(account) if account != session.username && self.app_auth.is_none() => {
+                    session.get_user_session(account).await?
+                }
+                _ => {
+                    return Ok((
+                        Client::new(
+                            session.access_token.clone(),
+                            self.drive_location(&session.drive_target),
+                        ),
+                        session.drive_target.clone(),
+                    ))
+                }
+            }
+        };
+
+        if account_session.is_expired() {
+            self.refresh_session(&mut account_session).await?;
+        }
+
+        Ok((
+            Client::new(
+                account_session.access_token,
+                self.drive_location(&account_session.drive_target),
+            ),
+            account_session.drive_target,
+        ))
+    }
 }
@@ -0,0 +1,122 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::OneDriveClient;
+use anyhow::{Context, Result};
+use std::time::{Duration, Instant};
+
+// the drive endpoint is called for every enqueued task, so its quota is cached for a while
+const QUOTA_CACHE_TTL: Duration = Duration::from_secs(60);
+
+// in bytes, for /usage
+pub struct DriveQuota {
+    pub used: u64,
+    pub total: u64,
+}
+
+// of each account by its username
+pub struct QuotaCache {
+    // none if the drive doesn't report it
+    remaining: Option<u64>,
+    fetched_at: Instant,
+}
+
+impl OneDriveClient {
+    pub async fn get_remaining_quota(&self) -> Result<Option<u64>> {
+        self.get_account_remaining_quota(None).await
+    }
+
+    // of the current account if none
+    pub async fn get_account_remaining_quota(&self, account: Option<&str>) -> Result<Option<u64>> {
+        let username = match account {
+            Some(account) => account.to_string(),
+            None => self.get_current_username().await?.unwrap_or_default(),
+        };
+
+        if let Some(cache) = self.quota_cache.read().await.get(&username) {
+            if cache.fetched_at.elapsed() < QUOTA_CACHE_TTL {
+                return Ok(cache.remaining);
+            }
+        }
+
+        let (client, drive_target) = self.get_account_client(account).await?;
+
+        // the quota of a shared folder is its owner's, which isn't visible
+        if self.is_shared_folder(&drive_target) {
+            return Ok(None);
+        }
+
+        let drive = self
+            .get_drive(&client, &drive_target)
+            .await
+            .context("failed to get drive for quota")?;
+
+        let remaining = drive
+            .quota
+            .as_ref()
+            .and_then(|quota| quota.get("remaining"))
+            .and_then(|remaining| remaining.as_u64());
+
+        self.quota_cache.write().await.insert(
+            username,
+            QuotaCache {
+                remaining,
+                fetched_at: Instant::now(),
+            },
+        );
+
+        Ok(remaining)
+    }
+
+    // not cached, only requested by /usage
+    pub async fn get_quota(&self) -> Result<Option<DriveQuota>> {
+        let drive_target = self.get_drive_target().await;
+
+        if self.is_shared_folder(&drive_target) {
+            return Ok(None);
+        }
+
+        let drive = self
+            .get_drive(&*self.client.read().await, &drive_target)
+            .await
+            .context("failed to get drive for quota")?;
+
+        let quota = drive.quota.as_ref().and_then(|quota| {
+            Some(DriveQuota {
+                used: quota.get("used")?.as_u64()?,
+                total: quota.get("total")?.as_u64()?,
+            })
+        });
+
+        Ok(quota)
+    }
+
+    // another account with enough quota, the current account isn't changed
+    pub async fn find_account_with_quota(&self, required: u64) -> Result<Option<String>> {
+        let Some(current_username) = self.get_current_username().await? else {
+            return Ok(None);
+        };
+
+        for username in self.get_usernames().await? {
+            if username == current_username {
+                continue;
+            }
+
+            if self
+                .get_account_remaining_quota(Some(&username))
+                .await?
+                .is_none_or(|remaining| remaining >= required)
+            {
+                tracing::info!("found {} with enough quota", username);
+
+                return Ok(Some(username));
+            }
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,151 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::env::ENV;
+use anyhow::{anyhow, Context, Result};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// shared by the upload sessions of all tasks and accounts, since they share the uplink
+pub static UPLOAD_LIMIT: LazyLock<RateLimit> =
+    LazyLock::new(|| RateLimit::new(ENV.get().unwrap().upload_limit));
+
+pub struct RateLimit {
+    // bytes per second, 0 for no limit
+    bytes_per_second: AtomicU64,
+    // parts are spaced by their sizes, the next one starts no earlier than it
+    next_at: Mutex<Option<Instant>>,
+}
+
+impl RateLimit {
+    fn new(bytes_per_second: Option<u64>) -> Self {
+        Self {
+            bytes_per_second: AtomicU64::new(bytes_per_second.unwrap_or_default()),
+            next_at: Mutex::new(None),
+        }
+    }
+
+    pub fn get(&self) -> Option<u64> {
+        Some(self.bytes_per_second.load(Ordering::Acquire)).filter(|limit| *limit > 0)
+    }
+
+    // none to remove the limit, parts already waiting keep their delays
+    pub fn set(&self, bytes_per_second: Option<u64>) {
+        self.bytes_per_second
+            .store(bytes_per_second.unwrap_or_default(), Ordering::Release);
+
+        *self.next_at.lock().unwrap() = None;
+    }
+
+    // the delay before sending a part of the length, unused time isn't saved for bursts
+    fn reserve(&self, length: u64, now: Instant) -> Option<Duration> {
+        let bytes_per_second = self.get()?;
+
+        let start_at = {
+            let mut next_at = self.next_at.lock().unwrap();
+
+            let start_at = next_at.filter(|next_at| *next_at > now).unwrap_or(now);
+            *next_at =
+                Some(start_at + Duration::from_secs_f64(length as f64 / bytes_per_second as f64));
+
+            start_at
+        };
+
+        Some(start_at - now).filter(|delay| !delay.is_zero())
+    }
+
+    // before each part, retries count too since they are sent again
+    pub async fn wait(&self, length: u64) {
+        if let Some(delay) = self.reserve(length, Instant::now()) {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+// like 5MB, 500KB/s or 1048576 in bytes per second, 0 or off for no limit
+pub fn parse_rate(s: &str) -> Result<Option<u64>> {
+    let s = s.trim().to_uppercase();
+    let s = s.strip_suffix("/S").unwrap_or(&s);
+
+    if s == "OFF" {
+        return Ok(None);
+    }
+
+    let split_at = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split_at);
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| anyhow!("invalid rate: {}", s))
+        .context("a rate is like 5MB, 500KB or 1048576 in bytes per second")?;
+
+    let multiplier = match unit.trim() {
+        "" | "B" => 1.0,
+        "K" | "KB" => 1024.0,
+        "M" | "MB" => 1024.0 * 1024.0,
+        "G" | "GB" => 1024.0 * 1024.0 * 1024.0,
+        unit => return Err(anyhow!("unknown unit of rate: {}", unit)),
+    };
+
+    let bytes_per_second = (number * multiplier) as u64;
+
+    Ok(Some(bytes_per_second).filter(|bytes_per_second| *bytes_per_second > 0))
+}
+
+pub fn format_rate(bytes_per_second: u64) -> String {
+    format!("{:.2}MB/s", bytes_per_second as f64 / 1024.0 / 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("5MB").unwrap(), Some(5 * 1024 * 1024));
+        assert_eq!(parse_rate("500kb/s").unwrap(), Some(500 * 1024));
+        assert_eq!(parse_rate("1.5 M").unwrap(), Some(1024 * 1024 * 3 / 2));
+        assert_eq!(parse_rate("1048576").unwrap(), Some(1_048_576));
+        assert_eq!(parse_rate("0").unwrap(), None);
+        assert_eq!(parse_rate("off").unwrap(), None);
+
+        assert!(parse_rate("MB").is_err());
+        assert!(parse_rate("5TB").is_err());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = RateLimit::new(Some(1024 * 1024));
+        let now = Instant::now();
+
+        // the first part starts at once, the next ones wait for the previous ones
+        assert!(limit.reserve(2 * 1024 * 1024, now).is_none());
+        assert_eq!(
+            limit.reserve(1024 * 1024, now),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            limit.reserve(1024 * 1024, now + Duration::from_secs(1)),
+            Some(Duration::from_secs(2))
+        );
+
+        // idle time isn't saved
+        assert!(limit
+            .reserve(1024 * 1024, now + Duration::from_secs(10))
+            .is_none());
+
+        limit.set(None);
+        assert!(limit.reserve(1024 * 1024, now).is_none());
+    }
+}
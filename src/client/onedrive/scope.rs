@@ -0,0 +1,149 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use anyhow::{anyhow, Error, Result};
+use onedrive_api::Permission;
+use std::str::FromStr;
+
+// graph permissions requested when authorizing, set by od_scope
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OneDriveScope {
+    // Files.ReadWrite, only the user's own files
+    Files,
+    // Files.ReadWrite.All, also the files shared with the user
+    FilesAll,
+    // Files.ReadWrite.All and Sites.ReadWrite.All, also sharepoint sites
+    Sites,
+}
+
+impl FromStr for OneDriveScope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "files" => Ok(Self::Files),
+            "files_all" => Ok(Self::FilesAll),
+            "sites" => Ok(Self::Sites),
+            _ => Err(anyhow!(
+                "onedrive scope should be files, files_all or sites"
+            )),
+        }
+    }
+}
+
+impl OneDriveScope {
+    pub fn permission(self) -> Permission {
+        Permission::new_read()
+            .write(true)
+            .access_shared(self != Self::Files)
+            .offline_access(true)
+    }
+
+    // scopes not covered by permission
+    const fn extra_scopes(self) -> &'static [&'static str] {
+        match self {
+            Self::Files | Self::FilesAll => &["User.Read"],
+            Self::Sites => &["User.Read", "Sites.ReadWrite.All"],
+        }
+    }
+
+    const fn required_scopes(self) -> &'static [&'static str] {
+        match self {
+            Self::Files => &["Files.ReadWrite"],
+            Self::FilesAll => &["Files.ReadWrite.All"],
+            Self::Sites => &["Files.ReadWrite.All", "Sites.ReadWrite.All"],
+        }
+    }
+
+    // all scopes of the auth url and token requests, same as permission with extra_scopes,
+    // graph scopes without the origin are of the global cloud, so they are prefixed for national clouds
+    pub fn scope_string(self, graph_origin: &str) -> String {
+        let mut scopes = vec!["offline_access".to_string()];
+        scopes.extend(
+            self.required_scopes()
+                .iter()
+                .chain(self.extra_scopes())
+                .map(|scope| format!("{}/{}", graph_origin, scope)),
+        );
+
+        scopes.join(" ")
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Files => "files",
+            Self::FilesAll => "files_all",
+            Self::Sites => "sites",
+        }
+    }
+
+    // organizational accounts may get fewer scopes than requested without admin consent
+    pub fn check_granted(self, granted_scopes: &[String]) -> Result<()> {
+        // scopes may be returned as urls like https://graph.microsoft.com/Files.ReadWrite.All
+        let granted_scopes = granted_scopes
+            .iter()
+            .map(|scope| scope.rsplit('/').next().unwrap_or_default().to_lowercase())
+            .collect::<Vec<String>>();
+
+        // scopes ending with .All cover the ones without it
+        let missing_scopes = self
+            .required_scopes()
+            .iter()
+            .filter(|scope| {
+                let scope = scope.to_lowercase();
+
+                !granted_scopes.iter().any(|granted_scope| {
+                    *granted_scope == scope || *granted_scope == scope.clone() + ".all"
+                })
+            })
+            .copied()
+            .collect::<Vec<&str>>();
+
+        if missing_scopes.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "OneDrive token lacks {} required by od_scope={}. Add the permissions in Azure portal's API permissions and grant admin consent if needed, or use a smaller od_scope, then /auth again.",
+                missing_scopes.join(", "),
+                self.name()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_string() {
+        assert_eq!(
+            OneDriveScope::Files.scope_string("https://microsoftgraph.chinacloudapi.cn"),
+            "offline_access https://microsoftgraph.chinacloudapi.cn/Files.ReadWrite https://microsoftgraph.chinacloudapi.cn/User.Read"
+        );
+    }
+
+    #[test]
+    fn test_check_granted() {
+        let granted_scopes = vec![
+            "https://graph.microsoft.com/Files.ReadWrite.All".to_string(),
+            "https://graph.microsoft.com/User.Read".to_string(),
+        ];
+
+        assert!(OneDriveScope::FilesAll
+            .check_granted(&granted_scopes)
+            .is_ok());
+        assert!(OneDriveScope::Sites.check_granted(&granted_scopes).is_err());
+        assert!(OneDriveScope::Files.check_granted(&granted_scopes).is_ok());
+
+        let granted_scopes = vec!["Files.ReadWrite".to_string()];
+
+        assert!(OneDriveScope::FilesAll
+            .check_granted(&granted_scopes)
+            .is_err());
+    }
+}
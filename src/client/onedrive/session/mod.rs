@@ -7,11 +7,18 @@
 
 mod models;
 
-use crate::utils::get_current_timestamp;
+use super::{
+    graph::{GraphRequest, Transport},
+    target::DriveTarget,
+};
+use crate::{
+    crypto::{open_string, seal_string},
+    env::ENV,
+    utils::get_current_timestamp,
+};
 use anyhow::{anyhow, Context, Result};
-use models::{current_user, session};
+use models::{current_user, drive_target, session};
 use onedrive_api::OneDrive;
-use reqwest::header;
 use sea_orm::{
     sea_query::Expr, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait,
     ModelTrait, QueryFilter, QuerySelect, Schema, Set,
@@ -19,6 +26,8 @@ use sea_orm::{
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+const REFRESH_TOKEN_NAME: &str = "onedrive refresh token";
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct OneDriveSession {
     pub username: String,
@@ -27,6 +36,8 @@ pub struct OneDriveSession {
     pub refresh_token: String,
     pub root_path: String,
     #[serde(skip)]
+    pub drive_target: DriveTarget,
+    #[serde(skip)]
     connection: DatabaseConnection,
 }
 
@@ -42,14 +53,18 @@ impl OneDriveSession {
         let expiration_timestamp = Self::get_expiration_timestamp(expires_in_secs);
         let connection = Self::connect_db(session_path).await?;
 
-        Ok(Self {
+        let mut session = Self {
             username,
             expiration_timestamp,
             access_token: client.access_token().to_string(),
             refresh_token: refresh_token.to_string(),
             root_path: root_path.to_string(),
+            drive_target: DriveTarget::Me,
             connection,
-        })
+        };
+        session.load_drive_target().await?;
+
+        Ok(session)
     }
 
     fn get_expiration_timestamp(expires_in_secs: u64) -> i64 {
@@ -73,27 +88,15 @@ impl OneDriveSession {
     }
 
     async fn get_username(client: &OneDrive) -> Result<String> {
-        let http_client = client.client();
+        let request = GraphRequest::get_me(&ENV.get().unwrap().onedrive.cloud.api_url());
 
-        let url = "https://graph.microsoft.com/v1.0/me/";
-
-        let response = http_client
-            .get(url)
-            .header(
-                header::AUTHORIZATION,
-                format!("Bearer {}", client.access_token()),
-            )
-            .send()
-            .await
-            .context("failed to send request for user profile")?;
-
-        let content = response
-            .text()
-            .await
-            .context("failed to get response text for user profile")?;
-
-        let user_profile = serde_json::from_str::<Value>(&content)
-            .context("failed to deserialize user profile into Value")?;
+        let user_profile = client
+            .client()
+            .send(request, client.access_token())
+            .await?
+            .json::<Value>()
+            .context("failed to get user profile")?
+            .ok_or_else(|| anyhow!("user profile not found"))?;
 
         let username = user_profile
             .get("userPrincipalName")
@@ -114,6 +117,7 @@ impl OneDriveSession {
 
         Self::create_table_if_not_exists(&connection, session::Entity).await?;
         Self::create_table_if_not_exists(&connection, current_user::Entity).await?;
+        Self::create_table_if_not_exists(&connection, drive_target::Entity).await?;
 
         Ok(connection)
     }
@@ -166,9 +170,10 @@ impl OneDriveSession {
 
         let connection = Self::connect_db(path).await?;
 
-        let mut session = Self::from(Self::get_current_session(&connection).await?);
+        let mut session = Self::try_from(Self::get_current_session(&connection).await?)?;
 
         session.connection = connection;
+        session.load_drive_target().await?;
 
         Ok(session)
     }
@@ -187,7 +192,7 @@ impl OneDriveSession {
                 username: Set(self.username.to_string()),
                 expiration_timestamp: Set(self.expiration_timestamp),
                 access_token: Set(self.access_token.to_string()),
-                refresh_token: Set(self.refresh_token.to_string()),
+                refresh_token: Set(self.sealed_refresh_token()?),
                 root_path: Set(self.root_path.to_string()),
             };
 
@@ -208,6 +213,7 @@ impl OneDriveSession {
             access_token,
             refresh_token,
             root_path,
+            drive_target,
             ..
         }: Self,
     ) {
@@ -216,6 +222,7 @@ impl OneDriveSession {
         self.access_token = access_token;
         self.refresh_token = refresh_token;
         self.root_path = root_path;
+        self.drive_target = drive_target;
     }
 
     async fn user_exists(&self) -> Result<bool> {
@@ -232,6 +239,8 @@ impl OneDriveSession {
     }
 
     async fn update(&self) -> Result<()> {
+        let refresh_token = self.sealed_refresh_token()?;
+
         session::Entity::update_many()
             .filter(session::Column::Username.eq(&self.username))
             .col_expr(
@@ -242,10 +251,7 @@ impl OneDriveSession {
                 session::Column::AccessToken,
                 Expr::value(&self.access_token),
             )
-            .col_expr(
-                session::Column::RefreshToken,
-                Expr::value(&self.refresh_token),
-            )
+            .col_expr(session::Column::RefreshToken, Expr::value(refresh_token))
             .col_expr(session::Column::RootPath, Expr::value(&self.root_path))
             .exec(&self.connection)
             .await
@@ -379,6 +385,12 @@ impl OneDriveSession {
             .await
             .context("failed to delete onedrive session")?;
 
+        drive_target::Entity::delete_many()
+            .filter(drive_target::Column::Username.eq(&username))
+            .exec(&self.connection)
+            .await
+            .context("failed to delete onedrive drive target")?;
+
         if username != self.username {
             return Ok(());
         }
@@ -389,7 +401,11 @@ impl OneDriveSession {
             Ok(Some(session)) => {
                 tracing::debug!("new onedrive user: {}", session.username);
 
-                self.overwrite(Self::from(session));
+                let mut session = Self::try_from(session)?;
+                session.connection = self.connection.clone();
+                session.load_drive_target().await?;
+
+                self.overwrite(session);
 
                 self.set_current_user().await?;
             }
@@ -415,12 +431,7 @@ impl OneDriveSession {
             return Ok(());
         }
 
-        let session = session::Entity::find()
-            .filter(session::Column::Username.eq(username))
-            .one(&self.connection)
-            .await
-            .context("failed to query onedrive session")?
-            .ok_or_else(|| anyhow!("onedrive session not found"))?;
+        let session = self.get_user_session(username).await?;
 
         tracing::debug!(
             "change onedrive session user from {} to {}",
@@ -428,31 +439,141 @@ impl OneDriveSession {
             session.username
         );
 
-        self.overwrite(Self::from(session));
+        self.overwrite(session);
 
         self.set_current_user().await?;
 
         Ok(())
     }
 
+    // the session of another account, sharing the connection so that it can be saved
+    pub async fn get_user_session(&self, username: &str) -> Result<Self> {
+        let session = session::Entity::find()
+            .filter(session::Column::Username.eq(username))
+            .one(&self.connection)
+            .await
+            .context("failed to query onedrive session")?
+            .ok_or_else(|| anyhow!("onedrive session of {} not found", username))?;
+
+        let mut session = Self::try_from(session)?;
+        session.connection = self.connection.clone();
+        session.load_drive_target().await?;
+
+        Ok(session)
+    }
+
+    async fn load_drive_target(&mut self) -> Result<()> {
+        self.drive_target = match drive_target::Entity::find_by_id(self.username.clone())
+            .one(&self.connection)
+            .await
+            .context("failed to query onedrive drive target")?
+        {
+            Some(model) => model.target.parse()?,
+            None => DriveTarget::Me,
+        };
+
+        Ok(())
+    }
+
+    pub async fn set_drive_target(&mut self, drive_target: DriveTarget) -> Result<()> {
+        drive_target::Entity::delete_many()
+            .filter(drive_target::Column::Username.eq(&self.username))
+            .exec(&self.connection)
+            .await
+            .context("failed to delete onedrive drive target")?;
+
+        if drive_target != DriveTarget::Me {
+            let insert_item = drive_target::ActiveModel {
+                username: Set(self.username.clone()),
+                target: Set(drive_target.to_string()),
+            };
+
+            drive_target::Entity::insert(insert_item)
+                .exec(&self.connection)
+                .await
+                .context("failed to insert onedrive drive target")?;
+        }
+
+        tracing::debug!("set drive target of {} to {}", self.username, drive_target);
+
+        self.drive_target = drive_target;
+
+        Ok(())
+    }
+
     pub fn is_expired(&self) -> bool {
-        let is_expired = self.expiration_timestamp < get_current_timestamp() + 60;
+        let is_expired = self.expires_within(60);
 
         tracing::debug!("onedrive session is expired: {}", is_expired);
 
         is_expired
     }
+
+    pub fn expires_within(&self, secs: i64) -> bool {
+        self.expiration_timestamp < get_current_timestamp() + secs
+    }
+
+    fn sealed_refresh_token(&self) -> Result<String> {
+        seal_refresh_token(
+            ENV.get().unwrap().session_key.as_deref(),
+            &self.refresh_token,
+        )
+    }
 }
 
-impl From<session::Model> for OneDriveSession {
-    fn from(model: session::Model) -> Self {
-        Self {
+impl TryFrom<session::Model> for OneDriveSession {
+    type Error = anyhow::Error;
+
+    fn try_from(model: session::Model) -> Result<Self> {
+        let refresh_token = open_string(
+            ENV.get().unwrap().session_key.as_deref(),
+            &model.refresh_token,
+            REFRESH_TOKEN_NAME,
+        )
+        .context(format!(
+            "failed to read onedrive refresh token of {}",
+            model.username
+        ))?;
+
+        Ok(Self {
             username: model.username,
             expiration_timestamp: model.expiration_timestamp,
             access_token: model.access_token,
-            refresh_token: model.refresh_token,
+            refresh_token,
             root_path: model.root_path,
+            drive_target: DriveTarget::Me,
             connection: DatabaseConnection::default(),
-        }
+        })
+    }
+}
+
+// tokens stored before session_key is set are encrypted on the next save
+fn seal_refresh_token(key: Option<&str>, refresh_token: &str) -> Result<String> {
+    // app-only sessions have no refresh token
+    if refresh_token.is_empty() {
+        return Ok(String::new());
+    }
+
+    seal_string(key, refresh_token, REFRESH_TOKEN_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::ENCRYPTED_PREFIX;
+
+    #[test]
+    fn test_seal_refresh_token() {
+        let sealed = seal_refresh_token(Some("passphrase"), "token").unwrap();
+
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+        assert_eq!(
+            open_string(Some("passphrase"), &sealed, REFRESH_TOKEN_NAME).unwrap(),
+            "token"
+        );
+
+        // plain tokens are kept without session_key
+        assert_eq!(seal_refresh_token(None, "token").unwrap(), "token");
+        assert_eq!(seal_refresh_token(Some("passphrase"), "").unwrap(), "");
     }
 }
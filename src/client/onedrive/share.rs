@@ -0,0 +1,46 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::OneDriveClient;
+use crate::settings::ShareLink;
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+
+impl OneDriveClient {
+    // a view-only link of the uploaded file, account is none for the current one
+    pub async fn create_share_link(
+        &self,
+        account: Option<&str>,
+        file_path: &str,
+        share_link: ShareLink,
+    ) -> Result<String> {
+        let scope = match share_link {
+            ShareLink::Off => return Err(anyhow!("share link is off")),
+            ShareLink::Anonymous => "anonymous",
+            ShareLink::Organization => "organization",
+        };
+
+        let graph = self.get_account_graph(account).await?;
+
+        // anonymous links can be disabled by the admin of the organization
+        let permission = graph
+            .send(graph.drive.create_link(file_path, scope))
+            .await?
+            .json::<Value>()
+            .and_then(|permission| permission.ok_or_else(|| anyhow!("{} not found", file_path)))
+            .context(format!(
+                "failed to create {} share link of {}",
+                scope, file_path
+            ))?;
+
+        permission
+            .pointer("/link/webUrl")
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+            .ok_or_else(|| anyhow!("field link.webUrl not found in share link"))
+    }
+}
@@ -0,0 +1,193 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use anyhow::anyhow;
+use onedrive_api::{DriveId, DriveLocation};
+use serde_json::Value;
+use std::{fmt::Display, str::FromStr};
+
+// the drive of an account to upload to, set by /drive target
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DriveTarget {
+    // the personal drive of the account
+    #[default]
+    Me,
+    // the default document library of a sharepoint site
+    Site(String),
+    // any document library, like the other libraries of a site
+    Drive(String),
+    // a folder shared with the account, addressed by its drive and item ids, used as the root
+    Shared { drive: String, item: String },
+}
+
+// a folder listed by /drive shared
+pub struct SharedFolder {
+    pub name: String,
+    pub owner: Option<String>,
+    pub target: DriveTarget,
+}
+
+impl FromStr for DriveTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "me" {
+            return Ok(Self::Me);
+        }
+
+        match s.split_once(':') {
+            Some(("site", site)) if !site.is_empty() => Ok(Self::Site(site.to_string())),
+            Some(("drive", drive)) if !drive.is_empty() => Ok(Self::Drive(drive.to_string())),
+            Some(("shared", ids)) => match ids.split_once('/') {
+                Some((drive, item)) if !drive.is_empty() && !item.is_empty() => Ok(Self::Shared {
+                    drive: drive.to_string(),
+                    item: item.to_string(),
+                }),
+                _ => Err(anyhow!(
+                    "shared folder should be shared:$drive_id/$item_id, listed by /drive shared"
+                )),
+            },
+            _ => Err(anyhow!(
+                "drive target should be me, site:$site_id, drive:$drive_id or shared:$drive_id/$item_id"
+            )),
+        }
+    }
+}
+
+// also saved in the session
+impl Display for DriveTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Me => write!(f, "me"),
+            Self::Site(site) => write!(f, "site:{}", site),
+            Self::Drive(drive) => write!(f, "drive:{}", drive),
+            Self::Shared { drive, item } => write!(f, "shared:{}/{}", drive, item),
+        }
+    }
+}
+
+impl DriveTarget {
+    pub fn drive_location(&self) -> DriveLocation {
+        match self {
+            Self::Me => DriveLocation::me(),
+            Self::Site(site) => DriveLocation::from_site(site.clone()),
+            Self::Drive(drive) | Self::Shared { drive, .. } => {
+                DriveLocation::from_id(DriveId(drive.clone()))
+            }
+        }
+    }
+
+    // path of the drive in graph api
+    pub fn drive_path(&self) -> String {
+        match self {
+            Self::Me => "me/drive".to_string(),
+            Self::Site(site) => format!("sites/{}/drive", site),
+            Self::Drive(drive) | Self::Shared { drive, .. } => format!("drives/{}", drive),
+        }
+    }
+
+    // the item paths are relative to in graph api
+    pub fn root_item(&self) -> String {
+        match self {
+            Self::Shared { item, .. } => format!("items/{}", item),
+            _ => "root".to_string(),
+        }
+    }
+}
+
+impl SharedFolder {
+    // an item of sharedWithMe, whose remoteItem is the folder in the drive of its owner
+    pub fn from_item(item: &Value) -> Option<Self> {
+        let remote_item = item.get("remoteItem")?;
+
+        remote_item.get("folder")?;
+
+        let name = remote_item
+            .get("name")
+            .or_else(|| item.get("name"))?
+            .as_str()?
+            .to_string();
+        let owner = remote_item
+            .pointer("/shared/owner/user/displayName")
+            .and_then(Value::as_str)
+            .map(ToString::to_string);
+        let drive = remote_item
+            .pointer("/parentReference/driveId")?
+            .as_str()?
+            .to_string();
+        let item = remote_item.get("id")?.as_str()?.to_string();
+
+        Some(Self {
+            name,
+            owner,
+            target: DriveTarget::Shared { drive, item },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_drive_target() {
+        assert_eq!("me".parse::<DriveTarget>().unwrap(), DriveTarget::Me);
+        assert_eq!(
+            "site:contoso.sharepoint.com,a,b"
+                .parse::<DriveTarget>()
+                .unwrap()
+                .drive_path(),
+            "sites/contoso.sharepoint.com,a,b/drive"
+        );
+        assert_eq!(
+            "drive:b!abc".parse::<DriveTarget>().unwrap().to_string(),
+            "drive:b!abc"
+        );
+
+        assert!("site:".parse::<DriveTarget>().is_err());
+        assert!("user:alice".parse::<DriveTarget>().is_err());
+
+        let shared = "shared:b!abc/01XYZ".parse::<DriveTarget>().unwrap();
+        assert_eq!(shared.drive_path(), "drives/b!abc");
+        assert_eq!(shared.root_item(), "items/01XYZ");
+        assert_eq!(shared.to_string(), "shared:b!abc/01XYZ");
+
+        assert!("shared:b!abc".parse::<DriveTarget>().is_err());
+        assert!("shared:/01XYZ".parse::<DriveTarget>().is_err());
+    }
+
+    #[test]
+    fn test_shared_folder() {
+        let item = json!({
+            "name": "Archive",
+            "remoteItem": {
+                "id": "01XYZ",
+                "name": "Archive",
+                "folder": { "childCount": 2 },
+                "parentReference": { "driveId": "b!abc" },
+                "shared": { "owner": { "user": { "displayName": "Alice" } } },
+            },
+        });
+
+        let folder = SharedFolder::from_item(&item).unwrap();
+        assert_eq!(folder.name, "Archive");
+        assert_eq!(folder.owner.as_deref(), Some("Alice"));
+        assert_eq!(folder.target.to_string(), "shared:b!abc/01XYZ");
+
+        // shared files can't be uploaded into
+        let file = json!({
+            "name": "a.mp4",
+            "remoteItem": {
+                "id": "01ABC",
+                "file": {},
+                "parentReference": { "driveId": "b!abc" },
+            },
+        });
+        assert!(SharedFolder::from_item(&file).is_none());
+    }
+}
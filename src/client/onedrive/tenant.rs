@@ -0,0 +1,72 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{graph::GraphRequest, OneDriveClient};
+use crate::{
+    env::{ENV, OD_TENANT_SESSION_PREFIX},
+    error::ResultExt,
+};
+use anyhow::{Context, Result};
+use std::path::Path;
+use tokio::fs;
+
+// accounts, the current account and root paths of a telegram user are kept in their own session
+fn get_tenant_session_path(tenant_id: i64) -> String {
+    ENV.get().unwrap().session_file(&format!(
+        "{}{}.session",
+        OD_TENANT_SESSION_PREFIX, tenant_id
+    ))
+}
+
+impl OneDriveClient {
+    pub async fn new_tenant(tenant_id: i64) -> Result<Self> {
+        tracing::info!("creating onedrive client for tenant {}", tenant_id);
+
+        Self::with_session_path(&get_tenant_session_path(tenant_id)).await
+    }
+
+    // before the tokens of a tenant are dropped, so that copies of them can't be used either,
+    // the account is still logged out if it fails, like for a personal account
+    pub async fn revoke_tokens(&self, account: Option<&str>) {
+        // there is no user to revoke in app-only mode
+        if self.app_auth.is_some() {
+            return;
+        }
+
+        async {
+            let graph = self.get_account_graph(account).await?;
+
+            graph
+                .send(GraphRequest::revoke_sign_in_sessions(&self.cloud.api_url()))
+                .await?
+                .content()?;
+
+            Ok::<(), anyhow::Error>(())
+        }
+        .await
+        .context(format!(
+            "failed to revoke onedrive tokens of {}",
+            account.unwrap_or("the current account")
+        ))
+        .trace();
+    }
+
+    // tokens of all accounts of the tenant are dropped with the session
+    pub async fn remove_tenant_session(tenant_id: i64) -> Result<()> {
+        let session_path = get_tenant_session_path(tenant_id);
+
+        if Path::new(&session_path).exists() {
+            fs::remove_file(&session_path)
+                .await
+                .context("failed to remove onedrive tenant session")?;
+        }
+
+        tracing::info!("removed onedrive session of tenant {}", tenant_id);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use std::{
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+// for 429 and 503 responses without Retry-After
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+// shared by the upload sessions of all tasks and accounts, since onedrive throttles the whole app
+pub static THROTTLE: LazyLock<Throttle> = LazyLock::new(Throttle::default);
+
+#[derive(Default)]
+pub struct Throttle {
+    resume_at: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    // retry after in seconds, from the Retry-After header
+    pub fn pause(&self, retry_after: Option<u32>) {
+        let duration = retry_after.map_or(DEFAULT_RETRY_AFTER, |seconds| {
+            Duration::from_secs(seconds.into())
+        });
+        let resume_at = Instant::now() + duration;
+
+        let mut current_resume_at = self.resume_at.lock().unwrap();
+
+        // a shorter pause doesn't end a longer one
+        if current_resume_at.is_none_or(|current_resume_at| current_resume_at < resume_at) {
+            *current_resume_at = Some(resume_at);
+
+            tracing::warn!(
+                "onedrive throttled, uploads paused for {}s",
+                duration.as_secs()
+            );
+        }
+    }
+
+    // none if not paused
+    pub fn remaining(&self) -> Option<Duration> {
+        self.resume_at
+            .lock()
+            .unwrap()
+            .and_then(|resume_at| resume_at.checked_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    // before each request, the pause may be extended while waiting
+    pub async fn wait(&self) {
+        while let Some(remaining) = self.remaining() {
+            tokio::time::sleep(remaining).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::default();
+        assert!(throttle.remaining().is_none());
+
+        throttle.pause(Some(60));
+        assert!(throttle.remaining().unwrap() > Duration::from_secs(59));
+
+        // kept
+        throttle.pause(Some(1));
+        assert!(throttle.remaining().unwrap() > Duration::from_secs(59));
+
+        throttle.pause(None);
+        assert!(throttle.remaining().unwrap() > Duration::from_secs(59));
+    }
+}
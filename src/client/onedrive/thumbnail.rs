@@ -0,0 +1,51 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::OneDriveClient;
+use anyhow::Result;
+use serde_json::Value;
+
+pub struct Thumbnail {
+    pub name: String,
+    pub url: String,
+    pub web_url: String,
+}
+
+impl Thumbnail {
+    // only images have thumbnails worth showing
+    fn from_item(item: &Value) -> Option<Self> {
+        item.get("image")?;
+
+        let name = item.get("name")?.as_str()?.to_string();
+        let web_url = item.get("webUrl")?.as_str()?.to_string();
+        let url = item
+            .get("thumbnails")?
+            .as_array()?
+            .first()?
+            .get("medium")?
+            .get("url")?
+            .as_str()?
+            .to_string();
+
+        Some(Self { name, url, web_url })
+    }
+}
+
+impl OneDriveClient {
+    pub async fn list_thumbnails(&self, path: &str) -> Result<Vec<Thumbnail>> {
+        let thumbnails = self
+            .list_children(path)
+            .await?
+            .iter()
+            .filter_map(Thumbnail::from_item)
+            .collect::<Vec<_>>();
+
+        tracing::debug!("got {} thumbnails in {}", thumbnails.len(), path);
+
+        Ok(thumbnails)
+    }
+}
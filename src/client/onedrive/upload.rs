@@ -6,40 +6,249 @@
 */
 
 use super::OneDriveClient;
+use crate::{settings::ConflictPolicy, utils::get_http_client};
 use anyhow::{anyhow, Context, Result};
-use onedrive_api::{
-    option::DriveItemPutOption, ConflictBehavior, ItemLocation, UploadSession, UploadSessionMeta,
-};
+use chrono::{DateTime, Utc};
+use onedrive_api::{resource::DriveItem, UploadSession, UploadSessionMeta};
 use path_slash::PathBufExt;
+use serde_json::{json, Value};
 use std::path::Path;
 
+// smaller files are uploaded in a single request, without creating an upload session
+const SMALL_FILE_LENGTH: u64 = 4 * 1024 * 1024;
+
+pub const fn is_small_file(length: u64) -> bool {
+    length < SMALL_FILE_LENGTH
+}
+
 impl OneDriveClient {
+    // account is none for the current one
     pub async fn multipart_upload_session_builder(
         &self,
+        account: Option<&str>,
         root_path: &str,
         filename: &str,
+        conflict_policy: ConflictPolicy,
     ) -> Result<(UploadSession, UploadSessionMeta)> {
         let file_path_obj = Path::new(root_path).join(filename);
         let file_path = file_path_obj.to_slash_lossy();
 
-        let item_location = ItemLocation::from_path(&file_path)
-            .ok_or_else(|| anyhow!("file path does not start with /"))?;
-
-        self.refresh_access_token().await?;
+        let graph = self.get_account_graph(account).await?;
 
-        let session = self
-            .client
-            .read()
-            .await
-            .new_upload_session_with_option(
-                item_location,
-                DriveItemPutOption::new().conflict_behavior(ConflictBehavior::Rename),
+        let content = graph
+            .send(
+                graph
+                    .drive
+                    .create_upload_session(&file_path, get_conflict_behavior(conflict_policy)),
             )
-            .await
+            .await?
+            .content()
+            .and_then(|content| content.ok_or_else(|| anyhow!("{} not found", file_path)))
             .context("failed to create upload session")?;
 
+        let session = serde_json::from_str::<UploadSession>(&content)
+            .context("failed to deserialize upload session")?;
+        let meta = serde_json::from_str::<UploadSessionMeta>(&content)
+            .context("failed to deserialize upload session meta")?;
+
         tracing::debug!("built upload session for {}", filename);
 
-        Ok(session)
+        Ok((session, meta))
+    }
+
+    // account is none for the current one, the item is renamed with the rename policy
+    pub async fn upload_small_file(
+        &self,
+        account: Option<&str>,
+        root_path: &str,
+        filename: &str,
+        conflict_policy: ConflictPolicy,
+        content: Vec<u8>,
+    ) -> Result<DriveItem> {
+        let file_path_obj = Path::new(root_path).join(filename);
+        let file_path = file_path_obj.to_slash_lossy();
+
+        let graph = self.get_account_graph(account).await?;
+
+        let item = graph
+            .send(graph.drive.upload_content(
+                &file_path,
+                get_conflict_behavior(conflict_policy),
+                content,
+            ))
+            .await?
+            .json::<DriveItem>()
+            .and_then(|item| item.ok_or_else(|| anyhow!("{} not found", file_path)))
+            .context("failed to upload small file")?;
+
+        tracing::debug!("uploaded small file {}", filename);
+
+        Ok(item)
+    }
+
+    // upload data in memory, returns the name of the uploaded file, which is renamed if conflicts
+    pub async fn upload_bytes(
+        &self,
+        account: Option<&str>,
+        root_path: &str,
+        filename: &str,
+        data: &[u8],
+    ) -> Result<String> {
+        const PART_SIZE: usize = 3276800;
+
+        if is_small_file(data.len() as u64) {
+            let item = self
+                .upload_small_file(
+                    account,
+                    root_path,
+                    filename,
+                    ConflictPolicy::Rename,
+                    data.to_vec(),
+                )
+                .await
+                .context(filename.to_string())?;
+
+            self.invalidate_items(root_path);
+
+            return item
+                .name
+                .ok_or_else(|| anyhow!("drive item name not found"));
+        }
+
+        let (upload_session, _) = self
+            .multipart_upload_session_builder(account, root_path, filename, ConflictPolicy::Rename)
+            .await?;
+
+        let http_client = get_http_client()?;
+
+        let total_length = data.len() as u64;
+        let mut current_length = 0;
+        let mut upload_response = None;
+
+        for chunk in data.chunks(PART_SIZE) {
+            upload_response = upload_session
+                .upload_part(
+                    chunk.to_vec(),
+                    current_length..current_length + chunk.len() as u64,
+                    total_length,
+                    &http_client,
+                )
+                .await
+                .context("failed to upload part")
+                .context(filename.to_string())?;
+
+            current_length += chunk.len() as u64;
+        }
+
+        self.invalidate_items(root_path);
+
+        upload_response
+            .ok_or_else(|| anyhow!("failed to get drive item after upload"))?
+            .name
+            .ok_or_else(|| anyhow!("drive item name not found"))
+    }
+
+    // size and quickXorHash of a file, none if it's missing, the hash is none if the drive doesn't report it
+    pub async fn get_file_hash(
+        &self,
+        account: Option<&str>,
+        root_path: &str,
+        filename: &str,
+    ) -> Result<Option<(u64, Option<String>)>> {
+        let graph = self.get_account_graph(account).await?;
+
+        let file_path = Path::new(root_path).join(filename).to_slash_lossy();
+
+        let Some(item) = graph
+            .send(graph.drive.get_item(&file_path))
+            .await?
+            .json::<Value>()
+            .context(format!("failed to get {}", file_path))?
+        else {
+            return Ok(None);
+        };
+
+        let size = item.get("size").and_then(Value::as_u64).unwrap_or_default();
+        let hash = item
+            .pointer("/file/hashes/quickXorHash")
+            .and_then(Value::as_str)
+            .map(ToString::to_string);
+
+        Ok(Some((size, hash)))
+    }
+
+    // not supported by some drives like sharepoint libraries
+    pub async fn set_description(
+        &self,
+        account: Option<&str>,
+        root_path: &str,
+        filename: &str,
+        description: &str,
+    ) -> Result<()> {
+        self.update_item(
+            account,
+            root_path,
+            filename,
+            json!({ "description": description }),
+        )
+        .await
+        .context("failed to set description")
+    }
+
+    // the time the file was posted instead of the time it was uploaded, for sorting by date
+    pub async fn set_file_date(
+        &self,
+        account: Option<&str>,
+        root_path: &str,
+        filename: &str,
+        date: DateTime<Utc>,
+    ) -> Result<()> {
+        let date = date.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+        self.update_item(
+            account,
+            root_path,
+            filename,
+            json!({
+                "fileSystemInfo": {
+                    "createdDateTime": date,
+                    "lastModifiedDateTime": date,
+                }
+            }),
+        )
+        .await
+        .context("failed to set file date")
+    }
+
+    async fn update_item(
+        &self,
+        account: Option<&str>,
+        root_path: &str,
+        filename: &str,
+        body: Value,
+    ) -> Result<()> {
+        let graph = self.get_account_graph(account).await?;
+
+        let file_path = Path::new(root_path).join(filename).to_slash_lossy();
+
+        graph
+            .send(graph.drive.update_item(&file_path, body))
+            .await?
+            .content()
+            .and_then(|content| content.ok_or_else(|| anyhow!("{} not found", file_path)))
+            .context(format!("failed to update {}", file_path))?;
+
+        self.invalidate_items(&file_path);
+
+        Ok(())
+    }
+}
+
+// skipped files are checked before uploading, it fails in case the file is uploaded meanwhile
+const fn get_conflict_behavior(conflict_policy: ConflictPolicy) -> &'static str {
+    match conflict_policy {
+        ConflictPolicy::Rename => "rename",
+        ConflictPolicy::Replace => "replace",
+        ConflictPolicy::Skip => "fail",
     }
 }
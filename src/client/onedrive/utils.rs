@@ -7,6 +7,20 @@
 
 use super::invalid_name::INVALID_FOLDER_DIR;
 use anyhow::{anyhow, Result};
+use percent_encoding::{AsciiSet, CONTROLS};
+
+// characters which must be encoded in the path of graph api
+pub const PATH_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
 
 pub fn validate_root_path(path: &str) -> Result<()> {
     if path == INVALID_FOLDER_DIR || path.starts_with(&format!("{}/", INVALID_FOLDER_DIR)) {
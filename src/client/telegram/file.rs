@@ -6,13 +6,25 @@
 */
 
 use super::TelegramClient;
+use crate::error::TaskAbortError;
 use anyhow::{Context, Result};
 use grammers_client::{
     client::files::DownloadIter,
     types::{media::Uploaded, Downloadable},
 };
-use std::path::Path;
-use tokio::io::AsyncRead;
+use std::{
+    io,
+    path::Path,
+    pin::Pin,
+    task::{ready, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::mpsc,
+    task::AbortHandle,
+};
+use tokio_util::sync::CancellationToken;
 
 impl TelegramClient {
     pub async fn upload_file<P: AsRef<Path>>(&self, path: P) -> Result<Uploaded> {
@@ -41,4 +53,212 @@ impl TelegramClient {
     pub fn iter_download<D: Downloadable>(&self, downloadable: &D) -> DownloadIter {
         self.raw().iter_download(downloadable)
     }
+
+    // download in background and read it as a stream, so that it can be uploaded without saving to disk
+    pub fn download_stream<D: Downloadable>(&self, downloadable: &D) -> DownloadStream {
+        self.download(downloadable).stream()
+    }
+
+    pub fn download<D: Downloadable>(&self, downloadable: &D) -> DownloadBuilder {
+        DownloadBuilder {
+            download: self.iter_download(downloadable),
+            read_ahead: DEFAULT_READ_AHEAD,
+            max_chunks: None,
+            max_retries: 0,
+            cancellation_token: None,
+        }
+    }
+}
+
+// chunks downloaded ahead of the reader, so that at most read_ahead chunks are kept in memory
+const DEFAULT_READ_AHEAD: usize = 4;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub struct DownloadBuilder {
+    download: DownloadIter,
+    read_ahead: usize,
+    max_chunks: Option<usize>,
+    max_retries: u32,
+    cancellation_token: Option<CancellationToken>,
+}
+
+impl DownloadBuilder {
+    // a multiple of 4KB, at most 512KB
+    pub fn chunk_size(mut self, size: i32) -> Self {
+        self.download = self.download.chunk_size(size);
+
+        self
+    }
+
+    pub fn skip_chunks(mut self, n: i32) -> Self {
+        self.download = self.download.skip_chunks(n);
+
+        self
+    }
+
+    pub fn read_ahead(mut self, read_ahead: usize) -> Self {
+        self.read_ahead = read_ahead.max(1);
+
+        self
+    }
+
+    // stop after some chunks, like a single chunk fetched by a parallel worker
+    pub fn max_chunks(mut self, max_chunks: usize) -> Self {
+        self.max_chunks = Some(max_chunks);
+
+        self
+    }
+
+    // a failed chunk is requested again, since the offset only moves on success
+    pub fn retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+
+        self
+    }
+
+    // the stream ends with TaskAbortError once cancelled
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+
+        self
+    }
+
+    pub fn stream(self) -> DownloadStream {
+        let Self {
+            mut download,
+            read_ahead,
+            max_chunks,
+            max_retries,
+            cancellation_token,
+        } = self;
+
+        let (tx, rx) = mpsc::channel(read_ahead);
+
+        let handle = tokio::spawn(async move {
+            let mut chunks = 0;
+
+            while max_chunks.is_none_or(|max_chunks| chunks < max_chunks) {
+                chunks += 1;
+
+                let mut retries = 0;
+
+                let result = loop {
+                    let next_chunk = async {
+                        download
+                            .next()
+                            .await
+                            .context("failed to download chunk for stream")
+                    };
+
+                    let result = match &cancellation_token {
+                        Some(cancellation_token) => tokio::select! {
+                            result = next_chunk => result,
+                            () = cancellation_token.cancelled() => Err(TaskAbortError.into()),
+                        },
+                        None => next_chunk.await,
+                    };
+
+                    match result {
+                        Err(e) if retries < max_retries && !e.is::<TaskAbortError>() => {
+                            tracing::warn!("retry downloading chunk {}: {:?}", chunks, e);
+
+                            retries += 1;
+
+                            tokio::time::sleep(RETRY_DELAY).await;
+                        }
+                        result => break result,
+                    }
+                };
+
+                match result {
+                    Ok(Some(chunk)) => {
+                        // stop downloading if the stream is dropped
+                        if tx.send(Ok(chunk)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        tx.send(Err(e)).await.ok();
+
+                        break;
+                    }
+                }
+            }
+        });
+
+        DownloadStream {
+            rx,
+            chunk: Vec::new(),
+            position: 0,
+            abort_handle: handle.abort_handle(),
+        }
+    }
+}
+
+pub struct DownloadStream {
+    rx: mpsc::Receiver<Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+    abort_handle: AbortHandle,
+}
+
+impl DownloadStream {
+    // none once finished, the rest of a chunk partially read as AsyncRead comes first
+    pub async fn next(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.position < self.chunk.len() {
+            let chunk = self.chunk.split_off(self.position);
+            self.chunk.clear();
+            self.position = 0;
+
+            return Ok(Some(chunk));
+        }
+
+        self.rx.recv().await.transpose()
+    }
+
+    // for small files which are needed as a whole, like stickers and batch scripts
+    pub async fn read_to_end(mut self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+
+        while let Some(chunk) = self.next().await? {
+            data.extend(chunk);
+        }
+
+        Ok(data)
+    }
+}
+
+impl Drop for DownloadStream {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+impl AsyncRead for DownloadStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.position >= self.chunk.len() {
+            match ready!(self.rx.poll_recv(cx)) {
+                Some(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Some(Err(e)) => return Poll::Ready(Err(io::Error::other(e.to_string()))),
+                // end of file
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = buf.remaining().min(self.chunk.len() - self.position);
+        let position = self.position;
+
+        buf.put_slice(&self.chunk[position..position + len]);
+        self.position += len;
+
+        Poll::Ready(Ok(()))
+    }
 }
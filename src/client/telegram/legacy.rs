@@ -0,0 +1,94 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::session_store::SessionStore;
+use crate::env::{Env, TelegramUserEnv, ENV};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::{session::Session, Client, Config};
+use sea_orm::{ConnectionTrait, Statement};
+use std::net::{IpAddr, SocketAddr};
+
+// telethon keeps the auth key of the home dc in table sessions of a sqlite file
+// returns the username of the migrated account, or its id if it has no username
+pub async fn import_telethon_session(path: &str) -> Result<String> {
+    let Env {
+        telegram_user:
+            TelegramUserEnv {
+                api_id,
+                api_hash,
+                params,
+                ..
+            },
+        telegram_session_path,
+        session_key,
+        ..
+    } = ENV.get().unwrap();
+
+    let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=ro", path))
+        .await
+        .context("failed to connect to telethon session")?;
+
+    let backend = connection.get_database_backend();
+
+    let row = connection
+        .query_one(Statement::from_string(
+            backend,
+            "SELECT dc_id, server_address, port, auth_key FROM sessions",
+        ))
+        .await
+        .context("failed to query telethon session")?
+        .ok_or_else(|| anyhow!("no telethon session found"))?;
+
+    let dc_id: i32 = row.try_get("", "dc_id").context("dc_id")?;
+    let server_address: String = row
+        .try_get("", "server_address")
+        .context("server_address")?;
+    let port: i32 = row.try_get("", "port").context("port")?;
+    let auth_key: Vec<u8> = row.try_get("", "auth_key").context("auth_key")?;
+
+    let auth_key: [u8; 256] = auth_key
+        .try_into()
+        .map_err(|_| anyhow!("telethon auth key should be 256 bytes"))?;
+
+    let ip = server_address
+        .parse::<IpAddr>()
+        .context("invalid telethon server address")?;
+    let port = u16::try_from(port).context("invalid telethon server port")?;
+
+    let session = Session::new();
+    session.insert_dc(dc_id, &SocketAddr::new(ip, port), &auth_key);
+    // the user id is unknown until connected, the dc decides which auth key is used
+    session.set_user(0, dc_id, false);
+
+    let config = Config {
+        session,
+        api_id: *api_id,
+        api_hash: api_hash.clone(),
+        params: params.clone(),
+    };
+
+    let client = Client::connect(config)
+        .await
+        .context("failed to connect with telethon session")?;
+
+    let me = client
+        .get_me()
+        .await
+        .context("telethon session is not authorized")?;
+
+    client.session().set_user(me.id(), dc_id, false);
+
+    let session_store = SessionStore::new(telegram_session_path, session_key.clone()).await?;
+    session_store
+        .save("user", &client.session().save())
+        .await
+        .context("failed to save migrated telegram user session")?;
+
+    Ok(me
+        .username()
+        .map_or_else(|| me.id().to_string(), ToString::to_string))
+}
@@ -5,15 +5,18 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::TelegramClient;
+use super::{session::MessageSession, TelegramClient};
 use crate::{
     error::ResultExt,
-    message::{ChatEntity, QueuedMessage, QueuedMessageType, TelegramMessage},
+    message::{
+        ChatEntity, MessagePriority, OutgoingMessage, QueuedMessage, QueuedMessageType,
+        TelegramMessage,
+    },
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{
     client::messages::MessageIter,
-    types::{Chat, InputMessage, PackedChat},
+    types::{Chat, PackedChat},
     Update,
 };
 use rand::{rngs::StdRng, Rng, SeedableRng};
@@ -25,6 +28,20 @@ use tokio::sync::mpsc;
 
 impl TelegramClient {
     pub async fn get_message<C>(&self, chat: C, message_id: i32) -> Result<TelegramMessage>
+    where
+        C: Into<PackedChat>,
+    {
+        self.get_message_if_exists(chat, message_id)
+            .await?
+            .ok_or_else(|| anyhow!("message not found"))
+    }
+
+    // none if the message has been deleted
+    pub async fn get_message_if_exists<C>(
+        &self,
+        chat: C,
+        message_id: i32,
+    ) -> Result<Option<TelegramMessage>>
     where
         C: Into<PackedChat>,
     {
@@ -35,12 +52,14 @@ impl TelegramClient {
             .context("failed to get message by id")?
             .first()
             .ok_or_else(|| anyhow!("message vec is empty"))?
-            .to_owned()
-            .ok_or_else(|| anyhow!("message not found"))?;
+            .to_owned();
 
-        let message = TelegramMessage::new(self.clone(), message_raw);
+        let message =
+            message_raw.map(|message_raw| TelegramMessage::new(self.clone(), message_raw));
 
-        tracing::debug!("got message {} in chat {}", message_id, message.chat().id());
+        if let Some(message) = &message {
+            tracing::debug!("got message {} in chat {}", message_id, message.chat().id());
+        }
 
         Ok(message)
     }
@@ -83,16 +102,39 @@ impl TelegramClient {
             .context("failed to delete messages")
     }
 
-    pub async fn send_message<C: Into<PackedChat>, M: Into<InputMessage>>(
+    pub async fn pin_message<C: Into<PackedChat>>(&self, chat: C, message_id: i32) -> Result<()> {
+        self.raw()
+            .pin_message(chat, message_id)
+            .await
+            .context("failed to pin message")
+    }
+
+    pub async fn unpin_message<C: Into<PackedChat>>(&self, chat: C, message_id: i32) -> Result<()> {
+        self.raw()
+            .unpin_message(chat, message_id)
+            .await
+            .context("failed to unpin message")
+    }
+
+    pub async fn send_message<C: Into<PackedChat>, M: Into<OutgoingMessage>>(
         &self,
         chat: C,
         message: M,
     ) -> Result<TelegramMessage> {
-        let (tx, mut rx) = mpsc::channel(1);
+        self.send_message_with_priority(chat, message, MessagePriority::High)
+            .await
+    }
 
-        let queued_message = QueuedMessage::new(QueuedMessageType::Respond, message, chat, tx);
+    pub async fn send_message_with_priority<C: Into<PackedChat>, M: Into<OutgoingMessage>>(
+        &self,
+        chat: C,
+        message: M,
+        priority: MessagePriority,
+    ) -> Result<TelegramMessage> {
+        let (tx, mut rx) = mpsc::channel(1);
 
-        self.push_queued_message(queued_message).await;
+        self.push_split_queued_message(QueuedMessageType::Respond, message, chat, tx, priority)
+            .await;
 
         rx.recv()
             .await
@@ -100,7 +142,7 @@ impl TelegramClient {
             .ok_or_else(|| anyhow!("received message is None"))
     }
 
-    pub async fn reply_message<C: Into<PackedChat>, M: Into<InputMessage>>(
+    pub async fn reply_message<C: Into<PackedChat>, M: Into<OutgoingMessage>>(
         &self,
         chat: C,
         message_id: i32,
@@ -108,10 +150,14 @@ impl TelegramClient {
     ) -> Result<TelegramMessage> {
         let (tx, mut rx) = mpsc::channel(1);
 
-        let queued_message =
-            QueuedMessage::new(QueuedMessageType::Reply(message_id), message, chat, tx);
-
-        self.push_queued_message(queued_message).await;
+        self.push_split_queued_message(
+            QueuedMessageType::Reply(message_id),
+            message,
+            chat,
+            tx,
+            MessagePriority::High,
+        )
+        .await;
 
         rx.recv()
             .await
@@ -119,16 +165,31 @@ impl TelegramClient {
             .ok_or_else(|| anyhow!("received message is None"))
     }
 
-    pub async fn edit_message<C: Into<PackedChat>, M: Into<InputMessage>>(
+    pub async fn edit_message<C: Into<PackedChat>, M: Into<OutgoingMessage>>(
         &self,
         chat: C,
         message_id: i32,
         new_message: M,
+    ) -> Result<()> {
+        self.edit_message_with_priority(chat, message_id, new_message, MessagePriority::High)
+            .await
+    }
+
+    pub async fn edit_message_with_priority<C: Into<PackedChat>, M: Into<OutgoingMessage>>(
+        &self,
+        chat: C,
+        message_id: i32,
+        new_message: M,
+        priority: MessagePriority,
     ) -> Result<()> {
         let (tx, mut rx) = mpsc::channel(1);
 
+        // an edit can't be split into several messages
+        let new_message = new_message.into().truncate();
+
         let queued_message =
-            QueuedMessage::new(QueuedMessageType::Edit(message_id), new_message, chat, tx);
+            QueuedMessage::new(QueuedMessageType::Edit(message_id), new_message, chat, tx)
+                .with_priority(priority);
 
         self.push_queued_message(queued_message).await;
 
@@ -153,6 +214,36 @@ impl TelegramClient {
             .push_back(queued_message);
     }
 
+    // the first part gets the result, the rest parts are sent as responds after it
+    async fn push_split_queued_message<C: Into<PackedChat>, M: Into<OutgoingMessage>>(
+        &self,
+        message_type: QueuedMessageType,
+        message: M,
+        chat: C,
+        tx: mpsc::Sender<Result<Option<TelegramMessage>>>,
+        priority: MessagePriority,
+    ) {
+        let chat = chat.into();
+        let mut messages = message.into().split().into_iter();
+
+        let chat_message_queue = self.chat_message_queue();
+        // keep the lock so that the parts won't be interleaved with other messages
+        let mut chat_message_queue = chat_message_queue.lock().await;
+
+        if let Some(message) = messages.next() {
+            chat_message_queue.push_back(
+                QueuedMessage::new(message_type, message, chat, tx).with_priority(priority),
+            );
+        }
+
+        for message in messages {
+            chat_message_queue.push_back(
+                QueuedMessage::detached(QueuedMessageType::Respond, message, chat)
+                    .with_priority(priority),
+            );
+        }
+    }
+
     pub fn run_message_loop(&self) {
         let chat_message_queue = self.chat_message_queue();
         let telegram_client = self.clone();
@@ -175,6 +266,7 @@ impl TelegramClient {
                         input_message,
                         chat,
                         tx,
+                        ..
                     }) = chat_message_queue.pop_front(chat_id)
                     {
                         let message_result = match message_type {
@@ -222,10 +314,14 @@ impl TelegramClient {
                             }
                         };
 
-                        tx.send(message_result)
-                            .await
-                            .context("failed to send message result to rx")
-                            .trace();
+                        if let Some(tx) = tx {
+                            tx.send(message_result)
+                                .await
+                                .context("failed to send message result to rx")
+                                .trace();
+                        } else {
+                            message_result.trace();
+                        }
                     }
 
                     if chat_message_queue.get(&chat_id).unwrap().is_empty() {
@@ -240,16 +336,94 @@ impl TelegramClient {
             }
         });
     }
+
+    pub async fn save_queued_messages(&self, message_session: &MessageSession) -> Result<()> {
+        let chat_message_queue = self.chat_message_queue();
+        // keep the lock so that the message loop won't send anything else
+        let mut chat_message_queue = chat_message_queue.lock().await;
+
+        let mut queued_messages = Vec::new();
+
+        for (_, message_vec_deque) in chat_message_queue.drain() {
+            for queued_message in message_vec_deque.into_messages() {
+                if queued_message.content.is_some() {
+                    queued_messages.push(queued_message);
+                } else {
+                    tracing::debug!("queued message without text content can't be saved, skip");
+                }
+            }
+        }
+
+        tracing::info!(
+            "saving {} queued messages for telegram {} client",
+            queued_messages.len(),
+            self.name()
+        );
+
+        message_session
+            .save_messages(self.name(), queued_messages)
+            .await
+    }
+
+    pub async fn restore_queued_messages(&self, message_session: &MessageSession) -> Result<()> {
+        let queued_messages = message_session.take_messages(self.name()).await?;
+
+        tracing::info!(
+            "restoring {} queued messages for telegram {} client",
+            queued_messages.len(),
+            self.name()
+        );
+
+        for queued_message in queued_messages {
+            self.push_queued_message(queued_message).await;
+        }
+
+        Ok(())
+    }
 }
 
+// messages of a chat, high priority messages are sent first
 pub struct MessageVecDeque {
+    high: MessageLane,
+    low: MessageLane,
+}
+
+impl MessageVecDeque {
+    pub fn new() -> Self {
+        Self {
+            high: MessageLane::new(),
+            low: MessageLane::new(),
+        }
+    }
+
+    fn push_back(&mut self, queued_message: QueuedMessage) {
+        match queued_message.priority {
+            MessagePriority::High => self.high.push_back(queued_message),
+            MessagePriority::Low => self.low.push_back(queued_message),
+        }
+    }
+
+    fn pop_front(&mut self) -> Option<QueuedMessage> {
+        self.high.pop_front().or_else(|| self.low.pop_front())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.high.is_empty() && self.low.is_empty()
+    }
+
+    fn into_messages(self) -> impl Iterator<Item = QueuedMessage> {
+        self.high.deque.into_iter().chain(self.low.deque)
+    }
+}
+
+struct MessageLane {
     deque: VecDeque<QueuedMessage>,
     // message id -> index in deque, only for edit messages
     key_map: HashMap<i32, usize>,
 }
 
-impl MessageVecDeque {
-    pub fn new() -> Self {
+impl MessageLane {
+    fn new() -> Self {
         Self {
             deque: VecDeque::new(),
             key_map: HashMap::new(),
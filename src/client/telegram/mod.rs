@@ -6,15 +6,24 @@
 */
 
 mod file;
+pub mod legacy;
 mod message;
+mod messages;
+pub mod session;
+mod session_store;
+mod sessions;
+mod split;
+pub mod sticker;
 
 use crate::{
     env::{Env, TelegramBotEnv, TelegramUserEnv, ENV},
     message::TelegramMessage,
 };
 use anyhow::{anyhow, Context, Result};
+pub use file::DownloadBuilder;
 use grammers_client::{session::Session, Client, Config, SignInError};
 use message::ChatMessageVecDeque;
+use session_store::SessionStore;
 use std::sync::Arc;
 use tokio::sync::{mpsc::Receiver, Mutex};
 
@@ -26,10 +35,12 @@ pub enum TelegramClient {
     Bot {
         client: Client,
         chat_message_queue: ChatMessageQueue,
+        session_store: Arc<SessionStore>,
     },
     User {
         client: Client,
         chat_message_queue: ChatMessageQueue,
+        session_store: Arc<SessionStore>,
     },
 }
 
@@ -44,11 +55,19 @@ impl TelegramClient {
                     session_path,
                     params,
                 },
+            telegram_session_path,
+            session_key,
             ..
         } = ENV.get().unwrap();
 
-        let session = Session::load_file_or_create(session_path)
-            .context("failed to load or create session for telegram bot client")?;
+        let session_store = SessionStore::new(telegram_session_path, session_key.clone()).await?;
+
+        let session = match session_store.load("bot", session_path).await? {
+            Some(data) => {
+                Session::load(&data).context("failed to load session for telegram bot client")?
+            }
+            None => Session::new(),
+        };
 
         let config = Config {
             session,
@@ -72,15 +91,16 @@ impl TelegramClient {
                 .await
                 .context("failed to sign in telegram bot")?;
 
-            client
-                .session()
-                .save_to_file(session_path)
+            session_store
+                .save("bot", &client.session().save())
+                .await
                 .context("failed to save session for telegram bot client")?;
         }
 
         let telegram_client = Self::Bot {
             client,
             chat_message_queue: Arc::new(Mutex::new(ChatMessageVecDeque::new())),
+            session_store: Arc::new(session_store),
         };
 
         telegram_client.run_message_loop();
@@ -98,11 +118,19 @@ impl TelegramClient {
                     params,
                     ..
                 },
+            telegram_session_path,
+            session_key,
             ..
         } = ENV.get().unwrap();
 
-        let session = Session::load_file_or_create(session_path)
-            .context("failed to load or create session for telegram user client")?;
+        let session_store = SessionStore::new(telegram_session_path, session_key.clone()).await?;
+
+        let session = match session_store.load("user", session_path).await? {
+            Some(data) => {
+                Session::load(&data).context("failed to load session for telegram user client")?
+            }
+            None => Session::new(),
+        };
 
         let config = Config {
             session,
@@ -118,6 +146,7 @@ impl TelegramClient {
         let telegram_client = Self::User {
             client,
             chat_message_queue: Arc::new(Mutex::new(ChatMessageVecDeque::new())),
+            session_store: Arc::new(session_store),
         };
 
         telegram_client.run_message_loop();
@@ -131,6 +160,27 @@ impl TelegramClient {
         }
     }
 
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Bot { .. } => "bot",
+            Self::User { .. } => "user",
+        }
+    }
+
+    async fn save_session(&self) -> Result<()> {
+        let session_store = match self {
+            Self::Bot { session_store, .. } | Self::User { session_store, .. } => session_store,
+        };
+
+        session_store
+            .save(self.name(), &self.raw().session().save())
+            .await
+            .context(format!(
+                "failed to save session for telegram {} client",
+                self.name()
+            ))
+    }
+
     fn chat_message_queue(&self) -> ChatMessageQueue {
         match self {
             Self::Bot {
@@ -142,14 +192,18 @@ impl TelegramClient {
         }
     }
 
-    pub async fn login(&self, message: TelegramMessage, mut rx: Receiver<String>) -> Result<()> {
+    pub async fn login(
+        &self,
+        message: TelegramMessage,
+        token: &str,
+        mut rx: Receiver<String>,
+    ) -> Result<()> {
         if !self.is_authorized().await? {
             let Env {
                 telegram_user:
                     TelegramUserEnv {
                         phone_number,
                         password,
-                        session_path,
                         ..
                     },
                 server_uri,
@@ -161,14 +215,14 @@ impl TelegramClient {
             let response = "Sending telegram login code...\nThis may take a while.";
             message.respond(response).await.context(response)?;
 
-            let token = client
+            let login_token = client
                 .request_login_code(phone_number)
                 .await
                 .context("failed to request telegram user login code")?;
 
             let response = format!(
-                "Please visit {} to input your code to login to Telegram.",
-                server_uri
+                "Please visit {}?token={} to input your code to login to Telegram.",
+                server_uri, token
             );
             message.respond(response.as_str()).await.context(response)?;
 
@@ -181,7 +235,7 @@ impl TelegramClient {
                 let response = "Code received, logining...";
                 message.respond(response).await.context(response)?;
 
-                match client.sign_in(&token, &code).await {
+                match client.sign_in(&login_token, &code).await {
                     Ok(_) => {
                         break;
                     }
@@ -205,10 +259,7 @@ impl TelegramClient {
                 };
             }
 
-            client
-                .session()
-                .save_to_file(session_path)
-                .context("failed to save session for telegram user client")?;
+            self.save_session().await?;
         }
 
         Ok(())
@@ -0,0 +1,169 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::messages;
+use crate::{
+    client::utils::chat_from_hex,
+    error::ErrorExt,
+    message::{MessageContent, QueuedMessage, QueuedMessageType},
+};
+use anyhow::{anyhow, Context, Result};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait,
+    QueryFilter, QueryOrder, Schema, Set,
+};
+
+// messages still queued when shutting down, they will be sent after restarting
+pub struct MessageSession {
+    connection: DatabaseConnection,
+}
+
+impl MessageSession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self { connection })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to message session")?;
+
+        Self::create_table_if_not_exists(&connection).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        if !Self::is_table_exists(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement =
+                Schema::new(backend).create_table_from_entity(messages::Entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!(
+                    "failed to create table {}",
+                    messages::Entity.table_name()
+                ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
+        let result = messages::Entity::find().all(connection).await;
+
+        result.is_ok()
+    }
+
+    pub async fn save_messages(
+        &self,
+        client: &str,
+        queued_messages: Vec<QueuedMessage>,
+    ) -> Result<()> {
+        for QueuedMessage {
+            message_type,
+            content,
+            chat,
+            ..
+        } in queued_messages
+        {
+            let Some(content) = content else {
+                continue;
+            };
+
+            let (message_type, target_message_id) = match message_type {
+                QueuedMessageType::Respond => ("respond", None),
+                QueuedMessageType::Reply(message_id) => ("reply", Some(message_id)),
+                QueuedMessageType::Edit(message_id) => ("edit", Some(message_id)),
+            };
+
+            let (parse_mode, text) = match content {
+                MessageContent::Text(text) => ("text", text),
+                MessageContent::Html(html) => ("html", html),
+                MessageContent::Markdown(markdown) => ("markdown", markdown),
+            };
+
+            let insert_item = messages::ActiveModel {
+                id: ActiveValue::default(),
+                client: Set(client.to_string()),
+                chat_hex: Set(chat.to_hex()),
+                message_type: Set(message_type.to_string()),
+                target_message_id: Set(target_message_id),
+                parse_mode: Set(parse_mode.to_string()),
+                text: Set(text),
+            };
+
+            messages::Entity::insert(insert_item)
+                .exec(&self.connection)
+                .await
+                .context("failed to insert queued message")?;
+        }
+
+        Ok(())
+    }
+
+    // load the saved messages and remove them from the session
+    pub async fn take_messages(&self, client: &str) -> Result<Vec<QueuedMessage>> {
+        let models = messages::Entity::find()
+            .filter(messages::Column::Client.eq(client))
+            .order_by_asc(messages::Column::Id)
+            .all(&self.connection)
+            .await
+            .context("failed to get queued messages")?;
+
+        messages::Entity::delete_many()
+            .filter(messages::Column::Client.eq(client))
+            .exec(&self.connection)
+            .await
+            .context("failed to delete queued messages")?;
+
+        let mut queued_messages = Vec::new();
+
+        for model in models {
+            match Self::restore_message(model) {
+                Ok(queued_message) => queued_messages.push(queued_message),
+                Err(e) => e.context("failed to restore queued message").trace(),
+            }
+        }
+
+        Ok(queued_messages)
+    }
+
+    fn restore_message(
+        messages::Model {
+            message_type,
+            target_message_id,
+            chat_hex,
+            parse_mode,
+            text,
+            ..
+        }: messages::Model,
+    ) -> Result<QueuedMessage> {
+        let message_type = match (message_type.as_str(), target_message_id) {
+            ("respond", _) => QueuedMessageType::Respond,
+            ("reply", Some(message_id)) => QueuedMessageType::Reply(message_id),
+            ("edit", Some(message_id)) => QueuedMessageType::Edit(message_id),
+            _ => return Err(anyhow!("invalid queued message type: {}", message_type)),
+        };
+
+        let content = match parse_mode.as_str() {
+            "text" => MessageContent::Text(text),
+            "html" => MessageContent::Html(text),
+            "markdown" => MessageContent::Markdown(text),
+            _ => return Err(anyhow!("invalid queued message parse mode: {}", parse_mode)),
+        };
+
+        let chat = chat_from_hex(&chat_hex)?;
+
+        Ok(QueuedMessage::detached(message_type, content, chat))
+    }
+}
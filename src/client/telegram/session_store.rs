@@ -0,0 +1,146 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::sessions;
+use crate::crypto::{decrypt, encrypt};
+use anyhow::{anyhow, Context, Result};
+use sea_orm::{
+    sea_query::OnConflict, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait, Schema,
+    Set,
+};
+use std::path::Path;
+use tokio::fs;
+
+// telegram sessions of both clients, stored in database instead of separate files
+pub struct SessionStore {
+    connection: DatabaseConnection,
+    key: Option<String>,
+}
+
+impl SessionStore {
+    pub async fn new(session_path: &str, key: Option<String>) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self { connection, key })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to telegram session")?;
+
+        Self::create_table_if_not_exists(&connection).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        if !Self::is_table_exists(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement =
+                Schema::new(backend).create_table_from_entity(sessions::Entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!(
+                    "failed to create table {}",
+                    sessions::Entity.table_name()
+                ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
+        let result = sessions::Entity::find().all(connection).await;
+
+        result.is_ok()
+    }
+
+    // returns the serialized session, migrated from the legacy session file if not stored yet
+    pub async fn load(&self, client: &str, legacy_path: &str) -> Result<Option<Vec<u8>>> {
+        let model = sessions::Entity::find_by_id(client)
+            .one(&self.connection)
+            .await
+            .context("failed to query telegram session")?;
+
+        if let Some(sessions::Model {
+            data, encrypted, ..
+        }) = model
+        {
+            let data = if encrypted {
+                let key = self.key.as_ref().ok_or_else(|| {
+                    anyhow!(
+                        "telegram {} session is encrypted, session_key is required",
+                        client
+                    )
+                })?;
+
+                decrypt(key, &data)
+                    .context(format!("failed to decrypt telegram {} session", client))?
+            } else {
+                data
+            };
+
+            // encrypt the session once session_key is set
+            if !encrypted && self.key.is_some() {
+                self.save(client, &data).await?;
+            }
+
+            return Ok(Some(data));
+        }
+
+        if Path::new(legacy_path).exists() {
+            tracing::info!("migrating telegram {} session from {}", client, legacy_path);
+
+            let data = fs::read(legacy_path)
+                .await
+                .context(format!("failed to read legacy telegram {} session", client))?;
+
+            self.save(client, &data).await?;
+
+            // the plaintext file would outlive session_key, and it can't diverge from the database then
+            fs::remove_file(legacy_path).await.context(format!(
+                "failed to remove legacy telegram {} session",
+                client
+            ))?;
+
+            return Ok(Some(data));
+        }
+
+        Ok(None)
+    }
+
+    pub async fn save(&self, client: &str, data: &[u8]) -> Result<()> {
+        let (data, encrypted) = match &self.key {
+            Some(key) => (encrypt(key, data)?, true),
+            None => (data.to_vec(), false),
+        };
+
+        let insert_item = sessions::ActiveModel {
+            client: Set(client.to_string()),
+            data: Set(data),
+            encrypted: Set(encrypted),
+        };
+
+        sessions::Entity::insert(insert_item)
+            .on_conflict(
+                OnConflict::column(sessions::Column::Client)
+                    .update_columns([sessions::Column::Data, sessions::Column::Encrypted])
+                    .to_owned(),
+            )
+            .exec(&self.connection)
+            .await
+            .context(format!("failed to save telegram {} session", client))?;
+
+        tracing::debug!("saved telegram {} session", client);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,418 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::message::{MessageContent, OutgoingMessage};
+
+// telegram limits the text length after entities parsing, counted in utf-16 code units
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+
+const TRUNCATION_MARK: &str = "…";
+
+enum Token<'a> {
+    Open { name: &'a str, raw: &'a str },
+    Close { name: &'a str, raw: &'a str },
+    // tags like <br/> which don't need to be closed
+    Void(&'a str),
+    // entities, escaped chars and links which can't be split
+    Atom { raw: &'a str, len: usize },
+    Char(char),
+}
+
+impl Token<'_> {
+    // visible length after entities parsing
+    const fn len(&self) -> usize {
+        match self {
+            Self::Open { .. } | Self::Close { .. } | Self::Void(_) => 0,
+            Self::Atom { len, .. } => *len,
+            Self::Char(c) => c.len_utf16(),
+        }
+    }
+}
+
+// name and raw opening tag of the tags which are not closed yet
+type TagStack<'a> = Vec<(&'a str, &'a str)>;
+
+struct Splitter<'a> {
+    is_html: bool,
+    max_length: usize,
+    chunks: Vec<String>,
+    buffer: String,
+    buffer_len: usize,
+    stack: TagStack<'a>,
+    // position right after the last newline, which is the preferred place to split
+    break_point: Option<(usize, usize, TagStack<'a>)>,
+}
+
+impl<'a> Splitter<'a> {
+    const fn new(is_html: bool, max_length: usize) -> Self {
+        Self {
+            is_html,
+            max_length,
+            chunks: Vec::new(),
+            buffer: String::new(),
+            buffer_len: 0,
+            stack: Vec::new(),
+            break_point: None,
+        }
+    }
+
+    fn push(&mut self, token: &Token<'a>) {
+        let token_len = token.len();
+
+        while token_len > 0 && self.buffer_len + token_len > self.max_length && self.buffer_len > 0
+        {
+            self.split();
+        }
+
+        match *token {
+            Token::Open { name, raw } => {
+                self.buffer.push_str(raw);
+                self.stack.push((name, raw));
+            }
+            Token::Close { name, raw } => {
+                self.buffer.push_str(raw);
+
+                if let Some(index) = self.stack.iter().rposition(|(open, _)| *open == name) {
+                    self.stack.remove(index);
+                }
+            }
+            Token::Void(raw) | Token::Atom { raw, .. } => self.buffer.push_str(raw),
+            Token::Char(c) => {
+                self.buffer.push(c);
+
+                if c == '\n' {
+                    self.break_point =
+                        Some((self.buffer.len(), self.buffer_len + 1, self.stack.clone()));
+                }
+            }
+        }
+
+        self.buffer_len += token_len;
+    }
+
+    fn split(&mut self) {
+        let (position, len, stack) = match self.break_point.take() {
+            Some(break_point) if break_point.1 > 0 => break_point,
+            _ => (self.buffer.len(), self.buffer_len, self.stack.clone()),
+        };
+
+        let rest = self.buffer.split_off(position);
+
+        let mut chunk = std::mem::take(&mut self.buffer);
+        for (name, _) in stack.iter().rev() {
+            if self.is_html {
+                chunk.push_str(&format!("</{}>", name));
+            } else {
+                // markdown markers are closed by themselves
+                chunk.push_str(name);
+            }
+        }
+        self.chunks.push(chunk);
+
+        // reopen the tags which are split into the next chunk
+        for (_, raw) in &stack {
+            self.buffer.push_str(raw);
+        }
+        self.buffer.push_str(&rest);
+        self.buffer_len -= len;
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        if self.buffer_len > 0 {
+            self.chunks.push(self.buffer);
+        }
+
+        self.chunks
+    }
+}
+
+fn tokenize_html(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+
+    while let Some(c) = rest.chars().next() {
+        let end = match c {
+            '<' => rest.find('>').map(|index| index + 1),
+            '&' => rest
+                .find(';')
+                .filter(|index| {
+                    rest[1..*index]
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '#')
+                })
+                .map(|index| index + 1),
+            _ => None,
+        };
+
+        if let Some(end) = end {
+            let raw = &rest[..end];
+
+            let token = if c == '&' {
+                Token::Atom { raw, len: 1 }
+            } else if raw.ends_with("/>") {
+                Token::Void(raw)
+            } else {
+                let inner = raw.trim_start_matches('<').trim_end_matches('>');
+                let is_close = inner.starts_with('/');
+                let name = inner
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace())
+                    .next()
+                    .unwrap_or_default();
+
+                if is_close {
+                    Token::Close { name, raw }
+                } else {
+                    Token::Open { name, raw }
+                }
+            };
+
+            tokens.push(token);
+            rest = &rest[end..];
+        } else {
+            tokens.push(Token::Char(c));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    tokens
+}
+
+fn tokenize_markdown(markdown: &str) -> Vec<Token<'_>> {
+    // longer markers first
+    const MARKERS: [&str; 7] = ["```", "**", "__", "~~", "`", "*", "_"];
+
+    let mut tokens = Vec::new();
+    let mut stack = Vec::new();
+    let mut rest = markdown;
+
+    while let Some(c) = rest.chars().next() {
+        let token = match stack.last().copied() {
+            // code is literal until it is closed
+            Some(marker @ ("```" | "`")) => rest.starts_with(marker).then(|| {
+                stack.pop();
+
+                (
+                    Token::Close {
+                        name: marker,
+                        raw: marker,
+                    },
+                    marker.len(),
+                )
+            }),
+            _ if c == '\\' => rest[1..].chars().next().map(|escaped| {
+                let end = 1 + escaped.len_utf8();
+
+                (
+                    Token::Atom {
+                        raw: &rest[..end],
+                        len: escaped.len_utf16(),
+                    },
+                    end,
+                )
+            }),
+            _ if c == '[' => find_markdown_link(rest).map(|(end, len)| {
+                (
+                    Token::Atom {
+                        raw: &rest[..end],
+                        len,
+                    },
+                    end,
+                )
+            }),
+            last => MARKERS
+                .iter()
+                .find(|marker| rest.starts_with(**marker))
+                .map(|&marker| {
+                    if last == Some(marker) {
+                        stack.pop();
+
+                        (
+                            Token::Close {
+                                name: marker,
+                                raw: marker,
+                            },
+                            marker.len(),
+                        )
+                    } else {
+                        stack.push(marker);
+
+                        // the language of a code block is part of its opening marker
+                        let end = if marker == "```" {
+                            rest.find('\n').map_or(rest.len(), |index| index + 1)
+                        } else {
+                            marker.len()
+                        };
+
+                        (
+                            Token::Open {
+                                name: marker,
+                                raw: &rest[..end],
+                            },
+                            end,
+                        )
+                    }
+                }),
+        };
+
+        if let Some((token, end)) = token {
+            tokens.push(token);
+            rest = &rest[end..];
+        } else {
+            tokens.push(Token::Char(c));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    tokens
+}
+
+// returns the end of [text](url) and the visible length of text
+fn find_markdown_link(markdown: &str) -> Option<(usize, usize)> {
+    let text_end = markdown.find("](")?;
+    let text = &markdown[1..text_end];
+
+    let mut escaped = false;
+    let url_end = markdown[text_end..].char_indices().find_map(|(index, c)| {
+        let is_end = c == ')' && !escaped;
+        escaped = c == '\\' && !escaped;
+
+        is_end.then_some(text_end + index)
+    })?;
+
+    let len = text
+        .chars()
+        .filter(|c| *c != '\\')
+        .map(char::len_utf16)
+        .sum();
+
+    Some((url_end + 1, len))
+}
+
+fn split_content(content: &MessageContent, max_length: usize) -> Vec<String> {
+    let (text, tokens): (&String, Vec<Token>) = match content {
+        MessageContent::Text(text) => (text, text.chars().map(Token::Char).collect()),
+        MessageContent::Html(html) => (html, tokenize_html(html)),
+        MessageContent::Markdown(markdown) => (markdown, tokenize_markdown(markdown)),
+    };
+
+    // nothing to split
+    if text.encode_utf16().count() <= max_length {
+        return vec![text.clone()];
+    }
+
+    let mut splitter = Splitter::new(matches!(content, MessageContent::Html(_)), max_length);
+
+    for token in &tokens {
+        splitter.push(token);
+    }
+
+    splitter.finish()
+}
+
+impl MessageContent {
+    // split into several parts without breaking any tag, entity or markdown marker
+    fn split(self, max_length: usize) -> Vec<Self> {
+        let chunks = split_content(&self, max_length);
+
+        chunks
+            .into_iter()
+            .map(|chunk| self.with_text(chunk))
+            .collect()
+    }
+
+    // keep the first part only, used by edits which can't be split into several messages
+    fn truncate(self, max_length: usize) -> Self {
+        let max_length = max_length.saturating_sub(TRUNCATION_MARK.encode_utf16().count());
+
+        let mut chunks = split_content(&self, max_length);
+
+        if chunks.len() > 1 {
+            self.with_text(chunks.swap_remove(0) + TRUNCATION_MARK)
+        } else {
+            self
+        }
+    }
+
+    const fn with_text(&self, text: String) -> Self {
+        match self {
+            Self::Text(_) => Self::Text(text),
+            Self::Html(_) => Self::Html(text),
+            Self::Markdown(_) => Self::Markdown(text),
+        }
+    }
+}
+
+impl OutgoingMessage {
+    // messages without text content are kept as they are
+    pub fn split(self) -> Vec<Self> {
+        let Some(content) = self.content.clone() else {
+            return vec![self];
+        };
+
+        let contents = content.split(MAX_MESSAGE_LENGTH);
+
+        if contents.len() > 1 {
+            contents.into_iter().map(Self::from).collect()
+        } else {
+            vec![self]
+        }
+    }
+
+    pub fn truncate(self) -> Self {
+        match self.content {
+            Some(content) => Self::from(content.truncate(MAX_MESSAGE_LENGTH)),
+            None => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_html() {
+        let html = MessageContent::Html("<b>first line</b>\n<code>second line</code>".to_string());
+
+        let chunks = split_content(&html, 15);
+
+        assert_eq!(
+            chunks,
+            vec!["<b>first line</b>\n", "<code>second line</code>"]
+        );
+
+        let html = MessageContent::Html("<b>bold &amp; long text</b>".to_string());
+
+        let chunks = split_content(&html, 10);
+
+        assert_eq!(chunks, vec!["<b>bold &amp; lon</b>", "<b>g text</b>"]);
+    }
+
+    #[test]
+    fn test_split_markdown() {
+        let markdown =
+            MessageContent::Markdown("```\nfirst\nsecond\n```\n[a\\_b](url)".to_string());
+
+        let chunks = split_content(&markdown, 8);
+
+        assert_eq!(
+            chunks,
+            vec!["```\nfirst\n```", "```\nsecond\n```\n", "[a\\_b](url)"]
+        );
+    }
+
+    #[test]
+    fn test_split_text() {
+        let text = MessageContent::Text("a".repeat(MAX_MESSAGE_LENGTH + 1));
+
+        let chunks = split_content(&text, MAX_MESSAGE_LENGTH);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1], "a");
+    }
+}
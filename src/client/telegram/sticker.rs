@@ -0,0 +1,91 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::TelegramClient;
+use anyhow::{anyhow, Context, Result};
+use grammers_client::{grammers_tl_types as tl, types::Downloadable};
+
+pub struct StickerSet {
+    pub title: String,
+    pub short_name: String,
+    pub stickers: Vec<Sticker>,
+}
+
+// raw document of a sticker in a set, which isn't attached to any message
+pub struct Sticker(tl::types::Document);
+
+impl Sticker {
+    pub const fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    pub fn mime_type(&self) -> &str {
+        &self.0.mime_type
+    }
+
+    pub const fn size(&self) -> i64 {
+        self.0.size
+    }
+}
+
+impl Downloadable for Sticker {
+    fn to_raw_input_location(&self) -> Option<tl::enums::InputFileLocation> {
+        Some(
+            tl::types::InputDocumentFileLocation {
+                id: self.0.id,
+                access_hash: self.0.access_hash,
+                file_reference: self.0.file_reference.clone(),
+                thumb_size: String::new(),
+            }
+            .into(),
+        )
+    }
+}
+
+impl TelegramClient {
+    pub async fn get_sticker_set(&self, short_name: &str) -> Result<StickerSet> {
+        let sticker_set = self
+            .raw()
+            .invoke(&tl::functions::messages::GetStickerSet {
+                stickerset: tl::types::InputStickerSetShortName {
+                    short_name: short_name.to_string(),
+                }
+                .into(),
+                hash: 0,
+            })
+            .await
+            .context("failed to get sticker set")
+            .context(short_name.to_string())?;
+
+        let tl::enums::messages::StickerSet::Set(sticker_set) = sticker_set else {
+            return Err(anyhow!("sticker set not modified"));
+        };
+
+        let tl::enums::StickerSet::Set(set) = sticker_set.set;
+
+        let stickers = sticker_set
+            .documents
+            .into_iter()
+            .filter_map(|document| match document {
+                tl::enums::Document::Document(document) => Some(Sticker(document)),
+                tl::enums::Document::Empty(_) => None,
+            })
+            .collect::<Vec<Sticker>>();
+
+        tracing::debug!(
+            "got sticker set {} with {} stickers",
+            set.short_name,
+            stickers.len()
+        );
+
+        Ok(StickerSet {
+            title: set.title,
+            short_name: set.short_name,
+            stickers,
+        })
+    }
+}
@@ -0,0 +1,163 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hmac, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{fmt::Write, num::NonZeroU32};
+
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+// stored strings starting with it are encrypted with session_key
+pub const ENCRYPTED_PREFIX: &str = "encrypted:";
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey> {
+    let mut key = [0; 32];
+
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+
+    let unbound_key =
+        UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow!("failed to create aead key"))?;
+
+    Ok(LessSafeKey::new(unbound_key))
+}
+
+// salt, nonce and sealed data are stored together
+pub fn encrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let rng = SystemRandom::new();
+
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|()| rng.fill(&mut nonce))
+        .map_err(|_| anyhow!("failed to generate random salt and nonce"))?;
+
+    let key = derive_key(passphrase, &salt)?;
+
+    let mut sealed = data.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut sealed,
+    )
+    .map_err(|_| anyhow!("failed to encrypt data"))?;
+
+    Ok([salt.as_slice(), nonce.as_slice(), sealed.as_slice()].concat())
+}
+
+pub fn decrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < SALT_LEN + NONCE_LEN {
+        return Err(anyhow!("encrypted data is too short"));
+    }
+
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt)?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("invalid nonce"))?;
+
+    let mut sealed = sealed.to_vec();
+    let data = key
+        .open_in_place(nonce, Aad::empty(), &mut sealed)
+        .map_err(|_| anyhow!("wrong session_key or corrupted data"))?;
+
+    Ok(data.to_vec())
+}
+
+// kept as it is without a key, what names the string in errors
+pub fn seal_string(key: Option<&str>, plain: &str, what: &str) -> Result<String> {
+    let Some(key) = key else {
+        return Ok(plain.to_string());
+    };
+
+    let sealed = encrypt(key, plain.as_bytes()).context(format!("failed to encrypt {}", what))?;
+
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed)))
+}
+
+// strings stored before session_key is set are read as they are
+pub fn open_string(key: Option<&str>, stored: &str, what: &str) -> Result<String> {
+    let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(stored.to_string());
+    };
+
+    let key = key.ok_or_else(|| anyhow!("the {} is encrypted, session_key is required", what))?;
+
+    let sealed = STANDARD
+        .decode(encoded)
+        .context(format!("failed to decode encrypted {}", what))?;
+
+    String::from_utf8(decrypt(key, &sealed)?).context(format!("{} is not valid utf-8", what))
+}
+
+// like $tenant_id.$signature, only the holder of the key can issue it
+pub fn derive_tenant_token(key: &str, tenant_id: i64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes());
+    let tag = hmac::sign(&key, tenant_id.to_string().as_bytes());
+
+    tag.as_ref()
+        .iter()
+        .fold(format!("{}.", tenant_id), |mut token, byte| {
+            let _ = write!(token, "{:02x}", byte);
+
+            token
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let data = b"session data";
+
+        let encrypted = encrypt("passphrase", data).unwrap();
+
+        assert_ne!(encrypted.as_slice(), data.as_slice());
+        assert_eq!(decrypt("passphrase", &encrypted).unwrap(), data);
+        assert!(decrypt("wrong passphrase", &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_seal_string() {
+        let sealed = seal_string(Some("passphrase"), "secret", "secret").unwrap();
+
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+        assert_eq!(
+            open_string(Some("passphrase"), &sealed, "secret").unwrap(),
+            "secret"
+        );
+        assert!(open_string(None, &sealed, "secret").is_err());
+        assert!(open_string(Some("wrong passphrase"), &sealed, "secret").is_err());
+
+        // kept as it is without session_key
+        assert_eq!(seal_string(None, "secret", "secret").unwrap(), "secret");
+        assert_eq!(open_string(None, "secret", "secret").unwrap(), "secret");
+    }
+
+    #[test]
+    fn test_derive_tenant_token() {
+        let token = derive_tenant_token("key", 42);
+
+        assert!(token.starts_with("42."));
+        assert_eq!(token.len(), "42.".len() + 64);
+        assert_eq!(token, derive_tenant_token("key", 42));
+        assert_ne!(token, derive_tenant_token("other key", 42));
+    }
+}
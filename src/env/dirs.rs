@@ -0,0 +1,65 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::ENV;
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+
+// warned below it, sessions and logs are small but sqlite fails once the disk is full
+const MIN_FREE_SPACE: u64 = 100 * 1024 * 1024;
+
+// at startup, so that a read-only or full volume fails early instead of in the middle of tasks
+pub fn check_dirs() -> Result<()> {
+    let env = ENV.get().unwrap();
+
+    let temp_quota = env.temp_quota.map(|quota| quota * 1024 * 1024);
+
+    for (name, dir, min_free_space) in [
+        ("session_dir", &env.session_dir, MIN_FREE_SPACE),
+        (
+            "temp_dir",
+            &env.temp_dir,
+            temp_quota.unwrap_or(MIN_FREE_SPACE),
+        ),
+        ("logs_dir", &env.logs_dir, MIN_FREE_SPACE),
+    ] {
+        check_writable(dir).context(format!("{} {} is not writable", name, dir))?;
+
+        let free_space = fs4::available_space(dir)
+            .context(format!("failed to get free space of {} {}", name, dir))?;
+
+        if free_space < min_free_space {
+            tracing::warn!(
+                "{} {} has only {:.2}MB free, at least {:.2}MB is recommended",
+                name,
+                dir,
+                free_space as f64 / 1024.0 / 1024.0,
+                min_free_space as f64 / 1024.0 / 1024.0
+            );
+        } else {
+            tracing::debug!(
+                "{} {} has {:.2}MB free",
+                name,
+                dir,
+                free_space as f64 / 1024.0 / 1024.0
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn check_writable(dir: &str) -> Result<()> {
+    fs::create_dir_all(dir).context("failed to create dir")?;
+
+    let probe_path = Path::new(dir).join(".write-test");
+
+    fs::write(&probe_path, b"").context("failed to write file")?;
+    fs::remove_file(&probe_path).context("failed to remove file")?;
+
+    Ok(())
+}
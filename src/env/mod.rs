@@ -5,6 +5,7 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod dirs;
 mod onedrive;
 mod telegram_bot;
 mod telegram_user;
@@ -12,15 +13,24 @@ mod utils;
 mod var;
 
 use anyhow::Context;
-pub use onedrive::OneDriveEnv;
-use std::{fs, sync::OnceLock};
+pub use dirs::check_dirs;
+pub use onedrive::{OneDriveAppEnv, OneDriveEnv};
+use std::{collections::HashMap, fs, net::IpAddr, sync::OnceLock};
 pub use telegram_bot::TelegramBotEnv;
 pub use telegram_user::TelegramUserEnv;
-use utils::{get_env_value, get_env_value_option, get_env_value_option_legacy};
-pub use var::LOGS_PATH;
-use var::SESSION_DIR;
+use utils::{get_env_value, get_env_value_option, get_env_value_option_legacy, join_path};
+pub use var::OD_TENANT_SESSION_PREFIX;
 
-use crate::error::ResultExt;
+use crate::{
+    auth_server::parse_trusted_proxies,
+    client::onedrive::parse_rate,
+    error::ResultExt,
+    features::{parse_features, Feature},
+    listener::parse_cooldowns,
+    message::ParseMode,
+    scan::ScanPolicy,
+    settings::{ConflictPolicy, PathTemplate, ShareLink},
+};
 
 pub static ENV: OnceLock<Env> = OnceLock::new();
 
@@ -32,26 +42,185 @@ pub struct Env {
     pub port: u16,
     pub server_uri: String,
     pub use_reverse_proxy: bool,
+    // forwarded headers are only read from these, so that the client ip can't be forged
+    pub trusted_proxies: Vec<IpAddr>,
+    // existing cert and key for the auth server, or a dir containing them
+    pub ssl_cert_path: Option<String>,
+    pub ssl_key_path: Option<String>,
+    pub ssl_dir: Option<String>,
+    // the web server is only started when the token is set
+    pub web_port: u16,
+    pub web_token: Option<String>,
     pub should_auto_delete: bool,
+    pub parse_mode: ParseMode,
+    // on a dedicated volume or tmpfs if needed, the session dir also keeps the tasks
+    pub session_dir: String,
+    // downloaded and processed files of tasks, like videos to remux
+    pub temp_dir: String,
+    pub logs_dir: String,
     pub tasker_session_path: String,
+    pub message_session_path: String,
+    pub settings_session_path: String,
+    pub usage_session_path: String,
+    pub history_session_path: String,
+    pub audit_session_path: String,
+    pub telegram_session_path: String,
+    pub tasks_import_path: String,
+    // self-destructing media buffered until its task is done, kept across restarts
+    pub ttl_buffer_dir: String,
+    // passphrase to encrypt telegram sessions
+    pub session_key: Option<String>,
     pub task_handler_num: u8,
+    // tasks uploading to onedrive at once, telegram downloads are still limited by task_handler_num
+    pub upload_sessions: usize,
+    // remux videos with ffmpeg so that they can be streamed in the onedrive web player
+    pub faststart: bool,
+    pub ffmpeg_path: String,
+    // reads the duration and resolution of fetched videos and audios
+    pub ffprobe_path: String,
+    // fill id3 tags of mp3 files from telegram attributes and the caption
+    pub audio_tags: bool,
+    // command converting animated stickers into gif, called with the input and output paths
+    pub tgs_converter_path: Option<String>,
+    // soft caps in MB, the queue is paused once any account exceeds them
+    pub daily_cap: Option<u64>,
+    pub monthly_cap: Option<u64>,
+    // switch to another onedrive account if the current one doesn't have enough quota
+    pub quota_auto_switch: bool,
+    // correct extensions of telegram files contradicting their mime types
+    pub fix_extension: bool,
+    // in MB, limits local files of each chat, like videos downloaded for remuxing
+    pub temp_quota: Option<u64>,
+    // each telegram user authorizes their own onedrive accounts, and only sees their own tasks
+    pub multi_tenant: bool,
+    // default of /conflict, for files with the same name existing in the directory
+    pub conflict_policy: ConflictPolicy,
+    // default of /share, for the link posted after a file is uploaded
+    pub share_link: ShareLink,
+    // tasks are queued but never started, and commands deleting anything are refused
+    pub read_only: bool,
+    // each chat uploads into a folder named after it, and /dir sets the directory of the chat
+    pub chat_dir: bool,
+    // files in the directory of each account older than it in days are moved into Archive/$year
+    pub archive_days: Option<u64>,
+    // default of /template, the path of each file in the directory
+    pub path_template: Option<PathTemplate>,
+    // files are nested into $year/$month folders in the directory, before the folders of the template
+    pub date_dir: bool,
+    // items deleted by /rm are kept in the trash for it in days, deleted at once if 0
+    pub trash_days: u64,
+    // number of uploaded files whose hashes are checked again every day
+    pub verify_samples: Option<u64>,
+    // optional subsystems turned off even if configured, like ffmpeg or gallery
+    pub disabled_features: Vec<Feature>,
+    // in bytes per second, shared by all uploads to onedrive, changed by /limit until restarting
+    pub upload_limit: Option<u64>,
+    // clamd unix socket or host:port, telegram files are scanned for malware if set
+    pub clamd_socket: Option<String>,
+    // in MB, larger files aren't scanned
+    pub scan_max_size: u64,
+    pub scan_policy: ScanPolicy,
+    // infected files are uploaded to it with the quarantine policy
+    pub quarantine_path: String,
+    // shell command run on each telegram file before uploading, which may replace or veto it
+    pub pre_upload_hook: Option<String>,
+    // shell command run after each upload, with the file in env values like TG_ONEDRIVE_PATH
+    pub post_upload_hook: Option<String>,
+    // url receiving the same values as json after each upload
+    pub post_upload_webhook: Option<String>,
+    // in seconds, the same command sent again by the sender within it is dropped, 0 to turn it off
+    pub debounce_window: u64,
+    // in seconds by command, like /links, which can't be run again in the chat before it passes
+    pub command_cooldowns: HashMap<String, u64>,
+    // the caption of each telegram file is also uploaded next to it as $filename.txt
+    pub caption_sidecar: bool,
 }
 
 impl Env {
     pub fn new() -> Self {
-        Self::init();
+        let session_dir = get_env_value_option("session_dir", var::SESSION_DIR.to_string());
+        let temp_dir = get_env_value_option("temp_dir", var::TEMP_DIR.to_string());
+        let logs_dir = get_env_value_option("logs_dir", var::LOGS_DIR.to_string());
 
-        let telegram_bot = TelegramBotEnv::new();
-        let telegram_user = TelegramUserEnv::new();
-        let onedrive = OneDriveEnv::new();
+        Self::init(&session_dir);
+
+        let telegram_bot = TelegramBotEnv::new(&session_dir);
+        let telegram_user = TelegramUserEnv::new(&session_dir);
+        let onedrive = OneDriveEnv::new(&session_dir);
         let trace_level = get_env_value_option("trace_level", "info".to_string());
         let port = get_env_value_option("port", 8080);
         let server_uri = get_env_value("server_uri").unwrap_or_trace();
         let use_reverse_proxy = get_env_value_option("reverse_proxy", false);
+        let trusted_proxies = get_env_value::<String>("trusted_proxies")
+            .map_or(Ok(Vec::new()), |proxies| parse_trusted_proxies(&proxies))
+            .context("invalid trusted_proxies")
+            .unwrap_or_trace();
+        let ssl_cert_path = get_env_value("ssl_cert").ok();
+        let ssl_key_path = get_env_value("ssl_key").ok();
+        let ssl_dir = get_env_value("ssl_dir").ok();
+        let web_port = get_env_value_option("web_port", 8081);
+        let web_token = get_env_value("web_token").ok();
         let should_auto_delete =
             get_env_value_option_legacy(&["auto_delete", "delete_flag"], false);
-        let tasker_session_path = var::TASKER_SESSION_PATH.to_string();
+        let parse_mode = get_env_value_option("parse_mode", ParseMode::Html);
+        let tasker_session_path = join_path(&session_dir, var::TASKER_SESSION_NAME);
+        let message_session_path = join_path(&session_dir, var::MESSAGE_SESSION_NAME);
+        let settings_session_path = join_path(&session_dir, var::SETTINGS_SESSION_NAME);
+        let usage_session_path = join_path(&session_dir, var::USAGE_SESSION_NAME);
+        let history_session_path = join_path(&session_dir, var::HISTORY_SESSION_NAME);
+        let audit_session_path = join_path(&session_dir, var::AUDIT_SESSION_NAME);
+        let telegram_session_path = join_path(&session_dir, var::TELEGRAM_SESSION_NAME);
+        let tasks_import_path = join_path(&session_dir, var::TASKS_IMPORT_NAME);
+        let ttl_buffer_dir = join_path(&session_dir, var::TTL_BUFFER_DIR_NAME);
+        let session_key = get_env_value("session_key").ok();
         let task_handler_num = get_env_value_option("worker_num", 5);
+        let upload_sessions = get_env_value_option("upload_sessions", task_handler_num as usize);
+        let faststart = get_env_value_option("faststart", false);
+        let ffmpeg_path = get_env_value_option("ffmpeg_path", "ffmpeg".to_string());
+        let ffprobe_path = get_env_value_option("ffprobe_path", "ffprobe".to_string());
+        let audio_tags = get_env_value_option("audio_tags", false);
+        let tgs_converter_path = get_env_value("tgs_converter_path").ok();
+        let daily_cap = get_env_value("daily_cap").ok();
+        let monthly_cap = get_env_value("monthly_cap").ok();
+        let quota_auto_switch = get_env_value_option("quota_auto_switch", false);
+        let fix_extension = get_env_value_option("fix_extension", false);
+        let temp_quota = get_env_value("temp_quota").ok();
+        let multi_tenant = get_env_value_option("multi_tenant", false);
+        let conflict_policy = get_env_value_option("conflict_policy", ConflictPolicy::Rename);
+        let share_link = get_env_value_option("share_link", ShareLink::Off);
+        let read_only = get_env_value_option("read_only", false);
+        let chat_dir = get_env_value_option("chat_dir", false);
+        let archive_days = get_env_value("archive_days").ok();
+        let path_template = get_env_value::<String>("path_template")
+            .ok()
+            .and_then(|path_template| path_template.parse().ok());
+        let date_dir = get_env_value_option("date_dir", false);
+        let trash_days = get_env_value_option("trash_days", 30);
+        let verify_samples = get_env_value("verify_samples").ok();
+        let disabled_features = get_env_value::<String>("disabled_features")
+            .map_or(Ok(Vec::new()), |features| parse_features(&features))
+            .context("invalid disabled_features")
+            .unwrap_or_trace();
+        let upload_limit = get_env_value::<String>("upload_limit")
+            .map_or(Ok(None), |upload_limit| parse_rate(&upload_limit))
+            .context("invalid upload_limit")
+            .unwrap_or_trace();
+        let clamd_socket = get_env_value("clamd_socket").ok();
+        let scan_max_size = get_env_value_option("scan_max_size", 25);
+        let scan_policy = get_env_value::<String>("scan_policy")
+            .map_or(Ok(ScanPolicy::Skip), |scan_policy| scan_policy.parse())
+            .context("invalid scan_policy")
+            .unwrap_or_trace();
+        let quarantine_path = get_env_value_option("quarantine_path", "/Quarantine".to_string());
+        let pre_upload_hook = get_env_value("pre_upload_hook").ok();
+        let post_upload_hook = get_env_value("post_upload_hook").ok();
+        let post_upload_webhook = get_env_value("post_upload_webhook").ok();
+        let debounce_window = get_env_value_option("debounce_window", 3);
+        let command_cooldowns = get_env_value::<String>("command_cooldowns")
+            .map_or(Ok(HashMap::new()), |cooldowns| parse_cooldowns(&cooldowns))
+            .context("invalid command_cooldowns")
+            .unwrap_or_trace();
+        let caption_sidecar = get_env_value_option("caption_sidecar", false);
 
         Self {
             telegram_bot,
@@ -61,14 +230,72 @@ impl Env {
             port,
             server_uri,
             use_reverse_proxy,
+            trusted_proxies,
+            ssl_cert_path,
+            ssl_key_path,
+            ssl_dir,
+            web_port,
+            web_token,
             should_auto_delete,
+            parse_mode,
+            session_dir,
+            temp_dir,
+            logs_dir,
             tasker_session_path,
+            message_session_path,
+            settings_session_path,
+            usage_session_path,
+            history_session_path,
+            audit_session_path,
+            telegram_session_path,
+            tasks_import_path,
+            ttl_buffer_dir,
+            session_key,
             task_handler_num,
+            upload_sessions,
+            faststart,
+            ffmpeg_path,
+            ffprobe_path,
+            audio_tags,
+            tgs_converter_path,
+            daily_cap,
+            monthly_cap,
+            quota_auto_switch,
+            fix_extension,
+            temp_quota,
+            multi_tenant,
+            conflict_policy,
+            share_link,
+            read_only,
+            chat_dir,
+            archive_days,
+            path_template,
+            date_dir,
+            trash_days,
+            verify_samples,
+            disabled_features,
+            upload_limit,
+            clamd_socket,
+            scan_max_size,
+            scan_policy,
+            quarantine_path,
+            pre_upload_hook,
+            post_upload_hook,
+            post_upload_webhook,
+            debounce_window,
+            command_cooldowns,
+            caption_sidecar,
         }
     }
 
-    fn init() {
-        fs::create_dir_all(SESSION_DIR)
+    // a file in the session dir, like the session of a tenant
+    pub fn session_file(&self, name: &str) -> String {
+        join_path(&self.session_dir, name)
+    }
+
+    // the temp and logs dirs are created when used
+    fn init(session_dir: &str) {
+        fs::create_dir_all(session_dir)
             .context("failed to create session dir")
             .unwrap_or_trace();
     }
@@ -5,11 +5,14 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::error::ResultExt;
+use crate::{
+    client::{AppTarget, NationalCloud, OneDriveScope},
+    error::ResultExt,
+};
 
 use super::{
-    utils::{get_env_value, get_env_value_option_legacy},
-    var::OD_SESSION_PATH,
+    utils::{get_env_value, get_env_value_option, get_env_value_option_legacy, join_path},
+    var::OD_SESSION_NAME,
 };
 
 pub struct OneDriveEnv {
@@ -17,21 +20,66 @@ pub struct OneDriveEnv {
     pub client_secret: String,
     pub root_path: String,
     pub session_path: String,
+    pub scope: OneDriveScope,
+    pub cloud: NationalCloud,
+    pub app: Option<OneDriveAppEnv>,
+}
+
+// app-only mode, authorized with client credentials instead of interactive consent
+pub struct OneDriveAppEnv {
+    pub tenant_id: String,
+    pub target: AppTarget,
+    // certificate credential, client secret is used if not set
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+}
+
+impl OneDriveAppEnv {
+    fn new() -> Option<Self> {
+        // app-only mode is enabled by setting the target
+        let target = get_env_value::<String>("od_app_target")
+            .ok()?
+            .parse::<AppTarget>()
+            .unwrap_or_trace();
+        let tenant_id = get_env_value("od_tenant_id").unwrap_or_trace();
+        let client_cert_path = get_env_value("od_client_cert").ok();
+        let client_key_path = get_env_value("od_client_key").ok();
+
+        Some(Self {
+            tenant_id,
+            target,
+            client_cert_path,
+            client_key_path,
+        })
+    }
 }
 
 impl OneDriveEnv {
-    pub fn new() -> Self {
+    pub fn new(session_dir: &str) -> Self {
         let client_id = get_env_value("od_client_id").unwrap_or_trace();
-        let client_secret = get_env_value("od_client_secret").unwrap_or_trace();
+        let app = OneDriveAppEnv::new();
+        // not needed in app-only mode with certificate credential
+        let client_secret = match &app {
+            Some(OneDriveAppEnv {
+                client_cert_path: Some(_),
+                ..
+            }) => get_env_value("od_client_secret").unwrap_or_default(),
+            _ => get_env_value("od_client_secret").unwrap_or_trace(),
+        };
         let root_path =
             get_env_value_option_legacy(&["od_root_path", "remote_root_path"], "/".to_string());
-        let session_path = OD_SESSION_PATH.to_string();
+        let session_path = join_path(session_dir, OD_SESSION_NAME);
+        let scope = get_env_value_option("od_scope", OneDriveScope::FilesAll);
+        let cloud = get_env_value_option("od_cloud", NationalCloud::Global);
 
         Self {
             client_id,
             client_secret,
             root_path,
             session_path,
+            scope,
+            cloud,
+            app,
         }
     }
 }
@@ -8,8 +8,8 @@
 use crate::error::ResultExt;
 
 use super::{
-    utils::get_env_value,
-    var::{RECONNECTION_POLICY, TG_BOT_SESSION_PATH},
+    utils::{get_env_value, join_path},
+    var::{RECONNECTION_POLICY, TG_BOT_SESSION_NAME, UPDATE_QUEUE_LIMIT},
 };
 
 pub struct TelegramBotEnv {
@@ -21,13 +21,14 @@ pub struct TelegramBotEnv {
 }
 
 impl TelegramBotEnv {
-    pub fn new() -> Self {
+    pub fn new(session_dir: &str) -> Self {
         let api_id = get_env_value("tg_api_id").unwrap_or_trace();
         let api_hash = get_env_value("tg_api_hash").unwrap_or_trace();
         let token = get_env_value("tg_bot_token").unwrap_or_trace();
-        let session_path = TG_BOT_SESSION_PATH.to_string();
+        let session_path = join_path(session_dir, TG_BOT_SESSION_NAME);
         let params = grammers_client::InitParams {
             reconnection_policy: &RECONNECTION_POLICY,
+            update_queue_limit: Some(UPDATE_QUEUE_LIMIT),
             ..Default::default()
         };
 
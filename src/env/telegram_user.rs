@@ -8,8 +8,8 @@
 use crate::error::ResultExt;
 
 use super::{
-    utils::get_env_value,
-    var::{RECONNECTION_POLICY, TG_USER_SESSION_PATH},
+    utils::{get_env_value, join_path},
+    var::{RECONNECTION_POLICY, TG_USER_SESSION_NAME, UPDATE_QUEUE_LIMIT},
 };
 
 pub struct TelegramUserEnv {
@@ -23,15 +23,16 @@ pub struct TelegramUserEnv {
 }
 
 impl TelegramUserEnv {
-    pub fn new() -> Self {
+    pub fn new(session_dir: &str) -> Self {
         let api_id = get_env_value("tg_api_id").unwrap_or_trace();
         let api_hash = get_env_value("tg_api_hash").unwrap_or_trace();
         let users = Self::parse_users();
         let phone_number = get_env_value("tg_user_phone").unwrap_or_trace();
         let password = get_env_value("tg_user_password").ok();
-        let session_path = TG_USER_SESSION_PATH.to_string();
+        let session_path = join_path(session_dir, TG_USER_SESSION_NAME);
         let params = grammers_client::InitParams {
             reconnection_policy: &RECONNECTION_POLICY,
+            update_queue_limit: Some(UPDATE_QUEUE_LIMIT),
             ..Default::default()
         };
 
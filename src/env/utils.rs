@@ -6,7 +6,7 @@
 */
 
 use anyhow::{Context, Result};
-use std::{env, str::FromStr};
+use std::{env, path::Path, str::FromStr};
 
 pub fn get_env_value<T>(name: &str) -> Result<T>
 where
@@ -48,3 +48,7 @@ where
 
     default
 }
+
+pub fn join_path(dir: &str, name: &str) -> String {
+    Path::new(dir).join(name).to_string_lossy().to_string()
+}
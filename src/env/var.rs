@@ -8,13 +8,32 @@
 use grammers_client::FixedReconnect;
 use std::time::Duration;
 
-pub const LOGS_PATH: &str = "./logs";
-
+// defaults of session_dir, temp_dir and logs_dir
 pub const SESSION_DIR: &str = "./session";
-pub const TG_BOT_SESSION_PATH: &str = "./session/tg-bot.session";
-pub const TG_USER_SESSION_PATH: &str = "./session/tg-user.session";
-pub const OD_SESSION_PATH: &str = "./session/od.session";
-pub const TASKER_SESSION_PATH: &str = "./session/tasker.session";
+pub const TEMP_DIR: &str = "./temp";
+pub const LOGS_DIR: &str = "./logs";
+
+// files in the session dir
+// legacy session files, migrated into TELEGRAM_SESSION_NAME
+pub const TG_BOT_SESSION_NAME: &str = "tg-bot.session";
+pub const TG_USER_SESSION_NAME: &str = "tg-user.session";
+pub const TELEGRAM_SESSION_NAME: &str = "telegram.session";
+pub const OD_SESSION_NAME: &str = "od.session";
+// od-tenant-$user_id.session in multi-tenant mode
+pub const OD_TENANT_SESSION_PREFIX: &str = "od-tenant-";
+pub const TASKER_SESSION_NAME: &str = "tasker.session";
+// written by import-tasks, loaded once the tasker starts
+pub const TASKS_IMPORT_NAME: &str = "tasks-import.json";
+pub const MESSAGE_SESSION_NAME: &str = "message.session";
+pub const SETTINGS_SESSION_NAME: &str = "settings.session";
+pub const USAGE_SESSION_NAME: &str = "usage.session";
+pub const HISTORY_SESSION_NAME: &str = "history.session";
+pub const AUDIT_SESSION_NAME: &str = "audit.session";
+// dir of buffered self-destructing media, named by the chat and the message
+pub const TTL_BUFFER_DIR_NAME: &str = "ttl-buffers";
+
+// updates buffered by grammers until the listener takes them, newer ones are dropped beyond it
+pub const UPDATE_QUEUE_LIMIT: usize = 1000;
 
 pub const RECONNECTION_POLICY: FixedReconnect = FixedReconnect {
     attempts: 5,
@@ -5,14 +5,32 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::{client::TelegramClient, message::TelegramMessage};
+use crate::{
+    client::TelegramClient,
+    env::ENV,
+    handlers::utils::callback::{parse_query_data, CallbackStore},
+    message::{
+        track_transient_message, MessageBuilder, MessageContent, OutgoingMessage, TelegramMessage,
+    },
+};
 use anyhow::{Context, Error, Result};
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use grammers_client::{types::PackedChat, InputMessage};
-use std::{fmt::Display, panic};
+use grammers_client::{
+    button, reply_markup,
+    types::{CallbackQuery, PackedChat},
+    InputMessage,
+};
+use std::{fmt::Display, panic, process::ExitStatus, sync::Mutex};
+
+const DETAILS_CALLBACK_PREFIX: &str = "error_details:";
+const MAX_ERROR_DETAILS: usize = 100;
+
+// full error chains of the latest errors sent to chats, revealed by the details button
+static ERROR_DETAILS: Mutex<CallbackStore<MessageContent>> =
+    Mutex::new(CallbackStore::new(MAX_ERROR_DETAILS));
 
 pub trait ResultExt<T> {
     fn trace(self);
@@ -40,6 +58,8 @@ pub trait ErrorExt {
 
     fn format_tg(&self) -> String;
 
+    fn build_tg_message(&self) -> OutgoingMessage;
+
     async fn send(self, message: TelegramMessage) -> Result<Error>;
 
     async fn send_chat<C>(self, telegram_client: &TelegramClient, chat: C) -> Result<Error>
@@ -67,9 +87,42 @@ impl ErrorExt for Error {
         message
     }
 
+    // the outermost context and the root cause, with a button to show the whole chain
+    fn build_tg_message(&self) -> OutgoingMessage {
+        let summary = {
+            let mut summary = self.to_string();
+
+            if self.chain().count() > 1 {
+                summary.push_str(&format!("\nCaused by: {}", self.root_cause()));
+            }
+
+            summary
+        };
+
+        if self.chain().count() <= 2 {
+            return OutgoingMessage::from(MessageBuilder::new().text(&summary).build());
+        }
+
+        let details = MessageBuilder::new().pre(&self.format_tg()).build();
+
+        let id = ERROR_DETAILS.lock().unwrap().insert(details);
+
+        let content = MessageBuilder::new().text(&summary).build();
+        let input_message =
+            InputMessage::from(content.clone()).reply_markup(&reply_markup::inline(vec![vec![
+                button::inline("Details", format!("{}{}", DETAILS_CALLBACK_PREFIX, id)),
+            ]]));
+
+        // the button is dropped if the message is restored after restarting
+        OutgoingMessage {
+            input_message,
+            content: Some(content),
+        }
+    }
+
     async fn send(self, message: TelegramMessage) -> Result<Self> {
         message
-            .reply(InputMessage::html(self.format_tg()))
+            .reply(self.build_tg_message())
             .await
             .context(self.format_tg())?;
 
@@ -80,28 +133,79 @@ impl ErrorExt for Error {
     where
         C: Into<PackedChat>,
     {
-        telegram_client
-            .send_message(chat, self.format_tg())
+        let message = telegram_client
+            .send_message(chat, self.build_tg_message())
             .await
             .context(self.format_tg())?;
 
+        track_transient_message(message.chat().pack(), message.id());
+
         Ok(self)
     }
 }
 
+// replace the error message with its details when the button is pressed
+pub async fn handle_error_details_query(query: CallbackQuery) -> Result<()> {
+    let Some((id, _)) = std::str::from_utf8(query.data())
+        .ok()
+        .and_then(|data| parse_query_data(DETAILS_CALLBACK_PREFIX, data))
+    else {
+        return Ok(());
+    };
+
+    let users = &ENV.get().unwrap().telegram_user.users;
+    let is_allowed = users.is_empty()
+        || query
+            .sender()
+            .username()
+            .is_some_and(|username| users.contains(&username.to_string()));
+
+    let details = ERROR_DETAILS.lock().unwrap().get(id);
+
+    match details {
+        Some(details) if is_allowed => query
+            .answer()
+            .edit(details)
+            .await
+            .context("failed to show error details")?,
+        Some(_) => query
+            .answer()
+            .alert("You are not allowed to see the details.")
+            .send()
+            .await
+            .context("failed to answer error details query")?,
+        None => query
+            .answer()
+            .alert("Details expired.")
+            .send()
+            .await
+            .context("failed to answer error details query")?,
+    }
+
+    Ok(())
+}
+
 pub struct HttpError {
+    status: StatusCode,
     raw: Box<dyn Display>,
 }
 
 impl HttpError {
     pub fn new<T: Display + 'static>(e: T) -> Self {
-        Self { raw: Box::new(e) }
+        Self::with_status(StatusCode::INTERNAL_SERVER_ERROR, e)
+    }
+
+    pub fn with_status<T: Display + 'static>(status: StatusCode, e: T) -> Self {
+        Self {
+            status,
+            raw: Box::new(e),
+        }
     }
 }
 
 impl IntoResponse for HttpError {
     fn into_response(self) -> Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, self.raw.to_string()).into_response()
+        (self.status, self.raw.to_string()).into_response()
     }
 }
 
@@ -125,3 +229,68 @@ impl Display for TaskAbortError {
         write!(f, "Task was aborted")
     }
 }
+
+// the source message is gone, so the task can't be retried
+#[derive(Debug)]
+pub struct SourceDeletedError;
+
+impl std::error::Error for SourceDeletedError {}
+
+impl Display for SourceDeletedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Source message was deleted")
+    }
+}
+
+// the hash reported by onedrive differs from the transferred data, so the uploaded file is replaced
+#[derive(Debug)]
+pub struct CorruptedUploadError {
+    pub filename: String,
+}
+
+impl std::error::Error for CorruptedUploadError {}
+
+impl Display for CorruptedUploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Uploaded file {} is corrupted", self.filename)
+    }
+}
+
+// found by clamd with the skip policy, scanning it again won't help, so the task can't be retried
+#[derive(Debug)]
+pub struct InfectedFileError {
+    pub filename: String,
+    pub signature: String,
+}
+
+impl std::error::Error for InfectedFileError {}
+
+impl Display for InfectedFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "File {} is infected with {}, not uploaded",
+            self.filename, self.signature
+        )
+    }
+}
+
+// the pre-upload hook exited non-zero on purpose, so the task isn't retried
+#[derive(Debug)]
+pub struct HookVetoError {
+    pub filename: String,
+    pub status: ExitStatus,
+    pub stderr: String,
+}
+
+impl std::error::Error for HookVetoError {}
+
+impl Display for HookVetoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} vetoed by pre-upload hook, exited with {}: {}",
+            self.filename, self.status, self.stderr
+        )
+    }
+}
@@ -0,0 +1,119 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::env::ENV;
+use anyhow::{anyhow, Context, Result};
+use std::str::FromStr;
+
+// optional subsystems, all enabled unless listed in disabled_features,
+// while most of them also need their own env values to be active
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    // remuxing videos for faststart and converting animations to gif
+    Ffmpeg,
+    AudioTags,
+    // the dashboard page and the progress websocket feeding it
+    Dashboard,
+    Gallery,
+    Metrics,
+    Archive,
+    Trash,
+    Verify,
+    // malware scanning with clamd
+    Scan,
+}
+
+impl Feature {
+    pub const ALL: [Self; 9] = [
+        Self::Ffmpeg,
+        Self::AudioTags,
+        Self::Dashboard,
+        Self::Gallery,
+        Self::Metrics,
+        Self::Archive,
+        Self::Trash,
+        Self::Verify,
+        Self::Scan,
+    ];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Ffmpeg => "ffmpeg",
+            Self::AudioTags => "audio_tags",
+            Self::Dashboard => "dashboard",
+            Self::Gallery => "gallery",
+            Self::Metrics => "metrics",
+            Self::Archive => "archive",
+            Self::Trash => "trash",
+            Self::Verify => "verify",
+            Self::Scan => "scan",
+        }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        !ENV.get().unwrap().disabled_features.contains(&self)
+    }
+
+    // the env value a feature is waiting for, if it's enabled but not configured
+    pub fn get_missing_env(self) -> Option<&'static str> {
+        let env = ENV.get().unwrap();
+
+        match self {
+            Self::Ffmpeg => None,
+            Self::AudioTags => (!env.audio_tags).then_some("audio_tags"),
+            Self::Dashboard | Self::Gallery | Self::Metrics => {
+                env.web_token.is_none().then_some("web_token")
+            }
+            Self::Archive => env.archive_days.is_none().then_some("archive_days"),
+            Self::Trash => (env.trash_days == 0).then_some("trash_days"),
+            Self::Verify => env.verify_samples.is_none().then_some("verify_samples"),
+            Self::Scan => env.clamd_socket.is_none().then_some("clamd_socket"),
+        }
+    }
+
+    pub fn is_active(self) -> bool {
+        self.is_enabled() && self.get_missing_env().is_none()
+    }
+}
+
+impl FromStr for Feature {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|feature| feature.as_str() == s.trim().to_lowercase())
+            .ok_or_else(|| anyhow!("unknown feature: {}", s.trim()))
+            .context(format!(
+                "features are {}",
+                Self::ALL.map(Self::as_str).join(", ")
+            ))
+    }
+}
+
+// a comma separated list like ffmpeg,gallery
+pub fn parse_features(s: &str) -> Result<Vec<Feature>> {
+    s.split(',')
+        .filter(|name| !name.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_features() {
+        assert_eq!(
+            parse_features("ffmpeg, Gallery,").unwrap(),
+            vec![Feature::Ffmpeg, Feature::Gallery]
+        );
+        assert!(parse_features("").unwrap().is_empty());
+        assert!(parse_features("ffmpeg,torrent").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{
+    message::TelegramMessage,
+    settings::{parse_animation_format, AnimationFormat},
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/animation";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /animation
+        show_animation_format(message, state).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /animation reset
+            reset_animation_format(message, state).await?;
+        } else if cmd[1] == "help" {
+            // /animation help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /animation $format
+            set_animation_format(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_animation_format(message: TelegramMessage, state: AppState) -> Result<()> {
+    let animation_format = state
+        .settings_session
+        .get_animation_format(message.chat().id())
+        .await?;
+
+    let response = format!("Current animation format is {}", animation_format.as_str());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_animation_format(
+    message: TelegramMessage,
+    state: AppState,
+    animation_format: &str,
+) -> Result<()> {
+    let animation_format = parse_animation_format(animation_format)?;
+
+    state
+        .settings_session
+        .set_animation_format(message.chat().id(), animation_format)
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!("Animation format set to {}", animation_format.as_str());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_animation_format(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .settings_session
+        .reset_animation_format(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "Animation format reset to {}.",
+        AnimationFormat::Mp4.as_str()
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::text::cmd_parser,
+};
+use crate::{
+    message::{MessageBuilder, TelegramMessage},
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_admin, check_in_group, check_senders};
+
+pub const PATTERN: &str = "/audit";
+
+#[check_admin]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /audit
+        show_audit(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /audit help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_audit(message: TelegramMessage, state: AppState) -> Result<()> {
+    let records = state.audit_session.get_records().await?;
+
+    if records.is_empty() {
+        let response = "No records found.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let chat_id = message.chat().id();
+
+    let mut builder = MessageBuilder::new()
+        .bold(&format!("Audit ({})", records.len()))
+        .line();
+
+    for record in records {
+        let created_at = state
+            .settings_session
+            .format_timestamp(chat_id, record.created_at)
+            .await?;
+
+        let actor = match (record.actor, record.actor_id) {
+            (Some(actor), _) => format!("@{}", actor),
+            (None, Some(actor_id)) => actor_id.to_string(),
+            (None, None) => "unknown".to_string(),
+        };
+
+        builder = builder
+            .line()
+            .text(&format!(
+                "{} {} in chat {}",
+                created_at, actor, record.chat_id
+            ))
+            .line()
+            .code(&record.action)
+            .line();
+    }
+
+    let response = builder.build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
@@ -5,7 +5,8 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::{auth_server, message::TelegramMessage, state::AppState};
+use super::utils::message::{get_tenant_id, is_admin};
+use crate::{auth_server, env::ENV, message::TelegramMessage, state::AppState};
 use anyhow::{Context, Result};
 use proc_macros::{check_in_group, check_senders};
 use tokio::sync::mpsc::Receiver;
@@ -15,13 +16,17 @@ pub const PATTERN: &str = "/auth";
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let (rx_tg, rx_od, _server_abort_handle) = auth_server::spawn().await?;
+    let (rx_tg, rx_od, token, _server_abort_handle) = auth_server::spawn(message.clone()).await?;
 
-    login_to_telegram(message.clone(), state.clone(), rx_tg).await?;
+    // the telegram account is shared by the instance, tenants only authorize onedrive
+    if !ENV.get().unwrap().multi_tenant || is_admin(&message) {
+        login_to_telegram(message.clone(), state.clone(), &token, rx_tg).await?;
+    }
 
-    authorize_onedrive(message, state.clone(), false, rx_od).await?;
+    let onedrive = state.get_onedrive(get_tenant_id(&message)).await?;
+
+    authorize_onedrive(message, state.clone(), false, &token, rx_od).await?;
 
-    let onedrive = &state.onedrive;
     onedrive.set_current_user().await?;
 
     Ok(())
@@ -30,11 +35,12 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 pub async fn login_to_telegram(
     message: TelegramMessage,
     state: AppState,
+    token: &str,
     rx: Receiver<String>,
 ) -> Result<()> {
     let telegram_user = &state.telegram_user;
 
-    telegram_user.login(message.clone(), rx).await?;
+    telegram_user.login(message.clone(), token, rx).await?;
 
     let response = "Login to Telegram successful!";
     message.respond(response).await.context(response)?;
@@ -46,11 +52,14 @@ pub async fn authorize_onedrive(
     message: TelegramMessage,
     state: AppState,
     should_add: bool,
+    token: &str,
     rx: Receiver<String>,
 ) -> Result<()> {
-    let onedrive = &state.onedrive;
+    let onedrive = state.get_onedrive(get_tenant_id(&message)).await?;
 
-    onedrive.login(message.clone(), should_add, rx).await?;
+    onedrive
+        .login(message.clone(), should_add, token, rx)
+        .await?;
 
     let response = "OneDrive authorization successful!";
     message.respond(response).await.context(response)?;
@@ -5,13 +5,15 @@
 :license: MIT, see LICENSE for more details.
 */
 
+use super::utils::audit::record_audit;
 use crate::{message::TelegramMessage, state::AppState};
 use anyhow::{Context, Result};
-use proc_macros::{check_in_group, check_senders};
+use proc_macros::{check_admin, check_in_group, check_senders};
 use std::sync::atomic::Ordering;
 
 pub const PATTERN: &str = "/autoDelete";
 
+#[check_admin]
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
@@ -21,6 +23,8 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         .should_auto_delete
         .store(!should_auto_delete, Ordering::Release);
 
+    record_audit(&message, &state).await;
+
     if should_auto_delete {
         let response = "Bot won't auto delete message.";
         message.respond(response).await.context(response)?;
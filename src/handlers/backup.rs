@@ -0,0 +1,70 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, check_read_only, text::cmd_parser},
+};
+use crate::{
+    backup::{create_backup, upload_backup},
+    message::TelegramMessage,
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_admin, check_in_group, check_senders};
+
+pub const PATTERN: &str = "/backup";
+
+#[check_admin]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /backup
+        backup(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /backup help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn backup(message: TelegramMessage, state: AppState) -> Result<()> {
+    check_read_only()?;
+
+    // pending usages are only in memory until flushed
+    state.usage_session.flush().await?;
+
+    let data = create_backup().await?;
+
+    message
+        .respond(format!(
+            "Uploading backup sized {:.2}KB...",
+            data.len() as f64 / 1024.0
+        ))
+        .await?;
+
+    let path = upload_backup(data, state.clone()).await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "Backup uploaded to {}\nTo restore, download it and run telegram-onedrive restore $path on the new instance.",
+        path
+    );
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, queue::get_task_from_code, text::cmd_parser},
+};
+use crate::{message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/cancel";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 {
+        if cmd[1] == "help" {
+            // /cancel help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /cancel $task_id
+            cancel_task(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn cancel_task(message: TelegramMessage, state: AppState, code: &str) -> Result<()> {
+    let task_session = &state.task_session;
+
+    // hold it so that the task won't be fetched by tasker in the meantime
+    let mut task_aborters = task_session.task_aborters.lock().await;
+
+    let task = get_task_from_code(&message, &state, code).await?;
+
+    if let Some(task_aborter) =
+        task_aborters.remove(&(message.chat().id(), task.message_indicator_id))
+    {
+        task_aborter.abort();
+    }
+
+    task_session.delete_task(task.id).await?;
+    drop(task_aborters);
+
+    record_audit(&message, &state).await;
+
+    let response = format!("Task #{} {} cancelled.", task.code, task.filename);
+    message.respond(response.as_str()).await.context(response)?;
+
+    tracing::info!("task {} cancelled by /cancel", task.filename);
+
+    Ok(())
+}
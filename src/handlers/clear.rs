@@ -5,24 +5,30 @@
 :license: MIT, see LICENSE for more details.
 */
 
+use super::utils::{audit::record_audit, check_read_only};
 use crate::{
     message::{ChatEntity, TelegramMessage},
     state::AppState,
 };
 use anyhow::{Context, Result};
-use proc_macros::{check_in_group, check_senders, check_tg_login};
+use proc_macros::{check_admin, check_in_group, check_senders, check_tg_login};
 
 pub const PATTERN: &str = "/clear";
 
 #[check_tg_login]
+#[check_admin]
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    check_read_only()?;
+
     let telegram_user = &state.telegram_user;
     let task_session = &state.task_session;
 
     task_session.clear().await?;
 
+    record_audit(&message, &state).await;
+
     let chat = telegram_user
         .get_chat(&ChatEntity::from(message.chat()))
         .await?;
@@ -0,0 +1,97 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{env::ENV, message::TelegramMessage, settings::ConflictPolicy, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/conflict";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /conflict
+        show_conflict_policy(message, state).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /conflict reset
+            reset_conflict_policy(message, state).await?;
+        } else if cmd[1] == "help" {
+            // /conflict help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /conflict $policy
+            set_conflict_policy(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_conflict_policy(message: TelegramMessage, state: AppState) -> Result<()> {
+    let conflict_policy = state
+        .settings_session
+        .get_conflict_policy(message.chat().id())
+        .await?;
+
+    let response = format!(
+        "Files with the same name are handled by {}.",
+        conflict_policy.as_str()
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_conflict_policy(
+    message: TelegramMessage,
+    state: AppState,
+    conflict_policy: &str,
+) -> Result<()> {
+    let conflict_policy = conflict_policy.parse::<ConflictPolicy>()?;
+
+    state
+        .settings_session
+        .set_conflict_policy(message.chat().id(), conflict_policy)
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!("Conflict policy set to {}", conflict_policy.as_str());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_conflict_policy(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .settings_session
+        .reset_conflict_policy(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "Conflict policy reset to {}.",
+        ENV.get().unwrap().conflict_policy.as_str()
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
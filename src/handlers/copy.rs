@@ -0,0 +1,161 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        check_read_only, get_tg_file_size,
+        message::{get_message_from_link, get_message_link, get_sender_name, get_tenant_id},
+        preprocess_tg_file_name,
+        queue::check_queue_limit,
+        text::cmd_parser,
+    },
+};
+use crate::{
+    message::{ChatEntity, MessageBuilder, TelegramMessage},
+    state::AppState,
+    tasker::{CmdType, InsertTask},
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::types::Media;
+use proc_macros::{check_admin, check_in_group, check_senders, check_tg_login};
+use std::sync::atomic::Ordering;
+
+pub const PATTERN: &str = "/copy";
+
+#[check_tg_login]
+#[check_admin]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /copy help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 3 {
+        // /copy $message_link $chat
+        copy_message(message, state, &cmd[1], &cmd[2]).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+// download from the source chat and upload to the target chat with user client, without onedrive,
+// queued as a task so that it runs with progress and can be cancelled
+async fn copy_message(
+    message: TelegramMessage,
+    state: AppState,
+    link: &str,
+    target: &str,
+) -> Result<()> {
+    check_read_only()?;
+
+    let telegram_user = &state.telegram_user;
+    let task_session = &state.task_session;
+
+    let message_origin = get_message_from_link(telegram_user, link).await?;
+
+    check_queue_limit(&message, &state).await?;
+
+    let media = message_origin
+        .media()
+        .ok_or_else(|| anyhow!("message does not contain any media"))?;
+
+    if !matches!(
+        media,
+        Media::Photo(_) | Media::Document(_) | Media::Sticker(_)
+    ) {
+        return Err(anyhow!(
+            "media type is not one of photo, document and sticker"
+        ));
+    }
+
+    // chat id, or username with or without @
+    let chat_entity = target.parse::<i64>().map_or_else(
+        |_| ChatEntity::from(target.trim_start_matches('@').to_string()),
+        ChatEntity::from,
+    );
+    let chat_target = telegram_user
+        .get_chat(&chat_entity)
+        .await
+        .context("target chat not found, make sure the user has joined it")?;
+
+    let chat_user = telegram_user
+        .get_chat(&ChatEntity::from(message.chat()))
+        .await?;
+
+    let filename = preprocess_tg_file_name(&media);
+    let total_length = get_tg_file_size(&media);
+
+    let code = task_session.generate_task_code().await?;
+
+    let response = MessageBuilder::new()
+        .text(&format!("#{} Copy ", code))
+        .link(
+            &filename,
+            &get_message_link(
+                &ChatEntity::from(message_origin.chat()),
+                message_origin.id(),
+            ),
+        )
+        .text(&format!(" to {}", chat_target.name()))
+        .build();
+    let message_indicator_id = message
+        .respond_final(response.clone())
+        .await
+        .context(response)?
+        .id();
+
+    let chat_bot_hex = message.chat().pack().to_hex();
+    let chat_user_hex = chat_user.pack().to_hex();
+    let chat_origin_hex = message_origin.chat().pack().to_hex();
+    let target_chat_hex = chat_target.pack().to_hex();
+
+    let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+
+    task_session
+        .insert_task(InsertTask {
+            code,
+            cmd_type: CmdType::Copy,
+            filename: filename.clone(),
+            original_filename: None,
+            root_path: String::new(),
+            url: None,
+            password: None,
+            upload_url: String::new(),
+            current_length: 0,
+            total_length,
+            mime_type: None,
+            photo_type: None,
+            chat_id: chat_user.id(),
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex: Some(chat_origin_hex),
+            message_id: message.id(),
+            message_indicator_id,
+            message_origin_id: Some(message_origin.id()),
+            target_chat_hex: Some(target_chat_hex),
+            group_id: None,
+            auto_delete,
+            label: None,
+            sender: get_sender_name(&message),
+            tenant_id: get_tenant_id(&message),
+            account: None,
+            buffer_path: None,
+        })
+        .await?;
+
+    tracing::info!("inserted copy task: {} size: {}", filename, total_length);
+
+    Ok(())
+}
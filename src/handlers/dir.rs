@@ -6,12 +6,15 @@
 */
 
 use super::{
+    dir_browser::open_browser,
     docs::{format_help, format_unknown_command_help},
-    utils::{text::cmd_parser, validate_root_path},
+    utils::{
+        audit::record_audit, get_root_path, message::get_tenant_id, text::cmd_parser,
+        validate_root_path,
+    },
 };
-use crate::{client::OneDriveClient, message::TelegramMessage, state::AppState};
+use crate::{client::OneDriveClient, env::ENV, message::TelegramMessage, state::AppState};
 use anyhow::{anyhow, Context, Result};
-use grammers_client::InputMessage;
 use proc_macros::{check_in_group, check_od_login, check_senders};
 
 pub const PATTERN: &str = "/dir";
@@ -20,37 +23,70 @@ pub const PATTERN: &str = "/dir";
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let onedrive = &state.onedrive;
+    let onedrive = &state.get_onedrive(get_tenant_id(&message)).await?;
 
     let cmd = cmd_parser(message.text());
 
     if cmd.len() == 1 {
         // /dir
-        show_dir(onedrive, message).await?;
+        show_dir(onedrive, message, &state).await?;
     } else if cmd.len() == 2 {
         if cmd[1] == "reset" {
             // /dir reset
-            reset_dir(onedrive, message).await?;
+            if ENV.get().unwrap().chat_dir {
+                reset_chat_dir(onedrive, message.clone(), None, &state).await?;
+            } else {
+                reset_dir(onedrive, message.clone()).await?;
+            }
+            record_audit(&message, &state).await;
         } else if cmd[1] == "help" {
             // /dir help
             message
-                .respond(InputMessage::html(format_help(PATTERN)))
+                .respond(format_help(PATTERN))
                 .await
                 .context("help")?;
         } else {
             // dir $root_path
             let root_path = &cmd[1];
-            set_dir(onedrive, message, root_path).await?;
+            if ENV.get().unwrap().chat_dir {
+                set_chat_dir(onedrive, message.clone(), None, root_path, &state).await?;
+            } else {
+                set_dir(onedrive, message.clone(), root_path).await?;
+            }
+            record_audit(&message, &state).await;
         }
     } else if cmd.len() == 3 {
-        if cmd[1] == "temp" {
+        if cmd[1] == "chat" || cmd[1] == "topic" {
+            // a topic inherits the directory of the chat if not set
+            let topic_id = if cmd[1] == "topic" {
+                Some(message.topic_id().ok_or_else(|| {
+                    anyhow!("this message is not sent in a topic, use /dir chat instead")
+                })?)
+            } else {
+                None
+            };
+
+            if cmd[2] == "reset" {
+                // /dir chat reset
+                // /dir topic reset
+                reset_chat_dir(onedrive, message.clone(), topic_id, &state).await?;
+            } else {
+                // /dir chat $path
+                // /dir topic $path
+                set_chat_dir(onedrive, message.clone(), topic_id, &cmd[2], &state).await?;
+            }
+
+            record_audit(&message, &state).await;
+        } else if cmd[1] == "temp" {
             if cmd[2] == "cancel" {
                 // /dir temp cancel
-                cancel_temp_dir(onedrive, message).await?;
+                cancel_temp_dir(onedrive, message.clone(), &state).await?;
+                record_audit(&message, &state).await;
             } else {
                 // /dir temp $path
                 let temp_root_path = &cmd[2];
-                set_temp_dir(onedrive, message, temp_root_path).await?;
+                set_temp_dir(onedrive, message.clone(), temp_root_path).await?;
+                record_audit(&message, &state).await;
             }
         } else {
             return Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN));
@@ -62,16 +98,48 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     Ok(())
 }
 
-async fn show_dir(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
-    let root_path = onedrive.get_root_path(false).await?;
+async fn show_dir(
+    onedrive: &OneDriveClient,
+    message: TelegramMessage,
+    state: &AppState,
+) -> Result<()> {
+    let root_path = get_root_path(&message, onedrive, state, false).await?;
     let is_temp = onedrive.does_temp_root_path_exist().await;
 
+    let chat_id = message.chat().id();
+    let topic_id = message.topic_id();
+
+    let is_topic_dir = match topic_id {
+        Some(topic_id) => state
+            .settings_session
+            .get_dir(chat_id, Some(topic_id))
+            .await?
+            .is_some(),
+        None => false,
+    };
+    let is_chat_dir = state
+        .settings_session
+        .get_dir(chat_id, None)
+        .await?
+        .is_some();
+
     let response = if is_temp {
         format!("Current directory is {}, and it's temporary.", root_path)
+    } else if is_topic_dir {
+        format!("Current directory is {}, set for this topic.", root_path)
+    } else if is_chat_dir {
+        format!("Current directory is {}, set for this chat.", root_path)
     } else {
         format!("Current directory is {}", root_path)
     };
-    message.respond(response.as_str()).await.context(response)?;
+
+    // the directory is created on upload, so browse from the root until then
+    let browse_path = match onedrive.get_item_by_path(&root_path).await? {
+        Some(item) if item.folder.is_some() => root_path,
+        _ => "/".to_string(),
+    };
+
+    open_browser(message, state, &response, &browse_path).await?;
 
     Ok(())
 }
@@ -91,6 +159,7 @@ async fn set_dir(
     root_path: &str,
 ) -> Result<()> {
     validate_root_path(root_path).await?;
+    onedrive.check_dir(root_path).await?;
 
     onedrive.set_root_path(root_path).await?;
 
@@ -100,12 +169,62 @@ async fn set_dir(
     Ok(())
 }
 
-async fn cancel_temp_dir(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
+async fn set_chat_dir(
+    onedrive: &OneDriveClient,
+    message: TelegramMessage,
+    topic_id: Option<i32>,
+    root_path: &str,
+    state: &AppState,
+) -> Result<()> {
+    validate_root_path(root_path).await?;
+    onedrive.check_dir(root_path).await?;
+
+    state
+        .settings_session
+        .set_dir(message.chat().id(), topic_id, root_path)
+        .await?;
+
+    let response = if topic_id.is_some() {
+        format!("Directory of this topic set to {}", root_path)
+    } else {
+        format!("Directory of this chat set to {}", root_path)
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_chat_dir(
+    onedrive: &OneDriveClient,
+    message: TelegramMessage,
+    topic_id: Option<i32>,
+    state: &AppState,
+) -> Result<()> {
+    state
+        .settings_session
+        .reset_dir(message.chat().id(), topic_id)
+        .await?;
+
+    let response = format!(
+        "Directory of this {} reset.\nCurrent directory is {}",
+        if topic_id.is_some() { "topic" } else { "chat" },
+        get_root_path(&message, onedrive, state, false).await?
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn cancel_temp_dir(
+    onedrive: &OneDriveClient,
+    message: TelegramMessage,
+    state: &AppState,
+) -> Result<()> {
     onedrive.clear_temp_root_path().await?;
 
     let response = format!(
         "Temporary directory canceled.\nCurrent directory is {}",
-        onedrive.get_root_path(false).await?
+        get_root_path(&message, onedrive, state, false).await?
     );
     message.respond(response.as_str()).await.context(response)?;
 
@@ -118,6 +237,7 @@ async fn set_temp_dir(
     temp_root_path: &str,
 ) -> Result<()> {
     validate_root_path(temp_root_path).await?;
+    onedrive.check_dir(temp_root_path).await?;
 
     onedrive.set_temp_root_path(temp_root_path).await?;
 
@@ -0,0 +1,391 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::{
+    audit::record_audit,
+    callback::{self, CallbackStore},
+    check_read_only,
+    message::get_tenant_id,
+};
+use crate::{
+    error::{ErrorExt, ResultExt, ResultUnwrapExt},
+    listener::CONVERSATION_TIMEOUT,
+    message::{MessageBuilder, OutgoingMessage, TelegramMessage},
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::{button, reply_markup, types::CallbackQuery, InputMessage};
+use serde_json::Value;
+use std::sync::Mutex;
+
+pub const CALLBACK_PREFIX: &str = "dir_browser:";
+const MAX_BROWSERS: usize = 100;
+const PAGE_SIZE: usize = 8;
+
+// browsers opened by /dir, only kept in memory like the details of errors
+static BROWSERS: Mutex<CallbackStore<Browser>> = Mutex::new(CallbackStore::new(MAX_BROWSERS));
+
+#[derive(Clone)]
+struct Browser {
+    // the /dir command, only its sender can use the browser
+    command: TelegramMessage,
+    // the message showing the browser, none before it is sent
+    message_id: Option<i32>,
+    // shown above the folder being browsed, like the current directory
+    title: String,
+    path: String,
+    page: usize,
+    // folders on the current page, opened by their index
+    folders: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Open(usize),
+    Up,
+    Page(usize),
+    Select,
+    NewFolder,
+    Close,
+}
+
+// folders of the path are listed as buttons, the selected one becomes the directory of the chat
+pub async fn open_browser(
+    message: TelegramMessage,
+    state: &AppState,
+    title: &str,
+    path: &str,
+) -> Result<()> {
+    let mut browser = Browser {
+        command: message.clone(),
+        message_id: None,
+        title: title.to_string(),
+        path: path.to_string(),
+        page: 0,
+        folders: Vec::new(),
+    };
+
+    let id = BROWSERS.lock().unwrap().insert(browser.clone());
+
+    let response = render(id, &mut browser, state).await?;
+    let browser_message = message.respond(response).await?;
+
+    browser.message_id = Some(browser_message.id());
+    BROWSERS.lock().unwrap().update(id, browser);
+
+    Ok(())
+}
+
+pub async fn handle_browser_query(query: CallbackQuery, state: AppState) -> Result<()> {
+    let Some((id, action)) = std::str::from_utf8(query.data())
+        .ok()
+        .and_then(parse_query_data)
+    else {
+        return Ok(());
+    };
+
+    let browser = BROWSERS.lock().unwrap().get(id);
+
+    let Some(browser) = browser else {
+        query
+            .answer()
+            .alert("Browser expired, send /dir again.")
+            .send()
+            .await
+            .context("failed to answer browser query")?;
+
+        return Ok(());
+    };
+
+    if browser.command.sender().map(|sender| sender.id()) != Some(query.sender().id()) {
+        query
+            .answer()
+            .alert("Only the sender of /dir can use this browser.")
+            .send()
+            .await
+            .context("failed to answer browser query")?;
+
+        return Ok(());
+    }
+
+    let command = browser.command.clone();
+
+    match apply_action(id, browser, action, &state).await {
+        Ok(response) => {
+            query
+                .answer()
+                .edit(response)
+                .await
+                .context("failed to update browser")?;
+        }
+        Err(e) => {
+            query
+                .answer()
+                .send()
+                .await
+                .context("failed to answer browser query")?;
+
+            e.send(command).await.unwrap_both().trace();
+        }
+    }
+
+    Ok(())
+}
+
+// the new content of the browser message
+async fn apply_action(
+    id: u64,
+    mut browser: Browser,
+    action: Action,
+    state: &AppState,
+) -> Result<InputMessage> {
+    match action {
+        Action::Open(index) => {
+            let folder = browser
+                .folders
+                .get(index)
+                .ok_or_else(|| anyhow!("folder not found, it may be removed"))?;
+
+            browser.path = join_path(&browser.path, folder);
+            browser.page = 0;
+        }
+        Action::Up => {
+            browser.path = get_parent_path(&browser.path);
+            browser.page = 0;
+        }
+        Action::Page(page) => browser.page = page,
+        Action::Select => {
+            let response = select_folder(&browser, state).await?;
+
+            BROWSERS.lock().unwrap().remove(id);
+
+            return Ok(InputMessage::text(response));
+        }
+        Action::NewFolder => expect_folder_name(id, &browser, state).await?,
+        Action::Close => {
+            BROWSERS.lock().unwrap().remove(id);
+
+            return Ok(InputMessage::text("Browser closed."));
+        }
+    }
+
+    let response = render(id, &mut browser, state).await?;
+    BROWSERS.lock().unwrap().update(id, browser);
+
+    Ok(response.input_message)
+}
+
+async fn render(id: u64, browser: &mut Browser, state: &AppState) -> Result<OutgoingMessage> {
+    let onedrive = state.get_onedrive(get_tenant_id(&browser.command)).await?;
+
+    let children = onedrive.list_children(&browser.path).await?;
+
+    let folders = children
+        .iter()
+        .filter(|item| item.get("folder").is_some())
+        .filter_map(|item| item.get("name").and_then(Value::as_str))
+        .collect::<Vec<_>>();
+
+    let page_count = folders.len().div_ceil(PAGE_SIZE).max(1);
+    browser.page = browser.page.min(page_count - 1);
+    browser.folders = folders
+        .iter()
+        .skip(browser.page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(ToString::to_string)
+        .collect();
+
+    let mut text = format!("{}\nBrowsing {}", browser.title, browser.path);
+
+    if page_count > 1 {
+        text.push_str(&format!("\nPage {}/{}", browser.page + 1, page_count));
+    }
+
+    if folders.is_empty() {
+        text.push_str("\nNo folders here.");
+    }
+
+    let callback_data = |action: &str| format!("{}{}:{}", CALLBACK_PREFIX, id, action);
+
+    let mut rows = browser
+        .folders
+        .iter()
+        .enumerate()
+        .map(|(index, folder)| {
+            vec![button::inline(
+                format!("{}/", folder),
+                callback_data(&format!("o{}", index)),
+            )]
+        })
+        .collect::<Vec<_>>();
+
+    let mut navigation = Vec::new();
+    if browser.page > 0 {
+        navigation.push(button::inline(
+            "< Prev",
+            callback_data(&format!("p{}", browser.page - 1)),
+        ));
+    }
+    if browser.path != "/" {
+        navigation.push(button::inline("Up", callback_data("up")));
+    }
+    if browser.page + 1 < page_count {
+        navigation.push(button::inline(
+            "Next >",
+            callback_data(&format!("p{}", browser.page + 1)),
+        ));
+    }
+    if !navigation.is_empty() {
+        rows.push(navigation);
+    }
+
+    rows.push(vec![
+        button::inline("Select", callback_data("sel")),
+        button::inline("New folder", callback_data("new")),
+        button::inline("Close", callback_data("close")),
+    ]);
+
+    let content = MessageBuilder::new().text(&text).build();
+
+    // the buttons are dropped if the message is restored after restarting
+    Ok(OutgoingMessage {
+        input_message: InputMessage::from(content.clone())
+            .reply_markup(&reply_markup::inline(rows)),
+        content: Some(content),
+    })
+}
+
+async fn select_folder(browser: &Browser, state: &AppState) -> Result<String> {
+    let chat_id = browser.command.chat().id();
+
+    state
+        .settings_session
+        .set_dir(chat_id, None, &browser.path)
+        .await?;
+
+    // recorded like the command setting it
+    let mut audit_message = browser.command.clone();
+    audit_message.override_text(format!("/dir chat {}", browser.path));
+    record_audit(&audit_message, state).await;
+
+    Ok(format!("Directory of this chat set to {}", browser.path))
+}
+
+// the next message of the sender is the name, then the browser opens the new folder
+async fn expect_folder_name(id: u64, browser: &Browser, state: &AppState) -> Result<()> {
+    check_read_only()?;
+
+    let prompt = format!(
+        "Send the name of the new folder in {}, or /cancel.",
+        browser.path
+    );
+    browser
+        .command
+        .respond(prompt.as_str())
+        .await
+        .context(prompt)?;
+
+    state.conversations.expect(
+        &browser.command,
+        CONVERSATION_TIMEOUT,
+        move |message, state| async move {
+            let name = message.text();
+            let name = name.trim();
+
+            let browser = BROWSERS.lock().unwrap().get(id);
+            let mut browser = browser.ok_or_else(|| anyhow!("browser expired, send /dir again"))?;
+
+            let onedrive = state.get_onedrive(get_tenant_id(&browser.command)).await?;
+
+            browser.path = onedrive.create_folder(&browser.path, name).await?;
+            browser.page = 0;
+
+            let response = format!("Folder {} created.", browser.path);
+            message.respond(response.as_str()).await.context(response)?;
+
+            let content = render(id, &mut browser, &state).await?;
+
+            if let Some(message_id) = browser.message_id {
+                browser.command.edit(message_id, content).await?;
+            }
+
+            BROWSERS.lock().unwrap().update(id, browser);
+
+            Ok(())
+        },
+    );
+
+    Ok(())
+}
+
+// like dir_browser:3:o1
+fn parse_query_data(data: &str) -> Option<(u64, Action)> {
+    let (id, action) = callback::parse_query_data(CALLBACK_PREFIX, data)?;
+
+    let action = match action {
+        "up" => Action::Up,
+        "sel" => Action::Select,
+        "new" => Action::NewFolder,
+        "close" => Action::Close,
+        _ => {
+            if let Some(index) = action.strip_prefix('o') {
+                Action::Open(index.parse().ok()?)
+            } else if let Some(page) = action.strip_prefix('p') {
+                Action::Page(page.parse().ok()?)
+            } else {
+                return None;
+            }
+        }
+    };
+
+    Some((id, action))
+}
+
+fn join_path(path: &str, name: &str) -> String {
+    format!("{}/{}", path.trim_end_matches('/'), name)
+}
+
+fn get_parent_path(path: &str) -> String {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some((parent, _)) if !parent.is_empty() => parent.to_string(),
+        _ => "/".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query_data() {
+        assert_eq!(
+            parse_query_data("dir_browser:3:o1"),
+            Some((3, Action::Open(1)))
+        );
+        assert_eq!(
+            parse_query_data("dir_browser:3:p2"),
+            Some((3, Action::Page(2)))
+        );
+        assert_eq!(
+            parse_query_data("dir_browser:0:sel"),
+            Some((0, Action::Select))
+        );
+
+        assert!(parse_query_data("error_details:3").is_none());
+        assert!(parse_query_data("dir_browser:3:ox").is_none());
+    }
+
+    #[test]
+    fn test_path() {
+        assert_eq!(join_path("/", "Movies"), "/Movies");
+        assert_eq!(join_path("/Movies", "2024"), "/Movies/2024");
+
+        assert_eq!(get_parent_path("/Movies/2024"), "/Movies");
+        assert_eq!(get_parent_path("/Movies"), "/");
+        assert_eq!(get_parent_path("/"), "/");
+    }
+}
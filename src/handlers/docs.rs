@@ -5,6 +5,8 @@
 :license: MIT, see LICENSE for more details.
 */
 
+use crate::message::{MessageBuilder, MessageContent, ParseMode};
+
 const GREETING: &str = "\
 Transfer files to Onedrive.
 
@@ -13,104 +15,534 @@ Forward or upload files to me, or pass message link to transfer restricted conte
 - /help: Ask for help.
 ";
 
-const HELP_BASE: &str = "\
-<pre><code>/auth</code></pre>
-To authorize for Telegram and OneDrive.
-<pre><code>/clear</code></pre>
-To clear all history.
-<pre><code>/autoDelete</code></pre>
-To toggle whether bot should auto delete message.
-<pre><code>/version</code></pre>
-To show the version.
-";
+const HELP_BASE: &[(&str, &str)] = &[
+    ("/auth", "To authorize for Telegram and OneDrive."),
+    ("/clear", "To clear all history."),
+    (
+        "/autoDelete",
+        "To toggle whether bot should auto delete message.",
+    ),
+    ("/version", "To show the version."),
+];
 
-const HELP_LINKS: &str = "\
-<pre><code>/links $message_link $num</code></pre>
-To transfer sequential restricted content.
-<pre><code>/links help</code></pre>
-To show command help.
-";
+const HELP_LINKS: &[(&str, &str)] = &[
+    (
+        "/links $message_link $num",
+        "To transfer sequential restricted content.",
+    ),
+    (
+        "/links $message_link $num -t $label",
+        "To transfer sequential restricted content with a label.",
+    ),
+    ("/links help", "To show command help."),
+];
 
-const HELP_URL: &str = "\
-<pre><code>/url $url</code></pre>
-To upload file through url.
-<pre><code>/url help</code></pre>
-To show command help.
-";
+const HELP_MIRRORALL: &[(&str, &str)] = &[
+    (
+        "/mirrorall $addlist_link",
+        "To choose chats of a chat folder and transfer all their files.",
+    ),
+    (
+        "/mirrorall $addlist_link -t $label",
+        "To choose chats of a chat folder and transfer all their files with a label.",
+    ),
+    ("/mirrorall help", "To show command help."),
+];
 
-const HELP_LOGS: &str = "\
-<pre><code>/logs</code></pre>
-To send logs zip.
-<pre><code>/logs clear</code></pre>
-To clear logs.
-<pre><code>/logs help</code></pre>
-To show command help.
-";
+const HELP_SCAN: &[(&str, &str)] = &[
+    (
+        "/scan $chat",
+        "To count files of a chat by type and size before transferring it.",
+    ),
+    ("/scan help", "To show command help."),
+];
 
-const HELP_DRIVE: &str = "\
-<pre><code>/drive</code></pre>
-To list all OneDrive accounts.
-<pre><code>/drive add</code></pre>
-To add a OneDrive account.
-<pre><code>/drive $index</code></pre>
-To change the OneDrive account.
-<pre><code>/drive logout</code></pre>
-To logout current OneDrive account.
-<pre><code>/drive logout $index</code></pre>
-To logout specified OneDrive account.
-<pre><code>/drive help</code></pre>
-To show command help.
-";
+const HELP_URL: &[(&str, &str)] = &[
+    ("/url $url", "To upload file through url."),
+    (
+        "/url $url -t $label",
+        "To upload file through url with a label.",
+    ),
+    (
+        "/url $url -p $password",
+        "To upload file through a password-protected url, like a Nextcloud share or a WebDAV file.",
+    ),
+    ("/url help", "To show command help."),
+];
 
-const HELP_DIR: &str = "\
-<pre><code>/dir</code></pre>
-To show current OneDrive directory.
-<pre><code>/dir $path</code></pre>
-To set OneDrive directory.
-<pre><code>/dir temp $path</code></pre>
-To set temporary OneDrive directory.
-<pre><code>/dir temp cancel</code></pre>
-To restore OneDrive directory to the previous one.
-<pre><code>/dir reset</code></pre>
-To reset OneDrive directory to default.
-<pre><code>/dir help</code></pre>
-To show command help.
-";
+const HELP_COPY: &[(&str, &str)] = &[
+    (
+        "/copy $message_link $chat",
+        "To copy restricted content to another chat without OneDrive, chat can be a username or an id.",
+    ),
+    ("/copy help", "To show command help."),
+];
+
+const HELP_STICKERPACK: &[(&str, &str)] = &[
+    (
+        "/stickerpack $link",
+        "To upload all stickers of a pack into a folder named after it, $link can also be the pack name.",
+    ),
+    (
+        "/stickerpack $link -c",
+        "To convert animated stickers into gif before uploading.",
+    ),
+    ("/stickerpack help", "To show command help."),
+];
+
+const HELP_LOGS: &[(&str, &str)] = &[
+    ("/logs", "To send logs zip."),
+    ("/logs clear", "To clear logs."),
+    ("/logs help", "To show command help."),
+];
+
+const HELP_DRIVE: &[(&str, &str)] = &[
+    ("/drive", "To list all OneDrive accounts."),
+    ("/drive add", "To add a OneDrive account."),
+    (
+        "/drive $index",
+        "To change the OneDrive account, queued tasks keep their accounts.",
+    ),
+    ("/drive target", "To show the drive files are uploaded to."),
+    (
+        "/drive target $target",
+        "To upload to a SharePoint document library, site:$site_id or drive:$drive_id, or a shared folder, shared:$drive_id/$item_id.",
+    ),
+    (
+        "/drive shared",
+        "To list folders shared with the account, with their targets.",
+    ),
+    (
+        "/drive target reset",
+        "To upload to the personal drive of the account.",
+    ),
+    ("/drive logout", "To logout current OneDrive account."),
+    (
+        "/drive logout $index",
+        "To logout specified OneDrive account.",
+    ),
+    ("/drive help", "To show command help."),
+];
+
+const HELP_LINK_OD: &[(&str, &str)] = &[
+    (
+        "/linkod",
+        "To link a OneDrive account to you in multi-tenant mode.",
+    ),
+    (
+        "/unlinkod",
+        "To unlink all your OneDrive accounts and remove their tokens.",
+    ),
+    ("/linkod help", "To show command help."),
+];
+
+const HELP_DIR: &[(&str, &str)] = &[
+    (
+        "/dir",
+        "To show current OneDrive directory, and browse folders to choose the directory of this chat.",
+    ),
+    ("/dir $path", "To set OneDrive directory."),
+    ("/dir temp $path", "To set temporary OneDrive directory."),
+    (
+        "/dir temp cancel",
+        "To restore OneDrive directory to the previous one.",
+    ),
+    ("/dir reset", "To reset OneDrive directory to default."),
+    (
+        "/dir chat $path",
+        "To set OneDrive directory of this chat, overriding the one of the account.",
+    ),
+    (
+        "/dir chat reset",
+        "To use OneDrive directory of the account in this chat again.",
+    ),
+    (
+        "/dir topic $path",
+        "To set OneDrive directory of this forum topic, overriding the one of the chat.",
+    ),
+    (
+        "/dir topic reset",
+        "To let this topic inherit OneDrive directory of the chat again.",
+    ),
+    ("/dir help", "To show command help."),
+];
+
+const HELP_LS: &[(&str, &str)] = &[
+    (
+        "/ls",
+        "To list names, sizes and modified dates of items in the current OneDrive directory.",
+    ),
+    ("/ls $path", "To list items in a OneDrive directory."),
+    ("/ls help", "To show command help."),
+];
+
+const HELP_FETCH: &[(&str, &str)] = &[
+    (
+        "/fetch $path",
+        "To send a file in OneDrive to this chat, up to 2000MB.",
+    ),
+    ("/fetch help", "To show command help."),
+];
+
+const HELP_MV: &[(&str, &str)] = &[
+    (
+        "/mv $source_path $destination_path",
+        "To move an item in OneDrive, into the destination if it's a folder.",
+    ),
+    ("/mv help", "To show command help."),
+];
+
+const HELP_RM: &[(&str, &str)] = &[
+    (
+        "/rm $path",
+        "To delete a file or folder in OneDrive after confirming.",
+    ),
+    (
+        "/undo",
+        "To restore the item last deleted by /rm in this chat from the trash.",
+    ),
+    ("/rm help", "To show command help."),
+];
+
+const HELP_RENAME_REMOTE: &[(&str, &str)] = &[
+    (
+        "/renameRemote $path $new_name",
+        "To rename an item in OneDrive.",
+    ),
+    ("/renameRemote help", "To show command help."),
+];
+
+const HELP_TZ: &[(&str, &str)] = &[
+    ("/tz", "To show the timezone of this chat."),
+    (
+        "/tz $timezone",
+        "To set the timezone of this chat, like Europe/Berlin.",
+    ),
+    ("/tz reset", "To reset the timezone to UTC."),
+    ("/tz help", "To show command help."),
+];
+
+const HELP_TIDY: &[(&str, &str)] = &[
+    (
+        "/tidy",
+        "To show whether bot messages are auto deleted in this chat.",
+    ),
+    (
+        "/tidy $minutes",
+        "To delete bot messages after some minutes, except the final messages of tasks.",
+    ),
+    ("/tidy reset", "To keep bot messages."),
+    ("/tidy help", "To show command help."),
+];
+
+const HELP_PIN_STATUS: &[(&str, &str)] = &[
+    (
+        "/pinStatus",
+        "To show whether an archive status is pinned in this chat.",
+    ),
+    (
+        "/pinStatus $minutes",
+        "To pin an archive status of tasks in this chat, updated every some minutes.",
+    ),
+    ("/pinStatus reset", "To unpin the archive status."),
+    ("/pinStatus help", "To show command help."),
+];
+
+const HELP_PHOTO: &[(&str, &str)] = &[
+    (
+        "/photo",
+        "To show the quality of photos transferred in this chat.",
+    ),
+    (
+        "/photo original",
+        "To transfer the largest size of photos kept by Telegram.",
+    ),
+    (
+        "/photo compressed",
+        "To transfer photos at most 1280px to save space.",
+    ),
+    ("/photo reset", "To reset the photo quality to original."),
+    ("/photo help", "To show command help."),
+];
+
+const HELP_ANIMATION: &[(&str, &str)] = &[
+    (
+        "/animation",
+        "To show the format of animations transferred in this chat.",
+    ),
+    (
+        "/animation mp4",
+        "To keep animations as mp4 with the extension corrected.",
+    ),
+    (
+        "/animation gif",
+        "To convert animations into gif with ffmpeg.",
+    ),
+    ("/animation reset", "To reset the animation format to mp4."),
+    ("/animation help", "To show command help."),
+];
+
+const HELP_CONFLICT: &[(&str, &str)] = &[
+    (
+        "/conflict",
+        "To show how files with the same name in the directory are handled in this chat.",
+    ),
+    (
+        "/conflict rename",
+        "To upload them as new files with a suffix.",
+    ),
+    ("/conflict replace", "To overwrite the existing files."),
+    ("/conflict skip", "To skip files which already exist."),
+    (
+        "/conflict reset",
+        "To reset the conflict policy to the default set by env.",
+    ),
+    ("/conflict help", "To show command help."),
+];
+
+const HELP_TEMPLATE: &[(&str, &str)] = &[
+    (
+        "/template",
+        "To show the path template of files uploaded from this chat.",
+    ),
+    (
+        "/template $template",
+        "To upload files to a path like {chat}/{year}/{month}/{sender}_{filename} in the directory, \
+        with variables {chat}, {chat_id}, {year}, {month}, {day}, {sender}, {label} and {filename}.",
+    ),
+    (
+        "/template off",
+        "To upload files to the directory with their own names.",
+    ),
+    (
+        "/template reset",
+        "To reset the path template to the default set by env.",
+    ),
+    ("/template help", "To show command help."),
+];
+
+const HELP_SHARE: &[(&str, &str)] = &[
+    (
+        "/share",
+        "To show whether a share link is posted after a file is uploaded in this chat.",
+    ),
+    (
+        "/share anonymous",
+        "To post a view-only link which anyone can open.",
+    ),
+    (
+        "/share organization",
+        "To post a view-only link which only people in your organization can open.",
+    ),
+    ("/share off", "To not post share links."),
+    ("/share reset", "To reset it to the default set by env."),
+    ("/share help", "To show command help."),
+];
+
+const HELP_QUEUE: &[(&str, &str)] = &[
+    ("/queue", "To show the tasks in queue."),
+    ("/history", "To show the latest finished tasks."),
+    (
+        "/history infected",
+        "To show the latest files found infected by the malware scan.",
+    ),
+    ("/failed", "To show the latest failed tasks."),
+    (
+        "/queue -t $label -c $chat_id -s $sender",
+        "To filter tasks by label, chat or sender, also works for /history and /failed.",
+    ),
+    (
+        "/queue limit $max $overflow",
+        "To limit pending tasks of this chat, overflow can be reject or drop_oldest, default to reject.",
+    ),
+    ("/queue limit", "To show the queue limit of this chat."),
+    ("/queue limit reset", "To remove the queue limit of this chat."),
+    ("/queue help", "To show command help."),
+];
+
+const HELP_TASK: &[(&str, &str)] = &[
+    (
+        "/info $task_id",
+        "To show the task, the task id is like #A1B2 in the task message.",
+    ),
+    ("/cancel $task_id", "To cancel the task."),
+    ("/retry $task_id", "To retry the failed task."),
+    (
+        "/priority $task_id",
+        "To move the waiting task to the front of the queue.",
+    ),
+    ("/info help", "To show command help."),
+];
+
+const HELP_USAGE: &[(&str, &str)] = &[
+    (
+        "/usage",
+        "To show the bandwidth usage of each account today and this month, or your own usage in multi-tenant mode.",
+    ),
+    ("/usage help", "To show command help."),
+];
+
+const HELP_STATUS: &[(&str, &str)] = &[
+    (
+        "/status",
+        "To show the number of running and waiting tasks, and whether the queue is paused.",
+    ),
+    (
+        "/status -v",
+        "To also show the latency and speed of chunks from Telegram, OneDrive and url hosts in the last 10 minutes.",
+    ),
+    ("/status help", "To show command help."),
+];
+
+const HELP_LIMIT: &[(&str, &str)] = &[
+    ("/limit", "To show the upload rate limit."),
+    (
+        "/limit $rate",
+        "To limit the total upload rate to OneDrive, like 5MB, 500KB or 1048576 in bytes per second, until restarting.",
+    ),
+    ("/limit off", "To remove the upload rate limit."),
+    ("/limit reset", "To reset the upload rate limit to upload_limit."),
+    ("/limit help", "To show command help."),
+];
+
+const HELP_BACKUP: &[(&str, &str)] = &[
+    (
+        "/backup",
+        "To upload an encrypted backup of sessions, settings, usage, history and the audit log to OneDrive.",
+    ),
+    ("/backup help", "To show command help."),
+];
+
+const HELP_TASKS: &[(&str, &str)] = &[
+    (
+        "/tasks export",
+        "To export unfinished tasks to a file, which can be imported on another instance.",
+    ),
+    (
+        "/tasks import",
+        "Reply to a file exported by /tasks export, or send it afterwards, to import its tasks.",
+    ),
+    ("/tasks help", "To show command help."),
+];
+
+const HELP_AUDIT: &[(&str, &str)] = &[
+    (
+        "/audit",
+        "To show the latest administrative actions, like settings changes, cancellations and deletions.",
+    ),
+    ("/audit help", "To show command help."),
+];
 
 const INSTRUCTION: &str = "\
 - To transfer files, forward or upload to me.
 - To transfer restricted content, right click the content, copy the message link, and send to me.
 - Tap the file name on the Progress message to locate the job.
 - To upload files through url, the headers of the file response must includes Content-Length.
-- To cancel a job, delete the responded message.
+- To cancel a job, delete the responded message, or use /cancel with its task id like #A1B2.
 - To cancel batch or links tasks, delete the message you sent.
+- To label a file, send it with caption -t $label.
+- Telegram doesn't keep the original of photos sent as photos, send them as files to transfer the original.
 - Support files with extension .t2o as scripts.
-
-See <a href=\"https://github.com/hlf20010508/telegram-onedrive#example\">example</a>.
 ";
 
+const EXAMPLE_URL: &str = "https://github.com/hlf20010508/telegram-onedrive#example";
+
+// rendered as plain text since it's sent as an error
 pub fn format_unknown_command_help(name: &str) -> String {
-    format!(
-        "Unknown command for {}\n\nUsage:\n{}",
-        name,
-        format_help(name)
-    )
+    let builder = MessageBuilder::with_parse_mode(ParseMode::Text)
+        .text(&format!("Unknown command for {}\n\nUsage:\n", name));
+
+    build_help(builder, name).build().to_string()
+}
+
+pub fn format_help(name: &str) -> MessageContent {
+    build_help(MessageBuilder::new(), name).build()
 }
 
-pub fn format_help(name: &str) -> String {
+fn build_help(builder: MessageBuilder, name: &str) -> MessageBuilder {
     match name {
-        "/help" => {
-            format!(
-                "{}{}{}{}{}{}\n{}",
-                HELP_BASE, HELP_LINKS, HELP_URL, HELP_LOGS, HELP_DRIVE, HELP_DIR, INSTRUCTION
-            )
-        }
-        "/start" => GREETING.to_string(),
-        "/links" => HELP_LINKS.to_string(),
-        "/url" => HELP_URL.to_string(),
-        "/logs" => HELP_LOGS.to_string(),
-        "/drive" => HELP_DRIVE.to_string(),
-        "/dir" => HELP_DIR.to_string(),
-        _ => String::new(),
+        "/help" => [
+            HELP_BASE,
+            HELP_LINKS,
+            HELP_MIRRORALL,
+            HELP_SCAN,
+            HELP_URL,
+            HELP_COPY,
+            HELP_STICKERPACK,
+            HELP_LOGS,
+            HELP_DRIVE,
+            HELP_LINK_OD,
+            HELP_DIR,
+            HELP_LS,
+            HELP_FETCH,
+            HELP_MV,
+            HELP_RENAME_REMOTE,
+            HELP_RM,
+            HELP_TZ,
+            HELP_TIDY,
+            HELP_PIN_STATUS,
+            HELP_PHOTO,
+            HELP_ANIMATION,
+            HELP_CONFLICT,
+            HELP_TEMPLATE,
+            HELP_SHARE,
+            HELP_QUEUE,
+            HELP_TASK,
+            HELP_USAGE,
+            HELP_STATUS,
+            HELP_LIMIT,
+            HELP_BACKUP,
+            HELP_TASKS,
+            HELP_AUDIT,
+        ]
+        .into_iter()
+        .fold(builder, build_commands_help)
+        .line()
+        .text(INSTRUCTION)
+        .line()
+        .text("See ")
+        .link("example", EXAMPLE_URL)
+        .text("."),
+        "/start" => builder.text(GREETING),
+        "/links" => build_commands_help(builder, HELP_LINKS),
+        "/mirrorall" => build_commands_help(builder, HELP_MIRRORALL),
+        "/scan" => build_commands_help(builder, HELP_SCAN),
+        "/url" => build_commands_help(builder, HELP_URL),
+        "/copy" => build_commands_help(builder, HELP_COPY),
+        "/stickerpack" => build_commands_help(builder, HELP_STICKERPACK),
+        "/logs" => build_commands_help(builder, HELP_LOGS),
+        "/drive" => build_commands_help(builder, HELP_DRIVE),
+        "/linkod" | "/unlinkod" => build_commands_help(builder, HELP_LINK_OD),
+        "/dir" => build_commands_help(builder, HELP_DIR),
+        "/ls" => build_commands_help(builder, HELP_LS),
+        "/fetch" => build_commands_help(builder, HELP_FETCH),
+        "/mv" => build_commands_help(builder, HELP_MV),
+        "/renameRemote" => build_commands_help(builder, HELP_RENAME_REMOTE),
+        "/rm" | "/undo" => build_commands_help(builder, HELP_RM),
+        "/tz" => build_commands_help(builder, HELP_TZ),
+        "/pinStatus" => build_commands_help(builder, HELP_PIN_STATUS),
+        "/tidy" => build_commands_help(builder, HELP_TIDY),
+        "/photo" => build_commands_help(builder, HELP_PHOTO),
+        "/animation" => build_commands_help(builder, HELP_ANIMATION),
+        "/conflict" => build_commands_help(builder, HELP_CONFLICT),
+        "/template" => build_commands_help(builder, HELP_TEMPLATE),
+        "/share" => build_commands_help(builder, HELP_SHARE),
+        "/queue" | "/history" | "/failed" => build_commands_help(builder, HELP_QUEUE),
+        "/info" | "/cancel" | "/retry" | "/priority" => build_commands_help(builder, HELP_TASK),
+        "/usage" => build_commands_help(builder, HELP_USAGE),
+        "/status" => build_commands_help(builder, HELP_STATUS),
+        "/limit" => build_commands_help(builder, HELP_LIMIT),
+        "/backup" => build_commands_help(builder, HELP_BACKUP),
+        "/tasks" => build_commands_help(builder, HELP_TASKS),
+        "/audit" => build_commands_help(builder, HELP_AUDIT),
+        _ => builder,
     }
 }
+
+fn build_commands_help(builder: MessageBuilder, commands: &[(&str, &str)]) -> MessageBuilder {
+    commands
+        .iter()
+        .fold(builder, |builder, (command, description)| {
+            builder.pre(command).line().text(description).line()
+        })
+}
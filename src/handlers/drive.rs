@@ -7,14 +7,16 @@
 
 use super::{
     docs::{format_help, format_unknown_command_help},
-    utils::text::cmd_parser,
+    utils::{audit::record_audit, check_read_only, message::get_tenant_id, text::cmd_parser},
 };
 use crate::{
-    auth_server, client::OneDriveClient, handlers::auth::authorize_onedrive,
-    message::TelegramMessage, state::AppState,
+    auth_server,
+    client::{DriveTarget, OneDriveClient},
+    handlers::auth::authorize_onedrive,
+    message::{MessageBuilder, TelegramMessage},
+    state::AppState,
 };
 use anyhow::{anyhow, Context, Result};
-use grammers_client::InputMessage;
 use proc_macros::{check_in_group, check_senders};
 
 pub const PATTERN: &str = "/drive";
@@ -22,7 +24,7 @@ pub const PATTERN: &str = "/drive";
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let onedrive = &state.onedrive;
+    let onedrive = &state.get_onedrive(get_tenant_id(&message)).await?;
 
     let cmd = cmd_parser(message.text());
 
@@ -35,11 +37,18 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             add_drive(message, state.clone()).await?;
         } else if cmd[1] == "logout" {
             // /drive logout
-            logout_current_drive(onedrive, message).await?;
+            logout_current_drive(onedrive, message.clone()).await?;
+            record_audit(&message, &state).await;
+        } else if cmd[1] == "target" {
+            // /drive target
+            show_drive_target(onedrive, message).await?;
+        } else if cmd[1] == "shared" {
+            // /drive shared
+            show_shared_folders(onedrive, message).await?;
         } else if cmd[1] == "help" {
             // /drive help
             message
-                .respond(InputMessage::html(format_help(PATTERN)))
+                .respond(format_help(PATTERN))
                 .await
                 .context("help")?;
         } else {
@@ -49,7 +58,8 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                 .context("account index should be integer")?
                 - 1;
 
-            set_drive(onedrive, message, index).await?;
+            set_drive(onedrive, message.clone(), index).await?;
+            record_audit(&message, &state).await;
         }
     } else if cmd.len() == 3 {
         if cmd[1] == "logout" {
@@ -59,7 +69,19 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                 .context("account index should be integer")?
                 - 1;
 
-            logout_drive(onedrive, message, index).await?;
+            logout_drive(onedrive, message.clone(), index).await?;
+            record_audit(&message, &state).await;
+        } else if cmd[1] == "target" {
+            // /drive target reset
+            // /drive target $target
+            let drive_target = if cmd[2] == "reset" {
+                DriveTarget::Me
+            } else {
+                cmd[2].parse::<DriveTarget>()?
+            };
+
+            set_drive_target(onedrive, message.clone(), drive_target).await?;
+            record_audit(&message, &state).await;
         } else {
             return Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN));
         }
@@ -99,18 +121,27 @@ async fn show_drive(onedrive: &OneDriveClient, message: TelegramMessage) -> Resu
 }
 
 async fn add_drive(message: TelegramMessage, state: AppState) -> Result<()> {
-    let (_, rx, _server_abort_handle) = auth_server::spawn().await?;
-    authorize_onedrive(message, state, true, rx).await?;
+    let (_, rx, token, _server_abort_handle) = auth_server::spawn(message.clone()).await?;
+    authorize_onedrive(message.clone(), state.clone(), true, &token, rx).await?;
+
+    record_audit(&message, &state).await;
 
     Ok(())
 }
 
 async fn logout_current_drive(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
+    check_read_only()?;
+
     let current_username = onedrive
         .get_current_username()
         .await?
         .ok_or_else(|| anyhow!("no onedrive account is logged in"))?;
 
+    // accounts linked by users are revoked like /unlinkod does
+    if get_tenant_id(&message).is_some() {
+        onedrive.revoke_tokens(None).await;
+    }
+
     onedrive.logout(None).await?;
 
     let response = {
@@ -154,7 +185,7 @@ async fn set_drive(
         message.respond(response).await.context(response)?;
     } else {
         let response = format!(
-            "Changed account from\n{}\nto\n{}",
+            "Changed account from\n{}\nto\n{}\n\nQueued tasks keep uploading to their accounts.",
             current_username, selected_username
         );
         message.respond(response.as_str()).await.context(response)?;
@@ -163,17 +194,90 @@ async fn set_drive(
     Ok(())
 }
 
+async fn show_drive_target(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
+    let response = match onedrive.get_drive_target().await {
+        DriveTarget::Me => "Files are uploaded to the personal drive of the account.".to_string(),
+        drive_target => format!("Files are uploaded to {}", drive_target),
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn show_shared_folders(onedrive: &OneDriveClient, message: TelegramMessage) -> Result<()> {
+    let shared_folders = onedrive.list_shared_folders().await?;
+
+    if shared_folders.is_empty() {
+        let response = "No folder is shared with the account.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let mut builder = MessageBuilder::new().bold("Folders shared with the account");
+
+    for (i, shared_folder) in shared_folders.iter().enumerate() {
+        let name = shared_folder.owner.as_ref().map_or_else(
+            || shared_folder.name.clone(),
+            |owner| format!("{} by {}", shared_folder.name, owner),
+        );
+
+        builder = builder
+            .line()
+            .line()
+            .text(&format!("{}. {}", i + 1, name))
+            .line()
+            .code(&format!("/drive target {}", shared_folder.target));
+    }
+
+    let response = builder.build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_drive_target(
+    onedrive: &OneDriveClient,
+    message: TelegramMessage,
+    drive_target: DriveTarget,
+) -> Result<()> {
+    let is_personal = drive_target == DriveTarget::Me;
+    let is_shared = matches!(drive_target, DriveTarget::Shared { .. });
+
+    let drive_name = onedrive.set_drive_target(drive_target).await?;
+
+    let response = if is_personal {
+        "Files will be uploaded to the personal drive of the account.".to_string()
+    } else if is_shared {
+        format!(
+            "Files will be uploaded to the shared folder {} as the root of the account.",
+            drive_name
+        )
+    } else {
+        format!("Files will be uploaded to {} of the account.", drive_name)
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
 async fn logout_drive(
     onedrive: &OneDriveClient,
     message: TelegramMessage,
     index: usize,
 ) -> Result<()> {
+    check_read_only()?;
+
     let usernames = onedrive.get_usernames().await?;
 
     let selected_username = usernames
         .get(index)
         .ok_or_else(|| anyhow!("account index out of range"))?;
 
+    if get_tenant_id(&message).is_some() {
+        onedrive.revoke_tokens(Some(selected_username)).await;
+    }
+
     onedrive.logout(Some(selected_username.clone())).await?;
 
     let response = {
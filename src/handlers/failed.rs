@@ -0,0 +1,51 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    history::respond_records,
+    utils::{
+        args::{Args, TASK_FILTER_SCHEMA},
+        queue::sandbox_task_filter,
+        text::parse_task_filter,
+    },
+};
+use crate::{message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/failed";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    // /failed -t $label -c $chat_id -s $sender
+    let args = Args::parse(message.text(), &TASK_FILTER_SCHEMA)
+        .context(format_unknown_command_help(PATTERN))?;
+    let cmd = args.positionals();
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /failed help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+
+        return Ok(());
+    }
+
+    let mut filter = parse_task_filter(&args).context(format_unknown_command_help(PATTERN))?;
+    sandbox_task_filter(&message, &mut filter)?;
+
+    if cmd.len() != 1 {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    let records = state.history_session.get_failed(&filter).await?;
+
+    respond_records(message, state, "Failed", records).await
+}
@@ -0,0 +1,148 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        message::{get_sender_name, get_tenant_id},
+        queue::check_queue_limit,
+        text::cmd_parser,
+        validate_root_path,
+    },
+};
+use crate::{
+    message::{ChatEntity, MessageBuilder, TelegramMessage},
+    state::AppState,
+    tasker::{CmdType, InsertTask, MAX_BOT_SIZE},
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_od_login, check_senders};
+use std::sync::atomic::Ordering;
+
+pub const PATTERN: &str = "/fetch";
+// the limit of telegram for accounts without premium
+const MAX_FETCH_SIZE: u64 = 2000 * 1024 * 1024;
+
+#[check_od_login]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /fetch help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 {
+        // /fetch $path
+        fetch(message, state, &cmd[1]).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+// queued as a task, which downloads the file from onedrive into the temp dir of the chat,
+// then sends it to the chat
+async fn fetch(message: TelegramMessage, state: AppState, path: &str) -> Result<()> {
+    validate_root_path(path).await?;
+
+    check_queue_limit(&message, &state).await?;
+
+    let onedrive = state.get_onedrive(get_tenant_id(&message)).await?;
+    let task_session = &state.task_session;
+
+    let account = onedrive.get_current_username().await?;
+
+    let (_, total_length) = onedrive
+        .get_download_url(account.as_deref(), path)
+        .await?
+        .ok_or_else(|| anyhow!("{} not found", path))?;
+
+    if total_length > MAX_FETCH_SIZE {
+        return Err(anyhow!(
+            "{} is larger than 2000MB, which telegram doesn't accept",
+            path
+        ));
+    }
+
+    let telegram_user = &state.telegram_user;
+
+    if total_length > MAX_BOT_SIZE && !telegram_user.is_authorized().await? {
+        return Err(anyhow!(
+            "files larger than 50MB are sent by the telegram account, log in with /auth first"
+        ));
+    }
+
+    let chat_user = telegram_user
+        .get_chat(&ChatEntity::from(message.chat()))
+        .await?;
+
+    let path = path.trim_end_matches('/');
+    let (root_path, filename) = path.rsplit_once('/').unwrap_or(("/", path));
+    let root_path = if root_path.is_empty() { "/" } else { root_path };
+
+    let code = task_session.generate_task_code().await?;
+
+    let response = MessageBuilder::new()
+        .text(&format!("#{} Fetch ", code))
+        .code(path)
+        .text(&format!(
+            " ({:.2}MB)",
+            total_length as f64 / 1024.0 / 1024.0
+        ))
+        .build();
+    let message_indicator_id = message
+        .respond_final(response.clone())
+        .await
+        .context(response)?
+        .id();
+
+    let chat_bot_hex = message.chat().pack().to_hex();
+    let chat_user_hex = chat_user.pack().to_hex();
+
+    let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+
+    task_session
+        .insert_task(InsertTask {
+            code,
+            cmd_type: CmdType::Fetch,
+            filename: filename.to_string(),
+            original_filename: None,
+            root_path: root_path.to_string(),
+            url: None,
+            password: None,
+            upload_url: String::new(),
+            current_length: 0,
+            total_length,
+            mime_type: None,
+            photo_type: None,
+            chat_id: message.chat().id(),
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex: None,
+            message_id: message.id(),
+            message_indicator_id,
+            message_origin_id: None,
+            target_chat_hex: None,
+            group_id: None,
+            auto_delete,
+            label: None,
+            sender: get_sender_name(&message),
+            tenant_id: get_tenant_id(&message),
+            account,
+            buffer_path: None,
+        })
+        .await?;
+
+    tracing::info!("inserted fetch task: {} size: {}", path, total_length);
+
+    Ok(())
+}
@@ -7,12 +7,24 @@
 
 use std::sync::atomic::Ordering;
 
-use super::utils::upload::upload_thumb;
+use super::utils::{
+    args::{Args, LABEL_SCHEMA},
+    queue::{check_queue_limit, check_quota},
+    upload::{skip_existing_file, upload_thumb},
+};
 use crate::{
-    handlers::utils::{get_tg_file_size, message::format_message_link, preprocess_tg_file_name},
-    message::{ChatEntity, TelegramMessage},
+    client::onedrive::is_small_file,
+    features::Feature,
+    handlers::utils::{
+        apply_path_template, get_animation_filename, get_root_path, get_tg_document_name,
+        get_tg_file_size, get_tg_mime_type, get_tg_photo_size, get_tg_ttl_seconds, is_tg_animation,
+        message::{get_message_link, get_sender_name, get_tenant_id},
+        preprocess_tg_file_name,
+    },
+    message::{ChatEntity, MessageBuilder, TelegramMessage},
+    settings::AnimationFormat,
     state::AppState,
-    tasker::{CmdType, InsertTask},
+    tasker::{buffer_tg_media, CmdType, InsertTask, TaskGroupKey},
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{types::Media, InputMessage};
@@ -24,9 +36,11 @@ use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login}
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let telegram_user = &state.telegram_user;
-    let onedrive = &state.onedrive;
+    let onedrive = &state.get_onedrive(get_tenant_id(&message)).await?;
     let task_session = &state.task_session;
 
+    check_queue_limit(&message, &state).await?;
+
     let chat_user = telegram_user
         .get_chat(&ChatEntity::from(message.chat()))
         .await?;
@@ -37,12 +51,49 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         .media()
         .ok_or_else(|| anyhow!("message does not contain any media"))?;
 
+    let document_name = get_tg_document_name(&media);
     let filename = preprocess_tg_file_name(&media);
+    let filename = if is_tg_animation(&media) {
+        // converting to gif needs ffmpeg
+        let animation_format = if Feature::Ffmpeg.is_enabled() {
+            state
+                .settings_session
+                .get_animation_format(message.chat().id())
+                .await?
+        } else {
+            AnimationFormat::Mp4
+        };
+
+        get_animation_filename(&filename, animation_format)
+    } else {
+        filename
+    };
+
+    let photo_quality = state
+        .settings_session
+        .get_photo_quality(message.chat().id())
+        .await?;
+    let photo_size = get_tg_photo_size(&media, photo_quality);
 
-    let total_length = get_tg_file_size(&media);
+    let total_length = photo_size
+        .as_ref()
+        .map_or_else(|| get_tg_file_size(&media), |size| size.size() as u64);
+
+    // pinned to another account if the current one doesn't have enough quota
+    let account = match check_quota(&message, &state, total_length).await? {
+        Some(account) => Some(account),
+        None => onedrive.get_current_username().await?,
+    };
+
+    let mime_type = get_tg_mime_type(&media);
 
     let message_id = message.id();
 
+    // caption like -t $label, ignored if it's not an option
+    let label = Args::parse(message.text(), &LABEL_SCHEMA)
+        .ok()
+        .and_then(|args| args.option("-t").map(ToString::to_string));
+
     let cmd_type = match media {
         Media::Photo(_) | Media::Document(_) | Media::Sticker(_) => CmdType::File,
         _ => Err(anyhow!(
@@ -50,6 +101,46 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         ))?,
     };
 
+    // normal queueing may be too late for self-destructing media, so it's downloaded at once
+    let buffer = match get_tg_ttl_seconds(&media) {
+        Some(ttl_seconds) => {
+            tracing::info!(
+                "message {} self-destructs in {}s, buffering it",
+                message_id,
+                ttl_seconds
+            );
+
+            let buffer = match &photo_size {
+                Some(photo_size) => {
+                    buffer_tg_media(
+                        chat_user.id(),
+                        message_id,
+                        photo_size,
+                        get_tenant_id(&message),
+                        account.as_deref(),
+                        &state,
+                    )
+                    .await
+                }
+                None => {
+                    buffer_tg_media(
+                        chat_user.id(),
+                        message_id,
+                        &media,
+                        get_tenant_id(&message),
+                        account.as_deref(),
+                        &state,
+                    )
+                    .await
+                }
+            }
+            .context("failed to buffer self-destructing media")?;
+
+            Some(buffer)
+        }
+        None => None,
+    };
+
     let uploaded = match media {
         Media::Photo(file) => upload_thumb(state.clone(), file.thumbs()).await?,
         Media::Document(file) => upload_thumb(state.clone(), file.thumbs()).await?,
@@ -62,48 +153,109 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     // in case if cancellation happens before inserting the task
     let _aborters = state.task_session.task_aborters.lock().await;
 
-    let response = format_message_link(chat_user.id(), message_id, &filename);
+    let code = task_session.generate_task_code().await?;
+
+    let group_id = message.raw.grouped_id();
+    let filename = task_session
+        .get_unique_filename(
+            chat_user.id(),
+            TaskGroupKey::new(group_id, message_id),
+            &filename,
+        )
+        .await?;
+
+    let root_path = get_root_path(&message, onedrive, &state, true).await?;
+    let (root_path, filename) = apply_path_template(
+        &message,
+        &message,
+        root_path,
+        filename,
+        label.as_deref(),
+        &state,
+    )
+    .await?;
+    let original_filename = document_name.filter(|name| *name != filename);
+    let conflict_policy = state
+        .settings_session
+        .get_conflict_policy(message.chat().id())
+        .await?;
+
+    if skip_existing_file(
+        &message,
+        onedrive,
+        account.as_deref(),
+        &root_path,
+        &filename,
+        conflict_policy,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let response = MessageBuilder::new()
+        .text(&format!("#{} ", code))
+        .link(
+            &filename,
+            &get_message_link(&ChatEntity::from(chat_user.id()), message_id),
+        )
+        .build();
     let message_indicator_id = match uploaded {
         Some(uploaded) => message
-            .respond(InputMessage::html(&response).photo(uploaded))
+            .respond_final(InputMessage::from(response.clone()).photo(uploaded))
             .await
             .context("message with thumb")
             .context(response)?
             .id(),
         None => message
-            .respond(InputMessage::html(&response))
+            .respond_final(response.clone())
             .await
             .context("message without thumn")
             .context(response)?
             .id(),
     };
 
-    let root_path = onedrive.get_root_path(true).await?;
+    // small files are uploaded in a single request when the task starts, without an upload session
+    let (upload_url, current_length) = if is_small_file(total_length) {
+        (String::new(), 0)
+    } else {
+        let (upload_session, upload_session_meta) = onedrive
+            .multipart_upload_session_builder(
+                account.as_deref(),
+                &root_path,
+                &filename,
+                conflict_policy,
+            )
+            .await?;
 
-    let (upload_session, upload_session_meta) = onedrive
-        .multipart_upload_session_builder(&root_path, &filename)
-        .await?;
+        // all task should be new, so this should always be 0
+        let current_length = upload_session_meta
+            .next_expected_ranges
+            .first()
+            .map_or(0, |range| range.start);
 
-    // all task should be new, so this should always be 0
-    let current_length = upload_session_meta
-        .next_expected_ranges
-        .first()
-        .map_or(0, |range| range.start);
+        (upload_session.upload_url().to_string(), current_length)
+    };
 
     let chat_bot_hex = message.chat().pack().to_hex();
     let chat_user_hex = chat_user.pack().to_hex();
 
     let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
 
-    task_session
+    let id = task_session
         .insert_task(InsertTask {
+            code,
             cmd_type,
             filename: filename.clone(),
+            original_filename,
             root_path,
             url: None,
-            upload_url: upload_session.upload_url().to_string(),
+            password: None,
+            upload_url,
             current_length,
             total_length,
+            mime_type,
+            photo_type: photo_size.map(|size| size.photo_type()),
             chat_id: chat_user.id(),
             chat_bot_hex,
             chat_user_hex,
@@ -111,10 +263,23 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             message_id,
             message_indicator_id,
             message_origin_id: None,
+            target_chat_hex: None,
+            group_id,
             auto_delete,
+            label,
+            sender: get_sender_name(&message),
+            tenant_id: get_tenant_id(&message),
+            account,
+            buffer_path: buffer.as_ref().map(|buffer| buffer.path.clone()),
         })
         .await?;
 
+    if let Some(buffer) = buffer {
+        buffer.keep();
+
+        task_session.prioritize_task(id).await?;
+    }
+
     tracing::info!("inserted file task: {} size: {}", filename, total_length);
 
     Ok(())
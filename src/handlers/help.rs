@@ -8,14 +8,11 @@
 use super::docs::format_help;
 use crate::{message::TelegramMessage, state::AppState};
 use anyhow::Result;
-use grammers_client::InputMessage;
 
 pub const PATTERN: &str = "/help";
 
 pub async fn handler(message: TelegramMessage, _state: AppState) -> Result<()> {
-    message
-        .respond(InputMessage::html(format_help(PATTERN)))
-        .await?;
+    message.respond(format_help(PATTERN)).await?;
 
     Ok(())
 }
@@ -0,0 +1,168 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        args::{Args, TASK_FILTER_SCHEMA},
+        queue::sandbox_task_filter,
+        text::parse_task_filter,
+    },
+};
+use crate::{
+    history::{HistoryRecord, ScanRecord},
+    message::{MessageBuilder, TelegramMessage},
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/history";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    // /history -t $label -c $chat_id -s $sender
+    let args = Args::parse(message.text(), &TASK_FILTER_SCHEMA)
+        .context(format_unknown_command_help(PATTERN))?;
+    let cmd = args.positionals();
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /history help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+
+        return Ok(());
+    }
+
+    let mut filter = parse_task_filter(&args).context(format_unknown_command_help(PATTERN))?;
+    sandbox_task_filter(&message, &mut filter)?;
+
+    if cmd.len() == 2 && cmd[1] == "infected" {
+        // /history infected
+        let records = state.history_session.get_infected(&filter).await?;
+
+        return respond_scan_records(message, state, records).await;
+    }
+
+    if cmd.len() != 1 {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    let records = state.history_session.get_history(&filter).await?;
+
+    respond_records(message, state, "History", records).await
+}
+
+async fn respond_scan_records(
+    message: TelegramMessage,
+    state: AppState,
+    records: Vec<ScanRecord>,
+) -> Result<()> {
+    if records.is_empty() {
+        let response = "No infected files found.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let chat_id = message.chat().id();
+
+    let mut builder = MessageBuilder::new()
+        .bold(&format!("Infected ({})", records.len()))
+        .line();
+
+    for record in records {
+        let scanned_at = state
+            .settings_session
+            .format_timestamp(chat_id, record.scanned_at)
+            .await?;
+
+        builder = builder
+            .line()
+            .code(&record.filename)
+            .line()
+            .text(&format!(
+                "{} {} {}{}",
+                scanned_at,
+                record.action.as_deref().unwrap_or_default(),
+                record.signature.as_deref().unwrap_or_default(),
+                format_tags(record.label.as_deref(), record.sender.as_deref())
+            ))
+            .line();
+    }
+
+    let response = builder.build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
+
+pub async fn respond_records(
+    message: TelegramMessage,
+    state: AppState,
+    title: &str,
+    records: Vec<HistoryRecord>,
+) -> Result<()> {
+    if records.is_empty() {
+        let response = "No records found.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let chat_id = message.chat().id();
+
+    let mut builder = MessageBuilder::new()
+        .bold(&format!("{} ({})", title, records.len()))
+        .line();
+
+    for record in records {
+        let finished_at = state
+            .settings_session
+            .format_timestamp(chat_id, record.finished_at)
+            .await?;
+
+        builder = builder
+            .line()
+            .code(&record.filename)
+            .line()
+            .text(&format!(
+                "{} {} {:.2}MB{}",
+                finished_at,
+                record.status,
+                record.total_length as f64 / 1024.0 / 1024.0,
+                format_tags(record.label.as_deref(), record.sender.as_deref())
+            ))
+            .line();
+
+        if let Some(error) = &record.error {
+            builder = builder.text(error).line();
+        }
+    }
+
+    let response = builder.build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
+
+pub fn format_tags(label: Option<&str>, sender: Option<&str>) -> String {
+    let mut tags = String::new();
+
+    if let Some(label) = label {
+        tags.push_str(&format!(" #{}", label));
+    }
+
+    if let Some(sender) = sender {
+        tags.push_str(&format!(" by @{}", sender));
+    }
+
+    tags
+}
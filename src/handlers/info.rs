@@ -0,0 +1,86 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    history::format_tags,
+    utils::{message::get_message_link, queue::get_task_from_code, text::cmd_parser},
+};
+use crate::{
+    message::{ChatEntity, MessageBuilder, TelegramMessage},
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use path_slash::PathBufExt;
+use proc_macros::{check_in_group, check_senders};
+use std::path::Path;
+
+pub const PATTERN: &str = "/info";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 {
+        if cmd[1] == "help" {
+            // /info help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /info $task_id
+            show_task_info(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_task_info(message: TelegramMessage, state: AppState, code: &str) -> Result<()> {
+    let task = get_task_from_code(&message, &state, code).await?;
+
+    let percentage = if task.total_length > 0 {
+        task.current_length as f64 / task.total_length as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    let file_path = Path::new(&task.root_path).join(&task.filename);
+
+    let response = MessageBuilder::new()
+        .bold(&format!("Task #{}", task.code))
+        .line()
+        .link(
+            &task.filename,
+            &get_message_link(&ChatEntity::from(task.chat_id), task.message_id),
+        )
+        .line()
+        .text(&format!(
+            "{} {} {:.2}% of {:.2}MB{}",
+            task.cmd_type,
+            task.status,
+            percentage,
+            task.total_length as f64 / 1024.0 / 1024.0,
+            task.mime_type
+                .as_ref()
+                .map_or_else(String::new, |mime_type| format!(" {}", mime_type)),
+        ))
+        .line()
+        .text(&format!(
+            "To {}{}",
+            file_path.to_slash_lossy(),
+            format_tags(task.label.as_deref(), task.sender.as_deref())
+        ))
+        .build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{
+    client::onedrive::{format_rate, parse_rate, UPLOAD_LIMIT},
+    env::ENV,
+    message::TelegramMessage,
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_admin, check_in_group, check_senders};
+
+pub const PATTERN: &str = "/limit";
+
+#[check_admin]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /limit
+        show_upload_limit(message).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /limit reset
+            set_upload_limit(message, state, ENV.get().unwrap().upload_limit).await?;
+        } else if cmd[1] == "help" {
+            // /limit help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /limit $rate
+            // /limit off
+            let upload_limit = parse_rate(&cmd[1])?;
+
+            set_upload_limit(message, state, upload_limit).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_upload_limit(message: TelegramMessage) -> Result<()> {
+    let response = format_upload_limit(UPLOAD_LIMIT.get());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_upload_limit(
+    message: TelegramMessage,
+    state: AppState,
+    upload_limit: Option<u64>,
+) -> Result<()> {
+    UPLOAD_LIMIT.set(upload_limit);
+
+    record_audit(&message, &state).await;
+
+    let response = format_upload_limit(upload_limit);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+fn format_upload_limit(upload_limit: Option<u64>) -> String {
+    upload_limit.map_or_else(
+        || "Uploads are not limited.".to_string(),
+        |upload_limit| {
+            format!(
+                "Uploads are limited to {} in total.",
+                format_rate(upload_limit)
+            )
+        },
+    )
+}
@@ -7,30 +7,48 @@
 
 use std::sync::atomic::Ordering;
 
-use super::utils::{message::get_message_from_link, upload::upload_thumb};
+use super::utils::{
+    args::{Args, LABEL_SCHEMA},
+    message::{get_message_from_link, get_message_link, get_sender_name, get_tenant_id},
+    queue::{check_queue_limit, check_quota},
+    upload::{skip_existing_file, upload_thumb},
+};
 use crate::{
-    handlers::utils::{get_tg_file_size, message::format_message_link, preprocess_tg_file_name},
-    message::{ChatEntity, TelegramMessage},
+    client::onedrive::is_small_file,
+    features::Feature,
+    handlers::utils::{
+        apply_path_template, get_animation_filename, get_root_path, get_tg_document_name,
+        get_tg_file_size, get_tg_mime_type, get_tg_photo_size, is_tg_animation,
+        preprocess_tg_file_name,
+    },
+    message::{ChatEntity, MessageBuilder, TelegramMessage},
+    settings::AnimationFormat,
     state::AppState,
-    tasker::{CmdType, InsertTask},
+    tasker::{CmdType, InsertTask, TaskGroupKey},
 };
 use anyhow::{anyhow, Context, Result};
 use grammers_client::{types::Media, InputMessage};
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_admin, check_in_group, check_od_login, check_senders, check_tg_login};
 
 #[check_od_login]
 #[check_tg_login]
+#[check_admin]
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     let telegram_user = &state.telegram_user;
-    let onedrive = &state.onedrive;
+    let onedrive = &state.get_onedrive(get_tenant_id(&message)).await?;
     let task_session = &state.task_session;
 
-    let link = message.text();
+    // $message_link -t $label
+    let args = Args::parse(message.text(), &LABEL_SCHEMA)?;
+    let label = args.option("-t").map(ToString::to_string);
+    let link = args.positionals().join(" ");
 
     let message_origin = get_message_from_link(telegram_user, &link).await?;
 
+    check_queue_limit(&message, &state).await?;
+
     let chat_user = telegram_user
         .get_chat(&ChatEntity::from(message.chat()))
         .await?;
@@ -39,9 +57,41 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         .media()
         .ok_or_else(|| anyhow!("message does not contain any media"))?;
 
+    let document_name = get_tg_document_name(&media);
     let filename = preprocess_tg_file_name(&media);
+    let filename = if is_tg_animation(&media) {
+        // converting to gif needs ffmpeg
+        let animation_format = if Feature::Ffmpeg.is_enabled() {
+            state
+                .settings_session
+                .get_animation_format(message.chat().id())
+                .await?
+        } else {
+            AnimationFormat::Mp4
+        };
+
+        get_animation_filename(&filename, animation_format)
+    } else {
+        filename
+    };
+
+    let photo_quality = state
+        .settings_session
+        .get_photo_quality(message.chat().id())
+        .await?;
+    let photo_size = get_tg_photo_size(&media, photo_quality);
 
-    let total_length = get_tg_file_size(&media);
+    let total_length = photo_size
+        .as_ref()
+        .map_or_else(|| get_tg_file_size(&media), |size| size.size() as u64);
+
+    // pinned to another account if the current one doesn't have enough quota
+    let account = match check_quota(&message, &state, total_length).await? {
+        Some(account) => Some(account),
+        None => onedrive.get_current_username().await?,
+    };
+
+    let mime_type = get_tg_mime_type(&media);
 
     let cmd_type = match media {
         Media::Photo(_) | Media::Document(_) | Media::Sticker(_) => CmdType::Link,
@@ -63,37 +113,91 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     // in case if cancellation happens before inserting the task
     let _aborters = state.task_session.task_aborters.lock().await;
 
-    let response = format!(
-        "{}\n\n{}",
-        link,
-        format_message_link(chat_user.id(), message.id(), &filename)
-    );
+    let code = task_session.generate_task_code().await?;
+
+    let filename = task_session
+        .get_unique_filename(
+            chat_user.id(),
+            TaskGroupKey::new(None, message.id()),
+            &filename,
+        )
+        .await?;
+
+    let root_path = get_root_path(&message, onedrive, &state, true).await?;
+    let (root_path, filename) = apply_path_template(
+        &message,
+        &message_origin,
+        root_path,
+        filename,
+        label.as_deref(),
+        &state,
+    )
+    .await?;
+    let original_filename = document_name.filter(|name| *name != filename);
+    let conflict_policy = state
+        .settings_session
+        .get_conflict_policy(message.chat().id())
+        .await?;
+
+    if skip_existing_file(
+        &message,
+        onedrive,
+        account.as_deref(),
+        &root_path,
+        &filename,
+        conflict_policy,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let response = MessageBuilder::new()
+        .text(&link)
+        .line()
+        .line()
+        .text(&format!("#{} ", code))
+        .link(
+            &filename,
+            &get_message_link(&ChatEntity::from(chat_user.id()), message.id()),
+        )
+        .build();
     let message_indicator_id = match uploaded {
         Some(uploaded) => message
-            .respond(InputMessage::html(&response).photo(uploaded))
+            .respond_final(InputMessage::from(response.clone()).photo(uploaded))
             .await
             .context("linked message with thumb")
             .context(response)?
             .id(),
         None => message
-            .respond(InputMessage::html(&response))
+            .respond_final(response.clone())
             .await
             .context("linked message without thumn")
             .context(response)?
             .id(),
     };
 
-    let root_path = onedrive.get_root_path(true).await?;
-
-    let (upload_session, upload_session_meta) = onedrive
-        .multipart_upload_session_builder(&root_path, &filename)
-        .await?;
-
-    // all task should be new, so this should always be 0
-    let current_length = upload_session_meta
-        .next_expected_ranges
-        .first()
-        .map_or(0, |range| range.start);
+    // small files are uploaded in a single request when the task starts, without an upload session
+    let (upload_url, current_length) = if is_small_file(total_length) {
+        (String::new(), 0)
+    } else {
+        let (upload_session, upload_session_meta) = onedrive
+            .multipart_upload_session_builder(
+                account.as_deref(),
+                &root_path,
+                &filename,
+                conflict_policy,
+            )
+            .await?;
+
+        // all task should be new, so this should always be 0
+        let current_length = upload_session_meta
+            .next_expected_ranges
+            .first()
+            .map_or(0, |range| range.start);
+
+        (upload_session.upload_url().to_string(), current_length)
+    };
 
     let chat_bot_hex = message.chat().pack().to_hex();
     let chat_user_hex = chat_user.pack().to_hex();
@@ -103,13 +207,18 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
     task_session
         .insert_task(InsertTask {
+            code,
             cmd_type,
             filename: filename.clone(),
+            original_filename,
             root_path,
             url: None,
-            upload_url: upload_session.upload_url().to_string(),
+            password: None,
+            upload_url,
             current_length,
             total_length,
+            mime_type,
+            photo_type: photo_size.map(|size| size.photo_type()),
             chat_id: chat_user.id(),
             chat_bot_hex,
             chat_user_hex,
@@ -117,7 +226,14 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
             message_id: message.id(),
             message_indicator_id,
             message_origin_id: Some(message_origin.id()),
+            target_chat_hex: None,
+            group_id: None,
             auto_delete,
+            label,
+            sender: get_sender_name(&message),
+            tenant_id: get_tenant_id(&message),
+            account,
+            buffer_path: None,
         })
         .await?;
 
@@ -0,0 +1,54 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    auth::authorize_onedrive,
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, message::get_tenant_id, text::cmd_parser},
+};
+use crate::{auth_server, message::TelegramMessage, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/linkod";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /linkod help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+
+        return Ok(());
+    }
+
+    if cmd.len() != 1 {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    // /linkod
+    link_onedrive(message, state).await
+}
+
+async fn link_onedrive(message: TelegramMessage, state: AppState) -> Result<()> {
+    get_tenant_id(&message)
+        .ok_or_else(|| anyhow!("onedrive accounts are only linked to users in multi-tenant mode"))
+        .context("use /auth or /drive add instead")?;
+
+    let (_, rx, token, _server_abort_handle) = auth_server::spawn(message.clone()).await?;
+    // saved in the session of the sender, see OneDriveClient::new_tenant
+    authorize_onedrive(message.clone(), state.clone(), true, &token, rx).await?;
+
+    record_audit(&message, &state).await;
+
+    Ok(())
+}
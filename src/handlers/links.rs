@@ -9,8 +9,8 @@ use super::{
     docs::{format_help, format_unknown_command_help},
     link,
     utils::{
+        args::{Args, LABEL_SCHEMA},
         message::{get_message_info, get_message_link},
-        text::cmd_parser,
     },
 };
 use crate::{
@@ -20,22 +20,25 @@ use crate::{
     tasker::BatchAborter,
 };
 use anyhow::{anyhow, Context, Result};
-use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use proc_macros::{check_admin, check_in_group, check_od_login, check_senders, check_tg_login};
 
 pub const PATTERN: &str = "/links";
 
 #[check_od_login]
 #[check_tg_login]
+#[check_admin]
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let cmd = cmd_parser(message.text());
+    let args =
+        Args::parse(message.text(), &LABEL_SCHEMA).context(format_unknown_command_help(PATTERN))?;
+    let cmd = args.positionals();
+    let label = args.option("-t").map(ToString::to_string);
 
     if cmd.len() == 2 && cmd[1] == "help" {
         // /links help
         message
-            .respond(InputMessage::html(format_help(PATTERN)))
+            .respond(format_help(PATTERN))
             .await
             .context("help")?;
     } else if cmd.len() == 3 {
@@ -78,7 +81,13 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                 let message_link = get_message_link(&chat_entity, message_origin_id);
 
                 let mut message_clone = message.clone();
-                message_clone.override_text(message_link.clone());
+                // pass the label to each link
+                match &label {
+                    Some(label) => {
+                        message_clone.override_text(format!("{} -t {}", message_link, label));
+                    }
+                    None => message_clone.override_text(message_link.clone()),
+                }
 
                 if link::handler(message_clone, state.clone()).await.is_err() {
                     message
@@ -7,23 +7,27 @@
 
 use super::{
     docs::format_help,
-    utils::{text::cmd_parser, zip::zip_dir},
+    utils::{audit::record_audit, check_read_only, text::cmd_parser, zip::zip_dir},
 };
-use crate::{client::TelegramClient, env::LOGS_PATH, message::TelegramMessage, state::AppState};
+use crate::{client::TelegramClient, env::ENV, message::TelegramMessage, state::AppState};
 use anyhow::{Context, Result};
 use grammers_client::InputMessage;
-use proc_macros::{check_in_group, check_senders};
+use proc_macros::{check_admin, check_in_group, check_senders};
+use std::path::Path;
 use tokio::fs;
 
 pub const PATTERN: &str = "/logs";
 
+#[check_admin]
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
     {
-        let metadata = fs::metadata(LOGS_PATH).await;
+        let logs_dir = &ENV.get().unwrap().logs_dir;
+
+        let metadata = fs::metadata(logs_dir).await;
         if metadata.is_err()
-            || (metadata.is_ok() && du::get_size(LOGS_PATH).context("failed to get dir size")? == 0)
+            || (metadata.is_ok() && du::get_size(logs_dir).context("failed to get dir size")? == 0)
         {
             let response = "Logs not found.";
             message.respond(response).await.context(response)?;
@@ -41,28 +45,29 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         send_log_zip(telegram_bot, message).await?;
     } else if cmd.len() == 2 && cmd[1] == "clear" {
         // /logs clear
-        clear_logs(message).await?;
+        clear_logs(message, &state).await?;
     } else if cmd.len() == 2 && cmd[1] == "help" {
         // /logs help
         message
-            .respond(InputMessage::html(format_help(PATTERN)))
+            .respond(format_help(PATTERN))
             .await
             .context("help")?;
     } else {
-        message
-            .reply(InputMessage::html(format_help(PATTERN)))
-            .await?;
+        message.reply(format_help(PATTERN)).await?;
     }
 
     Ok(())
 }
 
 async fn send_log_zip(telegram_bot: &TelegramClient, message: TelegramMessage) -> Result<()> {
-    const ZIP_PATH: &str = "./logs.zip";
+    let env = ENV.get().unwrap();
+
+    // the working dir may be read-only
+    let zip_path = Path::new(&env.temp_dir).join("logs.zip");
 
-    zip_dir(LOGS_PATH, ZIP_PATH).await?;
+    zip_dir(Path::new(&env.logs_dir), &zip_path).await?;
 
-    let file = fs::File::open(ZIP_PATH)
+    let file = fs::File::open(&zip_path)
         .await
         .context("failed to open logs zip file")?;
 
@@ -79,17 +84,19 @@ async fn send_log_zip(telegram_bot: &TelegramClient, message: TelegramMessage) -
         ))
         .await?;
 
-    let file = telegram_bot.upload_file(ZIP_PATH).await.context("logs")?;
+    let file = telegram_bot.upload_file(&zip_path).await.context("logs")?;
 
     message.respond(InputMessage::default().file(file)).await?;
 
-    std::fs::remove_file(ZIP_PATH).context("failed to remove file")?;
+    std::fs::remove_file(&zip_path).context("failed to remove file")?;
 
     Ok(())
 }
 
-async fn clear_logs(message: TelegramMessage) -> Result<()> {
-    while let Some(entry) = fs::read_dir(LOGS_PATH)
+async fn clear_logs(message: TelegramMessage, state: &AppState) -> Result<()> {
+    check_read_only()?;
+
+    while let Some(entry) = fs::read_dir(&ENV.get().unwrap().logs_dir)
         .await
         .context("failed to read logs dir")?
         .next_entry()
@@ -103,6 +110,8 @@ async fn clear_logs(message: TelegramMessage) -> Result<()> {
             .context(file_path.to_string_lossy().to_string())?;
     }
 
+    record_audit(&message, state).await;
+
     let response = "Logs cleared.";
     message.respond(response).await.context(response)?;
 
@@ -0,0 +1,300 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        callback::{self, CallbackStore},
+        get_root_path,
+        message::get_tenant_id,
+        text::cmd_parser,
+        validate_root_path,
+    },
+};
+use crate::{
+    message::{MessageBuilder, OutgoingMessage, TelegramMessage},
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use grammers_client::{button, reply_markup, types::CallbackQuery, InputMessage};
+use proc_macros::{check_in_group, check_od_login, check_senders};
+use serde_json::Value;
+use std::sync::Mutex;
+
+pub const PATTERN: &str = "/ls";
+pub const CALLBACK_PREFIX: &str = "ls:";
+const MAX_LISTINGS: usize = 100;
+const PAGE_SIZE: usize = 20;
+
+// listings posted by /ls, only kept in memory like the browsers of /dir
+static LISTINGS: Mutex<CallbackStore<Listing>> = Mutex::new(CallbackStore::new(MAX_LISTINGS));
+
+#[derive(Clone)]
+struct Listing {
+    // the /ls command, only its sender can turn pages
+    command: TelegramMessage,
+    path: String,
+    page: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Page(usize),
+    Close,
+}
+
+#[check_od_login]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    let path = if cmd.len() == 1 {
+        // /ls
+        let onedrive = state.get_onedrive(get_tenant_id(&message)).await?;
+
+        get_root_path(&message, &onedrive, &state, false).await?
+    } else if cmd.len() == 2 {
+        if cmd[1] == "help" {
+            // /ls help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+
+            return Ok(());
+        }
+
+        // /ls $path
+        validate_root_path(&cmd[1]).await?;
+
+        cmd[1].clone()
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    };
+
+    let mut listing = Listing {
+        command: message.clone(),
+        path,
+        page: 0,
+    };
+
+    let id = LISTINGS.lock().unwrap().insert(listing.clone());
+
+    let response = render(id, &mut listing, &state).await?;
+    message.respond(response).await?;
+
+    LISTINGS.lock().unwrap().update(id, listing);
+
+    Ok(())
+}
+
+pub async fn handle_listing_query(query: CallbackQuery, state: AppState) -> Result<()> {
+    let Some((id, action)) = std::str::from_utf8(query.data())
+        .ok()
+        .and_then(parse_query_data)
+    else {
+        return Ok(());
+    };
+
+    let listing = LISTINGS.lock().unwrap().get(id);
+
+    let Some(mut listing) = listing else {
+        query
+            .answer()
+            .alert("Listing expired, send /ls again.")
+            .send()
+            .await
+            .context("failed to answer listing query")?;
+
+        return Ok(());
+    };
+
+    if listing.command.sender().map(|sender| sender.id()) != Some(query.sender().id()) {
+        query
+            .answer()
+            .alert("Only the sender of /ls can turn pages.")
+            .send()
+            .await
+            .context("failed to answer listing query")?;
+
+        return Ok(());
+    }
+
+    let response = match action {
+        Action::Page(page) => {
+            listing.page = page;
+
+            let response = render(id, &mut listing, &state).await?;
+            LISTINGS.lock().unwrap().update(id, listing);
+
+            response.input_message
+        }
+        Action::Close => {
+            LISTINGS.lock().unwrap().remove(id);
+
+            InputMessage::text("Listing closed.")
+        }
+    };
+
+    query
+        .answer()
+        .edit(response)
+        .await
+        .context("failed to update listing")?;
+
+    Ok(())
+}
+
+async fn render(id: u64, listing: &mut Listing, state: &AppState) -> Result<OutgoingMessage> {
+    let onedrive = state.get_onedrive(get_tenant_id(&listing.command)).await?;
+
+    let children = onedrive.list_children(&listing.path).await?;
+
+    let timezone = state
+        .settings_session
+        .get_timezone(listing.command.chat().id())
+        .await?;
+
+    let page_count = children.len().div_ceil(PAGE_SIZE).max(1);
+    listing.page = listing.page.min(page_count - 1);
+
+    let mut text = format!("{} items in {}", children.len(), listing.path);
+
+    if page_count > 1 {
+        text.push_str(&format!("\nPage {}/{}", listing.page + 1, page_count));
+    }
+
+    for item in children
+        .iter()
+        .skip(listing.page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+    {
+        if let Some(entry) = format_entry(item, timezone) {
+            text.push('\n');
+            text.push_str(&entry);
+        }
+    }
+
+    let callback_data = |action: &str| format!("{}{}:{}", CALLBACK_PREFIX, id, action);
+
+    let mut buttons = Vec::new();
+    if listing.page > 0 {
+        buttons.push(button::inline(
+            "< Prev",
+            callback_data(&format!("p{}", listing.page - 1)),
+        ));
+    }
+    if listing.page + 1 < page_count {
+        buttons.push(button::inline(
+            "Next >",
+            callback_data(&format!("p{}", listing.page + 1)),
+        ));
+    }
+    buttons.push(button::inline("Close", callback_data("close")));
+
+    let content = MessageBuilder::new().text(&text).build();
+
+    // the buttons are dropped if the message is restored after restarting
+    Ok(OutgoingMessage {
+        input_message: InputMessage::from(content.clone())
+            .reply_markup(&reply_markup::inline(vec![buttons])),
+        content: Some(content),
+    })
+}
+
+// like a.mp4 | 1.23MB | 2024-01-01 12:00, folders end with / and show their item count
+fn format_entry(item: &Value, timezone: Tz) -> Option<String> {
+    let name = item.get("name").and_then(Value::as_str)?;
+
+    let modified = item
+        .get("lastModifiedDateTime")
+        .and_then(Value::as_str)
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        .map(|date| {
+            date.with_timezone(&timezone)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    let entry = item.get("folder").map_or_else(
+        || {
+            let size = item.get("size").and_then(Value::as_u64).unwrap_or_default();
+
+            format!(
+                "{} | {:.2}MB | {}",
+                name,
+                size as f64 / 1024.0 / 1024.0,
+                modified
+            )
+        },
+        |folder| {
+            let child_count = folder
+                .get("childCount")
+                .and_then(Value::as_u64)
+                .unwrap_or_default();
+
+            format!("{}/ | {} items | {}", name, child_count, modified)
+        },
+    );
+
+    Some(entry)
+}
+
+// like ls:3:p1
+fn parse_query_data(data: &str) -> Option<(u64, Action)> {
+    let (id, action) = callback::parse_query_data(CALLBACK_PREFIX, data)?;
+
+    let action = match action {
+        "close" => Action::Close,
+        _ => Action::Page(action.strip_prefix('p')?.parse().ok()?),
+    };
+
+    Some((id, action))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_format_entry() {
+        let file = json!({
+            "name": "a.mp4",
+            "size": 1_048_576,
+            "file": {},
+            "lastModifiedDateTime": "2024-01-01T12:00:00Z",
+        });
+        assert_eq!(
+            format_entry(&file, Tz::Asia__Shanghai).as_deref(),
+            Some("a.mp4 | 1.00MB | 2024-01-01 20:00")
+        );
+
+        let folder = json!({
+            "name": "Movies",
+            "folder": { "childCount": 3 },
+            "lastModifiedDateTime": "2024-01-01T12:00:00Z",
+        });
+        assert_eq!(
+            format_entry(&folder, Tz::UTC).as_deref(),
+            Some("Movies/ | 3 items | 2024-01-01 12:00")
+        );
+    }
+
+    #[test]
+    fn test_parse_query_data() {
+        assert_eq!(parse_query_data("ls:3:p1"), Some((3, Action::Page(1))));
+        assert_eq!(parse_query_data("ls:0:close"), Some((0, Action::Close)));
+
+        assert!(parse_query_data("dir_browser:3:p1").is_none());
+        assert!(parse_query_data("ls:3:px").is_none());
+    }
+}
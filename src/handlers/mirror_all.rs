@@ -0,0 +1,439 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    link,
+    utils::{
+        args::{Args, LABEL_SCHEMA},
+        callback::{self, CallbackStore},
+        message::get_message_link,
+    },
+};
+use crate::{
+    error::{ErrorExt, ResultExt, ResultUnwrapExt},
+    message::{ChatEntity, MessageBuilder, OutgoingMessage, TelegramMessage},
+    state::AppState,
+    tasker::BatchAborter,
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::{
+    button, grammers_tl_types as tl, reply_markup,
+    types::{CallbackQuery, Media},
+    InputMessage,
+};
+use proc_macros::{check_admin, check_in_group, check_od_login, check_senders, check_tg_login};
+use std::sync::Mutex;
+
+pub const PATTERN: &str = "/mirrorall";
+pub const CALLBACK_PREFIX: &str = "mirror_all:";
+const MAX_CHECKLISTS: usize = 100;
+
+// checklists posted by /mirrorall, only kept in memory like the browsers of /dir
+static CHECKLISTS: Mutex<CallbackStore<Checklist>> = Mutex::new(CallbackStore::new(MAX_CHECKLISTS));
+
+#[derive(Clone)]
+struct FolderChat {
+    title: String,
+    entity: ChatEntity,
+}
+
+#[derive(Clone)]
+struct Checklist {
+    // the /mirrorall command, only its sender can use the checklist
+    command: TelegramMessage,
+    label: Option<String>,
+    chats: Vec<FolderChat>,
+    selected: Vec<bool>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Toggle(usize),
+    Mirror,
+    Cancel,
+}
+
+#[check_od_login]
+#[check_tg_login]
+#[check_admin]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let args =
+        Args::parse(message.text(), &LABEL_SCHEMA).context(format_unknown_command_help(PATTERN))?;
+    let cmd = args.positionals();
+    let label = args.option("-t").map(ToString::to_string);
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /mirrorall help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 {
+        // /mirrorall $addlist_link
+        let slug = get_addlist_slug(&cmd[1])
+            .ok_or_else(|| anyhow!("not a chat folder link like https://t.me/addlist/xxxx"))?;
+
+        let chats = get_folder_chats(slug, &state).await?;
+
+        if chats.is_empty() {
+            message
+                .respond("No groups or channels in this chat folder.")
+                .await
+                .context("no chats in folder")?;
+
+            return Ok(());
+        }
+
+        let checklist = Checklist {
+            command: message.clone(),
+            label,
+            selected: vec![true; chats.len()],
+            chats,
+        };
+
+        let id = CHECKLISTS.lock().unwrap().insert(checklist.clone());
+
+        message.respond(render(id, &checklist)).await?;
+    } else {
+        return Err(anyhow!(format_unknown_command_help(PATTERN)));
+    }
+
+    Ok(())
+}
+
+pub async fn handle_checklist_query(query: CallbackQuery, state: AppState) -> Result<()> {
+    let Some((id, action)) = std::str::from_utf8(query.data())
+        .ok()
+        .and_then(parse_query_data)
+    else {
+        return Ok(());
+    };
+
+    let checklist = CHECKLISTS.lock().unwrap().get(id);
+
+    let Some(mut checklist) = checklist else {
+        query
+            .answer()
+            .alert("Checklist expired, send /mirrorall again.")
+            .send()
+            .await
+            .context("failed to answer checklist query")?;
+
+        return Ok(());
+    };
+
+    if checklist.command.sender().map(|sender| sender.id()) != Some(query.sender().id()) {
+        query
+            .answer()
+            .alert("Only the sender of /mirrorall can use this checklist.")
+            .send()
+            .await
+            .context("failed to answer checklist query")?;
+
+        return Ok(());
+    }
+
+    let response = match action {
+        Action::Toggle(index) => {
+            if let Some(selected) = checklist.selected.get_mut(index) {
+                *selected = !*selected;
+            }
+
+            let response = render(id, &checklist).input_message;
+            CHECKLISTS.lock().unwrap().update(id, checklist);
+
+            response
+        }
+        Action::Mirror => {
+            CHECKLISTS.lock().unwrap().remove(id);
+
+            let chats = checklist
+                .chats
+                .iter()
+                .zip(&checklist.selected)
+                .filter(|(_, selected)| **selected)
+                .map(|(chat, _)| chat.clone())
+                .collect::<Vec<_>>();
+
+            if chats.is_empty() {
+                InputMessage::text("No chats selected.")
+            } else {
+                let response = format!("Mirroring {} chats.", chats.len());
+
+                tokio::spawn(async move {
+                    let command = checklist.command.clone();
+
+                    if let Err(e) =
+                        mirror_chats(&checklist.command, chats, checklist.label, &state).await
+                    {
+                        e.send(command).await.unwrap_both().trace();
+                    }
+                });
+
+                InputMessage::text(response)
+            }
+        }
+        Action::Cancel => {
+            CHECKLISTS.lock().unwrap().remove(id);
+
+            InputMessage::text("Mirroring cancelled.")
+        }
+    };
+
+    query
+        .answer()
+        .edit(response)
+        .await
+        .context("failed to update checklist")?;
+
+    Ok(())
+}
+
+// chats of the folder the telegram account can see, users and bots are left out
+async fn get_folder_chats(slug: &str, state: &AppState) -> Result<Vec<FolderChat>> {
+    let invite = state
+        .telegram_user
+        .raw()
+        .invoke(&tl::functions::chatlists::CheckChatlistInvite {
+            slug: slug.to_string(),
+        })
+        .await
+        .context("failed to check chat folder link")?;
+
+    let chats = match invite {
+        tl::enums::chatlists::ChatlistInvite::Already(invite) => invite.chats,
+        tl::enums::chatlists::ChatlistInvite::Invite(invite) => invite.chats,
+    };
+
+    let chats = chats
+        .into_iter()
+        .filter_map(|chat| match chat {
+            tl::enums::Chat::Channel(channel) => Some(FolderChat {
+                title: channel.title,
+                entity: channel
+                    .username
+                    .map_or(ChatEntity::from(channel.id), ChatEntity::from),
+            }),
+            tl::enums::Chat::Chat(chat) => Some(FolderChat {
+                title: chat.title,
+                entity: ChatEntity::from(chat.id),
+            }),
+            _ => None,
+        })
+        .collect();
+
+    Ok(chats)
+}
+
+fn render(id: u64, checklist: &Checklist) -> OutgoingMessage {
+    let callback_data = |action: &str| format!("{}{}:{}", CALLBACK_PREFIX, id, action);
+
+    let mut rows = checklist
+        .chats
+        .iter()
+        .zip(&checklist.selected)
+        .enumerate()
+        .map(|(index, (chat, selected))| {
+            let mark = if *selected { "[x]" } else { "[ ]" };
+
+            vec![button::inline(
+                format!("{} {}", mark, chat.title),
+                callback_data(&format!("t{}", index)),
+            )]
+        })
+        .collect::<Vec<_>>();
+
+    rows.push(vec![
+        button::inline("Mirror selected", callback_data("go")),
+        button::inline("Cancel", callback_data("cancel")),
+    ]);
+
+    let content = MessageBuilder::new()
+        .text(&format!(
+            "{} chats in this folder, choose the ones to mirror.",
+            checklist.chats.len()
+        ))
+        .build();
+
+    // the buttons are dropped if the message is restored after restarting
+    OutgoingMessage {
+        input_message: InputMessage::from(content.clone())
+            .reply_markup(&reply_markup::inline(rows)),
+        content: Some(content),
+    }
+}
+
+// each chat is a job like /links, cancelled together by /cancel on the command
+async fn mirror_chats(
+    message: &TelegramMessage,
+    chats: Vec<FolderChat>,
+    label: Option<String>,
+    state: &AppState,
+) -> Result<()> {
+    let telegram_user = &state.telegram_user;
+
+    let chat_user = telegram_user
+        .get_chat(&ChatEntity::from(message.chat()))
+        .await?;
+
+    let batch_aborter = BatchAborter::new();
+    let cancellation_token = batch_aborter.token.clone();
+    state
+        .task_session
+        .batch_aborters
+        .lock()
+        .await
+        .insert((chat_user.id(), message.id()), batch_aborter);
+
+    let fut = async {
+        for chat in chats {
+            let response = match mirror_chat(message, &chat, label.as_deref(), state).await {
+                Ok(count) => format!("{}: {} files added to the queue.", chat.title, count),
+                Err(e) => format!("{}: {}", chat.title, e),
+            };
+
+            message
+                .respond(response.as_str())
+                .await
+                .context(response)
+                .unwrap_or_trace();
+        }
+    };
+
+    tokio::select! {
+        () = fut => {}
+        () = cancellation_token.cancelled() => {}
+    }
+
+    let mut batch_aborters = state.task_session.batch_aborters.lock().await;
+    if let Some(batch_aborter) = batch_aborters.get_mut(&(chat_user.id(), message.id())) {
+        batch_aborter.processing = false;
+    }
+
+    Ok(())
+}
+
+// files of the chat are sent to /links one by one, oldest first
+async fn mirror_chat(
+    message: &TelegramMessage,
+    chat: &FolderChat,
+    label: Option<&str>,
+    state: &AppState,
+) -> Result<usize> {
+    let telegram_user = &state.telegram_user;
+
+    // only chats in the dialogs can be read, the folder link doesn't join them
+    let source_chat = telegram_user
+        .get_chat(&chat.entity)
+        .await
+        .context("the Telegram account hasn't joined this chat, skipped")?;
+
+    let mut messages = telegram_user.iter_messages(&source_chat);
+    let mut message_ids = Vec::new();
+
+    while let Some(source_message) = messages
+        .next()
+        .await
+        .context("failed to iter messages of chat")?
+    {
+        if matches!(
+            source_message.media(),
+            Some(Media::Photo(_) | Media::Document(_) | Media::Sticker(_))
+        ) {
+            message_ids.push(source_message.id());
+        }
+    }
+
+    message_ids.reverse();
+
+    let chat_entity = ChatEntity::from(source_chat);
+
+    for (count, message_id) in message_ids.iter().enumerate() {
+        let message_link = get_message_link(&chat_entity, *message_id);
+
+        let mut message_clone = message.clone();
+        match label {
+            Some(label) => message_clone.override_text(format!("{} -t {}", message_link, label)),
+            None => message_clone.override_text(message_link.clone()),
+        }
+
+        // like a full queue, the rest of the chat would fail too
+        link::handler(message_clone, state.clone())
+            .await
+            .context(format!("stopped at {} after {} files", message_link, count))?;
+    }
+
+    Ok(message_ids.len())
+}
+
+// like https://t.me/addlist/xxxx
+fn get_addlist_slug(link: &str) -> Option<&str> {
+    let path = link
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .strip_prefix("t.me/addlist/")?;
+
+    let slug = path.trim_end_matches('/');
+
+    if slug.is_empty() || slug.contains('/') {
+        None
+    } else {
+        Some(slug)
+    }
+}
+
+// like mirror_all:3:t1
+fn parse_query_data(data: &str) -> Option<(u64, Action)> {
+    let (id, action) = callback::parse_query_data(CALLBACK_PREFIX, data)?;
+
+    let action = match action {
+        "go" => Action::Mirror,
+        "cancel" => Action::Cancel,
+        _ => Action::Toggle(action.strip_prefix('t')?.parse().ok()?),
+    };
+
+    Some((id, action))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_addlist_slug() {
+        assert_eq!(
+            get_addlist_slug("https://t.me/addlist/AbC_12"),
+            Some("AbC_12")
+        );
+        assert_eq!(get_addlist_slug("t.me/addlist/AbC_12/"), Some("AbC_12"));
+
+        assert!(get_addlist_slug("https://t.me/addlist/").is_none());
+        assert!(get_addlist_slug("https://t.me/c/123/4").is_none());
+    }
+
+    #[test]
+    fn test_parse_query_data() {
+        assert_eq!(
+            parse_query_data("mirror_all:3:t1"),
+            Some((3, Action::Toggle(1)))
+        );
+        assert_eq!(
+            parse_query_data("mirror_all:0:go"),
+            Some((0, Action::Mirror))
+        );
+        assert_eq!(
+            parse_query_data("mirror_all:0:cancel"),
+            Some((0, Action::Cancel))
+        );
+
+        assert!(parse_query_data("dir_browser:3:o1").is_none());
+        assert!(parse_query_data("mirror_all:3:tx").is_none());
+    }
+}
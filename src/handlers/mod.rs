@@ -5,19 +5,54 @@
 :license: MIT, see LICENSE for more details.
 */
 
+pub mod animation;
+pub mod audit;
 pub mod auth;
 pub mod auto_delete;
+pub mod backup;
 // pub mod batch;
+pub mod cancel;
 pub mod clear;
+pub mod conflict;
+pub mod copy;
 pub mod dir;
+pub mod dir_browser;
 mod docs;
 pub mod drive;
+pub mod failed;
+pub mod fetch;
 pub mod file;
 pub mod help;
+pub mod history;
+pub mod info;
+pub mod limit;
 pub mod link;
+pub mod link_od;
 pub mod links;
 pub mod logs;
+pub mod ls;
+pub mod mirror_all;
+pub mod mv;
+pub mod onboard;
+pub mod photo;
+pub mod pin_status;
+pub mod priority;
+pub mod queue;
+pub mod rename_remote;
+pub mod retry;
+pub mod rm;
+pub mod scan;
+pub mod share;
 pub mod start;
+pub mod status;
+pub mod stickerpack;
+pub mod tasks;
+pub mod template;
+pub mod tidy;
+pub mod tz;
+pub mod undo;
+pub mod unlink_od;
 pub mod url;
-mod utils;
+pub mod usage;
+pub mod utils;
 pub mod version;
@@ -0,0 +1,60 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        audit::record_audit, check_read_only, message::get_tenant_id, text::split_args,
+        validate_root_path,
+    },
+};
+use crate::{message::TelegramMessage, state::AppState, verify::move_upload_records};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_od_login, check_senders};
+
+pub const PATTERN: &str = "/mv";
+
+#[check_od_login]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    // paths with whitespaces are quoted, like "/Videos/My Trip"
+    let cmd = split_args(message.text());
+
+    if cmd.len() == 2 && !cmd[1].quoted && cmd[1].value == "help" {
+        // /mv help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 3 {
+        // /mv $source_path $destination_path
+        check_read_only()?;
+
+        let source_path = &cmd[1].value;
+        let dest_path = &cmd[2].value;
+
+        validate_root_path(source_path).await?;
+        validate_root_path(dest_path).await?;
+
+        let tenant_id = get_tenant_id(&message);
+        let onedrive = state.get_onedrive(tenant_id).await?;
+
+        let new_path = onedrive.move_item(source_path, dest_path).await?;
+
+        move_upload_records(&state, tenant_id, &onedrive, source_path, &new_path).await;
+
+        record_audit(&message, &state).await;
+
+        let response = format!("Moved {} to {}", source_path, new_path);
+        message.respond(response.as_str()).await.context(response)?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
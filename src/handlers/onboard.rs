@@ -0,0 +1,90 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::utils::message::{get_sender_name, is_admin};
+use crate::{
+    env::ENV,
+    message::{MessageBuilder, TelegramMessage},
+    state::AppState,
+};
+use anyhow::{Context, Result};
+use grammers_client::types::Chat;
+
+const NOT_ALLOWED: &str = "This bot was added by a user who is not allowed to use it, ask the admins of this instance to add it instead.";
+
+// not a command, triggered by the service message of the bot being added to a group
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let me = state
+        .telegram_bot
+        .raw()
+        .get_me()
+        .await
+        .context("failed to get the user of the bot")?;
+
+    if !message.added_user_ids().contains(&me.id()) {
+        return Ok(());
+    }
+
+    let Chat::Group(group) = message.chat() else {
+        return Ok(());
+    };
+
+    let env = ENV.get().unwrap();
+
+    // same as check_senders, everyone can add the bot in multi-tenant mode
+    if !env.multi_tenant && !env.telegram_user.users.is_empty() && !is_admin(&message) {
+        tracing::warn!(
+            "bot added to {} by {}, who is not in tg_user_name",
+            group.title(),
+            get_sender_name(&message).unwrap_or_default()
+        );
+
+        message.respond(NOT_ALLOWED).await.context(NOT_ALLOWED)?;
+
+        return Ok(());
+    }
+
+    let settings_session = &state.settings_session;
+    let chat_id = group.id();
+
+    let is_new = settings_session.register_chat(chat_id).await?;
+
+    tracing::info!(
+        "bot added to {} by {}, new: {}",
+        group.title(),
+        get_sender_name(&message).unwrap_or_default(),
+        is_new
+    );
+
+    let mut builder = MessageBuilder::new().bold("Setup").line();
+
+    builder = if is_new {
+        builder.text("This group is registered with the defaults of this instance.")
+    } else {
+        builder.text("Welcome back, the settings of this group are kept.")
+    };
+
+    let response = builder
+        .line()
+        .text(&format!(
+            "Conflict policy: {}\nShare link: {}",
+            settings_session.get_conflict_policy(chat_id).await?.as_str(),
+            settings_session.get_share_link(chat_id).await?.as_str()
+        ))
+        .line()
+        .line()
+        .text("/auth to login to Telegram and OneDrive if not yet.\n/dir chat $path to upload files of this group to a directory of its own.\n/tz $timezone to show times in your timezone.\n/help for all commands.")
+        .build();
+
+    // kept for later reference, unlike the responses of commands
+    message
+        .respond_final(response.clone())
+        .await
+        .context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{
+    message::TelegramMessage,
+    settings::{parse_photo_quality, PhotoQuality},
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/photo";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /photo
+        show_photo_quality(message, state).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /photo reset
+            reset_photo_quality(message, state).await?;
+        } else if cmd[1] == "help" {
+            // /photo help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /photo $quality
+            set_photo_quality(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_photo_quality(message: TelegramMessage, state: AppState) -> Result<()> {
+    let photo_quality = state
+        .settings_session
+        .get_photo_quality(message.chat().id())
+        .await?;
+
+    let response = format!("Current photo quality is {}", photo_quality.as_str());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_photo_quality(
+    message: TelegramMessage,
+    state: AppState,
+    photo_quality: &str,
+) -> Result<()> {
+    let photo_quality = parse_photo_quality(photo_quality)?;
+
+    state
+        .settings_session
+        .set_photo_quality(message.chat().id(), photo_quality)
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!("Photo quality set to {}", photo_quality.as_str());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_photo_quality(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .settings_session
+        .reset_photo_quality(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "Photo quality reset to {}.",
+        PhotoQuality::Original.as_str()
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
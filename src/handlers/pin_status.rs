@@ -0,0 +1,266 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{
+    client::utils::chat_from_hex,
+    error::ResultExt,
+    history::get_period_starts,
+    message::{MessageBuilder, MessageContent, MessagePriority, TelegramMessage},
+    settings::{parse_pin_interval, PinnedStatus},
+    state::AppState,
+    tasker::{TaskFilter, TaskStatus},
+    utils::get_current_timestamp,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+pub const PATTERN: &str = "/pinStatus";
+
+// completed files listed under the totals
+const RECENT_COMPLETIONS: usize = 5;
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /pinStatus
+        show_pinned_status(message, state).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /pinStatus reset
+            reset_pinned_status(message, state).await?;
+        } else if cmd[1] == "help" {
+            // /pinStatus help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /pinStatus $minutes
+            set_pinned_status(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_pinned_status(message: TelegramMessage, state: AppState) -> Result<()> {
+    let pinned_status = state
+        .settings_session
+        .get_pinned_status(message.chat().id())
+        .await?;
+
+    let response = match pinned_status {
+        Some(pinned_status) => format!(
+            "The archive status is pinned and updated every {} minutes.",
+            pinned_status.interval
+        ),
+        None => "No archive status is pinned.".to_string(),
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_pinned_status(
+    message: TelegramMessage,
+    state: AppState,
+    interval: &str,
+) -> Result<()> {
+    let interval = parse_pin_interval(interval)?;
+
+    let chat_id = message.chat().id();
+
+    // a new message is pinned, so that it's shown on top instead of the old one
+    unpin_status(&message, &state).await;
+
+    let content = build_status(chat_id, &state).await?;
+
+    // kept when /tidy deletes other bot messages
+    let status_message = message.respond_final(content).await?;
+
+    state
+        .telegram_bot
+        .pin_message(message.chat(), status_message.id())
+        .await
+        .context("the bot needs the right to pin messages in this chat")?;
+
+    state
+        .settings_session
+        .set_pinned_status(
+            chat_id,
+            &PinnedStatus {
+                interval,
+                message_id: status_message.id(),
+                chat_hex: message.chat().pack().to_hex(),
+            },
+        )
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "The archive status is pinned and will be updated every {} minutes.",
+        interval
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_pinned_status(message: TelegramMessage, state: AppState) -> Result<()> {
+    unpin_status(&message, &state).await;
+
+    state
+        .settings_session
+        .reset_pinned_status(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = "The archive status is no longer updated.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
+
+// the message may be unpinned or deleted by admins already
+async fn unpin_status(message: &TelegramMessage, state: &AppState) {
+    let pinned_status = state
+        .settings_session
+        .get_pinned_status(message.chat().id())
+        .await;
+
+    let Ok(Some(pinned_status)) = pinned_status else {
+        return;
+    };
+
+    state
+        .telegram_bot
+        .unpin_message(message.chat(), pinned_status.message_id)
+        .await
+        .trace();
+}
+
+// edit the pinned statuses whose interval has passed since they were last updated
+pub async fn update_pinned_statuses(
+    state: &AppState,
+    updated_at: &mut HashMap<i64, Instant>,
+) -> Result<()> {
+    let pinned_statuses = state.settings_session.get_pinned_statuses().await?;
+
+    // chats reset by /pinStatus reset or removed with the bot
+    updated_at.retain(|chat_id, _| pinned_statuses.iter().any(|(id, _)| id == chat_id));
+
+    for (chat_id, pinned_status) in pinned_statuses {
+        let interval = Duration::from_secs(pinned_status.interval * 60);
+
+        if updated_at
+            .get(&chat_id)
+            .is_some_and(|updated_at| updated_at.elapsed() < interval)
+        {
+            continue;
+        }
+
+        updated_at.insert(chat_id, Instant::now());
+
+        let result = async {
+            let content = build_status(chat_id, state).await?;
+            let chat = chat_from_hex(&pinned_status.chat_hex)?;
+
+            state
+                .telegram_bot
+                .edit_message_with_priority(
+                    chat,
+                    pinned_status.message_id,
+                    content,
+                    MessagePriority::Low,
+                )
+                .await
+        }
+        .await;
+
+        result
+            .context(format!(
+                "failed to update pinned status in chat {}",
+                chat_id
+            ))
+            .trace();
+    }
+
+    Ok(())
+}
+
+async fn build_status(chat_id: i64, state: &AppState) -> Result<MessageContent> {
+    let filter = TaskFilter {
+        chat_id: Some(chat_id),
+        ..Default::default()
+    };
+
+    let tasks = state.task_session.get_filtered_tasks(&filter).await?;
+
+    let running = tasks
+        .iter()
+        .filter(|task| task.status == TaskStatus::Started)
+        .count();
+    let waiting = tasks.len() - running;
+
+    let (today, _) = get_period_starts()?;
+    let totals = state.history_session.get_totals(&filter, today).await?;
+
+    let updated_at = state
+        .settings_session
+        .format_timestamp(chat_id, get_current_timestamp())
+        .await?;
+
+    let mut builder = MessageBuilder::new()
+        .bold("Archive status")
+        .line()
+        .text(&format!(
+            "Running: {}\nWaiting: {}\nToday: {} files, {:.2}MB, {} failed",
+            running,
+            waiting,
+            totals.completed,
+            totals.completed_length as f64 / 1024.0 / 1024.0,
+            totals.failed
+        ));
+
+    let recent_completions = state
+        .history_session
+        .get_history(&filter)
+        .await?
+        .into_iter()
+        .filter(|record| record.status == "completed")
+        .take(RECENT_COMPLETIONS)
+        .collect::<Vec<_>>();
+
+    if !recent_completions.is_empty() {
+        builder = builder.line().line().bold("Recently completed");
+
+        for record in recent_completions {
+            builder = builder.line().text(&record.filename);
+        }
+    }
+
+    Ok(builder
+        .line()
+        .line()
+        .text(&format!("Updated at {}", updated_at))
+        .build())
+}
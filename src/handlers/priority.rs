@@ -0,0 +1,64 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, queue::get_task_from_code, text::cmd_parser},
+};
+use crate::{message::TelegramMessage, state::AppState, tasker::TaskStatus};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/priority";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 {
+        if cmd[1] == "help" {
+            // /priority help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /priority $task_id
+            prioritize_task(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn prioritize_task(message: TelegramMessage, state: AppState, code: &str) -> Result<()> {
+    let task_session = &state.task_session;
+
+    let _task_aborters = task_session.task_aborters.lock().await;
+
+    let task = get_task_from_code(&message, &state, code).await?;
+
+    if task.status != TaskStatus::Waiting {
+        return Err(anyhow!("task #{} is {}", task.code, task.status))
+            .context("only waiting tasks can be prioritized");
+    }
+
+    task_session.prioritize_task(task.id).await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "Task #{} {} moved to the front of the queue.",
+        task.code, task.filename
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
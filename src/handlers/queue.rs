@@ -0,0 +1,198 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    history::format_tags,
+    utils::{
+        args::{Args, TASK_FILTER_SCHEMA},
+        audit::record_audit,
+        queue::sandbox_task_filter,
+        text::parse_task_filter,
+    },
+};
+use crate::{
+    message::{MessageBuilder, TelegramMessage},
+    settings::{parse_queue_overflow, QueueLimit, QueueOverflow},
+    state::AppState,
+    tasker::TaskFilter,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/queue";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    // /queue -t $label -c $chat_id -s $sender
+    let args = Args::parse(message.text(), &TASK_FILTER_SCHEMA)
+        .context(format_unknown_command_help(PATTERN))?;
+    let cmd = args.positionals();
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /queue help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+
+        return Ok(());
+    }
+
+    if cmd.len() > 1 && cmd[1] == "limit" {
+        if cmd.len() == 2 {
+            // /queue limit
+            show_queue_limit(message, state).await?;
+        } else if cmd.len() == 3 && cmd[2] == "reset" {
+            // /queue limit reset
+            reset_queue_limit(message, state).await?;
+        } else if cmd.len() == 3 {
+            // /queue limit $max
+            set_queue_limit(message, state, &cmd[2], None).await?;
+        } else if cmd.len() == 4 {
+            // /queue limit $max $overflow
+            set_queue_limit(message, state, &cmd[2], Some(&cmd[3])).await?;
+        } else {
+            return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+        }
+
+        return Ok(());
+    }
+
+    let mut filter = parse_task_filter(&args).context(format_unknown_command_help(PATTERN))?;
+    sandbox_task_filter(&message, &mut filter)?;
+
+    if cmd.len() != 1 {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    show_queue(message, state, &filter).await
+}
+
+async fn show_queue(message: TelegramMessage, state: AppState, filter: &TaskFilter) -> Result<()> {
+    let tasks = state.task_session.get_filtered_tasks(filter).await?;
+
+    if tasks.is_empty() {
+        let response = "No tasks in queue.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let remaining_length = tasks
+        .iter()
+        .map(|task| (task.total_length - task.current_length).max(0))
+        .sum::<i64>();
+
+    let mut builder = MessageBuilder::new()
+        .bold(&format!("Queue ({})", tasks.len()))
+        .line()
+        .text(&format!(
+            "{:.2}MB remaining",
+            remaining_length as f64 / 1024.0 / 1024.0
+        ))
+        .line();
+
+    for task in tasks {
+        let percentage = if task.total_length > 0 {
+            task.current_length as f64 / task.total_length as f64 * 100.0
+        } else {
+            0.0
+        };
+
+        builder = builder
+            .line()
+            .text(&format!("#{} ", task.code))
+            .code(&task.filename)
+            .line()
+            .text(&format!(
+                "{} {:.2}% of {:.2}MB{}{}",
+                task.status,
+                percentage,
+                task.total_length as f64 / 1024.0 / 1024.0,
+                task.mime_type
+                    .as_ref()
+                    .map_or_else(String::new, |mime_type| format!(" {}", mime_type)),
+                format_tags(task.label.as_deref(), task.sender.as_deref())
+            ))
+            .line();
+    }
+
+    let response = builder.build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn show_queue_limit(message: TelegramMessage, state: AppState) -> Result<()> {
+    let queue_limit = state
+        .settings_session
+        .get_queue_limit(message.chat().id())
+        .await?;
+
+    let response = match queue_limit {
+        Some(QueueLimit { max, overflow }) => format!(
+            "Queue of this chat is limited to {} pending tasks, overflow: {}",
+            max,
+            overflow.as_str()
+        ),
+        None => "Queue of this chat is unlimited.".to_string(),
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_queue_limit(
+    message: TelegramMessage,
+    state: AppState,
+    max: &str,
+    overflow: Option<&str>,
+) -> Result<()> {
+    let max = max
+        .parse::<u64>()
+        .ok()
+        .filter(|max| *max > 0)
+        .ok_or_else(|| anyhow!("invalid queue limit: {}", max))
+        .context("queue limit should be a positive number")?;
+
+    let overflow = overflow
+        .map(parse_queue_overflow)
+        .transpose()?
+        .unwrap_or(QueueOverflow::Reject);
+
+    state
+        .settings_session
+        .set_queue_limit(message.chat().id(), QueueLimit { max, overflow })
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "Queue limit set to {} pending tasks, overflow: {}",
+        max,
+        overflow.as_str()
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_queue_limit(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .settings_session
+        .reset_queue_limit(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = "Queue limit removed.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, queue::get_task_from_code, text::cmd_parser},
+};
+use crate::{
+    message::TelegramMessage,
+    state::AppState,
+    tasker::{create_upload_session, TaskStatus},
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_od_login, check_senders};
+
+pub const PATTERN: &str = "/retry";
+
+#[check_senders]
+#[check_in_group]
+#[check_od_login]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 {
+        if cmd[1] == "help" {
+            // /retry help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /retry $task_id
+            retry_task(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn retry_task(message: TelegramMessage, state: AppState, code: &str) -> Result<()> {
+    let task = get_task_from_code(&message, &state, code).await?;
+
+    if task.status != TaskStatus::Failed {
+        return Err(anyhow!("task #{} is {}", task.code, task.status))
+            .context("only failed tasks can be retried");
+    }
+
+    let upload_url = if task.cmd_type.has_upload_session() {
+        let upload_session = create_upload_session(&task, &task.filename, &state).await?;
+
+        Some(upload_session.upload_url().to_string())
+    } else {
+        None
+    };
+
+    state
+        .task_session
+        .requeue_task(task.id, upload_url.as_deref())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!("Task #{} {} requeued.", task.code, task.filename);
+    message.respond(response.as_str()).await.context(response)?;
+
+    tracing::info!("task {} requeued by /retry", task.filename);
+
+    Ok(())
+}
@@ -0,0 +1,241 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        audit::record_audit,
+        callback::{self, CallbackStore},
+        check_read_only,
+        message::get_tenant_id,
+        text::cmd_parser,
+        validate_root_path,
+    },
+};
+use crate::{
+    client::OneDriveClient,
+    env::ENV,
+    features::Feature,
+    message::{MessageBuilder, OutgoingMessage, TelegramMessage},
+    state::AppState,
+    trash::{is_in_trash, trash_item},
+    verify::{delete_upload_records, move_upload_records},
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::{button, reply_markup, types::CallbackQuery, InputMessage};
+use proc_macros::{check_in_group, check_od_login, check_senders};
+use std::sync::Mutex;
+
+pub const PATTERN: &str = "/rm";
+pub const CALLBACK_PREFIX: &str = "rm:";
+const MAX_DELETIONS: usize = 100;
+
+// deletions waiting for confirmation, only kept in memory like the browsers of /dir
+static DELETIONS: Mutex<CallbackStore<Deletion>> = Mutex::new(CallbackStore::new(MAX_DELETIONS));
+
+#[derive(Clone)]
+struct Deletion {
+    // the /rm command, only its sender can confirm it
+    command: TelegramMessage,
+    path: String,
+}
+
+enum Action {
+    Confirm,
+    Cancel,
+}
+
+#[check_od_login]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /rm help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 {
+        // /rm $path
+        check_read_only()?;
+
+        let path = &cmd[1];
+
+        validate_root_path(path).await?;
+
+        if path.trim_matches('/').is_empty() {
+            return Err(anyhow!("the root can't be deleted"));
+        }
+
+        let onedrive = state.get_onedrive(get_tenant_id(&message)).await?;
+
+        let item = onedrive
+            .get_item_by_path(path)
+            .await?
+            .ok_or_else(|| anyhow!("{} not found", path))?;
+
+        let description = if item.folder.is_some() {
+            format!("the folder {} and everything in it", path)
+        } else {
+            format!(
+                "{} ({:.2}MB)",
+                path,
+                item.size.unwrap_or_default() as f64 / 1024.0 / 1024.0
+            )
+        };
+
+        let destination = if should_trash(&onedrive, path).await {
+            format!(
+                "the trash for {} days, send /undo to restore it",
+                ENV.get().unwrap().trash_days
+            )
+        } else {
+            "the recycle bin of OneDrive".to_string()
+        };
+
+        let id = DELETIONS.lock().unwrap().insert(Deletion {
+            command: message.clone(),
+            path: path.to_string(),
+        });
+
+        let callback_data = |action: &str| format!("{}{}:{}", CALLBACK_PREFIX, id, action);
+
+        let content = MessageBuilder::new()
+            .text(&format!(
+                "Delete {}? It's moved to {}.",
+                description, destination
+            ))
+            .build();
+
+        // the buttons are dropped if the message is restored after restarting
+        let response = OutgoingMessage {
+            input_message: InputMessage::from(content.clone()).reply_markup(&reply_markup::inline(
+                vec![vec![
+                    button::inline("Delete", callback_data("yes")),
+                    button::inline("Cancel", callback_data("no")),
+                ]],
+            )),
+            content: Some(content),
+        };
+
+        message.respond(response).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+pub async fn handle_deletion_query(query: CallbackQuery, state: AppState) -> Result<()> {
+    let Some((id, action)) = std::str::from_utf8(query.data())
+        .ok()
+        .and_then(parse_query_data)
+    else {
+        return Ok(());
+    };
+
+    let deletion = DELETIONS.lock().unwrap().get(id);
+
+    let Some(deletion) = deletion else {
+        query
+            .answer()
+            .alert("Confirmation expired, send /rm again.")
+            .send()
+            .await
+            .context("failed to answer deletion query")?;
+
+        return Ok(());
+    };
+
+    if deletion.command.sender().map(|sender| sender.id()) != Some(query.sender().id()) {
+        query
+            .answer()
+            .alert("Only the sender of /rm can confirm it.")
+            .send()
+            .await
+            .context("failed to answer deletion query")?;
+
+        return Ok(());
+    }
+
+    DELETIONS.lock().unwrap().remove(id);
+
+    let response = match action {
+        Action::Confirm => match delete_item(&deletion, &state).await {
+            Ok(true) => format!(
+                "Moved {} to the trash, send /undo to restore it.",
+                deletion.path
+            ),
+            Ok(false) => format!("Deleted {}", deletion.path),
+            Err(e) => format!("Failed to delete {}: {}", deletion.path, e),
+        },
+        Action::Cancel => "Deletion cancelled.".to_string(),
+    };
+
+    query
+        .answer()
+        .edit(InputMessage::text(response))
+        .await
+        .context("failed to update deletion")?;
+
+    Ok(())
+}
+
+// returns whether the item is moved to the trash
+async fn delete_item(deletion: &Deletion, state: &AppState) -> Result<bool> {
+    let tenant_id = get_tenant_id(&deletion.command);
+    let onedrive = state.get_onedrive(tenant_id).await?;
+
+    let trashed = should_trash(&onedrive, &deletion.path).await;
+
+    if trashed {
+        let trashed_item = trash_item(&onedrive, &deletion.path).await?;
+
+        // recorded uploads follow it into the trash, so that /undo brings them back
+        move_upload_records(
+            state,
+            tenant_id,
+            &onedrive,
+            &trashed_item.path,
+            &trashed_item.trash_path,
+        )
+        .await;
+
+        state
+            .settings_session
+            .set_trashed_item(deletion.command.chat().id(), &trashed_item)
+            .await?;
+    } else {
+        onedrive.delete_item(&deletion.path).await?;
+
+        delete_upload_records(state, tenant_id, &onedrive, &deletion.path).await;
+    }
+
+    record_audit(&deletion.command, state).await;
+
+    Ok(trashed)
+}
+
+// items in the trash, or all items if trash_days is 0, go to the recycle bin of onedrive
+async fn should_trash(onedrive: &OneDriveClient, path: &str) -> bool {
+    Feature::Trash.is_active() && !is_in_trash(onedrive, path).await
+}
+
+// like rm:3:yes
+fn parse_query_data(data: &str) -> Option<(u64, Action)> {
+    let (id, action) = callback::parse_query_data(CALLBACK_PREFIX, data)?;
+
+    let action = match action {
+        "yes" => Action::Confirm,
+        "no" => Action::Cancel,
+        _ => return None,
+    };
+
+    Some((id, action))
+}
@@ -0,0 +1,292 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    usage::format_size,
+    utils::{
+        get_tg_file_size, get_tg_mime_type, get_tg_photo_size, is_tg_animation,
+        message::get_tenant_id, text::cmd_parser,
+    },
+};
+use crate::{
+    message::{ChatEntity, MessageBuilder, TelegramMessage},
+    state::AppState,
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::types::Media;
+use proc_macros::{check_admin, check_in_group, check_senders, check_tg_login};
+
+pub const PATTERN: &str = "/scan";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MediaKind {
+    Photo,
+    Video,
+    Audio,
+    Animation,
+    Sticker,
+    Document,
+}
+
+impl MediaKind {
+    const ALL: [Self; 6] = [
+        Self::Photo,
+        Self::Video,
+        Self::Audio,
+        Self::Animation,
+        Self::Sticker,
+        Self::Document,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Photo => "Photos",
+            Self::Video => "Videos",
+            Self::Audio => "Audio",
+            Self::Animation => "Animations",
+            Self::Sticker => "Stickers",
+            Self::Document => "Documents",
+        }
+    }
+
+    // documents are told apart by their mime types
+    fn from_mime_type(mime_type: Option<&str>) -> Self {
+        match mime_type {
+            Some(mime_type) if mime_type.starts_with("video/") => Self::Video,
+            Some(mime_type) if mime_type.starts_with("audio/") => Self::Audio,
+            _ => Self::Document,
+        }
+    }
+}
+
+#[derive(Default)]
+struct ScanStats {
+    messages: usize,
+    // other media like polls and locations, which are not transferred
+    skipped: usize,
+    // count and bytes of each kind, in the order of MediaKind::ALL
+    kinds: [(usize, u64); 6],
+}
+
+impl ScanStats {
+    const fn add(&mut self, kind: MediaKind, length: u64) {
+        let (count, total_length) = &mut self.kinds[kind as usize];
+
+        *count += 1;
+        *total_length += length;
+    }
+
+    fn total(&self) -> (usize, u64) {
+        self.kinds
+            .iter()
+            .fold((0, 0), |(count, length), (kind_count, kind_length)| {
+                (count + kind_count, length + kind_length)
+            })
+    }
+
+    // like Photos: 3, 1.20MB, kinds without files are left out
+    fn format_kinds(&self) -> Vec<String> {
+        MediaKind::ALL
+            .iter()
+            .zip(self.kinds)
+            .filter(|(_, (count, _))| *count > 0)
+            .map(|(kind, (count, length))| {
+                format!("{}: {}, {}", kind.name(), count, format_size(length))
+            })
+            .collect()
+    }
+}
+
+#[check_tg_login]
+#[check_admin]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 {
+        if cmd[1] == "help" {
+            // /scan help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /scan $chat
+            scan(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+// count the files a mirror of the chat would transfer, without transferring them
+async fn scan(message: TelegramMessage, state: AppState, target: &str) -> Result<()> {
+    let telegram_user = &state.telegram_user;
+
+    let chat_entity = get_chat_entity(target)
+        .ok_or_else(|| anyhow!("not a chat id, username or link: {}", target))?;
+
+    let chat = telegram_user
+        .get_chat(&chat_entity)
+        .await
+        .context("chat not found, make sure the user has joined it")?;
+
+    let response = format!(
+        "Scanning {}, it may take a while for a long history.",
+        chat.name()
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    // sizes of photos follow /photo, like the transfer does
+    let photo_quality = state
+        .settings_session
+        .get_photo_quality(message.chat().id())
+        .await?;
+
+    let mut stats = ScanStats::default();
+
+    let mut messages = telegram_user.iter_messages(&chat);
+    while let Some(source_message) = messages
+        .next()
+        .await
+        .context("failed to iter messages of chat")?
+    {
+        stats.messages += 1;
+
+        let Some(media) = source_message.media() else {
+            continue;
+        };
+
+        let length = get_tg_photo_size(&media, photo_quality)
+            .map_or_else(|| get_tg_file_size(&media), |size| size.size() as u64);
+
+        let kind = match &media {
+            Media::Photo(_) => MediaKind::Photo,
+            Media::Sticker(_) => MediaKind::Sticker,
+            Media::Document(_) if is_tg_animation(&media) => MediaKind::Animation,
+            Media::Document(_) => MediaKind::from_mime_type(get_tg_mime_type(&media).as_deref()),
+            _ => {
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        stats.add(kind, length);
+    }
+
+    let (count, length) = stats.total();
+
+    let mut builder = MessageBuilder::new()
+        .bold(&format!("Scan of {}", chat.name()))
+        .line()
+        .text(&format!(
+            "{} messages, {} files to transfer, {}",
+            stats.messages,
+            count,
+            format_size(length)
+        ));
+
+    for line in stats.format_kinds() {
+        builder = builder.line().text(&line);
+    }
+
+    if stats.skipped > 0 {
+        builder = builder.line().text(&format!(
+            "Skipped: {} other media, like polls and locations",
+            stats.skipped
+        ));
+    }
+
+    // only known if onedrive is logged in
+    let remaining_quota = match state.get_onedrive(get_tenant_id(&message)).await {
+        Ok(onedrive) => onedrive.get_remaining_quota().await.ok().flatten(),
+        Err(_) => None,
+    };
+
+    if let Some(remaining_quota) = remaining_quota {
+        builder = builder.line().text(&format!(
+            "Remaining OneDrive quota: {}",
+            format_size(remaining_quota)
+        ));
+    }
+
+    message.respond(builder.build()).await?;
+
+    Ok(())
+}
+
+// chat id, username with or without @, or a link like https://t.me/username or https://t.me/c/123/456
+fn get_chat_entity(target: &str) -> Option<ChatEntity> {
+    let target = target
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+
+    if let Some(path) = target.strip_prefix("t.me/c/") {
+        let chat_id = path.split('/').next()?;
+
+        return chat_id.parse::<i64>().ok().map(ChatEntity::from);
+    }
+
+    if let Some(path) = target.strip_prefix("t.me/") {
+        let username = path.split('/').next()?;
+
+        return (!username.is_empty()).then(|| ChatEntity::from(username.to_string()));
+    }
+
+    target.parse::<i64>().map_or_else(
+        |_| {
+            let username = target.trim_start_matches('@');
+
+            (!username.is_empty()).then(|| ChatEntity::from(username.to_string()))
+        },
+        |chat_id| Some(ChatEntity::from(chat_id)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(target: &str) -> Option<String> {
+        match get_chat_entity(target)? {
+            ChatEntity::Id(id) => Some(id.to_string()),
+            ChatEntity::Username(username) => Some(username),
+            ChatEntity::Chat(_) => None,
+        }
+    }
+
+    #[test]
+    fn test_get_chat_entity() {
+        assert_eq!(describe("https://t.me/c/123/456").as_deref(), Some("123"));
+        assert_eq!(describe("https://t.me/channel").as_deref(), Some("channel"));
+        assert_eq!(describe("t.me/channel/10").as_deref(), Some("channel"));
+        assert_eq!(describe("-100123").as_deref(), Some("-100123"));
+        assert_eq!(describe("@channel").as_deref(), Some("channel"));
+
+        assert!(get_chat_entity("https://t.me/").is_none());
+        assert!(get_chat_entity("@").is_none());
+    }
+
+    #[test]
+    fn test_scan_stats() {
+        let mut stats = ScanStats::default();
+        stats.add(MediaKind::Photo, 1_048_576);
+        stats.add(MediaKind::Photo, 1_048_576);
+        stats.add(MediaKind::from_mime_type(Some("video/mp4")), 3_145_728);
+
+        assert_eq!(stats.total(), (3, 5_242_880));
+        assert_eq!(
+            stats.format_kinds(),
+            vec!["Photos: 2, 2.00MB", "Videos: 1, 3.00MB"]
+        );
+        assert_eq!(MediaKind::from_mime_type(None), MediaKind::Document);
+    }
+}
@@ -0,0 +1,90 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{env::ENV, message::TelegramMessage, settings::ShareLink, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/share";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /share
+        show_share_link(message, state).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /share reset
+            reset_share_link(message, state).await?;
+        } else if cmd[1] == "help" {
+            // /share help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /share $scope
+            set_share_link(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_share_link(message: TelegramMessage, state: AppState) -> Result<()> {
+    let share_link = state
+        .settings_session
+        .get_share_link(message.chat().id())
+        .await?;
+
+    let response = format!("Share links of uploaded files: {}.", share_link.as_str());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_share_link(message: TelegramMessage, state: AppState, share_link: &str) -> Result<()> {
+    let share_link = share_link.parse::<ShareLink>()?;
+
+    state
+        .settings_session
+        .set_share_link(message.chat().id(), share_link)
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!("Share links set to {}.", share_link.as_str());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_share_link(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .settings_session
+        .reset_share_link(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "Share links reset to {}.",
+        ENV.get().unwrap().share_link.as_str()
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
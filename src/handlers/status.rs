@@ -0,0 +1,212 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        args::{ArgSchema, Args},
+        message::{get_tenant_id, is_admin},
+        queue::sandbox_task_filter,
+    },
+};
+use crate::{
+    client::onedrive::{format_rate, THROTTLE, UPLOAD_LIMIT},
+    crypto::derive_tenant_token,
+    env::ENV,
+    features::Feature,
+    message::{MessageBuilder, TelegramMessage},
+    state::AppState,
+    tasker::{HostStats, TaskFilter, TaskStatus},
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/status";
+
+// -v, --verbose
+const STATUS_SCHEMA: ArgSchema = ArgSchema {
+    options: &[],
+    flags: &["-v", "--verbose"],
+};
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let args = Args::parse(message.text(), &STATUS_SCHEMA)
+        .context(format_unknown_command_help(PATTERN))?;
+    let cmd = args.positionals();
+
+    if cmd.len() == 1 {
+        // /status -v
+        let is_verbose = args.flag("-v") || args.flag("--verbose");
+
+        show_status(message, state, is_verbose).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /status help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_status(message: TelegramMessage, state: AppState, is_verbose: bool) -> Result<()> {
+    let task_session = &state.task_session;
+
+    let mut filter = TaskFilter::default();
+    sandbox_task_filter(&message, &mut filter)?;
+
+    let tasks = task_session.get_filtered_tasks(&filter).await?;
+
+    let running = tasks
+        .iter()
+        .filter(|task| task.status == TaskStatus::Started)
+        .count();
+    let waiting = tasks.len() - running;
+
+    let mut builder = MessageBuilder::new().bold("Status").line().text(&format!(
+        "Running: {}\nWaiting: {}\nConcurrency: {}\nUpload sessions: {}",
+        running,
+        waiting,
+        task_session.concurrency.limit(),
+        ENV.get().unwrap().upload_sessions.max(1)
+    ));
+
+    if ENV.get().unwrap().read_only {
+        builder = builder.line().text("Queue paused, read-only mode.");
+    } else if let Some(reason) = state.usage_session.get_exceeded_cap().await? {
+        builder = builder.line().text(&format!("Queue paused, {}.", reason));
+    }
+
+    if let Some(remaining) = THROTTLE.remaining() {
+        builder = builder.line().text(&format!(
+            "Uploads throttled by OneDrive for {}s.",
+            remaining.as_secs() + 1
+        ));
+    }
+
+    if let Some(upload_limit) = UPLOAD_LIMIT.get() {
+        builder = builder.line().text(&format!(
+            "Uploads limited to {}.",
+            format_rate(upload_limit)
+        ));
+    }
+
+    builder = build_features(builder);
+
+    if is_verbose {
+        // hosts of all tasks are shown, so only to admins in multi-tenant mode
+        if ENV.get().unwrap().multi_tenant && !is_admin(&message) {
+            builder = builder
+                .line()
+                .line()
+                .text("Transfer stats are only shown to admins.");
+
+            if let Some(dashboard_token) = get_dashboard_token(&message) {
+                builder = builder
+                    .line()
+                    .text("Your dashboard token: ")
+                    .code(&dashboard_token);
+            }
+        } else {
+            builder = build_host_stats(builder, &task_session.throughput.get_stats());
+        }
+    }
+
+    let response = builder.build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
+
+// tenants only see their own tasks on the dashboard with it
+fn get_dashboard_token(message: &TelegramMessage) -> Option<String> {
+    let web_token = ENV.get().unwrap().web_token.as_deref()?;
+    let tenant_id = get_tenant_id(message)?;
+
+    Feature::Dashboard
+        .is_active()
+        .then(|| derive_tenant_token(web_token, tenant_id))
+}
+
+// active features, and why the others are not
+fn build_features(builder: MessageBuilder) -> MessageBuilder {
+    let (active, inactive): (Vec<_>, Vec<_>) = Feature::ALL
+        .into_iter()
+        .partition(|feature| feature.is_active());
+
+    let active = active.into_iter().map(Feature::as_str).collect::<Vec<_>>();
+
+    let inactive = inactive
+        .into_iter()
+        .map(|feature| match feature.get_missing_env() {
+            Some(env) if feature.is_enabled() => format!("{} (needs {})", feature.as_str(), env),
+            _ => format!("{} (disabled)", feature.as_str()),
+        })
+        .collect::<Vec<_>>();
+
+    let mut builder = builder.line().text(&format!(
+        "Features: {}",
+        if active.is_empty() {
+            "none".to_string()
+        } else {
+            active.join(", ")
+        }
+    ));
+
+    if !inactive.is_empty() {
+        builder = builder
+            .line()
+            .text(&format!("Inactive: {}", inactive.join(", ")));
+    }
+
+    builder
+}
+
+// the slowest side of a transfer is the bottleneck
+fn build_host_stats(mut builder: MessageBuilder, stats: &[HostStats]) -> MessageBuilder {
+    builder = builder.line().line().bold("Chunks in the last 10 minutes");
+
+    if stats.is_empty() {
+        return builder.line().text("No chunks transferred.");
+    }
+
+    for HostStats {
+        direction,
+        host,
+        chunks,
+        latency,
+        speed,
+    } in stats
+    {
+        builder = builder
+            .line()
+            .line()
+            .code(&format!("{} {}", direction, host))
+            .line()
+            .text(&format!(
+                "Chunks: {}\nLatency p50/p90/p99: {:.2}s / {:.2}s / {:.2}s\nSpeed p50/p90/p99: {} / {} / {}",
+                chunks,
+                latency.p50,
+                latency.p90,
+                latency.p99,
+                format_speed(speed.p50),
+                format_speed(speed.p90),
+                format_speed(speed.p99)
+            ));
+    }
+
+    builder
+}
+
+fn format_speed(bytes_per_second: f64) -> String {
+    format!("{:.2}MB/s", bytes_per_second / 1024.0 / 1024.0)
+}
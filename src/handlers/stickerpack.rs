@@ -0,0 +1,179 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{
+        args::{ArgSchema, Args},
+        check_read_only, get_root_path,
+        message::{get_sender_name, get_tenant_id},
+        queue::{check_queue_limit, check_quota},
+    },
+};
+use crate::{
+    env::ENV,
+    message::{ChatEntity, MessageBuilder, TelegramMessage},
+    state::AppState,
+    tasker::{CmdType, InsertTask, CONVERTED_MIME_TYPE},
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
+use std::sync::atomic::Ordering;
+
+pub const PATTERN: &str = "/stickerpack";
+
+const STICKER_PACK_LINK_PREFIX: &str = "https://t.me/addstickers/";
+
+const CONVERT_SCHEMA: ArgSchema = ArgSchema {
+    options: &[],
+    flags: &["-c"],
+};
+
+#[check_od_login]
+#[check_tg_login]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let args = Args::parse(message.text(), &CONVERT_SCHEMA)
+        .context(format_unknown_command_help(PATTERN))?;
+    let cmd = args.positionals();
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /stickerpack help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else if cmd.len() == 2 {
+        // /stickerpack $link_or_name [-c], -c to convert animated stickers into gif
+        archive_sticker_pack(message, state, &cmd[1], args.flag("-c")).await?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+// queued as a task, which downloads with the user client and uploads into a folder named after the pack
+async fn archive_sticker_pack(
+    message: TelegramMessage,
+    state: AppState,
+    link_or_name: &str,
+    should_convert: bool,
+) -> Result<()> {
+    check_read_only()?;
+
+    let short_name = parse_sticker_pack_name(link_or_name)?;
+
+    if should_convert && ENV.get().unwrap().tgs_converter_path.is_none() {
+        return Err(anyhow!(
+            "tgs_converter_path is required to convert stickers"
+        ));
+    }
+
+    check_queue_limit(&message, &state).await?;
+
+    let telegram_user = &state.telegram_user;
+    let task_session = &state.task_session;
+
+    let sticker_set = telegram_user.get_sticker_set(short_name).await?;
+
+    let total_length = sticker_set
+        .stickers
+        .iter()
+        .map(|sticker| sticker.size() as u64)
+        .sum::<u64>();
+
+    let onedrive = state.get_onedrive(get_tenant_id(&message)).await?;
+
+    // pinned to another account if the current one doesn't have enough quota
+    let account = match check_quota(&message, &state, total_length).await? {
+        Some(account) => Some(account),
+        None => onedrive.get_current_username().await?,
+    };
+
+    let root_path = get_root_path(&message, &onedrive, &state, true).await?;
+
+    let chat_user = telegram_user
+        .get_chat(&ChatEntity::from(message.chat()))
+        .await?;
+
+    let code = task_session.generate_task_code().await?;
+
+    let response = MessageBuilder::new()
+        .text(&format!("#{} Archive ", code))
+        .link(
+            &sticker_set.title,
+            &format!("{}{}", STICKER_PACK_LINK_PREFIX, sticker_set.short_name),
+        )
+        .text(&format!(" ({} stickers)", sticker_set.stickers.len()))
+        .build();
+    let message_indicator_id = message
+        .respond_final(response.clone())
+        .await
+        .context(response)?
+        .id();
+
+    let chat_bot_hex = message.chat().pack().to_hex();
+    let chat_user_hex = chat_user.pack().to_hex();
+
+    let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
+
+    task_session
+        .insert_task(InsertTask {
+            code,
+            cmd_type: CmdType::StickerPack,
+            filename: sticker_set.short_name.clone(),
+            original_filename: None,
+            root_path,
+            url: None,
+            password: None,
+            upload_url: String::new(),
+            current_length: 0,
+            total_length,
+            mime_type: should_convert.then(|| CONVERTED_MIME_TYPE.to_string()),
+            photo_type: None,
+            chat_id: message.chat().id(),
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex: None,
+            message_id: message.id(),
+            message_indicator_id,
+            message_origin_id: None,
+            target_chat_hex: None,
+            group_id: None,
+            auto_delete,
+            label: None,
+            sender: get_sender_name(&message),
+            tenant_id: get_tenant_id(&message),
+            account,
+            buffer_path: None,
+        })
+        .await?;
+
+    tracing::info!(
+        "inserted sticker pack task: {} size: {}",
+        sticker_set.short_name,
+        total_length
+    );
+
+    Ok(())
+}
+
+// like https://t.me/addstickers/$name or $name
+fn parse_sticker_pack_name(link_or_name: &str) -> Result<&str> {
+    let name = link_or_name
+        .strip_prefix(STICKER_PACK_LINK_PREFIX)
+        .unwrap_or(link_or_name)
+        .trim_end_matches('/');
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(anyhow!("invalid sticker pack: {}", link_or_name));
+    }
+
+    Ok(name)
+}
@@ -0,0 +1,146 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{
+    listener::CONVERSATION_TIMEOUT,
+    message::{ChatEntity, TelegramMessage},
+    state::AppState,
+    tasker::{
+        export::{deserialize_tasks, serialize_tasks},
+        TaskFilter,
+    },
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::InputMessage;
+use proc_macros::{check_admin, check_in_group, check_senders, check_tg_login};
+
+pub const PATTERN: &str = "/tasks";
+
+#[check_tg_login]
+#[check_admin]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 {
+        if cmd[1] == "export" {
+            // /tasks export
+            export_tasks(message, state).await?;
+        } else if cmd[1] == "import" {
+            // /tasks import, replying to the file or sending it afterwards
+            match message.reply_to_message_id() {
+                Some(file_message_id) => import_tasks(message, state, file_message_id).await?,
+                None => wait_for_tasks_file(message, &state).await?,
+            }
+        } else if cmd[1] == "help" {
+            // /tasks help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            return Err(anyhow!("sub command error")).context(format_unknown_command_help(PATTERN));
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn export_tasks(message: TelegramMessage, state: AppState) -> Result<()> {
+    let tasks = state
+        .task_session
+        .get_filtered_tasks(&TaskFilter::default())
+        .await?;
+
+    if tasks.is_empty() {
+        let response = "No tasks in queue.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    }
+
+    let count = tasks.len();
+    let data = serialize_tasks(tasks)?;
+
+    let file = state
+        .telegram_bot
+        .upload_stream(&mut data.as_slice(), data.len(), "tasks.json".to_string())
+        .await?;
+
+    let response = format!(
+        "{} tasks exported, reply /tasks import to this file on another instance.",
+        count
+    );
+    message
+        .respond(InputMessage::text(&response).file(file))
+        .await
+        .context(response)?;
+
+    Ok(())
+}
+
+async fn wait_for_tasks_file(message: TelegramMessage, state: &AppState) -> Result<()> {
+    let command_message = message.clone();
+
+    state.conversations.expect(
+        &message,
+        CONVERSATION_TIMEOUT,
+        |file_message, state| async move {
+            import_tasks(command_message, state, file_message.id()).await
+        },
+    );
+
+    let response = "Send the file exported by /tasks export, or /cancel.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
+
+// the exported file is downloaded by the user client, like batch scripts
+async fn import_tasks(
+    message: TelegramMessage,
+    state: AppState,
+    file_message_id: i32,
+) -> Result<()> {
+    let telegram_user = &state.telegram_user;
+
+    let chat_user = telegram_user
+        .get_chat(&ChatEntity::from(message.chat()))
+        .await?;
+
+    let media = telegram_user
+        .get_message(&chat_user, file_message_id)
+        .await?
+        .media()
+        .ok_or_else(|| anyhow!("the message does not contain a file"))
+        .context("send the file exported by /tasks export")?;
+
+    let data = telegram_user
+        .download_stream(&media)
+        .read_to_end()
+        .await
+        .context("failed to download exported tasks")?;
+
+    let count = state
+        .task_session
+        .import_tasks(deserialize_tasks(&data)?)
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!("{} tasks imported.", count);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{env::ENV, message::TelegramMessage, settings::PathTemplate, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/template";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /template
+        show_path_template(message, state).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /template reset
+            reset_path_template(message, state).await?;
+        } else if cmd[1] == "off" {
+            // /template off
+            set_path_template(message, state, None).await?;
+        } else if cmd[1] == "help" {
+            // /template help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /template $template
+            let path_template = cmd[1].parse::<PathTemplate>()?;
+
+            set_path_template(message, state, Some(path_template)).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_path_template(message: TelegramMessage, state: AppState) -> Result<()> {
+    let path_template = state
+        .settings_session
+        .get_path_template(message.chat().id())
+        .await?;
+
+    let response = path_template.map_or_else(
+        || "Files are uploaded to the directory with their own names.".to_string(),
+        |path_template| {
+            format!(
+                "Files are uploaded to {} in the directory.",
+                path_template.as_str()
+            )
+        },
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_path_template(
+    message: TelegramMessage,
+    state: AppState,
+    path_template: Option<PathTemplate>,
+) -> Result<()> {
+    state
+        .settings_session
+        .set_path_template(message.chat().id(), path_template.as_ref())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = path_template.map_or_else(
+        || "Path template turned off.".to_string(),
+        |path_template| format!("Path template set to {}", path_template.as_str()),
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_path_template(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .settings_session
+        .reset_path_template(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = ENV.get().unwrap().path_template.as_ref().map_or_else(
+        || "Path template reset, files are uploaded with their own names.".to_string(),
+        |path_template| format!("Path template reset to {}.", path_template.as_str()),
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{message::TelegramMessage, settings::parse_tidy_delay, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/tidy";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /tidy
+        show_tidy_delay(message, state).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /tidy reset
+            reset_tidy_delay(message, state).await?;
+        } else if cmd[1] == "help" {
+            // /tidy help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /tidy $minutes
+            set_tidy_delay(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_tidy_delay(message: TelegramMessage, state: AppState) -> Result<()> {
+    let delay = state
+        .settings_session
+        .get_tidy_delay(message.chat().id())
+        .await?;
+
+    let response = match delay {
+        Some(delay) => format!(
+            "Bot messages except final task messages are deleted after {} minutes.",
+            delay
+        ),
+        None => "Bot messages are kept.".to_string(),
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_tidy_delay(message: TelegramMessage, state: AppState, delay: &str) -> Result<()> {
+    let delay = parse_tidy_delay(delay)?;
+
+    state
+        .settings_session
+        .set_tidy_delay(message.chat().id(), delay)
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!(
+        "Bot messages except final task messages will be deleted after {} minutes.",
+        delay
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_tidy_delay(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .settings_session
+        .reset_tidy_delay(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = "Bot messages will be kept.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, text::cmd_parser},
+};
+use crate::{
+    message::TelegramMessage, settings::parse_timezone, state::AppState,
+    utils::get_current_timestamp,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/tz";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /tz
+        show_timezone(message, state).await?;
+    } else if cmd.len() == 2 {
+        if cmd[1] == "reset" {
+            // /tz reset
+            reset_timezone(message, state).await?;
+        } else if cmd[1] == "help" {
+            // /tz help
+            message
+                .respond(format_help(PATTERN))
+                .await
+                .context("help")?;
+        } else {
+            // /tz $timezone
+            set_timezone(message, state, &cmd[1]).await?;
+        }
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_timezone(message: TelegramMessage, state: AppState) -> Result<()> {
+    let settings_session = &state.settings_session;
+    let chat_id = message.chat().id();
+
+    let timezone = settings_session.get_timezone(chat_id).await?;
+    let current_time = settings_session
+        .format_timestamp(chat_id, get_current_timestamp())
+        .await?;
+
+    let response = format!(
+        "Current timezone is {}\nCurrent time is {}",
+        timezone.name(),
+        current_time
+    );
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn set_timezone(message: TelegramMessage, state: AppState, timezone: &str) -> Result<()> {
+    let timezone = parse_timezone(timezone)?;
+
+    state
+        .settings_session
+        .set_timezone(message.chat().id(), timezone)
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!("Timezone set to {}", timezone.name());
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
+
+async fn reset_timezone(message: TelegramMessage, state: AppState) -> Result<()> {
+    state
+        .settings_session
+        .reset_timezone(message.chat().id())
+        .await?;
+
+    record_audit(&message, &state).await;
+
+    let response = "Timezone reset to UTC.";
+    message.respond(response).await.context(response)?;
+
+    Ok(())
+}
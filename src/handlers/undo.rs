@@ -0,0 +1,80 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, check_read_only, message::get_tenant_id, text::cmd_parser},
+};
+use crate::{
+    message::TelegramMessage, state::AppState, trash::restore_item, verify::move_upload_records,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_od_login, check_senders};
+
+pub const PATTERN: &str = "/undo";
+
+#[check_od_login]
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /undo
+        check_read_only()?;
+
+        undo_deletion(message, state).await?;
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /undo help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+// only the last deletion of the chat can be undone
+async fn undo_deletion(message: TelegramMessage, state: AppState) -> Result<()> {
+    let chat_id = message.chat().id();
+    let settings_session = &state.settings_session;
+
+    let Some(trashed_item) = settings_session.get_trashed_item(chat_id).await? else {
+        let response = "Nothing to undo.";
+        message.respond(response).await.context(response)?;
+
+        return Ok(());
+    };
+
+    let tenant_id = get_tenant_id(&message);
+    let onedrive = state.get_onedrive(tenant_id).await?;
+
+    restore_item(&onedrive, &trashed_item)
+        .await
+        .context(format!("failed to restore {}", trashed_item.path))?;
+
+    move_upload_records(
+        &state,
+        tenant_id,
+        &onedrive,
+        &trashed_item.trash_path,
+        &trashed_item.path,
+    )
+    .await;
+
+    settings_session.remove_trashed_item(chat_id).await?;
+
+    record_audit(&message, &state).await;
+
+    let response = format!("Restored {}", trashed_item.path);
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
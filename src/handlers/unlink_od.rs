@@ -0,0 +1,87 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    utils::{audit::record_audit, check_read_only, message::get_tenant_id, text::cmd_parser},
+};
+use crate::{message::TelegramMessage, state::AppState, tasker::TaskFilter};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/unlinkod";
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 2 && cmd[1] == "help" {
+        // /unlinkod help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+
+        return Ok(());
+    }
+
+    if cmd.len() != 1 {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    // /unlinkod
+    unlink_onedrive(message, state).await
+}
+
+async fn unlink_onedrive(message: TelegramMessage, state: AppState) -> Result<()> {
+    check_read_only()?;
+
+    let tenant_id = get_tenant_id(&message)
+        .ok_or_else(|| anyhow!("onedrive accounts are only linked to users in multi-tenant mode"))
+        .context("use /drive logout instead")?;
+
+    // upload urls of queued tasks stay valid without the tokens
+    let unfinished_tasks = state
+        .task_session
+        .get_filtered_tasks(&TaskFilter {
+            tenant_id: Some(tenant_id),
+            ..Default::default()
+        })
+        .await?;
+
+    if !unfinished_tasks.is_empty() {
+        return Err(anyhow!(
+            "{} of your tasks are not finished",
+            unfinished_tasks.len()
+        ))
+        .context("wait for them or cancel them with /cancel before unlinking");
+    }
+
+    let onedrive = state.get_onedrive(Some(tenant_id)).await?;
+    let usernames = onedrive.get_usernames().await?;
+
+    for username in &usernames {
+        onedrive.revoke_tokens(Some(username)).await;
+    }
+
+    state.remove_onedrive_tenant(tenant_id).await?;
+
+    record_audit(&message, &state).await;
+
+    let response = if usernames.is_empty() {
+        "No OneDrive account linked to you.".to_string()
+    } else {
+        format!(
+            "OneDrive accounts unlinked, their tokens are revoked and removed:\n{}",
+            usernames.join("\n")
+        )
+    };
+    message.respond(response.as_str()).await.context(response)?;
+
+    Ok(())
+}
@@ -10,21 +10,26 @@ use std::sync::atomic::Ordering;
 use super::{
     docs::{format_help, format_unknown_command_help},
     utils::{
-        get_filename,
-        text::{cmd_parser, TextExt},
+        apply_path_template,
+        args::{Args, URL_SCHEMA},
+        get_filename, get_root_path, get_url_mime_type,
+        message::{get_message_link, get_sender_name, get_tenant_id},
+        queue::{check_queue_limit, check_quota},
+        text::TextExt,
+        upload::skip_existing_file,
     },
 };
 use crate::{
-    handlers::utils::message::format_message_link,
-    message::{ChatEntity, TelegramMessage},
+    error::{ErrorExt, ResultExt, ResultUnwrapExt},
+    message::{ChatEntity, MessageBuilder, TelegramMessage},
     state::AppState,
-    tasker::{CmdType, InsertTask},
+    tasker::{CmdType, InsertTask, TaskGroupKey},
+    url_auth::build_url_request,
     utils::get_http_client,
 };
 use anyhow::{anyhow, Context, Result};
-use grammers_client::InputMessage;
 use proc_macros::{check_in_group, check_od_login, check_senders, check_tg_login};
-use reqwest::header;
+use reqwest::{header, Method, StatusCode};
 
 pub const PATTERN: &str = "/url";
 
@@ -33,13 +38,62 @@ pub const PATTERN: &str = "/url";
 #[check_senders]
 #[check_in_group]
 pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
-    let cmd = cmd_parser(message.text());
+    let args =
+        Args::parse(message.text(), &URL_SCHEMA).context(format_unknown_command_help(PATTERN))?;
+
+    if args.option("-p").is_none() {
+        return handle_url(message, &args, state).await;
+    }
+
+    delete_command_with_password(&message, &state)
+        .await
+        .context("failed to delete /url command with password")
+        .trace();
+
+    // the command is gone, so errors are sent to the chat instead of replying to it
+    if let Err(e) = handle_url(message.clone(), &args, state.clone()).await {
+        e.send_chat(&state.telegram_bot, message.chat().pack())
+            .await
+            .unwrap_both()
+            .trace();
+    }
+
+    Ok(())
+}
+
+// also checked for /url commands dropped by the throttle before reaching the handler
+pub fn has_password(text: &str) -> bool {
+    Args::parse(text, &URL_SCHEMA).is_ok_and(|args| args.option("-p").is_some())
+}
+
+// deleted as soon as it's received, whatever the outcome, so that the password isn't left in the chat
+pub async fn delete_command_with_password(
+    message: &TelegramMessage,
+    state: &AppState,
+) -> Result<()> {
+    let telegram_user = &state.telegram_user;
+
+    let chat_user = telegram_user
+        .get_chat(&ChatEntity::from(message.chat()))
+        .await?;
+
+    telegram_user
+        .delete_messages(&chat_user, &[message.id()])
+        .await?;
+
+    Ok(())
+}
+
+async fn handle_url(message: TelegramMessage, args: &Args, state: AppState) -> Result<()> {
+    let cmd = args.positionals();
+    let label = args.option("-t").map(ToString::to_string);
+    let password = args.option("-p").map(ToString::to_string);
 
     if cmd.len() == 2 {
         if cmd[1] == "help" {
             // /url help
             message
-                .respond(InputMessage::html(format_help(PATTERN)))
+                .respond(format_help(PATTERN))
                 .await
                 .context("help")?;
 
@@ -47,24 +101,37 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
         } else {
             // /url $url
             let telegram_user = &state.telegram_user;
-            let onedrive = &state.onedrive;
+            let onedrive = &state.get_onedrive(get_tenant_id(&message)).await?;
             let task_session = &state.task_session;
 
             let url = cmd[1].url_encode();
 
             if url.starts_with("http://") || url.starts_with("https://") {
+                check_queue_limit(&message, &state).await?;
+
                 let http_client = get_http_client()?;
 
-                let response = http_client
-                    .head(&url)
-                    .send()
-                    .await
-                    .context("failed to send head request for /url")?;
+                let response =
+                    build_url_request(&http_client, Method::HEAD, &url, password.as_deref())?
+                        .send()
+                        .await
+                        .context("failed to send head request for /url")?;
+
+                if matches!(
+                    response.status(),
+                    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                ) {
+                    return Err(anyhow!("{}", response.status())).context(if password.is_some() {
+                        "wrong password for the url"
+                    } else {
+                        "the url is password-protected, pass the password with -p"
+                    });
+                }
 
                 let filename = get_filename(
                     response.url().as_ref(),
                     &response,
-                    &onedrive.get_root_path(false).await?,
+                    &get_root_path(&message, onedrive, &state, false).await?,
                 )?;
 
                 let total_length = match response.headers().get(header::CONTENT_LENGTH) {
@@ -80,25 +147,84 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
                     )),
                 };
 
+                // pinned to another account if the current one doesn't have enough quota
+                let account = match check_quota(&message, &state, total_length).await? {
+                    Some(account) => Some(account),
+                    None => onedrive.get_current_username().await?,
+                };
+
+                let mime_type = get_url_mime_type(&response);
+
                 let chat_user = telegram_user
                     .get_chat(&ChatEntity::from(message.chat()))
                     .await?;
 
-                let response = format!(
-                    "{}\n\n{}",
-                    url,
-                    format_message_link(chat_user.id(), message.id(), &filename)
-                );
+                let code = task_session.generate_task_code().await?;
+
+                let filename = task_session
+                    .get_unique_filename(
+                        message.chat().id(),
+                        TaskGroupKey::new(None, message.id()),
+                        &filename,
+                    )
+                    .await?;
+
+                let root_path = get_root_path(&message, onedrive, &state, true).await?;
+                let (root_path, filename) = apply_path_template(
+                    &message,
+                    &message,
+                    root_path,
+                    filename,
+                    label.as_deref(),
+                    &state,
+                )
+                .await?;
+                let conflict_policy = state
+                    .settings_session
+                    .get_conflict_policy(message.chat().id())
+                    .await?;
+
+                if skip_existing_file(
+                    &message,
+                    onedrive,
+                    account.as_deref(),
+                    &root_path,
+                    &filename,
+                    conflict_policy,
+                )
+                .await?
+                {
+                    return Ok(());
+                }
+
+                // the command with the password is deleted, so it's not linked
+                let response = MessageBuilder::new()
+                    .text(&url)
+                    .line()
+                    .line()
+                    .text(&format!("#{} ", code));
+                let response = if password.is_some() {
+                    response.text(&filename)
+                } else {
+                    response.link(
+                        &filename,
+                        &get_message_link(&ChatEntity::from(chat_user.id()), message.id()),
+                    )
+                }
+                .build();
                 let message_indicator_id = message
-                    .respond(InputMessage::html(&response))
+                    .respond_final(response.clone())
                     .await
                     .context(response)?
                     .id();
 
-                let root_path = onedrive.get_root_path(true).await?;
-
                 let (upload_session, upload_session_meta) = onedrive
-                    .multipart_upload_session_builder(&root_path, &filename)
+                    .multipart_upload_session_builder(
+                        account.as_deref(),
+                        &root_path,
+                        &filename,
+                        conflict_policy,
+                    )
                     .await?;
 
                 let current_length = upload_session_meta
@@ -111,26 +237,45 @@ pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
 
                 let auto_delete = state.should_auto_delete.load(Ordering::Acquire);
 
+                // bound to the indicator instead, so that deleting the command doesn't cancel the task
+                let message_id = if password.is_some() {
+                    message_indicator_id
+                } else {
+                    message.id()
+                };
+
                 // in case if cancellation happens before inserting the task
                 let _aborters = state.task_session.task_aborters.lock().await;
 
                 task_session
                     .insert_task(InsertTask {
+                        code,
                         cmd_type: CmdType::Url,
                         filename: filename.clone(),
+                        original_filename: None,
                         root_path,
                         url: Some(url),
+                        password,
                         upload_url: upload_session.upload_url().to_string(),
                         current_length,
                         total_length,
+                        mime_type,
+                        photo_type: None,
                         chat_id: message.chat().id(),
                         chat_bot_hex,
                         chat_user_hex,
                         chat_origin_hex: None,
-                        message_id: message.id(),
+                        message_id,
                         message_indicator_id,
                         message_origin_id: None,
+                        target_chat_hex: None,
+                        group_id: None,
                         auto_delete,
+                        label,
+                        sender: get_sender_name(&message),
+                        tenant_id: get_tenant_id(&message),
+                        account,
+                        buffer_path: None,
                     })
                     .await?;
 
@@ -0,0 +1,194 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    docs::{format_help, format_unknown_command_help},
+    history::format_tags,
+    utils::{
+        message::{get_tenant_id, is_admin},
+        queue::sandbox_task_filter,
+        text::cmd_parser,
+    },
+};
+use crate::{
+    env::ENV,
+    history::{get_period_starts, HistoryTotals},
+    message::{MessageBuilder, TelegramMessage},
+    state::AppState,
+    tasker::TaskFilter,
+};
+use anyhow::{anyhow, Context, Result};
+use proc_macros::{check_in_group, check_senders};
+
+pub const PATTERN: &str = "/usage";
+
+// number of records shown in the usage of a tenant
+const RECENT_LIMIT: usize = 5;
+
+#[check_senders]
+#[check_in_group]
+pub async fn handler(message: TelegramMessage, state: AppState) -> Result<()> {
+    let cmd = cmd_parser(message.text());
+
+    if cmd.len() == 1 {
+        // /usage
+        if ENV.get().unwrap().multi_tenant {
+            show_tenant_usage(message.clone(), state.clone()).await?;
+
+            // bandwidth of the instance is only shown to admins
+            if is_admin(&message) {
+                show_usage(message, state).await?;
+            }
+        } else {
+            show_usage(message, state).await?;
+        }
+    } else if cmd.len() == 2 && cmd[1] == "help" {
+        // /usage help
+        message
+            .respond(format_help(PATTERN))
+            .await
+            .context("help")?;
+    } else {
+        return Err(anyhow!("command error")).context(format_unknown_command_help(PATTERN));
+    }
+
+    Ok(())
+}
+
+async fn show_usage(message: TelegramMessage, state: AppState) -> Result<()> {
+    let env = ENV.get().unwrap();
+    let usage_session = &state.usage_session;
+
+    let usages = usage_session.get_current_usages().await?;
+
+    let mut builder = MessageBuilder::new().bold("Bandwidth usage").line();
+
+    if usages.is_empty() {
+        builder = builder.text("No usage today or this month.").line();
+    }
+
+    for usage in usages {
+        builder = builder
+            .line()
+            .code(&format!("{} {}", usage.account_type, usage.account))
+            .line()
+            .text(&format!(
+                "Today: {}\nThis month: {}",
+                format_size(usage.daily_bytes),
+                format_size(usage.monthly_bytes)
+            ))
+            .line();
+    }
+
+    builder = builder.line().text(&format!(
+        "Daily cap: {}\nMonthly cap: {}",
+        format_cap(env.daily_cap),
+        format_cap(env.monthly_cap)
+    ));
+
+    if let Some(reason) = usage_session.get_exceeded_cap().await? {
+        builder = builder
+            .line()
+            .line()
+            .text(&format!("Queue paused, {}.", reason));
+    }
+
+    let response = builder.build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
+
+// built from the history of the sender and their own onedrive accounts
+async fn show_tenant_usage(message: TelegramMessage, state: AppState) -> Result<()> {
+    let mut filter = TaskFilter::default();
+    sandbox_task_filter(&message, &mut filter)?;
+
+    let (today_start, month_start) = get_period_starts()?;
+
+    let history_session = &state.history_session;
+    let today_totals = history_session.get_totals(&filter, today_start).await?;
+    let month_totals = history_session.get_totals(&filter, month_start).await?;
+
+    let mut builder = MessageBuilder::new()
+        .bold("Your usage")
+        .line()
+        .text(&format!(
+            "Today: {}\nThis month: {}",
+            format_totals(&today_totals),
+            format_totals(&month_totals)
+        ))
+        .line();
+
+    let onedrive = state.get_onedrive(get_tenant_id(&message)).await?;
+
+    if onedrive.is_authorized().await {
+        let queued_length = state
+            .task_session
+            .get_reserved_length(get_tenant_id(&message))
+            .await?;
+
+        let quota = match onedrive.get_quota().await? {
+            Some(quota) => format!(
+                "{} of {} used",
+                format_size(quota.used),
+                format_size(quota.total)
+            ),
+            None => "not reported by the drive".to_string(),
+        };
+
+        builder = builder.line().text(&format!(
+            "OneDrive quota: {}\nQueued: {}",
+            quota,
+            format_size(queued_length)
+        ));
+    } else {
+        builder = builder
+            .line()
+            .text("No OneDrive account linked, use /linkod to link one.");
+    }
+
+    let records = history_session.get_history(&filter).await?;
+
+    if !records.is_empty() {
+        builder = builder.line().line().bold("Recent");
+    }
+
+    for record in records.iter().take(RECENT_LIMIT) {
+        builder = builder
+            .line()
+            .text(&format!("{} ", record.status))
+            .code(&record.filename)
+            .text(&format!(
+                " {}{}",
+                format_size(record.total_length.max(0) as u64),
+                format_tags(record.label.as_deref(), None)
+            ));
+    }
+
+    let response = builder.build();
+    message.respond(response.clone()).await.context(response)?;
+
+    Ok(())
+}
+
+fn format_totals(totals: &HistoryTotals) -> String {
+    format!(
+        "{} transferred in {} tasks, {} failed",
+        format_size(totals.completed_length),
+        totals.completed,
+        totals.failed
+    )
+}
+
+pub fn format_size(bytes: u64) -> String {
+    format!("{:.2}MB", bytes as f64 / 1024.0 / 1024.0)
+}
+
+fn format_cap(cap: Option<u64>) -> String {
+    cap.map_or_else(|| "none".to_string(), |cap| format!("{}MB", cap))
+}
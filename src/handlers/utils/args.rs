@@ -0,0 +1,184 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::text::{split_args, Arg};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+};
+
+// options of a command, like -t $label, and flags without values, like -c or --force
+pub struct ArgSchema {
+    pub options: &'static [&'static str],
+    pub flags: &'static [&'static str],
+}
+
+// -t $label -c $chat_id -s $sender
+pub const TASK_FILTER_SCHEMA: ArgSchema = ArgSchema {
+    options: &["-t", "-c", "-s"],
+    flags: &[],
+};
+
+// -t $label
+pub const LABEL_SCHEMA: ArgSchema = ArgSchema {
+    options: &["-t"],
+    flags: &[],
+};
+
+// -t $label -p $password
+pub const URL_SCHEMA: ArgSchema = ArgSchema {
+    options: &["-t", "-p"],
+    flags: &[],
+};
+
+impl ArgSchema {
+    fn names(&self) -> String {
+        self.options
+            .iter()
+            .chain(self.flags)
+            .copied()
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// options and flags are taken out, so that the rest can be parsed by position like cmd_parser
+pub struct Args {
+    positionals: Vec<String>,
+    options: HashMap<&'static str, String>,
+    flags: HashSet<&'static str>,
+}
+
+impl Args {
+    pub fn parse<T>(cmd: T, schema: &ArgSchema) -> Result<Self>
+    where
+        T: Display,
+    {
+        let mut positionals = Vec::new();
+        let mut options = HashMap::new();
+        let mut flags = HashSet::new();
+
+        let mut args = split_args(cmd).into_iter();
+
+        while let Some(Arg { value, quoted }) = args.next() {
+            // quoted ones and negative numbers like chat ids are never options
+            if quoted || !is_option_like(&value) {
+                positionals.push(value);
+            } else if let Some(option) = schema.options.iter().find(|option| **option == value) {
+                let option_value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("missing value for {}", option))?
+                    .value;
+
+                if options.insert(*option, option_value).is_some() {
+                    return Err(anyhow!("{} is given more than once", option));
+                }
+            } else if let Some(flag) = schema.flags.iter().find(|flag| **flag == value) {
+                flags.insert(*flag);
+            } else {
+                return Err(anyhow!(
+                    "unknown option {}, expected {}",
+                    value,
+                    schema.names()
+                ))
+                .context("quote it if it's not an option");
+            }
+        }
+
+        Ok(Self {
+            positionals,
+            options,
+            flags,
+        })
+    }
+
+    // the command itself is the first one
+    pub fn positionals(&self) -> &[String] {
+        &self.positionals
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    pub fn option_as<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Error + Send + Sync + 'static,
+    {
+        self.option(name)
+            .map(|value| {
+                value
+                    .parse::<T>()
+                    .context(format!("invalid value for {}: {}", name, value))
+            })
+            .transpose()
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+}
+
+fn is_option_like(value: &str) -> bool {
+    value.len() > 1
+        && value.starts_with('-')
+        && !value[1..].starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: ArgSchema = ArgSchema {
+        options: &["-t"],
+        flags: &["--force"],
+    };
+
+    #[test]
+    fn test_parse_args() {
+        let args = Args::parse("/url https://example.com/a.mp4 -t movies", &SCHEMA).unwrap();
+
+        assert_eq!(args.positionals(), ["/url", "https://example.com/a.mp4"]);
+        assert_eq!(args.option("-t"), Some("movies"));
+        assert!(!args.flag("--force"));
+
+        let args = Args::parse("/dir \"/My Files\" -t 'tv shows' --force", &SCHEMA).unwrap();
+
+        assert_eq!(args.positionals(), ["/dir", "/My Files"]);
+        assert_eq!(args.option("-t"), Some("tv shows"));
+        assert!(args.flag("--force"));
+
+        // negative numbers and quoted values are positional
+        let args = Args::parse("/copy -100123 \"-t\"", &SCHEMA).unwrap();
+
+        assert_eq!(args.positionals(), ["/copy", "-100123", "-t"]);
+        assert!(args.option("-t").is_none());
+    }
+
+    #[test]
+    fn test_parse_args_error() {
+        assert!(Args::parse("/queue -t", &SCHEMA).is_err());
+        assert!(Args::parse("/queue -t a -t b", &SCHEMA).is_err());
+        assert!(Args::parse("/queue -x", &SCHEMA).is_err());
+    }
+
+    #[test]
+    fn test_option_as() {
+        let args = Args::parse("/queue -c -100123", &TASK_FILTER_SCHEMA).unwrap();
+
+        assert_eq!(args.option_as::<i64>("-c").unwrap(), Some(-100_123));
+        assert!(args.option_as::<i64>("-t").unwrap().is_none());
+
+        let args = Args::parse("/queue -c abc", &TASK_FILTER_SCHEMA).unwrap();
+
+        assert!(args.option_as::<i64>("-c").is_err());
+    }
+}
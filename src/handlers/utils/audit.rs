@@ -0,0 +1,23 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::message::get_sender_name;
+use crate::{error::ResultExt, message::TelegramMessage, state::AppState};
+
+// the command is recorded as the action after it succeeded, failing to record it doesn't fail the action
+pub async fn record_audit(message: &TelegramMessage, state: &AppState) {
+    state
+        .audit_session
+        .insert(
+            message.chat().id(),
+            get_sender_name(message),
+            message.sender().map(|sender| sender.id()),
+            message.text(),
+        )
+        .await
+        .trace();
+}
@@ -0,0 +1,101 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use std::collections::VecDeque;
+
+// states of inline buttons, looked up by the id in their callback data
+// only kept in memory, so buttons sent before restarting are expired, and the oldest ones expire first
+pub struct CallbackStore<T> {
+    max: usize,
+    next_id: u64,
+    entries: VecDeque<(u64, T)>,
+}
+
+impl<T: Clone> CallbackStore<T> {
+    pub const fn new(max: usize) -> Self {
+        Self {
+            max,
+            next_id: 0,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        if self.entries.len() >= self.max {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((id, value));
+
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<T> {
+        self.entries
+            .iter()
+            .find(|(entry_id, _)| *entry_id == id)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn update(&mut self, id: u64, value: T) {
+        if let Some((_, old_value)) = self
+            .entries
+            .iter_mut()
+            .find(|(entry_id, _)| *entry_id == id)
+        {
+            *old_value = value;
+        }
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        self.entries.retain(|(entry_id, _)| *entry_id != id);
+    }
+}
+
+// callback data like $prefix$id:$action, or $prefix$id without an action
+pub fn parse_query_data<'a>(prefix: &str, data: &'a str) -> Option<(u64, &'a str)> {
+    let data = data.strip_prefix(prefix)?;
+    let (id, action) = data.split_once(':').unwrap_or((data, ""));
+
+    Some((id.parse().ok()?, action))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callback_store() {
+        let mut store = CallbackStore::new(2);
+
+        let first_id = store.insert("a");
+        let second_id = store.insert("b");
+        store.update(second_id, "c");
+        assert_eq!(store.get(second_id), Some("c"));
+
+        // the oldest one expires
+        let third_id = store.insert("d");
+        assert!(store.get(first_id).is_none());
+        assert_eq!(store.get(third_id), Some("d"));
+
+        store.remove(third_id);
+        assert!(store.get(third_id).is_none());
+    }
+
+    #[test]
+    fn test_parse_query_data() {
+        assert_eq!(parse_query_data("ls:", "ls:3:p2"), Some((3, "p2")));
+        assert_eq!(
+            parse_query_data("error_details:", "error_details:7"),
+            Some((7, ""))
+        );
+        assert!(parse_query_data("ls:", "rm:3:yes").is_none());
+        assert!(parse_query_data("ls:", "ls:x:p2").is_none());
+    }
+}
@@ -7,6 +7,7 @@
 
 use crate::{
     client::TelegramClient,
+    env::ENV,
     message::{ChatEntity, MessageInfo, TelegramMessage},
 };
 use anyhow::{anyhow, Context, Result};
@@ -78,9 +79,35 @@ pub fn get_message_link(chat_entity: &ChatEntity, id: i32) -> String {
     }
 }
 
-pub fn format_message_link(chat_id: i64, message_id: i32, filename: &str) -> String {
-    format!(
-        "<a href=\"https://t.me/c/{}/{}\">{}</a>",
-        chat_id, message_id, filename
-    )
+// username of the sender, used to filter tasks
+pub fn get_sender_name(message: &TelegramMessage) -> Option<String> {
+    message
+        .sender()
+        .and_then(|sender| sender.username().map(|username| username.to_string()))
+}
+
+// id of the sender in multi-tenant mode, which owns the onedrive accounts and tasks
+pub fn get_tenant_id(message: &TelegramMessage) -> Option<i64> {
+    if ENV.get().unwrap().multi_tenant {
+        message.sender().map(|sender| sender.id())
+    } else {
+        None
+    }
+}
+
+// everyone can use the bot in multi-tenant mode, see is_admin,
+// senders without a username can't be checked, so they are allowed
+pub fn is_allowed_sender(message: &TelegramMessage) -> bool {
+    let env = ENV.get().unwrap();
+    let users = &env.telegram_user.users;
+
+    env.multi_tenant
+        || users.is_empty()
+        || get_sender_name(message).is_none_or(|username| users.contains(&username))
+}
+
+// listed in tg_user_name, which can use commands affecting the whole instance in multi-tenant mode
+pub fn is_admin(message: &TelegramMessage) -> bool {
+    get_sender_name(message)
+        .is_some_and(|username| ENV.get().unwrap().telegram_user.users.contains(&username))
 }
@@ -5,18 +5,37 @@
 :license: MIT, see LICENSE for more details.
 */
 
+pub mod args;
+pub mod audit;
+pub mod callback;
 pub mod message;
+pub mod queue;
 pub mod text;
 pub mod upload;
 pub mod zip;
 
 use crate::{
-    client::onedrive::invalid_name::{INVALID_COMPONENT, INVALID_NAME, INVALID_NAME_PREFIX},
+    client::{
+        onedrive::invalid_name::{INVALID_COMPONENT, INVALID_NAME, INVALID_NAME_PREFIX},
+        OneDriveClient,
+    },
+    env::ENV,
     error::ResultExt,
+    message::TelegramMessage,
+    settings::{AnimationFormat, PathTemplate, PathVariables, PhotoQuality},
+    state::AppState,
     utils::{get_current_timestamp, get_ext},
 };
 use anyhow::{anyhow, Context, Result};
-use grammers_client::types::media::{Document, Media};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use grammers_client::{
+    grammers_tl_types as tl,
+    types::{
+        media::{Document, Media},
+        photo_sizes::PhotoSize,
+    },
+};
 use mime_guess::get_mime_extensions_str;
 use percent_encoding::percent_decode_str;
 use regex::Regex;
@@ -24,6 +43,16 @@ use reqwest::{header, Response, StatusCode};
 use std::collections::HashMap;
 use url::Url;
 
+// extensions preferred over the first one guessed from mime types
+const PREFERRED_EXTS: &[(&str, &str)] = &[
+    ("image/jpeg", "jpg"),
+    ("audio/mpeg", "mp3"),
+    ("audio/mp4", "m4a"),
+    ("video/quicktime", "mov"),
+    ("video/x-matroska", "mkv"),
+    ("text/plain", "txt"),
+];
+
 // according to https://support.microsoft.com/en-us/office/restrictions-and-limitations-in-onedrive-and-sharepoint-64883a5d-228e-48f5-b3d2-eb39e07630fa#filenamepathlengths
 const MAX_FILE_NAME_LEN: usize = 400;
 
@@ -210,6 +239,16 @@ fn validate_filename(filename: &str) -> bool {
     true
 }
 
+// for transfers and commands deleting anything, like logging out of accounts
+pub fn check_read_only() -> Result<()> {
+    if ENV.get().unwrap().read_only {
+        return Err(anyhow!("read-only mode"))
+            .context("this command is disabled in read-only mode, unset read_only to use it");
+    }
+
+    Ok(())
+}
+
 pub async fn validate_root_path(root_path: &str) -> Result<()> {
     if !root_path.starts_with('/') {
         return Err(anyhow!("directory path should start with /"));
@@ -218,6 +257,132 @@ pub async fn validate_root_path(root_path: &str) -> Result<()> {
     Ok(())
 }
 
+// a temporary directory goes first, then the one of the topic, the one of the chat,
+// and the directory of the account at last, or a folder named after the chat in it with chat_dir
+pub async fn get_root_path(
+    message: &TelegramMessage,
+    onedrive: &OneDriveClient,
+    state: &AppState,
+    should_consume_temp: bool,
+) -> Result<String> {
+    if !onedrive.does_temp_root_path_exist().await {
+        let chat_id = message.chat().id();
+        let settings_session = &state.settings_session;
+
+        if let Some(topic_id) = message.topic_id() {
+            if let Some(root_path) = settings_session.get_dir(chat_id, Some(topic_id)).await? {
+                return Ok(root_path);
+            }
+        }
+
+        if let Some(root_path) = settings_session.get_dir(chat_id, None).await? {
+            return Ok(root_path);
+        }
+
+        // stored as the directory of the chat, so that renaming the chat doesn't split its files
+        if ENV.get().unwrap().chat_dir {
+            let root_path = format!(
+                "{}/{}",
+                onedrive.get_root_path(false).await?.trim_end_matches('/'),
+                get_chat_dir_name(message.chat().name(), chat_id)
+            );
+
+            settings_session.set_dir(chat_id, None, &root_path).await?;
+
+            return Ok(root_path);
+        }
+    }
+
+    onedrive.get_root_path(should_consume_temp).await
+}
+
+// the folders expanded from the path template of the chat are appended to the root path,
+// after the date folders if date_dir is set, and the file is renamed by it, before the upload session is created
+// the chat, the sender and the date are the ones of the source message, like the linked one of /links
+pub async fn apply_path_template(
+    message: &TelegramMessage,
+    source_message: &TelegramMessage,
+    root_path: String,
+    filename: String,
+    label: Option<&str>,
+    state: &AppState,
+) -> Result<(String, String)> {
+    let chat_id = message.chat().id();
+    let settings_session = &state.settings_session;
+
+    let date_dir = ENV.get().unwrap().date_dir;
+    let path_template = settings_session.get_path_template(chat_id).await?;
+
+    if path_template.is_none() && !date_dir {
+        return Ok((root_path, filename));
+    }
+
+    let timezone = settings_session.get_timezone(chat_id).await?;
+    let date = source_message.date().with_timezone(&timezone);
+
+    let (folders, filename) = match path_template {
+        Some(path_template) => {
+            let sender = message::get_sender_name(source_message);
+
+            path_template.expand(&PathVariables {
+                chat: source_message.chat().name(),
+                chat_id: source_message.chat().id(),
+                date,
+                sender: sender.as_deref(),
+                label,
+                filename: &filename,
+            })
+        }
+        None => (String::new(), filename),
+    };
+
+    let folders = if date_dir {
+        get_date_folders(&date, &folders)
+    } else {
+        folders
+    };
+
+    if folders.is_empty() {
+        Ok((root_path, filename))
+    } else {
+        Ok((
+            format!("{}/{}", root_path.trim_end_matches('/'), folders),
+            filename,
+        ))
+    }
+}
+
+// like 2024/05, followed by the folders of the template
+fn get_date_folders(date: &DateTime<Tz>, folders: &str) -> String {
+    let date_folders = date.format("%Y/%m").to_string();
+
+    if folders.is_empty() {
+        date_folders
+    } else {
+        format!("{}/{}", date_folders, folders)
+    }
+}
+
+// invalid characters are replaced, the chat id is used if nothing valid is left
+fn get_chat_dir_name(chat_name: &str, chat_id: i64) -> String {
+    let mut name = chat_name.to_string();
+
+    for component in INVALID_COMPONENT {
+        name = name.replace(component, "_");
+    }
+
+    let name = name
+        .trim()
+        .trim_start_matches(INVALID_NAME_PREFIX)
+        .trim_end_matches('.');
+
+    if validate_filename(name) {
+        name.to_string()
+    } else {
+        chat_id.to_string()
+    }
+}
+
 fn preprocess_url_file_name(filename: &str) -> String {
     if validate_filename(filename) {
         filename
@@ -248,7 +413,7 @@ pub fn preprocess_tg_file_name(media: &Media) -> String {
         _ => Default::default(),
     };
 
-    if validate_filename(&filename) {
+    let filename = if validate_filename(&filename) {
         filename
             .trim()
             .trim_start_matches(INVALID_NAME_PREFIX)
@@ -257,7 +422,60 @@ pub fn preprocess_tg_file_name(media: &Media) -> String {
         let ext = get_ext(&filename);
 
         id.to_string() + "." + &ext
+    };
+
+    if ENV.get().unwrap().fix_extension {
+        if let Some(mime_type) = get_tg_mime_type(media) {
+            if let Some(corrected) = correct_ext(&filename, &mime_type) {
+                tracing::debug!("corrected {} to {}", filename, corrected);
+
+                return corrected;
+            }
+        }
     }
+
+    filename
+}
+
+// the name shown in telegram, kept if the uploaded file is named differently
+pub fn get_tg_document_name(media: &Media) -> Option<String> {
+    let name = match media {
+        Media::Document(file) => file.name(),
+        Media::Sticker(file) => file.document.name(),
+        _ => return None,
+    };
+
+    Some(name.to_string()).filter(|name| !name.is_empty())
+}
+
+// like video.bin to video.mp4 for video/mp4, none if the extension matches the mime type
+fn correct_ext(filename: &str, mime_type: &str) -> Option<String> {
+    // too generic to tell
+    if mime_type == "application/octet-stream" {
+        return None;
+    }
+
+    let exts = guess_exts(mime_type);
+
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, ext.to_lowercase()),
+        _ => (filename, String::new()),
+    };
+
+    if exts.is_empty() || exts.contains(&ext) {
+        return None;
+    }
+
+    let subtype = mime_type.split('/').next_back().unwrap_or_default();
+
+    let preferred_ext = PREFERRED_EXTS
+        .iter()
+        .find(|(mime, _)| *mime == mime_type)
+        .map(|(_, ext)| (*ext).to_string())
+        .or_else(|| exts.iter().find(|ext| *ext == subtype).cloned())
+        .unwrap_or_else(|| exts[0].clone());
+
+    Some(format!("{}.{}", stem, preferred_ext))
 }
 
 fn get_tg_document_name_and_id(document: &Document) -> (String, i64) {
@@ -278,6 +496,26 @@ fn get_tg_document_name_and_id(document: &Document) -> (String, i64) {
     (filename, file_id)
 }
 
+pub fn get_tg_mime_type(media: &Media) -> Option<String> {
+    match media {
+        Media::Photo(_) => Some("image/jpeg".to_string()),
+        Media::Document(file) => file.mime_type().map(|mime| mime.to_string()),
+        Media::Sticker(file) => file.document.mime_type().map(|mime| mime.to_string()),
+        _ => None,
+    }
+}
+
+// without parameters like charset
+pub fn get_url_mime_type(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime| mime.trim().to_string())
+        .filter(|mime| !mime.is_empty())
+}
+
 pub fn get_tg_file_size(media: &Media) -> u64 {
     let size = match media {
         Media::Photo(file) => file.size(),
@@ -288,3 +526,100 @@ pub fn get_tg_file_size(media: &Media) -> u64 {
 
     size as u64
 }
+
+// size types of photos up to 1280px, the larger ones are w and progressive sizes
+const COMPRESSED_PHOTO_TYPES: [&str; 4] = ["s", "m", "x", "y"];
+
+// a smaller size of the photo to transfer instead of the largest one, none for other media
+pub fn get_tg_photo_size(media: &Media, photo_quality: PhotoQuality) -> Option<PhotoSize> {
+    match (media, photo_quality) {
+        (Media::Photo(file), PhotoQuality::Compressed) => file
+            .thumbs()
+            .into_iter()
+            .filter(|size| COMPRESSED_PHOTO_TYPES.contains(&size.photo_type().as_str()))
+            .max_by_key(PhotoSize::size),
+        _ => None,
+    }
+}
+
+// gifs in telegram are mp4 animations, which may be named like animation.gif
+pub fn is_tg_animation(media: &Media) -> bool {
+    let Media::Document(file) = media else {
+        return false;
+    };
+
+    match &file.raw.document {
+        Some(tl::enums::Document::Document(document)) => document
+            .attributes
+            .iter()
+            .any(|attribute| matches!(attribute, tl::enums::DocumentAttribute::Animated)),
+        _ => false,
+    }
+}
+
+// view-once photos and videos, deleted once viewed or after the timer
+pub const fn get_tg_ttl_seconds(media: &Media) -> Option<i32> {
+    match media {
+        Media::Photo(file) => file.raw.ttl_seconds,
+        Media::Document(file) => file.raw.ttl_seconds,
+        _ => None,
+    }
+}
+
+// correct the extension, like animation.gif.mp4 to animation.mp4 or animation.gif
+pub fn get_animation_filename(filename: &str, animation_format: AnimationFormat) -> String {
+    let mut stem = filename;
+
+    while let Some((name, ext)) = stem.rsplit_once('.') {
+        if name.is_empty() || !matches!(ext.to_lowercase().as_str(), "gif" | "mp4") {
+            break;
+        }
+
+        stem = name;
+    }
+
+    format!("{}.{}", stem, animation_format.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correct_ext() {
+        assert_eq!(
+            correct_ext("video.bin", "video/mp4"),
+            Some("video.mp4".to_string())
+        );
+        assert_eq!(
+            correct_ext("song.bin", "audio/mpeg"),
+            Some("song.mp3".to_string())
+        );
+        assert_eq!(
+            correct_ext("video", "video/mp4"),
+            Some("video.mp4".to_string())
+        );
+
+        assert!(correct_ext("video.MP4", "video/mp4").is_none());
+        assert!(correct_ext("data.bin", "application/octet-stream").is_none());
+    }
+
+    #[test]
+    fn test_get_date_folders() {
+        let date = DateTime::from_timestamp(1_714_560_000, 0)
+            .unwrap()
+            .with_timezone(&Tz::Asia__Shanghai);
+
+        assert_eq!(get_date_folders(&date, ""), "2024/05");
+        assert_eq!(get_date_folders(&date, "My Group"), "2024/05/My Group");
+    }
+
+    #[test]
+    fn test_get_chat_dir_name() {
+        assert_eq!(get_chat_dir_name("My Group", -100123), "My Group");
+        assert_eq!(get_chat_dir_name("TV: S01/E02?", -100123), "TV_ S01_E02_");
+        assert_eq!(get_chat_dir_name(" ~$notes. ", -100123), "notes");
+        assert_eq!(get_chat_dir_name("", -100123), "-100123");
+        assert_eq!(get_chat_dir_name("CON", -100123), "-100123");
+    }
+}
@@ -0,0 +1,141 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{message::get_tenant_id, text::parse_task_code};
+use crate::{
+    env::ENV,
+    message::{MessageBuilder, TelegramMessage},
+    settings::QueueOverflow,
+    state::AppState,
+    tasker::{Task, TaskFilter},
+};
+use anyhow::{anyhow, Context, Result};
+
+// tasks are only visible to the chat they belong to
+pub async fn get_task_from_code(
+    message: &TelegramMessage,
+    state: &AppState,
+    code: &str,
+) -> Result<Task> {
+    let code = parse_task_code(code)?;
+
+    state
+        .task_session
+        .get_task_from_code(message.chat().id(), get_tenant_id(message), &code)
+        .await?
+        .ok_or_else(|| anyhow!("task #{} not found", code))
+        .context("it may be finished or cancelled")
+}
+
+// tenants only see their own tasks, history and scans in multi-tenant mode
+pub fn sandbox_task_filter(message: &TelegramMessage, filter: &mut TaskFilter) -> Result<()> {
+    if !ENV.get().unwrap().multi_tenant {
+        return Ok(());
+    }
+
+    let tenant_id = get_tenant_id(message)
+        .ok_or_else(|| anyhow!("sender not found"))
+        .context("send as yourself instead of the group in multi-tenant mode")?;
+
+    filter.tenant_id = Some(tenant_id);
+
+    Ok(())
+}
+
+// called before enqueueing, so that nothing is sent or created for a rejected task
+pub async fn check_queue_limit(message: &TelegramMessage, state: &AppState) -> Result<()> {
+    let Some(queue_limit) = state
+        .settings_session
+        .get_queue_limit(message.chat().id())
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let chat_bot_hex = message.chat().pack().to_hex();
+
+    let pending_tasks_number = state
+        .task_session
+        .get_chat_pending_tasks_number(&chat_bot_hex)
+        .await?;
+
+    if pending_tasks_number < queue_limit.max {
+        return Ok(());
+    }
+
+    let dropped_task = match queue_limit.overflow {
+        QueueOverflow::Reject => None,
+        QueueOverflow::DropOldest => {
+            state
+                .task_session
+                .drop_oldest_waiting_task(&chat_bot_hex)
+                .await?
+        }
+    };
+
+    match dropped_task {
+        Some(task) => {
+            let response = MessageBuilder::new()
+                .text("Queue is full, dropped ")
+                .code(&task.filename)
+                .text(".")
+                .build();
+            message.respond(response.clone()).await.context(response)?;
+
+            tracing::info!("dropped task {} since queue is full", task.filename);
+
+            Ok(())
+        }
+        // also when all pending tasks have been fetched
+        None => Err(anyhow!(
+            "queue of this chat is full, {} tasks pending",
+            pending_tasks_number
+        ))
+        .context("use /queue limit to change the limit"),
+    }
+}
+
+// fail before the upload session is created, instead of after uploading most of the file,
+// returns another account with enough quota to pin the task to, none for the current one
+pub async fn check_quota(
+    message: &TelegramMessage,
+    state: &AppState,
+    total_length: u64,
+) -> Result<Option<String>> {
+    let onedrive = &state.get_onedrive(get_tenant_id(message)).await?;
+
+    let Some(remaining) = onedrive.get_remaining_quota().await? else {
+        return Ok(None);
+    };
+
+    let required = total_length
+        + state
+            .task_session
+            .get_reserved_length(get_tenant_id(message))
+            .await?;
+
+    if required <= remaining {
+        return Ok(None);
+    }
+
+    // the current account of the chat is kept, only this task is uploaded to the other one
+    if ENV.get().unwrap().quota_auto_switch {
+        if let Some(username) = onedrive.find_account_with_quota(required).await? {
+            let response = format!("Not enough quota, uploading to\n{}", username);
+            message.respond(response.as_str()).await.context(response)?;
+
+            return Ok(Some(username));
+        }
+    }
+
+    Err(anyhow!(
+        "not enough onedrive quota, {:.2}MB required including queued tasks, {:.2}MB remaining",
+        required as f64 / 1024.0 / 1024.0,
+        remaining as f64 / 1024.0 / 1024.0
+    ))
+    .context("free up space or change the account with /drive")
+}
@@ -5,25 +5,104 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::error::ResultExt;
-use anyhow::Context;
-use regex::Regex;
+use super::args::Args;
+use crate::tasker::TaskFilter;
+use anyhow::{anyhow, Context, Result};
 use std::fmt::Display;
 use url::Url;
 
+// commands are parsed from the plain text of messages, so there are no entities to strip
 pub fn cmd_parser<T>(cmd: T) -> Vec<String>
 where
     T: Display,
 {
-    cmd.to_string()
-        .purify()
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect()
+    split_args(cmd).into_iter().map(|arg| arg.value).collect()
+}
+
+#[derive(Default)]
+pub struct Arg {
+    pub value: String,
+    pub quoted: bool,
+}
+
+// split by whitespaces, except in "quoted strings", 'single quoted' or “smart quoted” by phones
+// quotes only count at the start of an argument, so that urls and names like it's are kept
+pub fn split_args<T>(cmd: T) -> Vec<Arg>
+where
+    T: Display,
+{
+    let mut args = Vec::new();
+    let mut current: Option<Arg> = None;
+    let mut closing_quote = None;
+
+    for c in cmd.to_string().chars() {
+        if let Some(quote) = closing_quote {
+            if c == quote {
+                closing_quote = None;
+            } else {
+                current.get_or_insert_with(Arg::default).value.push(c);
+            }
+        } else if c.is_whitespace() {
+            args.extend(current.take());
+        } else if let (None, Some(quote)) = (&current, get_closing_quote(c)) {
+            closing_quote = Some(quote);
+            current = Some(Arg {
+                value: String::new(),
+                quoted: true,
+            });
+        } else {
+            current.get_or_insert_with(Arg::default).value.push(c);
+        }
+    }
+
+    // an unclosed quote takes the rest
+    args.extend(current);
+
+    args
+}
+
+const fn get_closing_quote(c: char) -> Option<char> {
+    match c {
+        '"' => Some('"'),
+        '\'' => Some('\''),
+        '“' => Some('”'),
+        _ => None,
+    }
+}
+
+// parsed with TASK_FILTER_SCHEMA
+pub fn parse_task_filter(args: &Args) -> Result<TaskFilter> {
+    let label = args.option("-t").map(ToString::to_string);
+
+    let chat_id = args
+        .option_as::<i64>("-c")
+        .context("chat id should be a number")?;
+
+    let sender = args
+        .option("-s")
+        .map(|sender| sender.trim_start_matches('@').to_string());
+
+    Ok(TaskFilter {
+        label,
+        chat_id,
+        sender,
+        tenant_id: None,
+    })
+}
+
+// #A1B2, the leading # and the case are optional
+pub fn parse_task_code(code: &str) -> Result<String> {
+    let code = code.trim_start_matches('#').to_uppercase();
+
+    if code.len() != 4 || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("invalid task id: {}", code))
+            .context("task id should be like #A1B2, see it in the task message or /queue");
+    }
+
+    Ok(code)
 }
 
 pub trait TextExt {
-    fn purify(&self) -> String;
     fn url_encode(&self) -> String;
 }
 
@@ -31,43 +110,39 @@ impl<T> TextExt for T
 where
     T: Display,
 {
-    fn purify(&self) -> String {
-        // ~ may be used in the url
-        let text = self
-            .to_string()
-            .trim()
-            .replace(['*', '`'], "")
-            .replace("<b>", "")
-            .replace("</b>", "")
-            .replace("<strong>", "")
-            .replace("</strong>", "")
-            .replace("<i>", "")
-            .replace("</i>", "")
-            .replace("<em>", "")
-            .replace("</em>", "")
-            .replace("<code>", "")
-            .replace("</code>", "")
-            .replace("<s>", "")
-            .replace("</s>", "")
-            .replace("<strike>", "")
-            .replace("</strike>", "")
-            .replace("<del>", "")
-            .replace("</del>", "")
-            .replace("<u>", "")
-            .replace("</u>", "")
-            .replace("</pre>", "");
-
-        let pattern = "<pre[^>]*>";
-        let re = Regex::new(pattern)
-            .context("invalid regex pattern")
-            .context(pattern)
-            .unwrap_or_trace();
-        re.replace_all(&text, "").to_string()
-    }
-
     fn url_encode(&self) -> String {
         Url::parse(&self.to_string())
             .expect("Failed to parse URL")
             .to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmd_parser() {
+        assert_eq!(
+            cmd_parser("/url  https://example.com/a.mp4 -t movies"),
+            vec!["/url", "https://example.com/a.mp4", "-t", "movies"]
+        );
+        assert_eq!(
+            cmd_parser("/dir set \"/My Files\" 'tv shows'"),
+            vec!["/dir", "set", "/My Files", "tv shows"]
+        );
+        assert_eq!(
+            cmd_parser("/dir “/It's Mine” /it's \"unclosed quote"),
+            vec!["/dir", "/It's Mine", "/it's", "unclosed quote"]
+        );
+    }
+
+    #[test]
+    fn test_parse_task_code() {
+        assert_eq!(parse_task_code("#A1B2").unwrap(), "A1B2");
+        assert_eq!(parse_task_code("a1b2").unwrap(), "A1B2");
+
+        assert!(parse_task_code("#A1B").is_err());
+        assert!(parse_task_code("#G1B2").is_err());
+    }
+}
@@ -5,33 +5,32 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::state::AppState;
+use crate::{
+    client::OneDriveClient, message::TelegramMessage, settings::ConflictPolicy, state::AppState,
+};
 use anyhow::{Context, Result};
 use grammers_client::types::{
     media::Uploaded,
     photo_sizes::{PhotoSize, VecExt},
 };
-use std::io::Cursor;
+use path_slash::PathBufExt;
+use std::path::Path;
+
+// thumbs are requested again on failures instead of failing the whole transfer
+const THUMB_RETRIES: u32 = 3;
 
+// streamed from the user client into the bot client, never kept as a whole
 pub async fn upload_thumb(state: AppState, thumbs: Vec<PhotoSize>) -> Result<Option<Uploaded>> {
     let uploaded = match thumbs.largest() {
         Some(thumb) => {
-            let mut download = state.telegram_user.iter_download(thumb);
-
-            let mut buffer = Vec::new();
-            while let Some(chunk) = download
-                .next()
-                .await
-                .context("failed to download chunk for thumb")?
-            {
-                buffer.extend(chunk);
-            }
-
-            let size = buffer.len();
-            let mut stream = Cursor::new(buffer);
+            let mut stream = state
+                .telegram_user
+                .download(thumb)
+                .retries(THUMB_RETRIES)
+                .stream();
             let uploaded = state
                 .telegram_bot
-                .upload_stream(&mut stream, size, "thumb.jpg".to_string())
+                .upload_stream(&mut stream, thumb.size(), "thumb.jpg".to_string())
                 .await
                 .context("thumb")?;
 
@@ -42,3 +41,37 @@ pub async fn upload_thumb(state: AppState, thumbs: Vec<PhotoSize>) -> Result<Opt
 
     Ok(uploaded)
 }
+
+// with the skip policy, files existing in the directory are reported instead of queued
+pub async fn skip_existing_file(
+    message: &TelegramMessage,
+    onedrive: &OneDriveClient,
+    account: Option<&str>,
+    root_path: &str,
+    filename: &str,
+    conflict_policy: ConflictPolicy,
+) -> Result<bool> {
+    if conflict_policy != ConflictPolicy::Skip {
+        return Ok(false);
+    }
+
+    let file_path = Path::new(root_path)
+        .join(filename)
+        .to_slash_lossy()
+        .to_string();
+
+    if onedrive
+        .get_account_item_by_path(account, &file_path)
+        .await?
+        .is_none()
+    {
+        return Ok(false);
+    }
+
+    let response = format!("Skipped, {} already exists.", file_path);
+    message.reply(response.as_str()).await.context(response)?;
+
+    tracing::info!("skipped existing file: {}", file_path);
+
+    Ok(true)
+}
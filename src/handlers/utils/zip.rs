@@ -83,10 +83,8 @@ async fn build_zip_date_time(file: &File) -> ZipDateTime {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::env::LOGS_PATH;
-
     #[tokio::test]
     async fn test_zip_dir() {
-        zip_dir(LOGS_PATH, "./logs.zip").await.unwrap();
+        zip_dir("./logs", "./logs.zip").await.unwrap();
     }
 }
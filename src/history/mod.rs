@@ -0,0 +1,463 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod records;
+mod scans;
+mod uploads;
+
+use crate::{
+    tasker::{Task, TaskFilter},
+    utils::get_current_timestamp,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, NaiveDate, Utc};
+pub use records::Model as HistoryRecord;
+pub use scans::Model as ScanRecord;
+use sea_orm::{
+    sea_query::{Expr, Query, Table},
+    ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityName,
+    EntityTrait, IdenStatic, Order, QueryFilter, QueryOrder, QuerySelect, Schema, Set,
+};
+pub use uploads::Model as UploadRecord;
+
+// number of records shown in /history and /failed
+const HISTORY_LIMIT: u64 = 20;
+
+const STATUS_COMPLETED: &str = "completed";
+const STATUS_FAILED: &str = "failed";
+
+// records finished in a period, for /usage in multi-tenant mode
+#[derive(Default)]
+pub struct HistoryTotals {
+    pub completed: u64,
+    pub failed: u64,
+    pub completed_length: u64,
+}
+
+// finished tasks, kept across restarts unlike the tasks in tasker session
+pub struct HistorySession {
+    connection: DatabaseConnection,
+}
+
+impl HistorySession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self { connection })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to history session")?;
+
+        Self::create_table_if_not_exists(&connection, records::Entity).await?;
+        // added later, so it's created separately in existing sessions
+        Self::create_table_if_not_exists(&connection, uploads::Entity).await?;
+        Self::create_table_if_not_exists(&connection, scans::Entity).await?;
+        Self::add_column_if_not_exists(&connection, records::Entity, records::Column::TenantId)
+            .await?;
+        Self::add_column_if_not_exists(&connection, scans::Entity, scans::Column::TenantId).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists<E: EntityTrait>(
+        connection: &DatabaseConnection,
+        entity: E,
+    ) -> Result<()> {
+        if !Self::is_table_exists::<E>(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement = Schema::new(backend).create_table_from_entity(entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!("failed to create table {}", entity.table_name()))?;
+        }
+
+        Ok(())
+    }
+
+    // added later to an existing table, as null in older rows
+    async fn add_column_if_not_exists<E: EntityTrait>(
+        connection: &DatabaseConnection,
+        entity: E,
+        column: E::Column,
+    ) -> Result<()> {
+        let backend = connection.get_database_backend();
+
+        let select_statement = Query::select()
+            .column(column)
+            .from(entity)
+            .limit(1)
+            .to_owned();
+
+        if connection
+            .query_one(backend.build(&select_statement))
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        let table_alter_statement = Table::alter()
+            .table(entity)
+            .add_column(&mut Schema::new(backend).get_column_def::<E>(column))
+            .to_owned();
+
+        connection
+            .execute(backend.build(&table_alter_statement))
+            .await
+            .context(format!(
+                "failed to add column {} to table {}",
+                column.as_str(),
+                entity.table_name()
+            ))?;
+
+        Ok(())
+    }
+
+    async fn is_table_exists<E: EntityTrait>(connection: &DatabaseConnection) -> bool {
+        let result = E::find().limit(1).all(connection).await;
+
+        result.is_ok()
+    }
+
+    pub async fn insert_completed(&self, task: &Task) -> Result<()> {
+        self.insert_record(task, STATUS_COMPLETED, None).await
+    }
+
+    pub async fn insert_failed(&self, task: &Task, error: String) -> Result<()> {
+        self.insert_record(task, STATUS_FAILED, Some(error)).await
+    }
+
+    async fn insert_record(&self, task: &Task, status: &str, error: Option<String>) -> Result<()> {
+        let insert_item = records::ActiveModel {
+            id: ActiveValue::default(),
+            cmd_type: Set(task.cmd_type.to_string()),
+            filename: Set(task.filename.clone()),
+            root_path: Set(task.root_path.clone()),
+            total_length: Set(task.total_length),
+            chat_id: Set(task.chat_id),
+            label: Set(task.label.clone()),
+            sender: Set(task.sender.clone()),
+            tenant_id: Set(task.tenant_id),
+            status: Set(status.to_string()),
+            error: Set(error),
+            finished_at: Set(get_current_timestamp()),
+        };
+
+        records::Entity::insert(insert_item)
+            .exec(&self.connection)
+            .await
+            .context("failed to insert history record")?;
+
+        Ok(())
+    }
+
+    pub async fn insert_upload(
+        &self,
+        task: &Task,
+        path: &str,
+        size: u64,
+        hash: &str,
+    ) -> Result<()> {
+        let insert_item = uploads::ActiveModel {
+            id: ActiveValue::default(),
+            path: Set(path.to_string()),
+            size: Set(size as i64),
+            hash: Set(hash.to_string()),
+            chat_id: Set(task.chat_id),
+            tenant_id: Set(task.tenant_id),
+            account: Set(task.account.clone()),
+            uploaded_at: Set(get_current_timestamp()),
+            checked_at: Set(None),
+        };
+
+        uploads::Entity::insert(insert_item)
+            .exec(&self.connection)
+            .await
+            .context("failed to insert upload record")?;
+
+        Ok(())
+    }
+
+    // action is the scan policy applied to an infected file
+    pub async fn insert_scan(
+        &self,
+        task: &Task,
+        signature: Option<&str>,
+        action: Option<&str>,
+    ) -> Result<()> {
+        let insert_item = scans::ActiveModel {
+            id: ActiveValue::default(),
+            filename: Set(task.filename.clone()),
+            root_path: Set(task.root_path.clone()),
+            chat_id: Set(task.chat_id),
+            label: Set(task.label.clone()),
+            sender: Set(task.sender.clone()),
+            tenant_id: Set(task.tenant_id),
+            signature: Set(signature.map(ToString::to_string)),
+            action: Set(action.map(ToString::to_string)),
+            scanned_at: Set(get_current_timestamp()),
+        };
+
+        scans::Entity::insert(insert_item)
+            .exec(&self.connection)
+            .await
+            .context("failed to insert scan record")?;
+
+        Ok(())
+    }
+
+    // infected files, latest first
+    pub async fn get_infected(&self, filter: &TaskFilter) -> Result<Vec<ScanRecord>> {
+        let mut condition = Condition::all().add(scans::Column::Signature.is_not_null());
+
+        if let Some(label) = &filter.label {
+            condition = condition.add(scans::Column::Label.eq(label));
+        }
+
+        if let Some(chat_id) = filter.chat_id {
+            condition = condition.add(scans::Column::ChatId.eq(chat_id));
+        }
+
+        if let Some(sender) = &filter.sender {
+            condition = condition.add(scans::Column::Sender.eq(sender));
+        }
+
+        if let Some(tenant_id) = filter.tenant_id {
+            condition = condition.add(scans::Column::TenantId.eq(tenant_id));
+        }
+
+        scans::Entity::find()
+            .filter(condition)
+            .order_by_desc(scans::Column::Id)
+            .limit(HISTORY_LIMIT)
+            .all(&self.connection)
+            .await
+            .context("failed to get scan records")
+    }
+
+    // picked at random, so that every file is checked sooner or later
+    pub async fn sample_uploads(&self, count: u64) -> Result<Vec<UploadRecord>> {
+        uploads::Entity::find()
+            .order_by(Expr::cust("RANDOM()"), Order::Asc)
+            .limit(count)
+            .all(&self.connection)
+            .await
+            .context("failed to sample upload records")
+    }
+
+    // the file is checked with its current size and hash from now on
+    pub async fn update_upload(&self, id: i64, size: u64, hash: &str) -> Result<()> {
+        uploads::Entity::update_many()
+            .col_expr(uploads::Column::Size, Expr::value(size as i64))
+            .col_expr(uploads::Column::Hash, Expr::value(hash))
+            .col_expr(
+                uploads::Column::CheckedAt,
+                Expr::value(get_current_timestamp()),
+            )
+            .filter(uploads::Column::Id.eq(id))
+            .exec(&self.connection)
+            .await
+            .context("failed to update upload record")?;
+
+        Ok(())
+    }
+
+    // a missing file is reported once
+    pub async fn delete_upload(&self, id: i64) -> Result<()> {
+        uploads::Entity::delete_by_id(id)
+            .exec(&self.connection)
+            .await
+            .context("failed to delete upload record")?;
+
+        Ok(())
+    }
+
+    // records of an item moved or renamed in onedrive follow it, also those of files in a moved folder
+    pub async fn move_uploads(
+        &self,
+        tenant_id: Option<i64>,
+        account: Option<&str>,
+        path: &str,
+        new_path: &str,
+    ) -> Result<()> {
+        let path = path.trim_end_matches('/');
+        let new_path = new_path.trim_end_matches('/');
+
+        for record in self.get_uploads_under(tenant_id, account, path).await? {
+            let record_path = format!("{}{}", new_path, &record.path[path.len()..]);
+
+            uploads::Entity::update_many()
+                .col_expr(uploads::Column::Path, Expr::value(record_path))
+                .filter(uploads::Column::Id.eq(record.id))
+                .exec(&self.connection)
+                .await
+                .context("failed to move upload record")?;
+        }
+
+        Ok(())
+    }
+
+    // items deleted from onedrive are no longer checked
+    pub async fn delete_uploads(
+        &self,
+        tenant_id: Option<i64>,
+        account: Option<&str>,
+        path: &str,
+    ) -> Result<()> {
+        let ids = self
+            .get_uploads_under(tenant_id, account, path)
+            .await?
+            .into_iter()
+            .map(|record| record.id);
+
+        uploads::Entity::delete_many()
+            .filter(uploads::Column::Id.is_in(ids))
+            .exec(&self.connection)
+            .await
+            .context("failed to delete upload records")?;
+
+        Ok(())
+    }
+
+    // the file at the path or files in the folder at it, in the account or recorded without one
+    async fn get_uploads_under(
+        &self,
+        tenant_id: Option<i64>,
+        account: Option<&str>,
+        path: &str,
+    ) -> Result<Vec<UploadRecord>> {
+        let path = path.trim_end_matches('/');
+
+        let mut condition = Condition::all()
+            .add(match tenant_id {
+                Some(tenant_id) => uploads::Column::TenantId.eq(tenant_id),
+                None => uploads::Column::TenantId.is_null(),
+            })
+            // _ and % in the path are wildcards of like, so the prefix is checked again below
+            .add(uploads::Column::Path.starts_with(path));
+
+        if let Some(account) = account {
+            condition = condition.add(
+                Condition::any()
+                    .add(uploads::Column::Account.eq(account))
+                    .add(uploads::Column::Account.is_null()),
+            );
+        }
+
+        let records = uploads::Entity::find()
+            .filter(condition)
+            .all(&self.connection)
+            .await
+            .context("failed to get upload records")?;
+
+        Ok(records
+            .into_iter()
+            .filter(|record| is_under(&record.path, path))
+            .collect())
+    }
+
+    pub async fn get_history(&self, filter: &TaskFilter) -> Result<Vec<HistoryRecord>> {
+        self.get_records(filter, None).await
+    }
+
+    pub async fn get_failed(&self, filter: &TaskFilter) -> Result<Vec<HistoryRecord>> {
+        self.get_records(filter, Some(STATUS_FAILED)).await
+    }
+
+    // latest records first
+    async fn get_records(
+        &self,
+        filter: &TaskFilter,
+        status: Option<&str>,
+    ) -> Result<Vec<HistoryRecord>> {
+        records::Entity::find()
+            .filter(get_condition(filter, status))
+            .order_by_desc(records::Column::Id)
+            .limit(HISTORY_LIMIT)
+            .all(&self.connection)
+            .await
+            .context("failed to get history records")
+    }
+
+    pub async fn get_totals(&self, filter: &TaskFilter, since: i64) -> Result<HistoryTotals> {
+        let records = records::Entity::find()
+            .filter(get_condition(filter, None).add(records::Column::FinishedAt.gte(since)))
+            .all(&self.connection)
+            .await
+            .context("failed to get history records for totals")?;
+
+        let totals = records
+            .iter()
+            .fold(HistoryTotals::default(), |mut totals, record| {
+                if record.status == STATUS_COMPLETED {
+                    totals.completed += 1;
+                    totals.completed_length += record.total_length.max(0) as u64;
+                } else {
+                    totals.failed += 1;
+                }
+
+                totals
+            });
+
+        Ok(totals)
+    }
+}
+
+fn get_condition(filter: &TaskFilter, status: Option<&str>) -> Condition {
+    let mut condition = Condition::all();
+
+    if let Some(status) = status {
+        condition = condition.add(records::Column::Status.eq(status));
+    }
+
+    if let Some(label) = &filter.label {
+        condition = condition.add(records::Column::Label.eq(label));
+    }
+
+    if let Some(chat_id) = filter.chat_id {
+        condition = condition.add(records::Column::ChatId.eq(chat_id));
+    }
+
+    if let Some(sender) = &filter.sender {
+        condition = condition.add(records::Column::Sender.eq(sender));
+    }
+
+    if let Some(tenant_id) = filter.tenant_id {
+        condition = condition.add(records::Column::TenantId.eq(tenant_id));
+    }
+
+    condition
+}
+
+// starts of today and this month in utc, same as the periods of bandwidth usage
+pub fn get_period_starts() -> Result<(i64, i64)> {
+    let today = Utc::now().date_naive();
+    let first_day = today
+        .with_day(1)
+        .ok_or_else(|| anyhow!("failed to get the first day of the month"))?;
+
+    let to_timestamp = |date: NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .map(|date_time| date_time.and_utc().timestamp())
+            .ok_or_else(|| anyhow!("failed to get the start of {}", date))
+    };
+
+    Ok((to_timestamp(today)?, to_timestamp(first_day)?))
+}
+
+// the path itself or anything in it, like /TG/a.mp4 under /TG
+fn is_under(record_path: &str, path: &str) -> bool {
+    record_path
+        .strip_prefix(path)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
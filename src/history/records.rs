@@ -0,0 +1,36 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use sea_orm::{
+    entity::prelude::DeriveEntityModel, ActiveModelBehavior, DerivePrimaryKey, DeriveRelation,
+    EntityTrait, EnumIter, PrimaryKeyTrait,
+};
+
+#[derive(Clone, Debug, DeriveEntityModel)]
+#[sea_orm(table_name = "history")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub cmd_type: String,
+    pub filename: String,
+    pub root_path: String,
+    pub total_length: i64,
+    pub chat_id: i64,
+    pub label: Option<String>,
+    pub sender: Option<String>,
+    // id of the sender in multi-tenant mode, added later so it's none in older records
+    pub tenant_id: Option<i64>,
+    // completed or failed
+    pub status: String,
+    pub error: Option<String>,
+    pub finished_at: i64,
+}
+
+#[derive(Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
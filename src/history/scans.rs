@@ -0,0 +1,36 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use sea_orm::{
+    entity::prelude::DeriveEntityModel, ActiveModelBehavior, DerivePrimaryKey, DeriveRelation,
+    EntityTrait, EnumIter, PrimaryKeyTrait,
+};
+
+// files scanned by clamd, clean or not
+#[derive(Clone, Debug, DeriveEntityModel)]
+#[sea_orm(table_name = "scans")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub filename: String,
+    pub root_path: String,
+    pub chat_id: i64,
+    pub label: Option<String>,
+    pub sender: Option<String>,
+    // id of the sender in multi-tenant mode, added later so it's none in older records
+    pub tenant_id: Option<i64>,
+    // none if clean
+    pub signature: Option<String>,
+    // the scan policy applied, none if clean
+    pub action: Option<String>,
+    pub scanned_at: i64,
+}
+
+#[derive(Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,33 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use sea_orm::{
+    entity::prelude::DeriveEntityModel, ActiveModelBehavior, DerivePrimaryKey, DeriveRelation,
+    EntityTrait, EnumIter, PrimaryKeyTrait,
+};
+
+// uploaded files with the hash reported by onedrive, re-checked by samples
+#[derive(Clone, Debug, DeriveEntityModel)]
+#[sea_orm(table_name = "uploads")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub path: String,
+    pub size: i64,
+    // quickXorHash
+    pub hash: String,
+    pub chat_id: i64,
+    pub tenant_id: Option<i64>,
+    pub account: Option<String>,
+    pub uploaded_at: i64,
+    pub checked_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,236 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    env::ENV,
+    error::{HookVetoError, ResultExt},
+    handlers::utils::message::get_message_link,
+    message::ChatEntity,
+    state::AppState,
+    tasker::{CmdType, Task},
+    utils::get_http_client,
+    verify::split_path,
+};
+use anyhow::{anyhow, Context, Result};
+use reqwest::header;
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+    time::Duration,
+};
+use tokio::{fs, process::Command};
+
+// hooks still running after it are killed, so that they don't pile up
+const HOOK_TIMEOUT: Duration = Duration::from_secs(300);
+
+// an uploaded file, passed to the command as env values and to the webhook as json
+#[derive(Serialize)]
+struct UploadEvent {
+    path: String,
+    size: u64,
+    // quickXorHash reported by onedrive, none if the drive doesn't report it
+    hash: Option<String>,
+    // the url or the telegram message the file came from
+    source: String,
+    account: Option<String>,
+}
+
+impl UploadEvent {
+    // the path is the one uploaded to, which may differ from the task if the file was renamed on conflict
+    async fn new(task: &Task, path: &str, state: &AppState) -> Result<Self> {
+        let (root_path, filename) = split_path(path);
+
+        let file_hash = state
+            .get_onedrive(task.tenant_id)
+            .await?
+            .get_file_hash(task.account.as_deref(), root_path, filename)
+            .await?;
+
+        let (size, hash) = file_hash.unwrap_or((task.total_length as u64, None));
+
+        let source = match (&task.cmd_type, &task.url) {
+            (CmdType::Url, Some(url)) => url.clone(),
+            _ => get_message_link(&ChatEntity::from(task.chat_id), task.message_id),
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            size,
+            hash,
+            source,
+            account: task.account.clone(),
+        })
+    }
+
+    fn env_values(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("TG_ONEDRIVE_PATH", self.path.clone()),
+            ("TG_ONEDRIVE_SIZE", self.size.to_string()),
+            ("TG_ONEDRIVE_HASH", self.hash.clone().unwrap_or_default()),
+            ("TG_ONEDRIVE_SOURCE", self.source.clone()),
+            (
+                "TG_ONEDRIVE_ACCOUNT",
+                self.account.clone().unwrap_or_default(),
+            ),
+        ]
+    }
+}
+
+// run in the background, failures are only logged since the file is already uploaded
+pub fn run_post_upload_hooks(task: &Task, path: &str, state: &AppState) {
+    let env = ENV.get().unwrap();
+
+    if env.post_upload_hook.is_none() && env.post_upload_webhook.is_none() {
+        return;
+    }
+
+    let task = task.clone();
+    let path = path.to_string();
+    let state = state.clone();
+
+    tokio::spawn(async move {
+        run_hooks(&task, &path, &state)
+            .await
+            .context(format!("post-upload hook of {} failed", path))
+            .trace();
+    });
+}
+
+async fn run_hooks(task: &Task, path: &str, state: &AppState) -> Result<()> {
+    let env = ENV.get().unwrap();
+
+    let event = UploadEvent::new(task, path, state).await?;
+
+    if let Some(command) = &env.post_upload_hook {
+        run_command(command, &event).await?;
+    }
+
+    if let Some(url) = &env.post_upload_webhook {
+        send_webhook(url, &event).await?;
+    }
+
+    Ok(())
+}
+
+async fn run_command(command: &str, event: &UploadEvent) -> Result<()> {
+    let output = run_shell(command, event.env_values()).await?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "hook exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+async fn send_webhook(url: &str, event: &UploadEvent) -> Result<()> {
+    let body = serde_json::to_string(event).context("failed to serialize upload event")?;
+
+    let response = get_http_client()?
+        .post(url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .timeout(HOOK_TIMEOUT)
+        .send()
+        .await
+        .context("failed to send webhook")?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("webhook responded with {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// the hook gets the spooled file in TG_ONEDRIVE_INPUT, and may write another one to TG_ONEDRIVE_OUTPUT,
+// it prints the path to upload instead, prints nothing to upload the input, or exits non-zero to veto it
+pub async fn run_pre_upload_hook(
+    command: &str,
+    input_path: &Path,
+    output_path: &Path,
+    filename: &str,
+) -> Result<Option<PathBuf>> {
+    let output = run_shell(
+        command,
+        vec![
+            (
+                "TG_ONEDRIVE_INPUT",
+                input_path.to_string_lossy().to_string(),
+            ),
+            (
+                "TG_ONEDRIVE_OUTPUT",
+                output_path.to_string_lossy().to_string(),
+            ),
+            ("TG_ONEDRIVE_FILENAME", filename.to_string()),
+        ],
+    )
+    .await?;
+
+    if !output.status.success() {
+        return Err(HookVetoError {
+            filename: filename.to_string(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+        .into());
+    }
+
+    let Some(path) = get_printed_path(&String::from_utf8_lossy(&output.stdout)) else {
+        return Ok(None);
+    };
+
+    fs::metadata(&path).await.context(format!(
+        "file returned by pre-upload hook not found: {}",
+        path.display()
+    ))?;
+
+    Ok(Some(path))
+}
+
+// the last line printed, so that the hook can log before it
+fn get_printed_path(stdout: &str) -> Option<PathBuf> {
+    stdout
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(PathBuf::from)
+}
+
+// killed if it doesn't finish in time
+async fn run_shell(command: &str, env_values: Vec<(&'static str, String)>) -> Result<Output> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env_values)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    tokio::time::timeout(HOOK_TIMEOUT, output)
+        .await
+        .map_err(|_| anyhow!("{} timed out after {}s", command, HOOK_TIMEOUT.as_secs()))?
+        .context(format!("failed to run {}", command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_printed_path() {
+        assert_eq!(
+            get_printed_path("scanning\n/tmp/1-output.mp4\n\n"),
+            Some(PathBuf::from("/tmp/1-output.mp4"))
+        );
+        assert!(get_printed_path(" \n").is_none());
+    }
+}
@@ -0,0 +1,89 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{message::TelegramMessage, state::AppState};
+use anyhow::Result;
+use futures::{future::BoxFuture, Future, FutureExt};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+pub const CONVERSATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+type StepFn = Box<dyn FnOnce(TelegramMessage, AppState) -> BoxFuture<'static, Result<()>> + Send>;
+
+// chat id and sender id, the sender is none for anonymous admins and channels
+type ConversationKey = (i64, Option<i64>);
+
+struct Conversation {
+    step: StepFn,
+    // the message which started the step, replied to when it times out
+    prompt: TelegramMessage,
+    expires_at: Instant,
+}
+
+// steps of multi-step commands, each waiting for the next message of the sender in the chat
+#[derive(Default)]
+pub struct Conversations {
+    pending: Mutex<HashMap<ConversationKey, Conversation>>,
+}
+
+impl Conversations {
+    // the step may expect another one to continue, a pending step of the sender is replaced
+    pub fn expect<F, Fut>(&self, message: &TelegramMessage, timeout: Duration, step: F)
+    where
+        F: FnOnce(TelegramMessage, AppState) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let conversation = Conversation {
+            step: Box::new(move |message, state| step(message, state).boxed()),
+            prompt: message.clone(),
+            expires_at: Instant::now() + timeout,
+        };
+
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(get_key(message), conversation);
+    }
+
+    pub fn take(&self, message: &TelegramMessage) -> Option<StepFn> {
+        self.pending
+            .lock()
+            .unwrap()
+            .remove(&get_key(message))
+            .filter(|conversation| conversation.expires_at > Instant::now())
+            .map(|conversation| conversation.step)
+    }
+
+    // prompts of the expired steps
+    pub fn take_expired(&self) -> Vec<TelegramMessage> {
+        let now = Instant::now();
+        let mut prompts = Vec::new();
+
+        self.pending.lock().unwrap().retain(|_, conversation| {
+            if conversation.expires_at > now {
+                return true;
+            }
+
+            prompts.push(conversation.prompt.clone());
+
+            false
+        });
+
+        prompts
+    }
+}
+
+fn get_key(message: &TelegramMessage) -> ConversationKey {
+    (
+        message.chat().id(),
+        message.sender().map(|sender| sender.id()),
+    )
+}
@@ -8,6 +8,7 @@
 use super::{EventType, Events};
 use crate::{
     error::{ErrorExt, ResultUnwrapExt},
+    handlers::{cancel, onboard},
     message::{ChatEntity, TelegramMessage},
     state::AppState,
     tasker::BatchAborter,
@@ -26,6 +27,16 @@ impl<'h> Handler<'h> {
     }
 
     pub async fn handle_message(&self, message: TelegramMessage) -> Result<()> {
+        if !message.added_user_ids().is_empty() {
+            tracing::info!("handle users added");
+
+            return onboard::handler(message, self.state.clone()).await;
+        }
+
+        if self.handle_conversation(message.clone()).await? {
+            return Ok(());
+        }
+
         match message.media() {
             Some(media) => match media {
                 Media::Document(document) if document.name().to_lowercase().ends_with(".t2o") => {
@@ -44,6 +55,33 @@ impl<'h> Handler<'h> {
         Ok(())
     }
 
+    // answers of multi-step commands, other commands are handled as usual while a step is pending
+    async fn handle_conversation(&self, message: TelegramMessage) -> Result<bool> {
+        let text = message.text();
+        let text = text.trim();
+
+        let is_cancel = text == cancel::PATTERN;
+
+        if text.starts_with('/') && !is_cancel {
+            return Ok(false);
+        }
+
+        let Some(step) = self.state.conversations.take(&message) else {
+            return Ok(false);
+        };
+
+        if is_cancel {
+            let response = "Cancelled.";
+            message.respond(response).await.context(response)?;
+        } else {
+            tracing::info!("handle conversation");
+
+            step(message, self.state.clone()).await?;
+        }
+
+        Ok(true)
+    }
+
     async fn handle_command(&self, message: TelegramMessage) -> Result<()> {
         let text = message.text();
 
@@ -97,15 +135,11 @@ impl<'h> Handler<'h> {
             .media()
             .ok_or_else(|| anyhow!("message does not contain any media"))?;
 
-        let mut download = telegram_user.iter_download(&media);
-        let mut batch_bytes = Vec::new();
-        while let Some(chunk) = download
-            .next()
+        let batch_bytes = telegram_user
+            .download_stream(&media)
+            .read_to_end()
             .await
-            .context("failed to get next chunk from tg file downloader")?
-        {
-            batch_bytes.extend(chunk);
-        }
+            .context("failed to download batch")?;
 
         let batch = String::from_utf8(batch_bytes).context("failed to parse batch")?;
         let batch = batch.trim();
@@ -5,25 +5,71 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod conversation;
 mod events;
 mod handler;
+mod removal;
+mod throttle;
+mod update_stats;
 
 use crate::{
+    archive::archive_old_files,
     client::utils::chat_from_hex,
-    error::{ErrorExt, ResultExt, ResultUnwrapExt},
-    message::{ChatEntity, TelegramMessage},
+    error::{handle_error_details_query, ErrorExt, ResultExt, ResultUnwrapExt},
+    handlers::{
+        cancel, dir_browser, failed, help, history, info, logs, ls, mirror_all, pin_status,
+        priority, queue, rm, scan, start, url, usage, utils::message::is_allowed_sender, version,
+    },
+    message::{
+        get_transient_chat_ids, take_expired_transient_messages, ChatEntity, TelegramMessage,
+    },
     state::{AppState, State},
     tasker::Tasker,
+    trash::purge_trash,
+    verify::verify_uploads,
+    web_server,
 };
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Context, Ok, Result};
+pub use conversation::{Conversations, CONVERSATION_TIMEOUT};
 use events::Events;
 pub use events::{EventType, HashMapExt};
 use grammers_client::Update;
 use handler::Handler;
-use std::sync::Arc;
+pub use removal::notify_owner;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use throttle::{get_command, Throttled};
+pub use throttle::{parse_cooldowns, CommandThrottle};
+use tokio::{
+    signal,
+    sync::mpsc::{self, Receiver, Sender},
+};
+pub use update_stats::UPDATE_STATS;
+
+// messages waiting in the ordered lane, the listener waits for room when a flood of messages fills it
+const ORDERED_LANE_CAPACITY: usize = 1000;
+
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// read-only commands, which are handled at once instead of waiting in the ordered lane
+// so that the bot stays responsive while files or links are being enqueued,
+// and commands controlling queued tasks, which shouldn't wait behind the tasks being enqueued
+const PRIORITY_COMMANDS: [&str; 12] = [
+    queue::PATTERN,
+    history::PATTERN,
+    failed::PATTERN,
+    info::PATTERN,
+    usage::PATTERN,
+    scan::PATTERN,
+    logs::PATTERN,
+    help::PATTERN,
+    start::PATTERN,
+    version::PATTERN,
+    cancel::PATTERN,
+    priority::PATTERN,
+];
 
 pub struct Listener {
-    pub events: Events,
+    pub events: Arc<Events>,
     pub state: AppState,
 }
 
@@ -31,17 +77,34 @@ impl Listener {
     pub async fn new(events: Events) -> Self {
         let state = Arc::new(State::new().await);
 
-        Self { events, state }
+        Self {
+            events: Arc::new(events),
+            state,
+        }
     }
 
     pub async fn run(self) {
         tracing::info!("listener started");
 
+        // tasks left by the last run are resumed without waiting for cold connections,
+        // unreachable servers don't hold the bot back for long
+        if tokio::time::timeout(WARM_UP_TIMEOUT, self.state.warm_up())
+            .await
+            .is_err()
+        {
+            tracing::warn!("warming up clients timed out");
+        }
+
         let tasker = Tasker::new(self.state.clone());
         tokio::spawn(async move {
             tasker.run().await;
         });
 
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            web_server::run(state).await.trace();
+        });
+
         let state = self.state.clone();
         tokio::spawn(async move {
             loop {
@@ -51,12 +114,93 @@ impl Listener {
             }
         });
 
-        loop {
-            self.handle_message().await.trace();
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            loop {
+                tidy_transient_messages(state.clone()).await.trace();
+
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+        });
+
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            // when each chat was last updated, so that they are updated at their own intervals
+            let mut updated_at = HashMap::new();
+
+            loop {
+                pin_status::update_pinned_statuses(&state, &mut updated_at)
+                    .await
+                    .trace();
+
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+        });
+
+        // instead of refreshing a token on the request that finds it expired, maybe mid-upload
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            loop {
+                state.refresh_onedrive_tokens().await;
+
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+        });
+
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            loop {
+                archive_old_files(&state).await;
+                purge_trash(&state).await;
+
+                tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+            }
+        });
+
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            loop {
+                verify_uploads(&state).await;
+
+                tokio::time::sleep(Duration::from_secs(24 * 60 * 60)).await;
+            }
+        });
+
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            loop {
+                notify_expired_conversations(&state).await;
+
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+        });
+
+        // other messages are handled one by one in order, such as /dir followed by files
+        let (ordered_sender, ordered_receiver) = mpsc::channel(ORDERED_LANE_CAPACITY);
+        let events = self.events.clone();
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            run_ordered_lane(events, state, ordered_receiver).await;
+        });
+
+        let fut = async {
+            loop {
+                self.handle_message(&ordered_sender).await.trace();
+            }
+        };
+
+        tokio::select! {
+            _ = fut => {}
+            () = shutdown_signal() => {}
         }
+
+        tracing::info!("listener shutting down");
+
+        self.state.save_queued_messages().await.trace();
+        self.state.usage_session.flush().await.trace();
     }
 
-    async fn handle_message(&self) -> Result<()> {
+    async fn handle_message(&self, ordered_sender: &Sender<TelegramMessage>) -> Result<()> {
         let client = &self.state.telegram_bot;
         let telegram_user = &self.state.telegram_user;
         let task_session = &self.state.task_session;
@@ -68,12 +212,75 @@ impl Listener {
                 if !message_raw.outgoing() {
                     let message = TelegramMessage::new(client.clone(), message_raw);
 
-                    let handler = Handler::new(&self.events, self.state.clone());
-                    if let Err(e) = handler.handle_message(message.clone()).await {
-                        e.send(message).await.unwrap_both().trace();
+                    if !is_relevant_message(&message) || !is_allowed_message(&message) {
+                        UPDATE_STATS.ignore();
+                    } else if let Some(throttled) = self.state.command_throttle.check(&message) {
+                        UPDATE_STATS.ignore();
+
+                        let state = self.state.clone();
+
+                        tokio::spawn(async move {
+                            respond_throttled(&message, throttled, &state).await.trace();
+                        });
+                    } else if is_priority_command(&message.text()) {
+                        UPDATE_STATS.handle();
+
+                        let events = self.events.clone();
+                        let state = self.state.clone();
+                        tokio::spawn(async move {
+                            handle_new_message(&events, state, message).await;
+                        });
+                    } else {
+                        // waits for room instead of dropping the message,
+                        // updates are buffered by grammers meanwhile
+                        ordered_sender
+                            .send(message)
+                            .await
+                            .map_err(|_| anyhow!("ordered lane is closed"))?;
+
+                        UPDATE_STATS.handle();
                     }
                 }
             }
+            Update::CallbackQuery(query) => {
+                if query
+                    .data()
+                    .starts_with(dir_browser::CALLBACK_PREFIX.as_bytes())
+                {
+                    // lists folders from onedrive, so it doesn't block other updates
+                    let state = self.state.clone();
+                    tokio::spawn(async move {
+                        dir_browser::handle_browser_query(query, state)
+                            .await
+                            .trace();
+                    });
+                } else if query.data().starts_with(ls::CALLBACK_PREFIX.as_bytes()) {
+                    // lists items from onedrive too
+                    let state = self.state.clone();
+                    tokio::spawn(async move {
+                        ls::handle_listing_query(query, state).await.trace();
+                    });
+                } else if query.data().starts_with(rm::CALLBACK_PREFIX.as_bytes()) {
+                    // deletes the item from onedrive once confirmed
+                    let state = self.state.clone();
+                    tokio::spawn(async move {
+                        rm::handle_deletion_query(query, state).await.trace();
+                    });
+                } else if query
+                    .data()
+                    .starts_with(mirror_all::CALLBACK_PREFIX.as_bytes())
+                {
+                    // starts mirroring the selected chats in its own task
+                    let state = self.state.clone();
+                    tokio::spawn(async move {
+                        mirror_all::handle_checklist_query(query, state)
+                            .await
+                            .trace();
+                    });
+                } else {
+                    handle_error_details_query(query).await?;
+                }
+            }
             Update::MessageDeleted(messages_info) => {
                 // abort the task if the related message is deleted
                 // bot can only catch deleted message immediately if it is sent by itself
@@ -126,6 +333,9 @@ impl Listener {
                     }
                 }
             }
+            Update::Raw(update) => {
+                removal::handle_raw_update(update, &self.state).await?;
+            }
             _ => {}
         }
 
@@ -133,6 +343,146 @@ impl Listener {
     }
 }
 
+// duplicates are dropped silently, since the first one is already answered,
+// only the command is logged, since the arguments may hold a password
+async fn respond_throttled(
+    message: &TelegramMessage,
+    throttled: Throttled,
+    state: &AppState,
+) -> Result<()> {
+    let text = message.text();
+    let command = get_command(text.trim());
+
+    // the handler isn't reached, so the password is removed here
+    if command == url::PATTERN && url::has_password(&text) {
+        url::delete_command_with_password(message, state)
+            .await
+            .context("failed to delete /url command with password")
+            .trace();
+    }
+
+    match throttled {
+        Throttled::Duplicate => {
+            tracing::info!("ignored duplicate command: {}", command);
+        }
+        Throttled::CoolingDown { command, remaining } => {
+            let response = format!(
+                "{} is cooling down in this chat, try again in {}s.",
+                command,
+                remaining.as_secs().max(1)
+            );
+            message.respond(response.as_str()).await.context(response)?;
+        }
+    }
+
+    Ok(())
+}
+
+// joins, leaves, pins and other service messages in busy groups never reach the handlers,
+// except the bot being added
+fn is_relevant_message(message: &TelegramMessage) -> bool {
+    !message.text().trim().is_empty()
+        || message.media().is_some()
+        || !message.added_user_ids().is_empty()
+}
+
+// senders not in tg_user_name are ignored before their commands are throttled or wait in the ordered lane,
+// except adding the bot, which is answered by onboarding
+fn is_allowed_message(message: &TelegramMessage) -> bool {
+    !message.added_user_ids().is_empty() || is_allowed_sender(message)
+}
+
+fn is_priority_command(text: &str) -> bool {
+    text.split_whitespace()
+        .next()
+        .and_then(|command| command.split('@').next())
+        .is_some_and(|command| PRIORITY_COMMANDS.contains(&command))
+}
+
+async fn run_ordered_lane(
+    events: Arc<Events>,
+    state: AppState,
+    mut ordered_receiver: Receiver<TelegramMessage>,
+) {
+    while let Some(message) = ordered_receiver.recv().await {
+        handle_new_message(&events, state.clone(), message).await;
+    }
+}
+
+async fn handle_new_message(events: &Events, state: AppState, message: TelegramMessage) {
+    let handler = Handler::new(events, state.clone());
+
+    match handler.handle_message(message.clone()).await {
+        // the cooldown starts once the command is accepted, so a failed one can be sent again at once
+        Result::Ok(()) => state.command_throttle.record_run(&message),
+        Err(e) => e.send(message).await.unwrap_both().trace(),
+    }
+}
+
+// delete bot messages older than the delay set by /tidy in their chats
+async fn tidy_transient_messages(state: AppState) -> Result<()> {
+    let mut delays = HashMap::new();
+
+    for chat_id in get_transient_chat_ids() {
+        let delay = state
+            .settings_session
+            .get_tidy_delay(chat_id)
+            .await?
+            .map(|delay| Duration::from_secs(delay * 60));
+
+        delays.insert(chat_id, delay);
+    }
+
+    for (chat, message_ids) in take_expired_transient_messages(&delays) {
+        state
+            .telegram_bot
+            .delete_messages(chat, &message_ids)
+            .await
+            .trace();
+    }
+
+    Ok(())
+}
+
+async fn notify_expired_conversations(state: &AppState) {
+    for prompt in state.conversations.take_expired() {
+        let response = "Timed out, send the command again.";
+        prompt.reply(response).await.context(response).trace();
+    }
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .context("failed to listen for ctrl-c")
+            .trace();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate())
+            .context("failed to listen for terminate signal")
+        {
+            Result::Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                e.trace();
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
 async fn handle_batch_cancellation(state: AppState) -> Result<()> {
     let telegram_user = &state.telegram_user;
     let task_session = &state.task_session;
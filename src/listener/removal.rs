@@ -0,0 +1,100 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::state::AppState;
+use anyhow::{Context, Result};
+use grammers_client::grammers_tl_types as tl;
+
+// filenames listed in the notification, the rest are counted
+const MAX_LISTED_TASKS: usize = 20;
+
+// tasks of a chat the bot was removed from would fail on every progress update, so they are dropped
+pub async fn handle_raw_update(update: tl::enums::Update, state: &AppState) -> Result<()> {
+    let (chat_id, user_id) = match update {
+        tl::enums::Update::ChannelParticipant(update) if is_channel_left(&update) => {
+            (update.channel_id, update.user_id)
+        }
+        tl::enums::Update::ChatParticipant(update) if update.new_participant.is_none() => {
+            (update.chat_id, update.user_id)
+        }
+        _ => return Ok(()),
+    };
+
+    let me = state
+        .telegram_bot
+        .raw()
+        .get_me()
+        .await
+        .context("failed to get the user of the bot")?;
+
+    if user_id != me.id() {
+        return Ok(());
+    }
+
+    tracing::info!("bot removed from chat {}", chat_id);
+
+    state.settings_session.clear_chat(chat_id).await?;
+
+    let removed_tasks = state.task_session.clear_chat(chat_id).await?;
+
+    if removed_tasks.is_empty() {
+        return Ok(());
+    }
+
+    let mut response = format!(
+        "The bot was removed from chat {}, its settings are cleared and {} tasks are cancelled:",
+        chat_id,
+        removed_tasks.len()
+    );
+
+    for task in removed_tasks.iter().take(MAX_LISTED_TASKS) {
+        response.push('\n');
+        response.push_str(&task.filename);
+    }
+
+    if removed_tasks.len() > MAX_LISTED_TASKS {
+        response.push_str(&format!(
+            "\nand {} more.",
+            removed_tasks.len() - MAX_LISTED_TASKS
+        ));
+    }
+
+    notify_owner(state, response).await
+}
+
+fn is_channel_left(update: &tl::types::UpdateChannelParticipant) -> bool {
+    matches!(
+        update.new_participant,
+        None | Some(
+            tl::enums::ChannelParticipant::Banned(_) | tl::enums::ChannelParticipant::Left(_)
+        )
+    )
+}
+
+// the saved messages of the telegram account, since the bot can't reach the chat anymore
+pub async fn notify_owner(state: &AppState, response: String) -> Result<()> {
+    let telegram_user = &state.telegram_user;
+
+    if !telegram_user.is_authorized().await? {
+        tracing::warn!("{}", response);
+
+        return Ok(());
+    }
+
+    let me = telegram_user
+        .raw()
+        .get_me()
+        .await
+        .context("failed to get the user of the telegram account")?;
+
+    telegram_user
+        .send_message(me.pack(), response.as_str())
+        .await
+        .context(response)?;
+
+    Ok(())
+}
@@ -0,0 +1,237 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{env::ENV, message::TelegramMessage};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// chat id, sender id and the command text
+type ReceivedKey = (i64, Option<i64>, String);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Throttled {
+    // the same command sent again by the sender within the debounce window, like a double tap
+    Duplicate,
+    // the command was run in the chat less than its cooldown ago
+    CoolingDown {
+        command: String,
+        remaining: Duration,
+    },
+}
+
+// commands are checked when they arrive, before waiting in the ordered lane,
+// while the cooldown is only recorded once the command is handled without an error
+pub struct CommandThrottle {
+    debounce_window: Duration,
+    cooldowns: HashMap<String, Duration>,
+    received_at: Mutex<HashMap<ReceivedKey, Instant>>,
+    // chat id and the command with a cooldown
+    run_at: Mutex<HashMap<(i64, String), Instant>>,
+}
+
+impl Default for CommandThrottle {
+    fn default() -> Self {
+        let env = ENV.get().unwrap();
+
+        Self::new(
+            Duration::from_secs(env.debounce_window),
+            env.command_cooldowns
+                .iter()
+                .map(|(command, secs)| (command.clone(), Duration::from_secs(*secs)))
+                .collect(),
+        )
+    }
+}
+
+impl CommandThrottle {
+    fn new(debounce_window: Duration, cooldowns: HashMap<String, Duration>) -> Self {
+        Self {
+            debounce_window,
+            cooldowns,
+            received_at: Mutex::new(HashMap::new()),
+            run_at: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // none if the message isn't a command or should be handled
+    pub fn check(&self, message: &TelegramMessage) -> Option<Throttled> {
+        let text = message.text();
+        let text = text.trim();
+
+        if !text.starts_with('/') {
+            return None;
+        }
+
+        self.check_at(
+            message.chat().id(),
+            message.sender().map(|sender| sender.id()),
+            text,
+            Instant::now(),
+        )
+    }
+
+    pub fn record_run(&self, message: &TelegramMessage) {
+        let text = message.text();
+        let text = text.trim();
+
+        if !text.starts_with('/') {
+            return;
+        }
+
+        self.record_run_at(message.chat().id(), text, Instant::now());
+    }
+
+    fn check_at(
+        &self,
+        chat_id: i64,
+        sender_id: Option<i64>,
+        text: &str,
+        now: Instant,
+    ) -> Option<Throttled> {
+        if self.is_duplicate(chat_id, sender_id, text, now) {
+            return Some(Throttled::Duplicate);
+        }
+
+        let command = get_command(text);
+
+        let cooldown = *self.cooldowns.get(command)?;
+
+        let mut run_at = self.run_at.lock().unwrap();
+
+        run_at.retain(|(_, command), run_at| {
+            self.cooldowns
+                .get(command)
+                .is_some_and(|cooldown| now.duration_since(*run_at) < *cooldown)
+        });
+
+        let last_run_at = run_at.get(&(chat_id, command.to_string()))?;
+
+        Some(Throttled::CoolingDown {
+            command: command.to_string(),
+            remaining: cooldown.saturating_sub(now.duration_since(*last_run_at)),
+        })
+    }
+
+    fn record_run_at(&self, chat_id: i64, text: &str, now: Instant) {
+        let command = get_command(text);
+
+        if self.cooldowns.contains_key(command) {
+            self.run_at
+                .lock()
+                .unwrap()
+                .insert((chat_id, command.to_string()), now);
+        }
+    }
+
+    fn is_duplicate(&self, chat_id: i64, sender_id: Option<i64>, text: &str, now: Instant) -> bool {
+        if self.debounce_window.is_zero() {
+            return false;
+        }
+
+        let mut received_at = self.received_at.lock().unwrap();
+
+        received_at
+            .retain(|_, received_at| now.duration_since(*received_at) < self.debounce_window);
+
+        received_at
+            .insert((chat_id, sender_id, text.to_string()), now)
+            .is_some()
+    }
+}
+
+pub fn get_command(text: &str) -> &str {
+    let command = text.split_whitespace().next().unwrap_or_default();
+
+    // like /links@bot in groups
+    command.split('@').next().unwrap_or_default()
+}
+
+// like /links=60,/mirrorAll=300 in seconds
+pub fn parse_cooldowns(s: &str) -> Result<HashMap<String, u64>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (command, secs) = item
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid cooldown: {}", item))
+                .context("a cooldown is like /links=60 in seconds")?;
+
+            let command = command.trim();
+            let command = if command.starts_with('/') {
+                command.to_string()
+            } else {
+                format!("/{}", command)
+            };
+
+            let secs = secs
+                .trim()
+                .parse::<u64>()
+                .context(format!("invalid seconds of cooldown: {}", item))?;
+
+            Ok((command, secs))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cooldowns() {
+        assert_eq!(
+            parse_cooldowns("/links=60, mirrorAll=300").unwrap(),
+            HashMap::from([("/links".to_string(), 60), ("/mirrorAll".to_string(), 300)])
+        );
+        assert!(parse_cooldowns("").unwrap().is_empty());
+        assert!(parse_cooldowns("/links").is_err());
+        assert!(parse_cooldowns("/links=1m").is_err());
+    }
+
+    #[test]
+    fn test_command_throttle() {
+        let throttle = CommandThrottle::new(
+            Duration::from_secs(3),
+            HashMap::from([("/links".to_string(), Duration::from_secs(60))]),
+        );
+        let now = Instant::now();
+
+        // a double tap is dropped, the same command from another sender or later isn't
+        assert!(throttle.check_at(1, Some(2), "/queue", now).is_none());
+        assert_eq!(
+            throttle.check_at(1, Some(2), "/queue", now + Duration::from_secs(1)),
+            Some(Throttled::Duplicate)
+        );
+        assert!(throttle.check_at(1, Some(3), "/queue", now).is_none());
+        assert!(throttle
+            .check_at(1, Some(2), "/queue", now + Duration::from_secs(5))
+            .is_none());
+
+        // the cooldown is per chat, whatever the arguments, and starts once the command is handled
+        assert!(throttle.check_at(1, Some(2), "/links a 1", now).is_none());
+        assert!(throttle
+            .check_at(1, Some(3), "/links b 2", now + Duration::from_secs(10))
+            .is_none());
+        throttle.record_run_at(1, "/links a 1", now);
+        assert_eq!(
+            throttle.check_at(1, Some(3), "/links@bot b 2", now + Duration::from_secs(20)),
+            Some(Throttled::CoolingDown {
+                command: "/links".to_string(),
+                remaining: Duration::from_secs(40),
+            })
+        );
+        assert!(throttle.check_at(4, Some(2), "/links a 1", now).is_none());
+        assert!(throttle
+            .check_at(1, Some(2), "/links c 3", now + Duration::from_secs(60))
+            .is_none());
+    }
+}
@@ -0,0 +1,43 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// counted since startup, exported by /metrics
+pub static UPDATE_STATS: UpdateStats = UpdateStats::new();
+
+pub struct UpdateStats {
+    handled: AtomicU64,
+    // service messages and others without text or media, messages of senders not allowed,
+    // and throttled commands, dropped before the ordered lane
+    ignored: AtomicU64,
+}
+
+impl UpdateStats {
+    const fn new() -> Self {
+        Self {
+            handled: AtomicU64::new(0),
+            ignored: AtomicU64::new(0),
+        }
+    }
+
+    pub fn handle(&self) {
+        self.handled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn ignore(&self) {
+        self.ignored.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // handled and ignored
+    pub fn get(&self) -> (u64, u64) {
+        (
+            self.handled.load(Ordering::Relaxed),
+            self.ignored.load(Ordering::Relaxed),
+        )
+    }
+}
@@ -5,21 +5,40 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod archive;
+mod audit;
 mod auth_server;
+mod backup;
+mod bandwidth;
 mod client;
+mod crypto;
 mod env;
 mod error;
+mod features;
 mod handlers;
+mod history;
+mod hooks;
 mod listener;
 mod message;
+mod migrate;
+mod scan;
+mod settings;
 mod state;
 mod tasker;
 mod trace;
+mod trash;
+mod url_auth;
 mod utils;
+mod verify;
+mod web_server;
 
-use env::{Env, ENV};
+use env::{check_dirs, Env, ENV};
+use error::ResultExt;
 use handlers::{
-    auth, auto_delete, clear, dir, drive, file, help, link, links, logs, start, url, version,
+    animation, audit, auth, auto_delete, backup as backup_handler, cancel, clear, conflict, copy,
+    dir, drive, failed, fetch, file, help, history, info, limit, link, link_od, links, logs, ls,
+    mirror_all, mv, photo, pin_status, priority, queue, rename_remote, retry, rm, scan, share, start,
+    status, stickerpack, tasks, template, tidy, tz, undo, unlink_od, url, usage, version,
 };
 use listener::{EventType, HashMapExt, Listener};
 use std::collections::HashMap;
@@ -34,6 +53,49 @@ async fn main() {
 
     trace_registor();
 
+    check_dirs().unwrap_or_trace();
+    auth_server::cert::check_cert_paths().unwrap_or_trace();
+
+    // restore <path>, rehydrate a new instance from a backup made by /backup
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() == 3 && args[1] == "restore" {
+        backup::restore_backup(&args[2]).await.unwrap_or_trace();
+
+        tracing::info!("backup restored, start the bot without arguments");
+
+        return;
+    }
+
+    // export-tasks <path> and import-tasks <path>, move unfinished tasks to another instance
+    if args.len() == 3 && args[1] == "export-tasks" {
+        let count = tasker::export::export_tasks(&args[2])
+            .await
+            .unwrap_or_trace();
+
+        tracing::info!("exported {} tasks", count);
+
+        return;
+    }
+
+    if args.len() == 3 && args[1] == "import-tasks" {
+        let count = tasker::export::import_tasks(&args[2])
+            .await
+            .unwrap_or_trace();
+
+        tracing::info!("{} tasks will be imported once the bot starts", count);
+
+        return;
+    }
+
+    // migrate-legacy <dir>, import sessions of the python version
+    if args.len() == 3 && args[1] == "migrate-legacy" {
+        migrate::migrate_legacy(&args[2]).await.unwrap_or_trace();
+
+        tracing::info!("legacy sessions migrated, start the bot without arguments");
+
+        return;
+    }
+
     let events = HashMap::new()
         .on(EventType::command(start::PATTERN), start::handler)
         .on(EventType::command(help::PATTERN), help::handler)
@@ -45,10 +107,52 @@ async fn main() {
         .on(EventType::command(auth::PATTERN), auth::handler)
         .on(EventType::command(clear::PATTERN), clear::handler)
         .on(EventType::command(dir::PATTERN), dir::handler)
+        .on(EventType::command(ls::PATTERN), ls::handler)
+        .on(EventType::command(fetch::PATTERN), fetch::handler)
+        .on(EventType::command(mv::PATTERN), mv::handler)
+        .on(EventType::command(rm::PATTERN), rm::handler)
+        .on(EventType::command(undo::PATTERN), undo::handler)
+        .on(
+            EventType::command(rename_remote::PATTERN),
+            rename_remote::handler,
+        )
         .on(EventType::command(drive::PATTERN), drive::handler)
+        .on(EventType::command(link_od::PATTERN), link_od::handler)
+        .on(EventType::command(unlink_od::PATTERN), unlink_od::handler)
         .on(EventType::command(url::PATTERN), url::handler)
         .on(EventType::command(links::PATTERN), links::handler)
+        .on(EventType::command(mirror_all::PATTERN), mirror_all::handler)
+        .on(EventType::command(scan::PATTERN), scan::handler)
+        .on(EventType::command(copy::PATTERN), copy::handler)
+        .on(
+            EventType::command(stickerpack::PATTERN),
+            stickerpack::handler,
+        )
         .on(EventType::command(version::PATTERN), version::handler)
+        .on(EventType::command(tz::PATTERN), tz::handler)
+        .on(EventType::command(tidy::PATTERN), tidy::handler)
+        .on(EventType::command(pin_status::PATTERN), pin_status::handler)
+        .on(EventType::command(photo::PATTERN), photo::handler)
+        .on(EventType::command(animation::PATTERN), animation::handler)
+        .on(EventType::command(conflict::PATTERN), conflict::handler)
+        .on(EventType::command(template::PATTERN), template::handler)
+        .on(EventType::command(share::PATTERN), share::handler)
+        .on(EventType::command(usage::PATTERN), usage::handler)
+        .on(EventType::command(status::PATTERN), status::handler)
+        .on(EventType::command(limit::PATTERN), limit::handler)
+        .on(EventType::command(queue::PATTERN), queue::handler)
+        .on(EventType::command(history::PATTERN), history::handler)
+        .on(EventType::command(failed::PATTERN), failed::handler)
+        .on(EventType::command(info::PATTERN), info::handler)
+        .on(EventType::command(cancel::PATTERN), cancel::handler)
+        .on(EventType::command(retry::PATTERN), retry::handler)
+        .on(EventType::command(priority::PATTERN), priority::handler)
+        .on(
+            EventType::command(backup_handler::PATTERN),
+            backup_handler::handler,
+        )
+        .on(EventType::command(tasks::PATTERN), tasks::handler)
+        .on(EventType::command(audit::PATTERN), audit::handler)
         .on(EventType::media(), file::handler)
         .on(EventType::text(), link::handler);
 
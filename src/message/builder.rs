@@ -0,0 +1,197 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{MessageContent, OutgoingMessage};
+use crate::env::ENV;
+use anyhow::{anyhow, Error};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    // no entities, only used internally such as for error messages
+    Text,
+    Html,
+    Markdown,
+}
+
+impl FromStr for ParseMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "html" => Ok(Self::Html),
+            "markdown" => Ok(Self::Markdown),
+            _ => Err(anyhow!("parse mode should be html or markdown")),
+        }
+    }
+}
+
+// build responses in the parse mode set by env, all texts except raw ones are escaped
+pub struct MessageBuilder {
+    parse_mode: ParseMode,
+    text: String,
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        Self::with_parse_mode(ENV.get().unwrap().parse_mode)
+    }
+
+    pub const fn with_parse_mode(parse_mode: ParseMode) -> Self {
+        Self {
+            parse_mode,
+            text: String::new(),
+        }
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.text.push_str(&self.escape(text));
+
+        self
+    }
+
+    // text which is already formatted in the same parse mode, such as the text of a sent message
+    pub fn raw(mut self, text: &str) -> Self {
+        self.text.push_str(text);
+
+        self
+    }
+
+    pub fn line(mut self) -> Self {
+        self.text.push('\n');
+
+        self
+    }
+
+    pub fn bold(mut self, text: &str) -> Self {
+        let text = self.escape(text);
+
+        match self.parse_mode {
+            ParseMode::Text => self.text.push_str(&text),
+            ParseMode::Html => self.text.push_str(&format!("<b>{}</b>", text)),
+            ParseMode::Markdown => self.text.push_str(&format!("**{}**", text)),
+        }
+
+        self
+    }
+
+    pub fn code(mut self, text: &str) -> Self {
+        match self.parse_mode {
+            ParseMode::Text => self.text.push_str(text),
+            ParseMode::Html => self
+                .text
+                .push_str(&format!("<code>{}</code>", self.escape(text))),
+            // backslash escapes don't work in code spans
+            ParseMode::Markdown if text.contains('`') => self.text.push_str(&self.escape(text)),
+            ParseMode::Markdown => self.text.push_str(&format!("`{}`", text)),
+        }
+
+        self
+    }
+
+    pub fn pre(mut self, text: &str) -> Self {
+        match self.parse_mode {
+            ParseMode::Text => self.text.push_str(text),
+            ParseMode::Html => self
+                .text
+                .push_str(&format!("<pre><code>{}</code></pre>", self.escape(text))),
+            ParseMode::Markdown if text.contains("```") => {
+                self.text.push_str(&self.escape(text));
+            }
+            ParseMode::Markdown => self.text.push_str(&format!("```\n{}\n```", text)),
+        }
+
+        self
+    }
+
+    pub fn link(mut self, text: &str, url: &str) -> Self {
+        let text = self.escape(text);
+
+        match self.parse_mode {
+            ParseMode::Text => self.text.push_str(&format!("{} ({})", text, url)),
+            ParseMode::Html => self.text.push_str(&format!(
+                "<a href=\"{}\">{}</a>",
+                url.replace('&', "&amp;").replace('"', "&quot;"),
+                text
+            )),
+            ParseMode::Markdown => self.text.push_str(&format!(
+                "[{}]({})",
+                text,
+                url.replace('\\', "\\\\").replace(')', "\\)")
+            )),
+        }
+
+        self
+    }
+
+    fn escape(&self, text: &str) -> String {
+        match self.parse_mode {
+            ParseMode::Text => text.to_string(),
+            ParseMode::Html => text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;"),
+            // filenames often contain _, * and [
+            ParseMode::Markdown => {
+                let mut escaped = String::with_capacity(text.len());
+
+                for c in text.chars() {
+                    if c.is_ascii_punctuation() {
+                        escaped.push('\\');
+                    }
+                    escaped.push(c);
+                }
+
+                escaped
+            }
+        }
+    }
+
+    pub fn build(self) -> MessageContent {
+        match self.parse_mode {
+            ParseMode::Text => MessageContent::Text(self.text),
+            ParseMode::Html => MessageContent::Html(self.text),
+            ParseMode::Markdown => MessageContent::Markdown(self.text),
+        }
+    }
+}
+
+impl From<MessageBuilder> for OutgoingMessage {
+    fn from(builder: MessageBuilder) -> Self {
+        Self::from(builder.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_filename() {
+        let filename = "my_file *final* [1].mp4";
+
+        let content = MessageBuilder::with_parse_mode(ParseMode::Markdown)
+            .code(filename)
+            .line()
+            .text(filename)
+            .build();
+
+        assert_eq!(
+            content.to_string(),
+            "`my_file *final* [1].mp4`\nmy\\_file \\*final\\* \\[1\\]\\.mp4"
+        );
+
+        let content = MessageBuilder::with_parse_mode(ParseMode::Html)
+            .link("a<b>&c.mp4", "https://t.me/c/1/2")
+            .build();
+
+        assert_eq!(
+            content.to_string(),
+            "<a href=\"https://t.me/c/1/2\">a&lt;b&gt;&amp;c.mp4</a>"
+        );
+    }
+}
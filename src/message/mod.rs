@@ -0,0 +1,313 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod builder;
+mod tidy;
+
+use crate::{client::TelegramClient, env::ENV};
+use anyhow::Result;
+pub use builder::{MessageBuilder, ParseMode};
+use chrono::{DateTime, Utc};
+use grammers_client::{
+    grammers_tl_types as tl,
+    types::{Chat, InputMessage, Media, Message, PackedChat},
+};
+use std::{fmt::Display, sync::Arc};
+pub use tidy::{get_transient_chat_ids, take_expired_transient_messages, track_transient_message};
+use tokio::sync::mpsc::Sender;
+
+#[derive(Clone)]
+pub struct TelegramMessage {
+    pub raw: Arc<Message>,
+    client: TelegramClient,
+    text_override: Option<String>,
+}
+
+impl TelegramMessage {
+    pub fn new(client: TelegramClient, message: Message) -> Self {
+        Self {
+            raw: Arc::new(message),
+            client,
+            text_override: None,
+        }
+    }
+
+    pub fn override_text(&mut self, text: String) {
+        self.text_override = Some(text);
+    }
+
+    pub fn chat(&self) -> Chat {
+        self.raw.chat()
+    }
+
+    // plain text without entities, used to parse commands
+    pub fn text(&self) -> String {
+        self.text_override
+            .clone()
+            .unwrap_or_else(|| self.raw.text().to_string())
+    }
+
+    // text with entities in the parse mode set by env, used to edit the message based on itself
+    pub fn formatted_text(&self) -> String {
+        match ENV.get().unwrap().parse_mode {
+            ParseMode::Text => self.raw.text().to_string(),
+            ParseMode::Html => self.raw.html_text(),
+            ParseMode::Markdown => self.raw.markdown_text(),
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        self.raw.id()
+    }
+
+    pub fn media(&self) -> Option<Media> {
+        self.raw.media()
+    }
+
+    pub fn sender(&self) -> Option<Chat> {
+        self.raw.sender()
+    }
+
+    pub fn date(&self) -> DateTime<Utc> {
+        self.raw.date()
+    }
+
+    pub fn reply_to_message_id(&self) -> Option<i32> {
+        self.raw.reply_to_message_id()
+    }
+
+    // the forum topic the message is sent in, none for the general topic and chats without topics
+    pub fn topic_id(&self) -> Option<i32> {
+        match self.raw.reply_header() {
+            Some(tl::enums::MessageReplyHeader::Header(header)) if header.forum_topic => {
+                // replies in a topic point to the message they reply to, and the topic in top id
+                header.reply_to_top_id.or(header.reply_to_msg_id)
+            }
+            _ => None,
+        }
+    }
+
+    // users added by a service message, including the members of a newly created group
+    pub fn added_user_ids(&self) -> Vec<i64> {
+        match self.raw.action() {
+            Some(tl::enums::MessageAction::ChatAddUser(action)) => action.users.clone(),
+            Some(tl::enums::MessageAction::ChatCreate(action)) => action.users.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    // tracked as transient, deleted after the delay set by /tidy
+    pub async fn respond<M: Into<OutgoingMessage>>(&self, message: M) -> Result<Self> {
+        let message = self.respond_final(message).await?;
+
+        track_transient_message(message.chat().pack(), message.id());
+
+        Ok(message)
+    }
+
+    // for messages showing the final state of tasks, which are kept
+    pub async fn respond_final<M: Into<OutgoingMessage>>(&self, message: M) -> Result<Self> {
+        self.client.send_message(self.chat(), message).await
+    }
+
+    pub async fn reply<M: Into<OutgoingMessage>>(&self, message: M) -> Result<Self> {
+        let message = self
+            .client
+            .reply_message(self.chat(), self.id(), message)
+            .await?;
+
+        track_transient_message(message.chat().pack(), message.id());
+
+        Ok(message)
+    }
+
+    pub async fn edit<M: Into<OutgoingMessage>>(
+        &self,
+        message_id: i32,
+        new_message: M,
+    ) -> Result<()> {
+        self.client
+            .edit_message(self.chat(), message_id, new_message)
+            .await
+    }
+}
+
+pub struct QueuedMessage {
+    pub message_type: QueuedMessageType,
+    pub input_message: InputMessage,
+    // kept to persist the message if it is still queued when shutting down
+    pub content: Option<MessageContent>,
+    pub chat: PackedChat,
+    // none if the message is restored after restarting and nobody waits for the result
+    pub tx: Option<Sender<Result<Option<TelegramMessage>>>>,
+    pub priority: MessagePriority,
+}
+
+impl QueuedMessage {
+    pub fn new<M: Into<OutgoingMessage>, C: Into<PackedChat>>(
+        message_type: QueuedMessageType,
+        message: M,
+        chat: C,
+        tx: Sender<Result<Option<TelegramMessage>>>,
+    ) -> Self {
+        let OutgoingMessage {
+            input_message,
+            content,
+        } = message.into();
+
+        Self {
+            message_type,
+            input_message,
+            content,
+            chat: chat.into(),
+            tx: Some(tx),
+            priority: MessagePriority::High,
+        }
+    }
+
+    // nobody waits for the result, such as restored messages and the rest parts of split messages
+    pub fn detached<M: Into<OutgoingMessage>, C: Into<PackedChat>>(
+        message_type: QueuedMessageType,
+        message: M,
+        chat: C,
+    ) -> Self {
+        let OutgoingMessage {
+            input_message,
+            content,
+        } = message.into();
+
+        Self {
+            message_type,
+            input_message,
+            content,
+            chat: chat.into(),
+            tx: None,
+            priority: MessagePriority::High,
+        }
+    }
+
+    pub const fn with_priority(mut self, priority: MessagePriority) -> Self {
+        self.priority = priority;
+
+        self
+    }
+}
+
+// high priority messages in a chat are sent before low priority ones
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MessagePriority {
+    // responses to commands
+    High,
+    // progress of transfers, which are updated frequently
+    Low,
+}
+
+#[derive(Clone, Copy)]
+pub enum QueuedMessageType {
+    Respond,
+    // need to specify the target message id
+    Reply(i32),
+    Edit(i32),
+}
+
+// text only message content, it can be rebuilt into an input message after restarting
+#[derive(Clone)]
+pub enum MessageContent {
+    Text(String),
+    Html(String),
+    Markdown(String),
+}
+
+impl Display for MessageContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text(text) | Self::Html(text) | Self::Markdown(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl From<MessageContent> for InputMessage {
+    fn from(content: MessageContent) -> Self {
+        match content {
+            MessageContent::Text(text) => Self::text(text),
+            MessageContent::Html(html) => Self::html(html),
+            MessageContent::Markdown(markdown) => Self::markdown(markdown),
+        }
+    }
+}
+
+pub struct OutgoingMessage {
+    pub input_message: InputMessage,
+    // none if the message is built as an input message directly, such as messages with media
+    pub content: Option<MessageContent>,
+}
+
+impl From<InputMessage> for OutgoingMessage {
+    fn from(input_message: InputMessage) -> Self {
+        Self {
+            input_message,
+            content: None,
+        }
+    }
+}
+
+impl From<MessageContent> for OutgoingMessage {
+    fn from(content: MessageContent) -> Self {
+        Self {
+            input_message: content.clone().into(),
+            content: Some(content),
+        }
+    }
+}
+
+impl From<&str> for OutgoingMessage {
+    fn from(text: &str) -> Self {
+        Self::from(MessageContent::Text(text.to_string()))
+    }
+}
+
+impl From<String> for OutgoingMessage {
+    fn from(text: String) -> Self {
+        Self::from(MessageContent::Text(text))
+    }
+}
+
+#[derive(Clone)]
+pub enum ChatEntity {
+    Chat(Chat),
+    Id(i64),
+    Username(String),
+}
+
+impl From<Chat> for ChatEntity {
+    fn from(chat: Chat) -> Self {
+        Self::Chat(chat)
+    }
+}
+
+impl From<i64> for ChatEntity {
+    fn from(id: i64) -> Self {
+        Self::Id(id)
+    }
+}
+
+impl From<String> for ChatEntity {
+    fn from(username: String) -> Self {
+        Self::Username(username)
+    }
+}
+
+pub struct MessageInfo {
+    pub chat_entity: ChatEntity,
+    pub id: i32,
+}
+
+impl MessageInfo {
+    pub const fn new(chat_entity: ChatEntity, id: i32) -> Self {
+        Self { chat_entity, id }
+    }
+}
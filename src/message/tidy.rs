@@ -0,0 +1,73 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use grammers_client::types::PackedChat;
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+// bot messages which can be deleted by /tidy, final messages of tasks are not tracked
+static TRANSIENT_MESSAGES: LazyLock<Mutex<Vec<TransientMessage>>> =
+    LazyLock::new(|| Mutex::new(Vec::new()));
+
+struct TransientMessage {
+    chat: PackedChat,
+    id: i32,
+    sent_at: Instant,
+}
+
+pub fn track_transient_message(chat: PackedChat, id: i32) {
+    TRANSIENT_MESSAGES.lock().unwrap().push(TransientMessage {
+        chat,
+        id,
+        sent_at: Instant::now(),
+    });
+}
+
+pub fn get_transient_chat_ids() -> Vec<i64> {
+    let mut chat_ids = TRANSIENT_MESSAGES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|message| message.chat.id)
+        .collect::<Vec<_>>();
+
+    chat_ids.sort_unstable();
+    chat_ids.dedup();
+
+    chat_ids
+}
+
+// messages of chats without a delay are forgotten, chats not in delays are left for the next round
+pub fn take_expired_transient_messages(
+    delays: &HashMap<i64, Option<Duration>>,
+) -> Vec<(PackedChat, Vec<i32>)> {
+    let mut expired_messages: Vec<(PackedChat, Vec<i32>)> = Vec::new();
+
+    TRANSIENT_MESSAGES
+        .lock()
+        .unwrap()
+        .retain(|message| match delays.get(&message.chat.id) {
+            Some(Some(delay)) if message.sent_at.elapsed() >= *delay => {
+                match expired_messages
+                    .iter_mut()
+                    .find(|(chat, _)| chat.id == message.chat.id)
+                {
+                    Some((_, message_ids)) => message_ids.push(message.id),
+                    None => expired_messages.push((message.chat, vec![message.id])),
+                }
+
+                false
+            }
+            Some(None) => false,
+            _ => true,
+        });
+
+    expired_messages
+}
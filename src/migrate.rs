@@ -0,0 +1,167 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::client::{telegram::legacy::import_telethon_session, OneDriveClient};
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use tokio::fs;
+
+// session files of the python version, the bot session isn't needed since the bot signs in with its token
+const LEGACY_USER_SESSION: &str = "user.session";
+const LEGACY_ONEDRIVE_SESSION: &str = "onedrive.session";
+
+// pickle opcodes of strings, see https://github.com/python/cpython/blob/main/Lib/pickletools.py
+const SHORT_BINUNICODE: u8 = 0x8c;
+const BINUNICODE: u8 = b'X';
+const SHORT_BINSTRING: u8 = b'U';
+const BINSTRING: u8 = b'T';
+// memo opcodes may follow a string before the next one
+const MEMOIZE: u8 = 0x94;
+const BINPUT: u8 = b'q';
+const LONG_BINPUT: u8 = b'r';
+
+// run before the clients start, settings of the python version are env values which are still read
+pub async fn migrate_legacy(legacy_dir: &str) -> Result<()> {
+    let legacy_dir = Path::new(legacy_dir);
+
+    if !legacy_dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", legacy_dir.display()));
+    }
+
+    let mut migrated = false;
+
+    let user_session_path = legacy_dir.join(LEGACY_USER_SESSION);
+    if user_session_path.exists() {
+        let username = import_telethon_session(&user_session_path.to_string_lossy())
+            .await
+            .context("failed to migrate telegram user session")?;
+
+        tracing::info!("migrated telegram user session of {}", username);
+
+        migrated = true;
+    }
+
+    let onedrive_session_path = legacy_dir.join(LEGACY_ONEDRIVE_SESSION);
+    if onedrive_session_path.exists() {
+        let data = fs::read(&onedrive_session_path)
+            .await
+            .context("failed to read legacy onedrive session")?;
+
+        let refresh_token = find_pickled_value(&data, "refresh_token")
+            .ok_or_else(|| anyhow!("refresh token not found in legacy onedrive session"))?;
+
+        let username = OneDriveClient::new()
+            .await?
+            .login_with_refresh_token(&refresh_token)
+            .await
+            .context("failed to migrate onedrive session")?;
+
+        tracing::info!("migrated onedrive session of {}", username);
+
+        migrated = true;
+    }
+
+    if !migrated {
+        return Err(anyhow!(
+            "neither {} nor {} found in {}",
+            LEGACY_USER_SESSION,
+            LEGACY_ONEDRIVE_SESSION,
+            legacy_dir.display()
+        ));
+    }
+
+    Ok(())
+}
+
+// the python version pickles the whole onedrivesdk session, only the string after the key is needed
+fn find_pickled_value(data: &[u8], key: &str) -> Option<String> {
+    let mut index = 0;
+
+    while index < data.len() {
+        let Some((value, next_index)) = read_pickled_string(data, index) else {
+            index += 1;
+
+            continue;
+        };
+
+        if value == key {
+            return read_pickled_string(data, skip_memo(data, next_index)).map(|(value, _)| value);
+        }
+
+        index = next_index;
+    }
+
+    None
+}
+
+// returns the string and the index after it if a string opcode is at the index
+fn read_pickled_string(data: &[u8], index: usize) -> Option<(String, usize)> {
+    let (len, start) = match *data.get(index)? {
+        SHORT_BINUNICODE | SHORT_BINSTRING => (*data.get(index + 1)? as usize, index + 2),
+        BINUNICODE | BINSTRING => {
+            let len = u32::from_le_bytes(data.get(index + 1..index + 5)?.try_into().ok()?);
+
+            (len as usize, index + 5)
+        }
+        _ => return None,
+    };
+
+    let value = std::str::from_utf8(data.get(start..start + len)?).ok()?;
+
+    Some((value.to_string(), start + len))
+}
+
+fn skip_memo(data: &[u8], mut index: usize) -> usize {
+    loop {
+        match data.get(index) {
+            Some(&MEMOIZE) => index += 1,
+            Some(&BINPUT) => index += 2,
+            Some(&LONG_BINPUT) => index += 5,
+            _ => return index,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn short_binunicode(s: &str) -> Vec<u8> {
+        let mut data = vec![SHORT_BINUNICODE, s.len() as u8];
+        data.extend_from_slice(s.as_bytes());
+        data.push(MEMOIZE);
+
+        data
+    }
+
+    #[test]
+    fn test_find_pickled_value() {
+        let mut data = vec![0x80, 0x04, 0x95, 0x7d, 0x94];
+        data.extend(short_binunicode("access_token"));
+        data.extend(short_binunicode("access"));
+        data.extend(short_binunicode("refresh_token"));
+        data.extend(short_binunicode("refresh"));
+        data.push(b'u');
+
+        assert_eq!(
+            find_pickled_value(&data, "refresh_token"),
+            Some("refresh".to_string())
+        );
+        assert_eq!(find_pickled_value(&data, "client_secret"), None);
+
+        // protocol 2 used by python 2
+        let mut data = vec![BINUNICODE, 13, 0, 0, 0];
+        data.extend_from_slice(b"refresh_token");
+        data.extend_from_slice(&[BINPUT, 1, BINUNICODE, 7, 0, 0, 0]);
+        data.extend_from_slice(b"refresh");
+
+        assert_eq!(
+            find_pickled_value(&data, "refresh_token"),
+            Some("refresh".to_string())
+        );
+    }
+}
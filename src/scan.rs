@@ -0,0 +1,167 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{env::ENV, features::Feature};
+use anyhow::{anyhow, Context, Result};
+use std::str::FromStr;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UnixStream},
+};
+
+// clamd reads the stream in chunks prefixed with their lengths
+const MAX_CHUNK_LENGTH: usize = 1024 * 1024;
+
+// what to do with an infected file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanPolicy {
+    // not uploaded, the task fails
+    Skip,
+    // uploaded to quarantine_path instead of the directory
+    Quarantine,
+    // uploaded as usual, with the signature in the description
+    Annotate,
+}
+
+impl ScanPolicy {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Skip => "skip",
+            Self::Quarantine => "quarantine",
+            Self::Annotate => "annotate",
+        }
+    }
+}
+
+// also parsed from env
+impl FromStr for ScanPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "skip" => Ok(Self::Skip),
+            "quarantine" => Ok(Self::Quarantine),
+            "annotate" => Ok(Self::Annotate),
+            _ => Err(anyhow!("unknown scan policy: {}", s))
+                .context("scan policy is one of skip, quarantine and annotate"),
+        }
+    }
+}
+
+// larger files are uploaded without scanning, since they would be downloaded twice
+pub fn should_scan(length: u64) -> bool {
+    Feature::Scan.is_active() && length <= ENV.get().unwrap().scan_max_size * 1024 * 1024
+}
+
+trait ClamdStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ClamdStream for T {}
+
+// a file streamed to clamd with INSTREAM, chunk by chunk as it's downloaded
+pub struct ClamdScanner {
+    stream: Box<dyn ClamdStream>,
+}
+
+impl ClamdScanner {
+    // a unix socket path like /run/clamav/clamd.ctl, or host:port
+    pub async fn connect(socket: &str) -> Result<Self> {
+        let stream: Box<dyn ClamdStream> = if socket.starts_with('/') {
+            Box::new(
+                UnixStream::connect(socket)
+                    .await
+                    .context(format!("failed to connect to clamd at {}", socket))?,
+            )
+        } else {
+            Box::new(
+                TcpStream::connect(socket)
+                    .await
+                    .context(format!("failed to connect to clamd at {}", socket))?,
+            )
+        };
+
+        let mut scanner = Self { stream };
+
+        scanner
+            .stream
+            .write_all(b"zINSTREAM\0")
+            .await
+            .context("failed to start clamd scan")?;
+
+        Ok(scanner)
+    }
+
+    pub async fn send(&mut self, data: &[u8]) -> Result<()> {
+        for chunk in data.chunks(MAX_CHUNK_LENGTH) {
+            self.stream
+                .write_all(&(chunk.len() as u32).to_be_bytes())
+                .await
+                .context("failed to send chunk length to clamd")?;
+
+            self.stream
+                .write_all(chunk)
+                .await
+                .context("failed to send chunk to clamd, the file may exceed StreamMaxLength")?;
+        }
+
+        Ok(())
+    }
+
+    // the signature found, none if the file is clean
+    pub async fn finish(mut self) -> Result<Option<String>> {
+        self.stream
+            .write_all(&0_u32.to_be_bytes())
+            .await
+            .context("failed to finish clamd scan")?;
+
+        let mut response = Vec::new();
+        self.stream
+            .read_to_end(&mut response)
+            .await
+            .context("failed to read clamd response")?;
+
+        parse_response(&String::from_utf8_lossy(&response))
+    }
+}
+
+// like stream: OK or stream: Eicar-Signature FOUND, ended with a null byte
+fn parse_response(response: &str) -> Result<Option<String>> {
+    let response = response.trim_end_matches('\0').trim();
+    let result = response.strip_prefix("stream:").map_or(response, str::trim);
+
+    if result == "OK" {
+        return Ok(None);
+    }
+
+    result
+        .strip_suffix(" FOUND")
+        .map(|signature| Some(signature.trim().to_string()))
+        .ok_or_else(|| anyhow!("clamd failed to scan: {}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(parse_response("stream: OK\0").unwrap(), None);
+        assert_eq!(
+            parse_response("stream: Win.Test.EICAR_HDB-1 FOUND\0").unwrap(),
+            Some("Win.Test.EICAR_HDB-1".to_string())
+        );
+        assert!(parse_response("INSTREAM size limit exceeded. ERROR\0").is_err());
+    }
+
+    #[test]
+    fn test_parse_scan_policy() {
+        assert_eq!(
+            "Quarantine".parse::<ScanPolicy>().unwrap(),
+            ScanPolicy::Quarantine
+        );
+        assert!("delete".parse::<ScanPolicy>().is_err());
+    }
+}
@@ -0,0 +1,675 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod chat_settings;
+mod path_template;
+
+use crate::{env::ENV, trash::TrashedItem, utils::get_current_timestamp};
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName,
+    EntityTrait, QueryFilter, Schema, Set,
+};
+use std::str::FromStr;
+
+pub use path_template::{PathTemplate, PathVariables};
+
+const TIMEZONE: &str = "timezone";
+const PHOTO_QUALITY: &str = "photo_quality";
+const ANIMATION_FORMAT: &str = "animation_format";
+const QUEUE_LIMIT: &str = "queue_limit";
+const QUEUE_OVERFLOW: &str = "queue_overflow";
+const TIDY_DELAY: &str = "tidy_delay";
+const DIR: &str = "dir";
+const CONFLICT_POLICY: &str = "conflict_policy";
+const SHARE_LINK: &str = "share_link";
+const PATH_TEMPLATE: &str = "path_template";
+const TRASHED_ITEM: &str = "trashed_item";
+const REGISTERED_AT: &str = "registered_at";
+const PINNED_STATUS: &str = "pinned_status";
+
+// the archive status message kept pinned in a chat by /pinStatus
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PinnedStatus {
+    // minutes between updates
+    pub interval: u64,
+    pub message_id: i32,
+    // the chat packed for the bot, since updates are edited without a command to respond to
+    pub chat_hex: String,
+}
+
+impl PinnedStatus {
+    fn to_value(&self) -> String {
+        format!("{}:{}:{}", self.interval, self.message_id, self.chat_hex)
+    }
+}
+
+impl FromStr for PinnedStatus {
+    type Err = anyhow::Error;
+
+    // like 10:123:$chat_hex
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+
+        let (Some(interval), Some(message_id), Some(chat_hex)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(anyhow!("invalid pinned status: {}", s));
+        };
+
+        Ok(Self {
+            interval: parse_pin_interval(interval)?,
+            message_id: message_id
+                .parse()
+                .context(format!("invalid message id of pinned status: {}", s))?,
+            chat_hex: chat_hex.to_string(),
+        })
+    }
+}
+
+// which size of photos sent as photos to transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhotoQuality {
+    // the largest size kept by telegram
+    Original,
+    // at most 1280px
+    Compressed,
+}
+
+impl PhotoQuality {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Original => "original",
+            Self::Compressed => "compressed",
+        }
+    }
+}
+
+// telegram stores gifs as mp4 animations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationFormat {
+    // keep as it is, only the extension is corrected
+    Mp4,
+    // converted with ffmpeg
+    Gif,
+}
+
+impl AnimationFormat {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Gif => "gif",
+        }
+    }
+}
+
+// pending tasks allowed in a chat
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueLimit {
+    pub max: u64,
+    pub overflow: QueueOverflow,
+}
+
+// what to do with a new task once the queue of the chat is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueOverflow {
+    Reject,
+    // drop the oldest waiting task to make room for the new one
+    DropOldest,
+}
+
+impl QueueOverflow {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Reject => "reject",
+            Self::DropOldest => "drop_oldest",
+        }
+    }
+}
+
+// what to do if a file with the same name exists in the directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    // upload as a new file with a suffix, like a 1.mp4
+    Rename,
+    // overwrite the existing file
+    Replace,
+    // don't upload the file again
+    Skip,
+}
+
+impl ConflictPolicy {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Rename => "rename",
+            Self::Replace => "replace",
+            Self::Skip => "skip",
+        }
+    }
+}
+
+// also parsed from env
+impl FromStr for ConflictPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rename" => Ok(Self::Rename),
+            "replace" => Ok(Self::Replace),
+            "skip" => Ok(Self::Skip),
+            _ => Err(anyhow!("invalid conflict policy: {}", s))
+                .context("conflict policy should be rename, replace or skip"),
+        }
+    }
+}
+
+// who can open the link posted after a file is uploaded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShareLink {
+    // no link is created
+    Off,
+    // anyone with the link
+    Anonymous,
+    // only people in the organization, for work or school accounts
+    Organization,
+}
+
+impl ShareLink {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Anonymous => "anonymous",
+            Self::Organization => "organization",
+        }
+    }
+}
+
+// also parsed from env
+impl FromStr for ShareLink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "anonymous" => Ok(Self::Anonymous),
+            "organization" => Ok(Self::Organization),
+            _ => Err(anyhow!("invalid share link: {}", s))
+                .context("share link should be off, anonymous or organization"),
+        }
+    }
+}
+
+// settings which can be changed per chat by commands
+pub struct SettingsSession {
+    connection: DatabaseConnection,
+}
+
+impl SettingsSession {
+    pub async fn new(session_path: &str) -> Result<Self> {
+        let connection = Self::connect_db(session_path).await?;
+
+        Ok(Self { connection })
+    }
+
+    async fn connect_db(path: &str) -> Result<DatabaseConnection> {
+        let connection = sea_orm::Database::connect(format!("sqlite://{}?mode=rwc", path))
+            .await
+            .context("failed to connect to settings session")?;
+
+        Self::create_table_if_not_exists(&connection).await?;
+
+        Ok(connection)
+    }
+
+    async fn create_table_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        if !Self::is_table_exists(connection).await {
+            let backend = connection.get_database_backend();
+
+            let table_create_statement =
+                Schema::new(backend).create_table_from_entity(chat_settings::Entity);
+
+            connection
+                .execute(backend.build(&table_create_statement))
+                .await
+                .context(format!(
+                    "failed to create table {}",
+                    chat_settings::Entity.table_name()
+                ))?;
+        }
+
+        Ok(())
+    }
+
+    async fn is_table_exists(connection: &DatabaseConnection) -> bool {
+        let result = chat_settings::Entity::find().all(connection).await;
+
+        result.is_ok()
+    }
+
+    async fn get_setting(&self, chat_id: i64, name: &str) -> Result<Option<String>> {
+        let model = chat_settings::Entity::find()
+            .filter(chat_settings::Column::ChatId.eq(chat_id))
+            .filter(chat_settings::Column::Name.eq(name))
+            .one(&self.connection)
+            .await
+            .context("failed to get chat setting")
+            .context(name.to_string())?;
+
+        Ok(model.map(|model| model.value))
+    }
+
+    async fn set_setting(&self, chat_id: i64, name: &str, value: &str) -> Result<()> {
+        let model = chat_settings::Entity::find()
+            .filter(chat_settings::Column::ChatId.eq(chat_id))
+            .filter(chat_settings::Column::Name.eq(name))
+            .one(&self.connection)
+            .await
+            .context("failed to get chat setting")
+            .context(name.to_string())?;
+
+        match model {
+            Some(model) => {
+                let mut active_model: chat_settings::ActiveModel = model.into();
+                active_model.value = Set(value.to_string());
+
+                active_model
+                    .update(&self.connection)
+                    .await
+                    .context("failed to update chat setting")
+                    .context(name.to_string())?;
+            }
+            None => {
+                let insert_item = chat_settings::ActiveModel {
+                    id: ActiveValue::default(),
+                    chat_id: Set(chat_id),
+                    name: Set(name.to_string()),
+                    value: Set(value.to_string()),
+                };
+
+                chat_settings::Entity::insert(insert_item)
+                    .exec(&self.connection)
+                    .await
+                    .context("failed to insert chat setting")
+                    .context(name.to_string())?;
+            }
+        }
+
+        tracing::debug!(
+            "set chat setting {} to {} for chat {}",
+            name,
+            value,
+            chat_id
+        );
+
+        Ok(())
+    }
+
+    async fn remove_setting(&self, chat_id: i64, name: &str) -> Result<()> {
+        chat_settings::Entity::delete_many()
+            .filter(chat_settings::Column::ChatId.eq(chat_id))
+            .filter(chat_settings::Column::Name.eq(name))
+            .exec(&self.connection)
+            .await
+            .context("failed to remove chat setting")
+            .context(name.to_string())?;
+
+        tracing::debug!("removed chat setting {} for chat {}", name, chat_id);
+
+        Ok(())
+    }
+
+    // when the bot is removed from the chat
+    pub async fn clear_chat(&self, chat_id: i64) -> Result<()> {
+        chat_settings::Entity::delete_many()
+            .filter(chat_settings::Column::ChatId.eq(chat_id))
+            .exec(&self.connection)
+            .await
+            .context("failed to clear chat settings")?;
+
+        tracing::debug!("cleared chat settings for chat {}", chat_id);
+
+        Ok(())
+    }
+
+    // utc if not set
+    pub async fn get_timezone(&self, chat_id: i64) -> Result<Tz> {
+        match self.get_setting(chat_id, TIMEZONE).await? {
+            Some(timezone) => parse_timezone(&timezone),
+            None => Ok(Tz::UTC),
+        }
+    }
+
+    pub async fn set_timezone(&self, chat_id: i64, timezone: Tz) -> Result<()> {
+        self.set_setting(chat_id, TIMEZONE, timezone.name()).await
+    }
+
+    pub async fn reset_timezone(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, TIMEZONE).await
+    }
+
+    // original if not set
+    pub async fn get_photo_quality(&self, chat_id: i64) -> Result<PhotoQuality> {
+        match self.get_setting(chat_id, PHOTO_QUALITY).await? {
+            Some(photo_quality) => parse_photo_quality(&photo_quality),
+            None => Ok(PhotoQuality::Original),
+        }
+    }
+
+    pub async fn set_photo_quality(&self, chat_id: i64, photo_quality: PhotoQuality) -> Result<()> {
+        self.set_setting(chat_id, PHOTO_QUALITY, photo_quality.as_str())
+            .await
+    }
+
+    pub async fn reset_photo_quality(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, PHOTO_QUALITY).await
+    }
+
+    // mp4 if not set
+    pub async fn get_animation_format(&self, chat_id: i64) -> Result<AnimationFormat> {
+        match self.get_setting(chat_id, ANIMATION_FORMAT).await? {
+            Some(animation_format) => parse_animation_format(&animation_format),
+            None => Ok(AnimationFormat::Mp4),
+        }
+    }
+
+    pub async fn set_animation_format(
+        &self,
+        chat_id: i64,
+        animation_format: AnimationFormat,
+    ) -> Result<()> {
+        self.set_setting(chat_id, ANIMATION_FORMAT, animation_format.as_str())
+            .await
+    }
+
+    pub async fn reset_animation_format(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, ANIMATION_FORMAT).await
+    }
+
+    // unlimited if not set
+    pub async fn get_queue_limit(&self, chat_id: i64) -> Result<Option<QueueLimit>> {
+        let Some(max) = self.get_setting(chat_id, QUEUE_LIMIT).await? else {
+            return Ok(None);
+        };
+
+        let max = max
+            .parse::<u64>()
+            .context(format!("invalid queue limit: {}", max))?;
+
+        let overflow = match self.get_setting(chat_id, QUEUE_OVERFLOW).await? {
+            Some(overflow) => parse_queue_overflow(&overflow)?,
+            None => QueueOverflow::Reject,
+        };
+
+        Ok(Some(QueueLimit { max, overflow }))
+    }
+
+    pub async fn set_queue_limit(&self, chat_id: i64, queue_limit: QueueLimit) -> Result<()> {
+        self.set_setting(chat_id, QUEUE_LIMIT, &queue_limit.max.to_string())
+            .await?;
+
+        self.set_setting(chat_id, QUEUE_OVERFLOW, queue_limit.overflow.as_str())
+            .await
+    }
+
+    pub async fn reset_queue_limit(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, QUEUE_LIMIT).await?;
+
+        self.remove_setting(chat_id, QUEUE_OVERFLOW).await
+    }
+
+    // in minutes, transient messages are kept if not set
+    pub async fn get_tidy_delay(&self, chat_id: i64) -> Result<Option<u64>> {
+        self.get_setting(chat_id, TIDY_DELAY)
+            .await?
+            .map(|delay| parse_tidy_delay(&delay))
+            .transpose()
+    }
+
+    pub async fn set_tidy_delay(&self, chat_id: i64, delay: u64) -> Result<()> {
+        self.set_setting(chat_id, TIDY_DELAY, &delay.to_string())
+            .await
+    }
+
+    pub async fn reset_tidy_delay(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, TIDY_DELAY).await
+    }
+
+    // the directory of the chat if topic id is none, otherwise the one of the topic
+    pub async fn get_dir(&self, chat_id: i64, topic_id: Option<i32>) -> Result<Option<String>> {
+        self.get_setting(chat_id, &get_dir_name(topic_id)).await
+    }
+
+    pub async fn set_dir(&self, chat_id: i64, topic_id: Option<i32>, path: &str) -> Result<()> {
+        self.set_setting(chat_id, &get_dir_name(topic_id), path)
+            .await
+    }
+
+    pub async fn reset_dir(&self, chat_id: i64, topic_id: Option<i32>) -> Result<()> {
+        self.remove_setting(chat_id, &get_dir_name(topic_id)).await
+    }
+
+    // set by env if not set
+    pub async fn get_conflict_policy(&self, chat_id: i64) -> Result<ConflictPolicy> {
+        match self.get_setting(chat_id, CONFLICT_POLICY).await? {
+            Some(conflict_policy) => conflict_policy.parse(),
+            None => Ok(ENV.get().unwrap().conflict_policy),
+        }
+    }
+
+    pub async fn set_conflict_policy(
+        &self,
+        chat_id: i64,
+        conflict_policy: ConflictPolicy,
+    ) -> Result<()> {
+        self.set_setting(chat_id, CONFLICT_POLICY, conflict_policy.as_str())
+            .await
+    }
+
+    pub async fn reset_conflict_policy(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, CONFLICT_POLICY).await
+    }
+
+    // set by env if not set
+    pub async fn get_share_link(&self, chat_id: i64) -> Result<ShareLink> {
+        match self.get_setting(chat_id, SHARE_LINK).await? {
+            Some(share_link) => share_link.parse(),
+            None => Ok(ENV.get().unwrap().share_link),
+        }
+    }
+
+    pub async fn set_share_link(&self, chat_id: i64, share_link: ShareLink) -> Result<()> {
+        self.set_setting(chat_id, SHARE_LINK, share_link.as_str())
+            .await
+    }
+
+    pub async fn reset_share_link(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, SHARE_LINK).await
+    }
+
+    // set by env if not set, an empty value turns it off in the chat
+    pub async fn get_path_template(&self, chat_id: i64) -> Result<Option<PathTemplate>> {
+        match self.get_setting(chat_id, PATH_TEMPLATE).await? {
+            Some(path_template) if path_template.is_empty() => Ok(None),
+            Some(path_template) => path_template.parse().map(Some),
+            None => Ok(ENV.get().unwrap().path_template.clone()),
+        }
+    }
+
+    pub async fn set_path_template(
+        &self,
+        chat_id: i64,
+        path_template: Option<&PathTemplate>,
+    ) -> Result<()> {
+        self.set_setting(
+            chat_id,
+            PATH_TEMPLATE,
+            path_template.map_or("", PathTemplate::as_str),
+        )
+        .await
+    }
+
+    pub async fn reset_path_template(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, PATH_TEMPLATE).await
+    }
+
+    // the item last deleted by /rm in the chat, restored by /undo
+    pub async fn get_trashed_item(&self, chat_id: i64) -> Result<Option<TrashedItem>> {
+        self.get_setting(chat_id, TRASHED_ITEM)
+            .await?
+            .map(|trashed_item| {
+                serde_json::from_str(&trashed_item).context("failed to parse trashed item")
+            })
+            .transpose()
+    }
+
+    pub async fn set_trashed_item(&self, chat_id: i64, trashed_item: &TrashedItem) -> Result<()> {
+        let trashed_item =
+            serde_json::to_string(trashed_item).context("failed to serialize trashed item")?;
+
+        self.set_setting(chat_id, TRASHED_ITEM, &trashed_item).await
+    }
+
+    pub async fn remove_trashed_item(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, TRASHED_ITEM).await
+    }
+
+    // when the bot is added to the chat, false if it was registered before,
+    // settings defaulting to env are kept by the chat, so changing env later only affects new chats
+    pub async fn register_chat(&self, chat_id: i64) -> Result<bool> {
+        if self.get_setting(chat_id, REGISTERED_AT).await?.is_some() {
+            return Ok(false);
+        }
+
+        let env = ENV.get().unwrap();
+
+        if self.get_setting(chat_id, CONFLICT_POLICY).await?.is_none() {
+            self.set_conflict_policy(chat_id, env.conflict_policy)
+                .await?;
+        }
+
+        if self.get_setting(chat_id, SHARE_LINK).await?.is_none() {
+            self.set_share_link(chat_id, env.share_link).await?;
+        }
+
+        if let Some(path_template) = &env.path_template {
+            if self.get_setting(chat_id, PATH_TEMPLATE).await?.is_none() {
+                self.set_path_template(chat_id, Some(path_template)).await?;
+            }
+        }
+
+        self.set_setting(chat_id, REGISTERED_AT, &get_current_timestamp().to_string())
+            .await?;
+
+        Ok(true)
+    }
+
+    pub async fn get_pinned_status(&self, chat_id: i64) -> Result<Option<PinnedStatus>> {
+        self.get_setting(chat_id, PINNED_STATUS)
+            .await?
+            .map(|pinned_status| pinned_status.parse())
+            .transpose()
+    }
+
+    pub async fn set_pinned_status(
+        &self,
+        chat_id: i64,
+        pinned_status: &PinnedStatus,
+    ) -> Result<()> {
+        self.set_setting(chat_id, PINNED_STATUS, &pinned_status.to_value())
+            .await
+    }
+
+    pub async fn reset_pinned_status(&self, chat_id: i64) -> Result<()> {
+        self.remove_setting(chat_id, PINNED_STATUS).await
+    }
+
+    // chats with a pinned status, updated periodically
+    pub async fn get_pinned_statuses(&self) -> Result<Vec<(i64, PinnedStatus)>> {
+        let models = chat_settings::Entity::find()
+            .filter(chat_settings::Column::Name.eq(PINNED_STATUS))
+            .all(&self.connection)
+            .await
+            .context("failed to get pinned statuses")?;
+
+        models
+            .into_iter()
+            .map(|model| Ok((model.chat_id, model.value.parse()?)))
+            .collect()
+    }
+
+    // render a unix timestamp in the timezone of the chat
+    pub async fn format_timestamp(&self, chat_id: i64, timestamp: i64) -> Result<String> {
+        let timezone = self.get_timezone(chat_id).await?;
+
+        let date_time = DateTime::from_timestamp(timestamp, 0)
+            .ok_or_else(|| anyhow!("timestamp out of range: {}", timestamp))?
+            .with_timezone(&timezone);
+
+        Ok(date_time.format("%Y-%m-%d %H:%M:%S %Z").to_string())
+    }
+}
+
+fn get_dir_name(topic_id: Option<i32>) -> String {
+    topic_id.map_or_else(
+        || DIR.to_string(),
+        |topic_id| format!("{}:{}", DIR, topic_id),
+    )
+}
+
+pub fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|_| anyhow!("invalid timezone: {}", timezone))
+        .context("timezone should be like Europe/Berlin")
+}
+
+pub fn parse_photo_quality(photo_quality: &str) -> Result<PhotoQuality> {
+    match photo_quality {
+        "original" => Ok(PhotoQuality::Original),
+        "compressed" => Ok(PhotoQuality::Compressed),
+        _ => Err(anyhow!("invalid photo quality: {}", photo_quality))
+            .context("photo quality should be original or compressed"),
+    }
+}
+
+pub fn parse_animation_format(animation_format: &str) -> Result<AnimationFormat> {
+    match animation_format {
+        "mp4" => Ok(AnimationFormat::Mp4),
+        "gif" => Ok(AnimationFormat::Gif),
+        _ => Err(anyhow!("invalid animation format: {}", animation_format))
+            .context("animation format should be mp4 or gif"),
+    }
+}
+
+pub fn parse_queue_overflow(overflow: &str) -> Result<QueueOverflow> {
+    match overflow {
+        "reject" => Ok(QueueOverflow::Reject),
+        "drop_oldest" => Ok(QueueOverflow::DropOldest),
+        _ => Err(anyhow!("invalid queue overflow: {}", overflow))
+            .context("queue overflow should be reject or drop_oldest"),
+    }
+}
+
+pub fn parse_pin_interval(interval: &str) -> Result<u64> {
+    interval
+        .parse::<u64>()
+        .ok()
+        .filter(|interval| *interval > 0)
+        .ok_or_else(|| anyhow!("invalid pin interval: {}", interval))
+        .context("pin interval should be a positive number of minutes")
+}
+
+pub fn parse_tidy_delay(delay: &str) -> Result<u64> {
+    delay
+        .parse::<u64>()
+        .ok()
+        .filter(|delay| *delay > 0)
+        .ok_or_else(|| anyhow!("invalid tidy delay: {}", delay))
+        .context("tidy delay should be a positive number of minutes")
+}
@@ -0,0 +1,201 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::client::onedrive::invalid_name::INVALID_COMPONENT;
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+const VARIABLES: [&str; 8] = [
+    "chat", "chat_id", "year", "month", "day", "sender", "label", "filename",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    // one of VARIABLES
+    Variable(&'static str),
+}
+
+// the path of a file in the directory, like {chat}/{year}/{month}/{sender}_{filename}
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathTemplate {
+    template: String,
+    parts: Vec<Part>,
+}
+
+// values of a task when it starts, the date is in the timezone of the chat
+pub struct PathVariables<'a> {
+    pub chat: &'a str,
+    pub chat_id: i64,
+    pub date: DateTime<Tz>,
+    pub sender: Option<&'a str>,
+    pub label: Option<&'a str>,
+    pub filename: &'a str,
+}
+
+impl PathTemplate {
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    // returns the folders relative to the directory, empty if none, and the name of the file
+    pub fn expand(&self, variables: &PathVariables) -> (String, String) {
+        let mut path = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => path.push_str(text),
+                Part::Variable(name) => path.push_str(&sanitize(&variables.get(name))),
+            }
+        }
+
+        // folders of empty values like a missing label are left out
+        let segments = path
+            .split('/')
+            .map(str::trim)
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+
+        match segments.split_last() {
+            Some((filename, folders)) => (folders.join("/"), (*filename).to_string()),
+            None => (String::new(), variables.filename.to_string()),
+        }
+    }
+}
+
+impl PathVariables<'_> {
+    fn get(&self, name: &str) -> String {
+        match name {
+            "chat" => self.chat.to_string(),
+            "chat_id" => self.chat_id.to_string(),
+            "year" => self.date.format("%Y").to_string(),
+            "month" => self.date.format("%m").to_string(),
+            "day" => self.date.format("%d").to_string(),
+            "sender" => self.sender.unwrap_or_default().to_string(),
+            "label" => self.label.unwrap_or_default().to_string(),
+            _ => self.filename.to_string(),
+        }
+    }
+}
+
+// also parsed from env
+impl FromStr for PathTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("unclosed {{ in path template: {}", s))?;
+            let name = &rest[start + 1..start + end];
+
+            let variable = VARIABLES
+                .into_iter()
+                .find(|variable| *variable == name)
+                .ok_or_else(|| anyhow!("unknown variable {{{}}} in path template", name))
+                .context(format!("variables are {{{}}}", VARIABLES.join("}, {")))?;
+
+            parts.push(Part::Variable(variable));
+
+            rest = &rest[start + end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        for part in &parts {
+            if let Part::Text(text) = part {
+                if text.contains('}')
+                    || INVALID_COMPONENT
+                        .iter()
+                        .any(|component| *component != "/" && text.contains(component))
+                {
+                    return Err(anyhow!("invalid characters in path template: {}", text));
+                }
+            }
+        }
+
+        if !parts.contains(&Part::Variable("filename")) {
+            return Err(anyhow!("path template should contain {{filename}}"))
+                .context("so that files with different names don't overwrite each other");
+        }
+
+        Ok(Self {
+            template: s.to_string(),
+            parts,
+        })
+    }
+}
+
+// values can't add folders or contain characters rejected by onedrive
+fn sanitize(value: &str) -> String {
+    let mut value = value.to_string();
+
+    for component in INVALID_COMPONENT {
+        value = value.replace(component, "_");
+    }
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(label: Option<&str>) -> PathVariables<'_> {
+        PathVariables {
+            chat: "Movies/TV",
+            chat_id: -100123,
+            date: DateTime::parse_from_rfc3339("2024-05-01T20:00:00Z")
+                .unwrap()
+                .with_timezone(&Tz::Asia__Shanghai),
+            sender: Some("alice"),
+            label,
+            filename: "a.mp4",
+        }
+    }
+
+    #[test]
+    fn test_expand_path_template() {
+        let template = "{chat}/{year}/{month}/{sender}_{filename}"
+            .parse::<PathTemplate>()
+            .unwrap();
+        assert_eq!(
+            template.expand(&variables(None)),
+            ("Movies_TV/2024/05".to_string(), "alice_a.mp4".to_string())
+        );
+
+        let template = "{label}/{day}/{filename}".parse::<PathTemplate>().unwrap();
+        assert_eq!(
+            template.expand(&variables(None)),
+            ("02".to_string(), "a.mp4".to_string())
+        );
+        assert_eq!(
+            template.expand(&variables(Some("tv"))),
+            ("tv/02".to_string(), "a.mp4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_path_template() {
+        assert!("{chat}/{year}".parse::<PathTemplate>().is_err());
+        assert!("{chat/{filename}".parse::<PathTemplate>().is_err());
+        assert!("{unknown}/{filename}".parse::<PathTemplate>().is_err());
+        assert!("a:b/{filename}".parse::<PathTemplate>().is_err());
+        assert!("{filename".parse::<PathTemplate>().is_err());
+    }
+}
@@ -6,19 +6,41 @@
 */
 
 use crate::{
-    client::{OneDriveClient, TelegramClient},
+    audit::AuditSession,
+    bandwidth::UsageSession,
+    client::{telegram::session::MessageSession, OneDriveClient, TelegramClient},
     env::ENV,
     error::ResultExt,
+    history::HistorySession,
+    listener::{CommandThrottle, Conversations},
+    settings::SettingsSession,
     tasker::TaskSession,
 };
-use std::sync::{atomic::AtomicBool, Arc};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::HashMap,
+    sync::{atomic::AtomicBool, Arc},
+    time::Instant,
+};
+use tokio::sync::RwLock;
 
 pub struct State {
     pub telegram_bot: TelegramClient,
     pub telegram_user: TelegramClient,
-    pub onedrive: OneDriveClient,
+    pub onedrive: Arc<OneDriveClient>,
+    // clients of telegram users in multi-tenant mode, created when first used
+    onedrive_tenants: RwLock<HashMap<i64, Arc<OneDriveClient>>>,
     pub should_auto_delete: AtomicBool,
     pub task_session: TaskSession,
+    pub message_session: MessageSession,
+    pub settings_session: SettingsSession,
+    pub usage_session: UsageSession,
+    pub history_session: HistorySession,
+    pub audit_session: AuditSession,
+    // pending steps of multi-step commands
+    pub conversations: Conversations,
+    // duplicate commands and commands cooling down in the chat are dropped when they arrive
+    pub command_throttle: CommandThrottle,
 }
 
 impl State {
@@ -27,19 +49,146 @@ impl State {
 
         let telegram_bot = TelegramClient::new_bot().await.unwrap_or_trace();
         let telegram_user = TelegramClient::new_user().await.unwrap_or_trace();
-        let onedrive = OneDriveClient::new().await.unwrap_or_trace();
+        let onedrive = Arc::new(OneDriveClient::new().await.unwrap_or_trace());
         let should_auto_delete = AtomicBool::new(env.should_auto_delete);
         let task_session = TaskSession::new(&env.tasker_session_path)
             .await
             .unwrap_or_trace();
+        let message_session = MessageSession::new(&env.message_session_path)
+            .await
+            .unwrap_or_trace();
+        let settings_session = SettingsSession::new(&env.settings_session_path)
+            .await
+            .unwrap_or_trace();
+        let usage_session = UsageSession::new(&env.usage_session_path)
+            .await
+            .unwrap_or_trace();
+        let history_session = HistorySession::new(&env.history_session_path)
+            .await
+            .unwrap_or_trace();
+        let audit_session = AuditSession::new(&env.audit_session_path)
+            .await
+            .unwrap_or_trace();
+
+        telegram_bot
+            .restore_queued_messages(&message_session)
+            .await
+            .trace();
+        telegram_user
+            .restore_queued_messages(&message_session)
+            .await
+            .trace();
 
         Self {
             telegram_bot,
             telegram_user,
             onedrive,
+            onedrive_tenants: RwLock::new(HashMap::new()),
             should_auto_delete,
             task_session,
+            message_session,
+            settings_session,
+            usage_session,
+            history_session,
+            audit_session,
+            conversations: Conversations::default(),
+            command_throttle: CommandThrottle::default(),
+        }
+    }
+
+    // the shared client, or the one of the tenant in multi-tenant mode
+    pub async fn get_onedrive(&self, tenant_id: Option<i64>) -> Result<Arc<OneDriveClient>> {
+        if !ENV.get().unwrap().multi_tenant {
+            return Ok(self.onedrive.clone());
+        }
+
+        let tenant_id = tenant_id
+            .ok_or_else(|| anyhow!("sender of the message is unknown"))
+            .context("send messages as yourself instead of the group in multi-tenant mode")?;
+
+        if let Some(onedrive) = self.onedrive_tenants.read().await.get(&tenant_id) {
+            return Ok(onedrive.clone());
+        }
+
+        let mut onedrive_tenants = self.onedrive_tenants.write().await;
+
+        // created by another task while waiting for the lock
+        if let Some(onedrive) = onedrive_tenants.get(&tenant_id) {
+            return Ok(onedrive.clone());
         }
+
+        let onedrive = Arc::new(OneDriveClient::new_tenant(tenant_id).await?);
+        onedrive_tenants.insert(tenant_id, onedrive.clone());
+
+        Ok(onedrive)
+    }
+
+    // the client is created again with an empty session when the tenant uses the bot next time
+    pub async fn remove_onedrive_tenant(&self, tenant_id: i64) -> Result<()> {
+        self.onedrive_tenants.write().await.remove(&tenant_id);
+
+        OneDriveClient::remove_tenant_session(tenant_id).await
+    }
+
+    // the shared client and clients of tenants created so far, with the tenant id, none for the shared one
+    pub async fn get_loaded_onedrives(&self) -> Vec<(Option<i64>, Arc<OneDriveClient>)> {
+        self.onedrive_tenants
+            .read()
+            .await
+            .iter()
+            .map(|(tenant_id, onedrive)| (Some(*tenant_id), onedrive.clone()))
+            .chain([(None, self.onedrive.clone())])
+            .collect()
+    }
+
+    pub async fn refresh_onedrive_tokens(&self) {
+        for (_, onedrive) in self.get_loaded_onedrives().await {
+            onedrive.refresh_access_tokens_ahead().await.trace();
+        }
+    }
+
+    // connect and authorize clients before the tasker starts, instead of on the first task after booting
+    pub async fn warm_up(&self) {
+        let start = Instant::now();
+
+        for telegram_client in [&self.telegram_bot, &self.telegram_user] {
+            async {
+                // the user client may not be logged in yet
+                if telegram_client.is_authorized().await? {
+                    telegram_client
+                        .raw()
+                        .get_me()
+                        .await
+                        .context("failed to get the user of the telegram client")?;
+                }
+
+                Ok::<(), anyhow::Error>(())
+            }
+            .await
+            .context("failed to warm up telegram client")
+            .trace();
+        }
+
+        for (_, onedrive) in self.get_loaded_onedrives().await {
+            onedrive
+                .warm_up()
+                .await
+                .context("failed to warm up onedrive client")
+                .trace();
+        }
+
+        tracing::info!("clients warmed up in {:.2}s", start.elapsed().as_secs_f64());
+    }
+
+    pub async fn save_queued_messages(&self) -> Result<()> {
+        self.telegram_bot
+            .save_queued_messages(&self.message_session)
+            .await?;
+        self.telegram_user
+            .save_queued_messages(&self.message_session)
+            .await?;
+
+        Ok(())
     }
 }
 
@@ -0,0 +1,107 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    local_files::LocalFiles,
+    remux::run_ffmpeg,
+    tasks,
+    transfer::{
+        create_upload_session, download_tg_file, get_local_upload_session, get_tg_media,
+        multi_parts_uploader_from_file,
+    },
+    Progress,
+};
+use crate::{state::AppState, utils::get_ext};
+use anyhow::Result;
+use std::{path::Path, sync::Arc};
+use tokio_util::sync::CancellationToken;
+
+// named as gif when enqueueing if the chat prefers gif, while telegram stores them as mp4
+pub fn should_convert_to_gif(task: &tasks::Model) -> bool {
+    task.mime_type.as_deref() == Some("video/mp4") && get_ext(&task.filename) == "gif"
+}
+
+pub async fn gif_uploader_from_tg_file(
+    task: &tasks::Model,
+    progress: Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: AppState,
+) -> Result<String> {
+    let files = LocalFiles::new(
+        task.chat_id,
+        task.id,
+        "mp4",
+        "gif",
+        task.total_length as u64,
+    )
+    .await?;
+    let LocalFiles {
+        input_path,
+        output_path,
+        ..
+    } = &files;
+
+    let media = get_tg_media(task, &state).await?;
+    download_tg_file(
+        task,
+        &media,
+        input_path,
+        progress.clone(),
+        cancellation_token,
+        state.clone(),
+    )
+    .await?;
+
+    let (path, filename, upload_session) = match convert_to_gif(input_path, output_path).await {
+        Ok(()) => (
+            output_path,
+            task.filename.clone(),
+            get_local_upload_session(task, &state).await?,
+        ),
+        // upload the mp4 instead, the upload session created when enqueueing is left to expire
+        Err(e) => {
+            tracing::warn!("failed to convert {} to gif: {:?}", task.filename, e);
+
+            let filename = Path::new(&task.filename)
+                .with_extension("mp4")
+                .to_string_lossy()
+                .to_string();
+
+            let upload_session = create_upload_session(task, &filename, &state).await?;
+
+            (input_path, filename, upload_session)
+        }
+    };
+
+    multi_parts_uploader_from_file(
+        task,
+        &filename,
+        path,
+        &upload_session,
+        progress,
+        cancellation_token,
+        state,
+    )
+    .await
+}
+
+async fn convert_to_gif(input_path: &Path, output_path: &Path) -> Result<()> {
+    // a palette generated from the animation itself keeps the colors
+    run_ffmpeg(
+        input_path,
+        &[
+            "-vf",
+            "split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse",
+            "-loop",
+            "0",
+            "-f",
+            "gif",
+        ],
+        output_path,
+    )
+    .await
+}
@@ -0,0 +1,165 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+
+// throttling or timeout errors within the window before the limit is halved
+const ERROR_THRESHOLD: usize = 3;
+const ERROR_WINDOW: Duration = Duration::from_secs(60);
+// the limit grows by one after each quiet interval
+const RAMP_UP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct ErrorRecords {
+    recent: VecDeque<Instant>,
+    last: Option<Instant>,
+}
+
+// limits parallel tasks and scales chunk sizes, backing off when errors spike
+pub struct Concurrency {
+    semaphore: Arc<Semaphore>,
+    max_limit: usize,
+    limit: AtomicUsize,
+    errors: Mutex<ErrorRecords>,
+}
+
+impl Concurrency {
+    pub fn new(max_limit: usize) -> Self {
+        let max_limit = max_limit.max(1);
+
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_limit)),
+            max_limit,
+            limit: AtomicUsize::new(max_limit),
+            errors: Mutex::new(ErrorRecords::default()),
+        }
+    }
+
+    pub fn semaphore(&self) -> Arc<Semaphore> {
+        self.semaphore.clone()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    // scale a count in proportion to the current limit, at least 1
+    pub fn scale(&self, value: usize) -> usize {
+        (value * self.limit() / self.max_limit).max(1)
+    }
+
+    pub fn record_error(&self) {
+        let now = Instant::now();
+
+        let mut errors = self.errors.lock().unwrap();
+
+        errors.last = Some(now);
+        errors.recent.push_back(now);
+        while errors
+            .recent
+            .front()
+            .is_some_and(|time| now.duration_since(*time) > ERROR_WINDOW)
+        {
+            errors.recent.pop_front();
+        }
+
+        if errors.recent.len() < ERROR_THRESHOLD {
+            return;
+        }
+
+        errors.recent.clear();
+        drop(errors);
+
+        let limit = self.limit();
+        let new_limit = (limit / 2).max(1);
+
+        if new_limit == limit {
+            return;
+        }
+
+        self.limit.store(new_limit, Ordering::Relaxed);
+
+        // running tasks keep their permits, which are forgotten once released
+        let semaphore = self.semaphore.clone();
+        tokio::spawn(async move {
+            if let Ok(permits) = semaphore
+                .acquire_many_owned((limit - new_limit) as u32)
+                .await
+            {
+                permits.forget();
+            }
+        });
+
+        tracing::warn!(
+            "too many throttling or timeout errors, concurrency reduced to {}",
+            new_limit
+        );
+    }
+
+    pub async fn run(&self) {
+        loop {
+            tokio::time::sleep(RAMP_UP_INTERVAL).await;
+
+            self.ramp_up();
+        }
+    }
+
+    fn ramp_up(&self) {
+        let is_quiet = self
+            .errors
+            .lock()
+            .unwrap()
+            .last
+            .is_none_or(|time| time.elapsed() > RAMP_UP_INTERVAL);
+
+        let limit = self.limit();
+
+        if is_quiet && limit < self.max_limit {
+            self.limit.store(limit + 1, Ordering::Relaxed);
+            self.semaphore.add_permits(1);
+
+            tracing::info!("concurrency increased to {}", limit + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_error() {
+        let concurrency = Concurrency::new(5);
+
+        assert_eq!(concurrency.scale(4), 4);
+
+        for _ in 0..ERROR_THRESHOLD {
+            concurrency.record_error();
+        }
+
+        assert_eq!(concurrency.limit(), 2);
+        assert_eq!(concurrency.scale(4), 1);
+        assert_eq!(concurrency.scale(10), 4);
+
+        concurrency.record_error();
+
+        assert_eq!(concurrency.limit(), 2);
+
+        // not quiet yet
+        concurrency.ramp_up();
+
+        assert_eq!(concurrency.limit(), 2);
+    }
+}
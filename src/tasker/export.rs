@@ -0,0 +1,84 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{tasks, InsertTask, TaskSession};
+use crate::env::ENV;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs;
+
+// unfinished tasks with their sources, destinations and upload sessions
+#[derive(Serialize, Deserialize)]
+struct TaskExport {
+    tasks: Vec<InsertTask>,
+}
+
+pub fn serialize_tasks(tasks: Vec<tasks::Model>) -> Result<Vec<u8>> {
+    let export = TaskExport {
+        tasks: tasks.into_iter().map(InsertTask::from).collect(),
+    };
+
+    serde_json::to_vec_pretty(&export).context("failed to serialize tasks")
+}
+
+pub fn deserialize_tasks(data: &[u8]) -> Result<Vec<InsertTask>> {
+    let TaskExport { tasks } =
+        serde_json::from_slice(data).context("failed to deserialize exported tasks")?;
+
+    Ok(tasks)
+}
+
+// export-tasks <path>, run while the bot is stopped, since tasks are picked up once it starts
+pub async fn export_tasks(path: &str) -> Result<usize> {
+    let tasks = TaskSession::read_tasks(&ENV.get().unwrap().tasker_session_path).await?;
+    let count = tasks.len();
+
+    fs::write(path, serialize_tasks(tasks)?)
+        .await
+        .context(format!("failed to write exported tasks to {}", path))?;
+
+    Ok(count)
+}
+
+// import-tasks <path>, the tasks are inserted once the tasker starts
+pub async fn import_tasks(path: &str) -> Result<usize> {
+    let data = fs::read(path)
+        .await
+        .context(format!("failed to read exported tasks from {}", path))?;
+
+    let count = deserialize_tasks(&data)?.len();
+
+    fs::write(&ENV.get().unwrap().tasks_import_path, data)
+        .await
+        .context("failed to save tasks to import")?;
+
+    Ok(count)
+}
+
+pub async fn import_pending_tasks(session: &TaskSession) -> Result<()> {
+    let tasks_import_path = &ENV.get().unwrap().tasks_import_path;
+
+    if !Path::new(tasks_import_path).exists() {
+        return Ok(());
+    }
+
+    let data = fs::read(tasks_import_path)
+        .await
+        .context("failed to read tasks to import")?;
+
+    let count = session.import_tasks(deserialize_tasks(&data)?).await?;
+
+    // never imported twice
+    fs::rename(tasks_import_path, format!("{}.bak", tasks_import_path))
+        .await
+        .context("failed to back up imported tasks")?;
+
+    tracing::info!("imported {} tasks", count);
+
+    Ok(())
+}
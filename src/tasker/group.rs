@@ -0,0 +1,156 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::tasks;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+// photos or videos sent as an album, or tasks created by the same /links or batch message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TaskGroupKey {
+    Album(i64),
+    Message(i32),
+}
+
+impl TaskGroupKey {
+    pub const fn new(group_id: Option<i64>, message_id: i32) -> Self {
+        match group_id {
+            Some(group_id) => Self::Album(group_id),
+            None => Self::Message(message_id),
+        }
+    }
+
+    pub const fn from_task(task: &tasks::Model) -> Self {
+        Self::new(task.group_id, task.message_id)
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Album(_) => "Album",
+            Self::Message(_) => "Batch",
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct TaskGroup {
+    pub items: u64,
+    pub finished_items: u64,
+    pub total_length: u64,
+    pub finished_length: u64,
+}
+
+// finished tasks are deleted, so the totals of each group are counted here
+#[derive(Default)]
+pub struct TaskGroups {
+    groups: Mutex<HashMap<(i64, TaskGroupKey), TaskGroup>>,
+}
+
+impl TaskGroups {
+    pub fn add(&self, chat_id: i64, key: TaskGroupKey, total_length: u64) {
+        let mut groups = self.groups.lock().unwrap();
+        let group = groups.entry((chat_id, key)).or_default();
+
+        group.items += 1;
+        group.total_length += total_length;
+    }
+
+    pub fn finish(&self, task: &tasks::Model) {
+        let mut groups = self.groups.lock().unwrap();
+
+        if let Some(group) = groups.get_mut(&(task.chat_id, TaskGroupKey::from_task(task))) {
+            group.finished_items += 1;
+            group.finished_length += task.total_length as u64;
+        }
+    }
+
+    pub fn get(&self, chat_id: i64, key: TaskGroupKey) -> Option<TaskGroup> {
+        self.groups.lock().unwrap().get(&(chat_id, key)).cloned()
+    }
+
+    pub fn keys(&self) -> Vec<(i64, TaskGroupKey)> {
+        self.groups.lock().unwrap().keys().copied().collect()
+    }
+
+    // forget groups whose tasks are all gone, including cancelled ones
+    pub fn retain(&self, existing: &HashSet<(i64, TaskGroupKey)>) {
+        self.groups
+            .lock()
+            .unwrap()
+            .retain(|chat_key, _| existing.contains(chat_key));
+    }
+}
+
+// suffixed like name (1).ext as onedrive does, names are case insensitive in onedrive
+pub fn get_unique_filename(filename: &str, taken: &[String]) -> String {
+    let is_taken = |name: &str| taken.iter().any(|taken| taken.eq_ignore_ascii_case(name));
+
+    if !is_taken(filename) {
+        return filename.to_string();
+    }
+
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (filename, String::new()),
+    };
+
+    (1..)
+        .map(|index| format!("{} ({}){}", stem, index, ext))
+        .find(|name| !is_taken(name))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_groups() {
+        let groups = TaskGroups::default();
+        let key = TaskGroupKey::new(Some(1), 10);
+
+        groups.add(100, key, 1024);
+        groups.add(100, key, 2048);
+        groups.add(100, TaskGroupKey::new(None, 10), 512);
+
+        assert_eq!(
+            groups.get(100, key),
+            Some(TaskGroup {
+                items: 2,
+                finished_items: 0,
+                total_length: 3072,
+                finished_length: 0,
+            })
+        );
+        assert_eq!(groups.get(100, TaskGroupKey::Message(10)).unwrap().items, 1);
+
+        groups.retain(&HashSet::from([(100, key)]));
+
+        assert!(groups.get(100, TaskGroupKey::Message(10)).is_none());
+        assert!(groups.get(100, key).is_some());
+    }
+
+    #[test]
+    fn test_get_unique_filename() {
+        let taken = vec![
+            "1700000000.jpg".to_string(),
+            "1700000000 (1).JPG".to_string(),
+        ];
+
+        assert_eq!(get_unique_filename("a.jpg", &taken), "a.jpg");
+        assert_eq!(
+            get_unique_filename("1700000000.jpg", &taken),
+            "1700000000 (2).jpg"
+        );
+        assert_eq!(
+            get_unique_filename("README", &["readme".to_string()]),
+            "README (1)"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    local_files::LocalFiles,
+    tasks,
+    transfer::{download_tg_file, get_tg_message, record_tg_usage},
+    Progress, TaskOutcome,
+};
+use crate::{client::utils::chat_from_hex, message::MessageBuilder, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::{types::Media, InputMessage};
+use std::sync::Arc;
+use tokio::fs;
+use tokio_util::sync::CancellationToken;
+
+// download from the origin chat and upload to the target chat with user client, without onedrive
+pub async fn handler(
+    task: tasks::Model,
+    progress: Arc<Progress>,
+    cancellation_token: CancellationToken,
+    state: AppState,
+) -> Result<TaskOutcome> {
+    let telegram_user = &state.telegram_user;
+
+    let message_origin = get_tg_message(&task, &state).await?;
+
+    let media = message_origin
+        .media()
+        .ok_or_else(|| anyhow!("message does not contain any media"))?;
+
+    let chat_target = chat_from_hex(
+        task.target_chat_hex
+            .as_ref()
+            .ok_or_else(|| anyhow!("target_chat_hex is None"))?,
+    )?;
+
+    let files = LocalFiles::new(
+        task.chat_id,
+        task.id,
+        "copy",
+        "copy",
+        task.total_length as u64,
+    )
+    .await?;
+
+    download_tg_file(
+        &task,
+        &media,
+        &files.input_path,
+        progress,
+        &cancellation_token,
+        state.clone(),
+    )
+    .await?;
+
+    let mut file = fs::File::open(&files.input_path)
+        .await
+        .context("failed to open copied file")?;
+
+    let uploaded = telegram_user
+        .upload_stream(&mut file, task.total_length as usize, task.filename.clone())
+        .await?;
+
+    record_tg_usage(&state, task.total_length as u64).await?;
+
+    // keep the caption of the origin message
+    let caption = MessageBuilder::new()
+        .raw(&message_origin.formatted_text())
+        .build();
+    let input_message = match media {
+        Media::Photo(_) => InputMessage::from(caption).photo(uploaded),
+        Media::Document(_) | Media::Sticker(_) => InputMessage::from(caption).document(uploaded),
+        _ => {
+            return Err(anyhow!(
+                "media type is not one of photo, document and sticker"
+            ))
+        }
+    };
+
+    telegram_user
+        .send_message(chat_target, input_message)
+        .await
+        .context("failed to send copied message")?;
+
+    tracing::info!("copied file: {} size: {}", task.filename, task.total_length);
+
+    Ok(TaskOutcome::Sent)
+}
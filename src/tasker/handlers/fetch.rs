@@ -0,0 +1,317 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    local_files::LocalFiles, remux::run_ffmpeg, tasks, transfer::record_usage, Progress,
+    TaskOutcome,
+};
+use crate::{
+    client::{utils::chat_from_hex, TelegramClient},
+    env::ENV,
+    features::Feature,
+    message::MessageBuilder,
+    state::AppState,
+    utils::get_http_client,
+};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::{
+    types::{attributes::Attribute, media::Uploaded},
+    InputMessage,
+};
+use path_slash::PathBufExt;
+use serde_json::Value;
+use std::{path::Path, process::Stdio, sync::Arc, time::Duration};
+use tokio::{fs, io::AsyncWriteExt, process::Command};
+
+// larger files are sent by the user client
+pub const MAX_BOT_SIZE: u64 = 50 * 1024 * 1024;
+
+// download the file from onedrive into the temp dir of the chat, then send it to the chat
+pub async fn handler(
+    task: tasks::Model,
+    progress: Arc<Progress>,
+    state: AppState,
+) -> Result<TaskOutcome> {
+    let path = Path::new(&task.root_path)
+        .join(&task.filename)
+        .to_slash_lossy()
+        .to_string();
+
+    // the download url is short-lived, so it's got when the task starts
+    let (url, total_length) = state
+        .get_onedrive(task.tenant_id)
+        .await?
+        .get_download_url(task.account.as_deref(), &path)
+        .await?
+        .ok_or_else(|| anyhow!("{} not found", path))?;
+
+    // the output is the thumbnail
+    let files = LocalFiles::new(task.chat_id, task.id, "fetch", "jpg", total_length).await?;
+
+    download_file(&task, &url, &files, progress, &state).await?;
+
+    let mut file = fs::File::open(&files.input_path)
+        .await
+        .context("failed to open fetched file")?;
+
+    let (telegram_client, chat_hex) = if total_length > MAX_BOT_SIZE {
+        (&state.telegram_user, &task.chat_user_hex)
+    } else {
+        (&state.telegram_bot, &task.chat_bot_hex)
+    };
+
+    let uploaded = telegram_client
+        .upload_stream(&mut file, total_length as usize, task.filename.clone())
+        .await?;
+
+    // the path in onedrive is kept as the caption
+    let caption = MessageBuilder::new().code(&path).build();
+    let mut input_message = InputMessage::from(caption).document(uploaded);
+
+    // so that fetched media is displayed as a video or an audio instead of a generic file,
+    // it's still sent without them if ffmpeg fails
+    if let Some(media_type) = get_media_type(&task.filename) {
+        if Feature::Ffmpeg.is_enabled() {
+            match probe_media(&files.input_path).await {
+                Ok(media_info) => {
+                    if let Some(attribute) = media_info.to_attribute(media_type) {
+                        input_message = input_message.attribute(attribute);
+                    }
+                }
+                Err(e) => tracing::warn!("failed to probe {}: {:?}", task.filename, e),
+            }
+
+            match upload_thumbnail(telegram_client, &files).await {
+                Ok(thumbnail) => input_message = input_message.thumbnail(thumbnail),
+                Err(e) => {
+                    tracing::warn!("failed to create thumbnail of {}: {:?}", task.filename, e)
+                }
+            }
+        }
+    }
+
+    telegram_client
+        .send_message(chat_from_hex(chat_hex)?, input_message)
+        .await
+        .context("failed to send fetched file")?;
+
+    tracing::info!("fetched file: {} size: {}", path, total_length);
+
+    Ok(TaskOutcome::Sent)
+}
+
+// the download url doesn't need authorization
+async fn download_file(
+    task: &tasks::Model,
+    url: &str,
+    files: &LocalFiles,
+    progress: Arc<Progress>,
+    state: &AppState,
+) -> Result<()> {
+    let mut response = get_http_client()?
+        .get(url)
+        .send()
+        .await
+        .context("failed to send request for fetching file")?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("failed to fetch file: {}", response.status()));
+    }
+
+    let mut file = fs::File::create(&files.input_path)
+        .await
+        .context("failed to create fetched file")?;
+
+    let mut current_length = 0;
+    progress.set_current_length(task.id, current_length).await?;
+
+    while let Some(chunk) = response.chunk().await.context("failed to get chunk")? {
+        file.write_all(&chunk)
+            .await
+            .context("failed to write fetched file")?;
+
+        // downloaded from onedrive, then sent to telegram
+        record_usage(
+            state,
+            task.tenant_id,
+            task.account.as_deref(),
+            true,
+            chunk.len() as u64,
+        )
+        .await?;
+
+        current_length += chunk.len() as u64;
+        progress.set_current_length(task.id, current_length).await?;
+    }
+
+    file.flush().await.context("failed to flush fetched file")?;
+
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum MediaType {
+    Video,
+    Audio,
+    Image,
+}
+
+fn get_media_type(filename: &str) -> Option<MediaType> {
+    let mime_type = mime_guess::from_path(filename).first_raw()?;
+
+    match mime_type.split('/').next()? {
+        "video" => Some(MediaType::Video),
+        "audio" => Some(MediaType::Audio),
+        "image" => Some(MediaType::Image),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct MediaInfo {
+    duration: Duration,
+    // of the first video stream
+    width: Option<i32>,
+    height: Option<i32>,
+}
+
+impl MediaInfo {
+    fn to_attribute(&self, media_type: MediaType) -> Option<Attribute> {
+        match media_type {
+            MediaType::Video => Some(Attribute::Video {
+                round_message: false,
+                supports_streaming: false,
+                duration: self.duration,
+                w: self.width.unwrap_or_default(),
+                h: self.height.unwrap_or_default(),
+            }),
+            MediaType::Audio => Some(Attribute::Audio {
+                duration: self.duration,
+                title: None,
+                performer: None,
+            }),
+            MediaType::Image => None,
+        }
+    }
+}
+
+async fn probe_media(path: &Path) -> Result<MediaInfo> {
+    let ffprobe_path = &ENV.get().unwrap().ffprobe_path;
+
+    let output = Command::new(ffprobe_path)
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration:stream=codec_type,width,height",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context(format!("failed to run {}", ffprobe_path))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffprobe exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_probe_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_probe_output(output: &str) -> Result<MediaInfo> {
+    let value = serde_json::from_str::<Value>(output).context("failed to parse ffprobe output")?;
+
+    // images have no duration
+    let duration = value
+        .get("format")
+        .and_then(|format| format.get("duration"))
+        .and_then(Value::as_str)
+        .and_then(|duration| duration.parse::<f64>().ok())
+        .filter(|duration| duration.is_finite() && *duration >= 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or_default();
+
+    let video_stream = value
+        .get("streams")
+        .and_then(Value::as_array)
+        .and_then(|streams| {
+            streams
+                .iter()
+                .find(|stream| stream.get("codec_type").and_then(Value::as_str) == Some("video"))
+        });
+
+    let get_size = |key: &str| {
+        video_stream
+            .and_then(|stream| stream.get(key))
+            .and_then(Value::as_i64)
+            .map(|size| size as i32)
+    };
+
+    Ok(MediaInfo {
+        duration,
+        width: get_size("width"),
+        height: get_size("height"),
+    })
+}
+
+// a frame of the video, the image itself, or the cover of the audio, within 320px as telegram requires
+async fn upload_thumbnail(
+    telegram_client: &TelegramClient,
+    files: &LocalFiles,
+) -> Result<Uploaded> {
+    run_ffmpeg(
+        &files.input_path,
+        &[
+            "-vf",
+            "thumbnail,scale=320:320:force_original_aspect_ratio=decrease",
+            "-frames:v",
+            "1",
+            "-f",
+            "mjpeg",
+        ],
+        &files.output_path,
+    )
+    .await?;
+
+    telegram_client.upload_file(&files.output_path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_output() {
+        let output = r#"{
+            "streams": [
+                {"codec_type": "audio"},
+                {"codec_type": "video", "width": 1920, "height": 1080}
+            ],
+            "format": {"duration": "12.500000"}
+        }"#;
+
+        assert_eq!(
+            parse_probe_output(output).unwrap(),
+            MediaInfo {
+                duration: Duration::from_millis(12500),
+                width: Some(1920),
+                height: Some(1080),
+            }
+        );
+        assert_eq!(
+            parse_probe_output(r#"{"streams": [{"codec_type": "audio"}], "format": {}}"#).unwrap(),
+            MediaInfo::default()
+        );
+        assert!(parse_probe_output("").is_err());
+    }
+}
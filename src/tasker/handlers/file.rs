@@ -5,10 +5,34 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::{tasks, transfer::multi_parts_uploader_from_tg_file, Progress};
-use crate::{error::TaskAbortError, state::AppState};
-use anyhow::Result;
-use std::sync::Arc;
+use super::{
+    animation::{gif_uploader_from_tg_file, should_convert_to_gif},
+    is_duplicate,
+    pre_upload::{hook_uploader_from_tg_file, should_run_pre_upload_hook},
+    remux::{get_remux_filename, remux_uploader_from_tg_file},
+    retry_corrupted,
+    tagging::{should_tag, tagging_uploader_from_tg_file},
+    tasks,
+    transfer::{
+        create_upload_session, get_local_upload_session, get_tg_message, hash_file, hash_tg_file,
+        multi_parts_uploader_from_file, multi_parts_uploader_from_tg_file, scan_file, scan_tg_file,
+        small_file_uploader_from_tg_file,
+    },
+    Progress, TaskOutcome,
+};
+use crate::{
+    env::ENV,
+    error::{ErrorExt, InfectedFileError, ResultExt},
+    handlers::utils::args::{Args, LABEL_SCHEMA},
+    scan::{should_scan, ScanPolicy},
+    state::AppState,
+};
+use anyhow::{Context, Result};
+use path_slash::PathBufExt;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio_util::sync::CancellationToken;
 
 pub async fn handler(
@@ -16,21 +40,366 @@ pub async fn handler(
     progress: Arc<Progress>,
     cancellation_token: CancellationToken,
     state: AppState,
-) -> Result<()> {
-    let filename =
-        match multi_parts_uploader_from_tg_file(&task, progress.clone(), cancellation_token, state)
-            .await
-        {
-            Ok(filename) => filename,
-            Err(e) => {
-                if e.downcast_ref::<TaskAbortError>().is_some() {
-                    return Ok(());
-                }
-                return Err(e);
+) -> Result<TaskOutcome> {
+    // self-destructing media downloaded when it was received, uploaded as it is
+    let buffer = task.buffer_path.clone().map(PathBuf::from);
+
+    let result =
+        match is_duplicate_tg_file(&task, buffer.as_deref(), &cancellation_token, &state).await {
+            Ok(true) => return Ok(TaskOutcome::Duplicate),
+            Ok(false) => {
+                scan_and_upload(
+                    task,
+                    buffer.as_deref(),
+                    &progress,
+                    &cancellation_token,
+                    &state,
+                )
+                .await
             }
+            Err(e) => Err(e),
         };
 
+    // the error is ignored by the tasker once aborted
+    let (task, filename, signature) = result?;
+
     progress.update_filename(task.id, &filename).await?;
 
-    Ok(())
+    let onedrive = state.get_onedrive(task.tenant_id).await?;
+
+    let caption = match get_tg_message(&task, &state).await {
+        Ok(message) => {
+            onedrive
+                .set_file_date(
+                    task.account.as_deref(),
+                    &task.root_path,
+                    &filename,
+                    message.date(),
+                )
+                .await
+                .context("failed to keep the message date")
+                .trace();
+
+            get_caption(&message.text())
+        }
+        Err(e) => {
+            e.context("failed to get the message of the file").trace();
+
+            None
+        }
+    };
+
+    if let Some(caption) = &caption {
+        if ENV.get().unwrap().caption_sidecar {
+            let conflict_policy = state
+                .settings_session
+                .get_conflict_policy(task.chat_id)
+                .await?;
+
+            onedrive
+                .upload_small_file(
+                    task.account.as_deref(),
+                    &task.root_path,
+                    &format!("{}.txt", filename),
+                    conflict_policy,
+                    caption.as_bytes().to_vec(),
+                )
+                .await
+                .context("failed to upload the caption")
+                .trace();
+        }
+    }
+
+    let description = [
+        signature
+            .as_ref()
+            .map(|signature| format!("Infected: {}", signature)),
+        task.original_filename
+            .as_ref()
+            .map(|original_filename| format!("Original name: {}", original_filename)),
+        caption,
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+
+    if !description.is_empty() {
+        onedrive
+            .set_description(
+                task.account.as_deref(),
+                &task.root_path,
+                &filename,
+                &description.join("\n"),
+            )
+            .await
+            .context("failed to set the description")
+            .trace();
+    }
+
+    if signature.is_some() && ENV.get().unwrap().scan_policy == ScanPolicy::Quarantine {
+        // the folder of the task is invalidated by the tasker
+        onedrive.invalidate_items(&task.root_path);
+
+        return Ok(TaskOutcome::Quarantined);
+    }
+
+    let path = Path::new(&task.root_path)
+        .join(&filename)
+        .to_slash_lossy()
+        .to_string();
+
+    Ok(TaskOutcome::Uploaded(path))
+}
+
+// returns the task, moved to the quarantine folder if infected, the uploaded filename and the signature found
+async fn scan_and_upload(
+    task: tasks::Model,
+    buffer: Option<&Path>,
+    progress: &Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: &AppState,
+) -> Result<(tasks::Model, String, Option<String>)> {
+    let signature = scan_tg_file_if_needed(&task, buffer, cancellation_token, state).await?;
+
+    let task = match &signature {
+        Some(signature) => {
+            let scan_policy = ENV.get().unwrap().scan_policy;
+
+            tracing::warn!(
+                "{} is infected with {}, {}",
+                task.filename,
+                signature,
+                scan_policy.as_str()
+            );
+
+            state
+                .history_session
+                .insert_scan(&task, Some(signature), Some(scan_policy.as_str()))
+                .await?;
+
+            match scan_policy {
+                ScanPolicy::Skip => {
+                    return Err(InfectedFileError {
+                        filename: task.filename.clone(),
+                        signature: signature.clone(),
+                    }
+                    .into())
+                }
+                ScanPolicy::Quarantine => quarantine_task(task, state).await?,
+                ScanPolicy::Annotate => task,
+            }
+        }
+        None => task,
+    };
+
+    let filename = upload(&task, buffer, progress, cancellation_token, state).await?;
+
+    Ok((task, filename, signature))
+}
+
+async fn scan_tg_file_if_needed(
+    task: &tasks::Model,
+    buffer: Option<&Path>,
+    cancellation_token: &CancellationToken,
+    state: &AppState,
+) -> Result<Option<String>> {
+    let Some(socket) = &ENV.get().unwrap().clamd_socket else {
+        return Ok(None);
+    };
+
+    if !should_scan(task.total_length as u64) {
+        return Ok(None);
+    }
+
+    let result = match buffer {
+        Some(buffer_path) => scan_file(buffer_path, socket).await,
+        None => scan_tg_file(task, socket, cancellation_token, state).await,
+    };
+    let signature = result.context(format!("failed to scan {}", task.filename))?;
+
+    if signature.is_none() {
+        state.history_session.insert_scan(task, None, None).await?;
+    }
+
+    Ok(signature)
+}
+
+// with a new upload session in the folder, small files don't need one
+async fn quarantine_task(task: tasks::Model, state: &AppState) -> Result<tasks::Model> {
+    let mut task = tasks::Model {
+        root_path: ENV.get().unwrap().quarantine_path.clone(),
+        current_length: 0,
+        ..task
+    };
+
+    if !task.upload_url.is_empty() {
+        let upload_session = create_upload_session(&task, &task.filename, state).await?;
+
+        state
+            .task_session
+            .restart_upload(task.id, upload_session.upload_url())
+            .await?;
+
+        task.upload_url = upload_session.upload_url().to_string();
+    }
+
+    Ok(task)
+}
+
+// converted files differ from the source, so only files uploaded as they are can be duplicates
+async fn is_duplicate_tg_file(
+    task: &tasks::Model,
+    buffer: Option<&Path>,
+    cancellation_token: &CancellationToken,
+    state: &AppState,
+) -> Result<bool> {
+    // the pre-upload hook may replace the file
+    if should_run_pre_upload_hook() {
+        return Ok(false);
+    }
+
+    if let Some(buffer_path) = buffer {
+        return is_duplicate(task, state, || hash_file(buffer_path)).await;
+    }
+
+    if get_remux_filename(&task.filename).is_some()
+        || should_convert_to_gif(task)
+        || should_tag(task)
+    {
+        return Ok(false);
+    }
+
+    is_duplicate(task, state, || {
+        hash_tg_file(task, cancellation_token, state)
+    })
+    .await
+}
+
+async fn upload(
+    task: &tasks::Model,
+    buffer: Option<&Path>,
+    progress: &Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: &AppState,
+) -> Result<String> {
+    retry_corrupted(task, state, |task| {
+        let progress = progress.clone();
+        let cancellation_token = cancellation_token.clone();
+        let state = state.clone();
+        let buffer_path = buffer.map(Path::to_path_buf);
+
+        async move {
+            if should_run_pre_upload_hook() {
+                return hook_uploader_from_tg_file(
+                    &task,
+                    buffer_path.as_deref(),
+                    progress,
+                    &cancellation_token,
+                    state,
+                )
+                .await;
+            }
+
+            if let Some(buffer_path) = buffer_path {
+                let upload_session = get_local_upload_session(&task, &state).await?;
+
+                return multi_parts_uploader_from_file(
+                    &task,
+                    &task.filename,
+                    &buffer_path,
+                    &upload_session,
+                    progress,
+                    &cancellation_token,
+                    state,
+                )
+                .await;
+            }
+
+            match get_remux_filename(&task.filename) {
+                Some(remux_filename) => {
+                    remux_uploader_from_tg_file(
+                        &task,
+                        &remux_filename,
+                        progress,
+                        &cancellation_token,
+                        state,
+                    )
+                    .await
+                }
+                None if should_convert_to_gif(&task) => {
+                    gif_uploader_from_tg_file(&task, progress, &cancellation_token, state).await
+                }
+                None if should_tag(&task) => {
+                    tagging_uploader_from_tg_file(&task, progress, &cancellation_token, state).await
+                }
+                None if task.upload_url.is_empty() => {
+                    small_file_uploader_from_tg_file(&task, progress, cancellation_token, state)
+                        .await
+                }
+                None => {
+                    multi_parts_uploader_from_tg_file(&task, progress, cancellation_token, state)
+                        .await
+                }
+            }
+        }
+    })
+    .await
+}
+
+// the caption of the message, without -t $label which is only for the bot
+fn get_caption(text: &str) -> Option<String> {
+    let has_label = Args::parse(text, &LABEL_SCHEMA).is_ok_and(|args| args.option("-t").is_some());
+
+    let caption = if has_label {
+        remove_label_option(text)
+    } else {
+        text.to_string()
+    };
+
+    Some(caption.trim().to_string()).filter(|caption| !caption.is_empty())
+}
+
+// like -t movies or -t 'tv shows', the rest of the caption is kept as it is
+fn remove_label_option(text: &str) -> String {
+    let start = text.match_indices("-t").map(|(i, _)| i).find(|i| {
+        text[..*i].chars().last().is_none_or(char::is_whitespace)
+            && text[i + 2..].starts_with(char::is_whitespace)
+    });
+
+    let Some(start) = start else {
+        return text.to_string();
+    };
+
+    let value = text[start + 2..].trim_start();
+    let rest = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => value[1..].find(quote).map_or("", |end| &value[end + 2..]),
+        _ => value
+            .find(char::is_whitespace)
+            .map_or("", |end| &value[end..]),
+    };
+
+    format!("{}{}", &text[..start], rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_caption() {
+        assert_eq!(
+            get_caption("Trip to the lake\nday 2"),
+            Some("Trip to the lake\nday 2".to_string())
+        );
+        assert_eq!(
+            get_caption("Trip to the lake -t 'summer 2024'"),
+            Some("Trip to the lake".to_string())
+        );
+        assert_eq!(
+            get_caption("-t movies\nThe Matrix"),
+            Some("The Matrix".to_string())
+        );
+        assert!(get_caption("-t movies").is_none());
+        assert!(get_caption("  ").is_none());
+    }
 }
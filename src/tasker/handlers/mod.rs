@@ -5,7 +5,120 @@
 :license: MIT, see LICENSE for more details.
 */
 
+pub mod copy;
+pub mod fetch;
 pub mod file;
+pub mod stickerpack;
 pub mod url;
 
-use super::{tasks, transfer, Progress};
+use super::{animation, local_files, pre_upload, remux, tagging, tasks, transfer, Progress};
+use crate::{error::CorruptedUploadError, settings::ConflictPolicy, state::AppState};
+use anyhow::{Context, Result};
+use std::future::Future;
+
+// a corrupted upload is transferred again, replacing the uploaded file
+const MAX_CORRUPTED_RETRIES: usize = 2;
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum TaskOutcome {
+    // with the path in onedrive, which may differ from the task if the file was renamed on conflict
+    Uploaded(String),
+    // the same file is in the directory already, like one uploaded by an earlier run of a batch
+    Duplicate,
+    // infected, uploaded to quarantine_path instead of the directory
+    Quarantined,
+    // a sticker pack uploaded as a folder of stickers
+    Archived,
+    // copied or fetched into a chat, nothing is uploaded to onedrive
+    Sent,
+}
+
+// a file with the same name, size and quickXorHash is in the directory,
+// only checked before anything is uploaded, the source is hashed only if the size matches
+async fn is_duplicate<F, Fut>(task: &tasks::Model, state: &AppState, hash_source: F) -> Result<bool>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    if task.current_length > 0 {
+        return Ok(false);
+    }
+
+    let Some((size, Some(remote_hash))) = state
+        .get_onedrive(task.tenant_id)
+        .await?
+        .get_file_hash(task.account.as_deref(), &task.root_path, &task.filename)
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    if size != task.total_length as u64 {
+        return Ok(false);
+    }
+
+    tracing::info!("hashing {} to check for duplicate", task.filename);
+
+    let is_duplicate = hash_source().await? == remote_hash;
+
+    if is_duplicate {
+        tracing::info!("skipped duplicate: {}/{}", task.root_path, task.filename);
+    }
+
+    Ok(is_duplicate)
+}
+
+async fn retry_corrupted<F, Fut>(task: &tasks::Model, state: &AppState, upload: F) -> Result<String>
+where
+    F: Fn(tasks::Model) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let mut task = task.clone();
+    let mut retries = 0;
+
+    loop {
+        let e = match upload(task.clone()).await {
+            Ok(filename) => return Ok(filename),
+            Err(e) => e,
+        };
+
+        let Some(CorruptedUploadError { filename }) = e.downcast_ref::<CorruptedUploadError>()
+        else {
+            return Err(e);
+        };
+
+        if retries >= MAX_CORRUPTED_RETRIES {
+            return Err(e).context(format!(
+                "still corrupted after {} retries",
+                MAX_CORRUPTED_RETRIES
+            ));
+        }
+
+        retries += 1;
+
+        tracing::warn!(
+            "{} is corrupted, uploading again, retry {}",
+            filename,
+            retries
+        );
+
+        let (upload_session, _) = state
+            .get_onedrive(task.tenant_id)
+            .await?
+            .multipart_upload_session_builder(
+                task.account.as_deref(),
+                &task.root_path,
+                filename,
+                ConflictPolicy::Replace,
+            )
+            .await?;
+
+        state
+            .task_session
+            .restart_upload(task.id, upload_session.upload_url())
+            .await?;
+
+        task.upload_url = upload_session.upload_url().to_string();
+        task.current_length = 0;
+    }
+}
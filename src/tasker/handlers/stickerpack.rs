@@ -0,0 +1,155 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{local_files::LocalFiles, tasks, transfer::record_usage, Progress, TaskOutcome};
+use crate::{client::telegram::sticker::Sticker, env::ENV, state::AppState};
+use anyhow::{anyhow, Context, Result};
+use path_slash::PathBufExt;
+use std::{path::Path, process::Stdio, sync::Arc};
+use tokio::{fs, process::Command};
+
+// the mime type of a sticker pack task whose animated stickers are converted
+pub const CONVERTED_MIME_TYPE: &str = "image/gif";
+
+// download with the user client and upload into a folder named after the pack,
+// stickers archived before the task failed are skipped when it's retried
+pub async fn handler(
+    task: tasks::Model,
+    progress: Arc<Progress>,
+    state: AppState,
+) -> Result<TaskOutcome> {
+    let tgs_converter_path = if task.mime_type.as_deref() == Some(CONVERTED_MIME_TYPE) {
+        Some(
+            ENV.get()
+                .unwrap()
+                .tgs_converter_path
+                .as_deref()
+                .ok_or_else(|| anyhow!("tgs_converter_path is required to convert stickers"))?,
+        )
+    } else {
+        None
+    };
+
+    let sticker_set = state.telegram_user.get_sticker_set(&task.filename).await?;
+
+    let onedrive = state.get_onedrive(task.tenant_id).await?;
+
+    let pack_path = Path::new(&task.root_path)
+        .join(&task.filename)
+        .to_slash_lossy()
+        .to_string();
+
+    let mut current_length = 0;
+
+    for (index, sticker) in sticker_set.stickers.iter().enumerate() {
+        let sticker_length = sticker.size() as u64;
+
+        if current_length + sticker_length <= task.current_length as u64 {
+            current_length += sticker_length;
+            continue;
+        }
+
+        let (filename, data) =
+            download_sticker(sticker, index, tgs_converter_path, task.chat_id, &state).await?;
+
+        onedrive
+            .upload_bytes(task.account.as_deref(), &pack_path, &filename, &data)
+            .await?;
+
+        record_usage(
+            &state,
+            task.tenant_id,
+            task.account.as_deref(),
+            true,
+            sticker_length,
+        )
+        .await?;
+
+        current_length += sticker_length;
+        progress.set_current_length(task.id, current_length).await?;
+    }
+
+    tracing::info!(
+        "archived sticker pack: {} size: {}",
+        sticker_set.short_name,
+        current_length
+    );
+
+    Ok(TaskOutcome::Archived)
+}
+
+// named by the index so that the order in the pack is kept
+// animated stickers are converted if the converter is given
+async fn download_sticker(
+    sticker: &Sticker,
+    index: usize,
+    tgs_converter_path: Option<&str>,
+    chat_id: i64,
+    state: &AppState,
+) -> Result<(String, Vec<u8>)> {
+    let ext = match sticker.mime_type() {
+        "application/x-tgsticker" => "tgs",
+        "video/webm" => "webm",
+        "image/webp" => "webp",
+        _ => "bin",
+    };
+
+    let data = state
+        .telegram_user
+        .download_stream(sticker)
+        .read_to_end()
+        .await
+        .context("failed to download sticker")?;
+
+    if let (Some(tgs_converter_path), "tgs") = (tgs_converter_path, ext) {
+        match convert_tgs(chat_id, sticker.id(), &data, tgs_converter_path).await {
+            Ok(data) => return Ok((format!("{:03}.gif", index + 1), data)),
+            // still upload the tgs file
+            Err(e) => tracing::warn!("failed to convert sticker {}: {:?}", sticker.id(), e),
+        }
+    }
+
+    Ok((format!("{:03}.{}", index + 1, ext), data))
+}
+
+async fn convert_tgs(
+    chat_id: i64,
+    id: i64,
+    data: &[u8],
+    tgs_converter_path: &str,
+) -> Result<Vec<u8>> {
+    let files = LocalFiles::new(chat_id, id, "tgs", "gif", data.len() as u64).await?;
+    let LocalFiles {
+        input_path,
+        output_path,
+        ..
+    } = &files;
+
+    fs::write(input_path, data)
+        .await
+        .context("failed to write sticker")?;
+
+    let output = Command::new(tgs_converter_path)
+        .arg(input_path)
+        .arg(output_path)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context(format!("failed to run {}", tgs_converter_path))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "tgs converter exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    fs::read(output_path)
+        .await
+        .context("failed to read converted sticker")
+}
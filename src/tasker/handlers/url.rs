@@ -5,14 +5,45 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::{tasks, transfer::multi_parts_uploader_from_url, Progress};
+use super::{
+    is_duplicate, retry_corrupted, tasks,
+    transfer::{hash_url, multi_parts_uploader_from_url},
+    Progress, TaskOutcome,
+};
+use crate::state::AppState;
 use anyhow::Result;
-use std::sync::Arc;
+use path_slash::PathBufExt;
+use std::{path::Path, sync::Arc};
+use tokio_util::sync::CancellationToken;
 
-pub async fn handler(task: tasks::Model, progress: Arc<Progress>) -> Result<()> {
-    let filename = multi_parts_uploader_from_url(&task, progress.clone()).await?;
+pub async fn handler(
+    task: tasks::Model,
+    progress: Arc<Progress>,
+    cancellation_token: CancellationToken,
+    state: AppState,
+) -> Result<TaskOutcome> {
+    if is_duplicate(&task, &state, || hash_url(&task)).await? {
+        return Ok(TaskOutcome::Duplicate);
+    }
+
+    let filename =
+        retry_corrupted(&task, &state, |task| {
+            let progress = progress.clone();
+            let cancellation_token = cancellation_token.clone();
+            let state = state.clone();
+
+            async move {
+                multi_parts_uploader_from_url(&task, progress, &cancellation_token, state).await
+            }
+        })
+        .await?;
 
     progress.update_filename(task.id, &filename).await?;
 
-    Ok(())
+    let path = Path::new(&task.root_path)
+        .join(&filename)
+        .to_slash_lossy()
+        .to_string();
+
+    Ok(TaskOutcome::Uploaded(path))
 }
@@ -0,0 +1,174 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{env::ENV, error::ResultExt};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
+use tokio::fs;
+
+// bytes reserved by local files of each chat, so that a chat can't take all the disk space
+static RESERVED_LENGTHS: LazyLock<Mutex<HashMap<i64, u64>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// downloaded and processed files of a task, removed when dropped, also when the task is aborted
+pub struct LocalFiles {
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    chat_id: i64,
+    reserved_length: u64,
+}
+
+impl LocalFiles {
+    // the output is expected to be about as large as the input
+    pub async fn new(
+        chat_id: i64,
+        id: i64,
+        input_ext: &str,
+        output_ext: &str,
+        input_length: u64,
+    ) -> Result<Self> {
+        let reserved_length = input_length * 2;
+
+        let quota = ENV
+            .get()
+            .unwrap()
+            .temp_quota
+            .map(|quota| quota * 1024 * 1024);
+
+        if !try_reserve(
+            &mut RESERVED_LENGTHS.lock().unwrap(),
+            chat_id,
+            reserved_length,
+            quota,
+        ) {
+            return Err(anyhow!(
+                "not enough temp space for this chat, {:.2}MB required",
+                reserved_length as f64 / 1024.0 / 1024.0
+            ))
+            .context("wait for other tasks of this chat to finish, or increase temp_quota");
+        }
+
+        let dir = Path::new(&ENV.get().unwrap().temp_dir).join(chat_id.to_string());
+
+        // released when dropped even if the dir can't be created
+        let files = Self {
+            input_path: dir.join(format!("{}-input.{}", id, input_ext)),
+            output_path: dir.join(format!("{}-output.{}", id, output_ext)),
+            chat_id,
+            reserved_length,
+        };
+
+        fs::create_dir_all(&dir)
+            .await
+            .context("failed to create local files dir")?;
+
+        Ok(files)
+    }
+}
+
+impl Drop for LocalFiles {
+    fn drop(&mut self) {
+        for path in [&self.input_path, &self.output_path] {
+            if path.exists() {
+                std::fs::remove_file(path)
+                    .context("failed to remove local file")
+                    .trace();
+            }
+        }
+
+        // fails if other tasks of the chat are still using it
+        if let Some(dir) = self.input_path.parent() {
+            let _ = std::fs::remove_dir(dir);
+        }
+
+        release(
+            &mut RESERVED_LENGTHS.lock().unwrap(),
+            self.chat_id,
+            self.reserved_length,
+        );
+    }
+}
+
+// leftovers of the last run, in case it was killed
+pub async fn clear_local_files() -> Result<()> {
+    let temp_dir = Path::new(&ENV.get().unwrap().temp_dir);
+
+    // only the files of tasks, the dir may be a mount point
+    if temp_dir.exists() {
+        let mut entries = fs::read_dir(temp_dir)
+            .await
+            .context("failed to read temp dir")?;
+
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context("failed to read temp dir entry")?
+        {
+            let path = entry.path();
+
+            if path.is_dir() {
+                fs::remove_dir_all(path).await
+            } else {
+                fs::remove_file(path).await
+            }
+            .context("failed to clear local files")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn try_reserve(
+    reserved_lengths: &mut HashMap<i64, u64>,
+    chat_id: i64,
+    length: u64,
+    quota: Option<u64>,
+) -> bool {
+    let reserved_length = reserved_lengths.entry(chat_id).or_default();
+
+    if quota.is_some_and(|quota| *reserved_length + length > quota) {
+        return false;
+    }
+
+    *reserved_length += length;
+
+    true
+}
+
+fn release(reserved_lengths: &mut HashMap<i64, u64>, chat_id: i64, length: u64) {
+    if let Some(reserved_length) = reserved_lengths.get_mut(&chat_id) {
+        *reserved_length = reserved_length.saturating_sub(length);
+
+        if *reserved_length == 0 {
+            reserved_lengths.remove(&chat_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let mut reserved_lengths = HashMap::new();
+
+        assert!(try_reserve(&mut reserved_lengths, 1, 60, Some(100)));
+        assert!(!try_reserve(&mut reserved_lengths, 1, 60, Some(100)));
+        // other chats are not affected
+        assert!(try_reserve(&mut reserved_lengths, 2, 60, Some(100)));
+
+        release(&mut reserved_lengths, 1, 60);
+
+        assert!(try_reserve(&mut reserved_lengths, 1, 100, Some(100)));
+        assert!(try_reserve(&mut reserved_lengths, 3, 1000, None));
+    }
+}
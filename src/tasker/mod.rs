@@ -5,39 +5,76 @@
 :license: MIT, see LICENSE for more details.
 */
 
+mod animation;
+mod concurrency;
+pub mod export;
+mod group;
 mod handlers;
+mod local_files;
+mod pre_upload;
 mod progress;
+mod quick_xor;
+mod remux;
 mod session;
+mod tagging;
 mod tasks;
+mod throughput;
 mod transfer;
+mod ttl_buffer;
+mod tuning;
 
 use crate::{
     client::utils::chat_from_hex,
     env::ENV,
-    error::{ErrorExt, ResultExt, ResultUnwrapExt},
-    message::TelegramMessage,
+    error::{
+        ErrorExt, HookVetoError, InfectedFileError, ResultExt, ResultUnwrapExt, SourceDeletedError,
+    },
+    hooks::run_post_upload_hooks,
+    message::{MessageBuilder, TelegramMessage},
+    settings::ShareLink,
     state::AppState,
+    verify::record_upload,
 };
 use anyhow::{Context, Result};
-use grammers_client::InputMessage;
+pub use group::TaskGroupKey;
+use handlers::TaskOutcome;
+pub use handlers::{fetch::MAX_BOT_SIZE, stickerpack::CONVERTED_MIME_TYPE};
+pub use local_files::LocalFiles;
 use path_slash::PathBufExt;
 use progress::Progress;
+pub use progress::ProgressEvent;
 pub use session::{BatchAborter, TaskAborter, TaskSession};
-use std::{path::Path, sync::Arc, time::Duration};
-pub use tasks::{CmdType, InsertTask};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+pub use tasks::{CmdType, InsertTask, Model as Task, TaskFilter, TaskStatus};
+pub use throughput::HostStats;
 use tokio::sync::Semaphore;
 use tokio_util::sync::CancellationToken;
+pub use transfer::create_upload_session;
+pub use ttl_buffer::buffer_tg_media;
 
 pub struct Tasker {
     state: AppState,
     progress: Arc<Progress>,
+    // paused when any account exceeds the bandwidth caps
+    is_paused: AtomicBool,
 }
 
 impl Tasker {
     pub fn new(state: AppState) -> Self {
         let progress = Arc::new(Progress::new(state.clone()));
 
-        Self { state, progress }
+        Self {
+            state,
+            progress,
+            is_paused: AtomicBool::new(false),
+        }
     }
 
     fn session(&self) -> &TaskSession {
@@ -45,16 +82,28 @@ impl Tasker {
     }
 
     pub async fn run(&self) {
+        // local files and tasks to import are kept too, for inspecting them
+        if ENV.get().unwrap().read_only {
+            tracing::warn!("tasker not started in read-only mode, queued tasks keep waiting");
+            return;
+        }
+
         tracing::info!("tasker started");
 
+        local_files::clear_local_files().await.trace();
+
+        export::import_pending_tasks(self.session()).await.trace();
+
         let progress_clone = self.progress.clone();
         tokio::spawn(async move {
             progress_clone.run().await;
         });
 
-        let handler_num = ENV.get().unwrap().task_handler_num;
-
-        let semaphore = Arc::new(Semaphore::new(handler_num as usize));
+        let concurrency = self.session().concurrency.clone();
+        let semaphore = concurrency.semaphore();
+        tokio::spawn(async move {
+            concurrency.run().await;
+        });
 
         loop {
             self.handle_tasks(semaphore.clone()).await.trace();
@@ -64,6 +113,10 @@ impl Tasker {
     }
 
     async fn handle_tasks(&self, semaphore: Arc<Semaphore>) -> Result<()> {
+        if self.check_paused().await? {
+            return Ok(());
+        }
+
         let mut aborters = self.state.task_session.task_aborters.lock().await;
         let task = self.session().fetch_task().await?;
 
@@ -71,23 +124,38 @@ impl Tasker {
             let chat = chat_from_hex(&task.chat_bot_hex)?;
 
             // in case that message is sent and deleted immediately
-            let Ok(message) = self
+            let message = match self
                 .state
                 .telegram_bot
                 .get_message(chat, task.message_id)
                 .await
-            else {
+            {
+                Ok(message) => Ok(message),
+                // self-destructing media is gone once viewed, but its buffer is still uploaded
+                Err(_) if task.buffer_path.is_some() => {
+                    self.state
+                        .telegram_bot
+                        .get_message(chat, task.message_indicator_id)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+
+            let Ok(message) = message else {
                 self.state
                     .task_session
                     .delete_task_from_message_id_if_exists(chat.id, task.message_id)
                     .await?;
 
+                ttl_buffer::remove_buffer(&task).await;
+
                 tracing::info!("task {} aborted", task.filename);
 
                 return Ok(());
             };
 
             let semaphore_clone = semaphore.clone();
+            let upload_sessions = self.session().upload_sessions.clone();
             let state_clone = self.state.clone();
             let progress_clone = self.progress.clone();
 
@@ -103,11 +171,33 @@ impl Tasker {
             drop(aborters);
 
             tokio::spawn(async move {
-                let _permit = semaphore_clone
-                    .acquire()
-                    .await
-                    .context("failed to acquire semaphore for task handler")
-                    .unwrap_or_trace();
+                // url tasks don't use telegram, so they only wait for an upload session
+                let _permit = match task.cmd_type {
+                    CmdType::File
+                    | CmdType::Link
+                    | CmdType::Copy
+                    | CmdType::Fetch
+                    | CmdType::StickerPack => Some(
+                        semaphore_clone
+                            .acquire()
+                            .await
+                            .context("failed to acquire semaphore for task handler")
+                            .unwrap_or_trace(),
+                    ),
+                    CmdType::Url => None,
+                };
+
+                let _upload_permit = if task.cmd_type.has_upload_session() {
+                    Some(
+                        upload_sessions
+                            .acquire()
+                            .await
+                            .context("failed to acquire semaphore for upload session")
+                            .unwrap_or_trace(),
+                    )
+                } else {
+                    None
+                };
 
                 if let Err(e) = handler_dispatch(
                     task,
@@ -125,6 +215,32 @@ impl Tasker {
 
         Ok(())
     }
+
+    // started tasks keep running, only new tasks wait until the usage drops below the caps
+    async fn check_paused(&self) -> Result<bool> {
+        let exceeded_cap = self.state.usage_session.get_exceeded_cap().await?;
+
+        let was_paused = self
+            .is_paused
+            .swap(exceeded_cap.is_some(), Ordering::Relaxed);
+
+        match exceeded_cap {
+            Some(reason) => {
+                if !was_paused {
+                    tracing::warn!("tasker paused: {}", reason);
+                }
+
+                Ok(true)
+            }
+            None => {
+                if was_paused {
+                    tracing::info!("tasker resumed");
+                }
+
+                Ok(false)
+            }
+        }
+    }
 }
 
 async fn handler_dispatch(
@@ -147,7 +263,13 @@ async fn handler_dispatch(
             CmdType::Url => {
                 tracing::info!("handle url task");
 
-                handlers::url::handler(task.clone(), progress).await
+                handlers::url::handler(
+                    task.clone(),
+                    progress,
+                    cancellation_token.clone(),
+                    state.clone(),
+                )
+                .await
             }
             CmdType::File | CmdType::Link => {
                 tracing::info!("handle file or link task");
@@ -160,20 +282,39 @@ async fn handler_dispatch(
                 )
                 .await
             }
-        }
-    };
+            CmdType::Copy => {
+                tracing::info!("handle copy task");
 
-    let mut aborted = false;
+                handlers::copy::handler(
+                    task.clone(),
+                    progress,
+                    cancellation_token.clone(),
+                    state.clone(),
+                )
+                .await
+            }
+            CmdType::Fetch => {
+                tracing::info!("handle fetch task");
 
-    let result = tokio::select! {
-        result = fut => result,
-        () = cancellation_token.cancelled() => {
-            aborted = true;
+                handlers::fetch::handler(task.clone(), progress, state.clone()).await
+            }
+            CmdType::StickerPack => {
+                tracing::info!("handle sticker pack task");
 
-            Ok(())
+                handlers::stickerpack::handler(task.clone(), progress, state.clone()).await
+            }
         }
     };
 
+    // none if aborted, also when the handler stopped with an error because of it
+    let result = tokio::select! {
+        result = fut => match result {
+            Err(_) if cancellation_token.is_cancelled() => None,
+            result => Some(result),
+        },
+        () = cancellation_token.cancelled() => None,
+    };
+
     let chat_id = message.chat().id();
 
     let mut task_aborters = state.task_session.task_aborters.lock().await;
@@ -187,16 +328,37 @@ async fn handler_dispatch(
     let batch_is_processing = batch_aborter.is_some_and(|batch_aborter| batch_aborter.processing);
     drop(batch_aborters);
 
-    if aborted {
+    let Some(result) = result else {
         return Ok(());
-    }
+    };
 
     match result {
-        Ok(()) => {
+        Ok(outcome) => {
             session
                 .set_task_status(task.id, tasks::TaskStatus::Completed)
                 .await?;
 
+            state.history_session.insert_completed(&task).await?;
+
+            if let TaskOutcome::Uploaded(path) = &outcome {
+                record_upload(&task, path, &state)
+                    .await
+                    .context("failed to record upload for verification")
+                    .trace();
+
+                run_post_upload_hooks(&task, path, &state);
+            }
+
+            // the uploaded file may be renamed, so the whole folder is outdated
+            if task.cmd_type.is_stored_in_onedrive() {
+                state
+                    .get_onedrive(task.tenant_id)
+                    .await?
+                    .invalidate_items(&task.root_path);
+            }
+
+            session.task_groups.finish(&task);
+
             if task_aborter_exists {
                 if task.auto_delete {
                     let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
@@ -221,31 +383,61 @@ async fn handler_dispatch(
                             .await?;
                     }
                 } else {
-                    handle_completed_task(task.clone(), state.clone()).await?;
+                    handle_completed_task(task.clone(), outcome, state.clone()).await?;
                 }
             }
+
+            session.delete_task(task.id).await?;
+
+            ttl_buffer::remove_buffer(&task).await;
         }
         Err(e) => {
+            let error = e.to_string();
+            let is_retryable = e.downcast_ref::<SourceDeletedError>().is_none()
+                && e.downcast_ref::<InfectedFileError>().is_none()
+                && e.downcast_ref::<HookVetoError>().is_none();
+
             e.send(message.clone()).await.unwrap_both().trace();
 
             session
                 .set_task_status(task.id, tasks::TaskStatus::Failed)
                 .await?;
 
-            handle_failed_task(task.clone(), state.clone()).await?;
+            state.history_session.insert_failed(&task, error).await?;
+
+            handle_failed_task(task.clone(), is_retryable, state.clone()).await?;
+
+            // retryable ones are kept for /retry until the related message is deleted
+            if !is_retryable {
+                session.task_groups.finish(&task);
+                session.delete_task(task.id).await?;
+
+                ttl_buffer::remove_buffer(&task).await;
+            }
         }
     }
 
-    session.delete_task(task.id).await?;
-
     Ok(())
 }
 
-async fn handle_completed_task(task: tasks::Model, state: AppState) -> Result<()> {
+async fn handle_completed_task(
+    task: tasks::Model,
+    outcome: TaskOutcome,
+    state: AppState,
+) -> Result<()> {
     let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
 
-    let file_path_raw = Path::new(&task.root_path).join(task.filename);
-    let file_path = file_path_raw.to_slash_lossy();
+    let file_path = match &outcome {
+        TaskOutcome::Uploaded(path) => path.clone(),
+        TaskOutcome::Quarantined => Path::new(&ENV.get().unwrap().quarantine_path)
+            .join(&task.filename)
+            .to_slash_lossy()
+            .to_string(),
+        _ => Path::new(&task.root_path)
+            .join(&task.filename)
+            .to_slash_lossy()
+            .to_string(),
+    };
 
     let telegram_bot = &state.telegram_bot;
 
@@ -253,21 +445,59 @@ async fn handle_completed_task(task: tasks::Model, state: AppState) -> Result<()
         .get_message(chat_bot, task.message_indicator_id)
         .await?;
 
-    let response = format!(
-        "{}\n\nDone.\nFile uploaded to {}\nSize {:.2}MB.",
-        message_indicator.text(),
-        file_path,
-        task.total_length as f64 / 1024.0 / 1024.0
-    );
+    let result = match (&outcome, &task.cmd_type) {
+        (TaskOutcome::Uploaded(_), _) => "Done.\nFile uploaded to",
+        (TaskOutcome::Duplicate, _) => "Skipped (duplicate).\nSame file already in",
+        (TaskOutcome::Quarantined, _) => "Quarantined (infected).\nFile uploaded to",
+        (TaskOutcome::Archived, _) => "Done.\nStickers uploaded to",
+        (TaskOutcome::Sent, CmdType::Fetch) => "Done.\nFetched",
+        (TaskOutcome::Sent, _) => "Done.\nCopied",
+    };
+
+    let mut builder = MessageBuilder::new()
+        .raw(&message_indicator.formatted_text())
+        .line()
+        .line()
+        .text(&format!(
+            "{} {}\nSize {:.2}MB.",
+            result,
+            file_path,
+            task.total_length as f64 / 1024.0 / 1024.0
+        ));
+
+    let share_link = state.settings_session.get_share_link(task.chat_id).await?;
+
+    // infected files aren't shared, neither are files only sent to telegram
+    if share_link != ShareLink::Off
+        && outcome != TaskOutcome::Quarantined
+        && outcome != TaskOutcome::Sent
+    {
+        // the file is uploaded anyway, so the task doesn't fail
+        match state
+            .get_onedrive(task.tenant_id)
+            .await?
+            .create_share_link(task.account.as_deref(), &file_path, share_link)
+            .await
+        {
+            Ok(url) => builder = builder.line().link("Share link", &url),
+            Err(e) => {
+                e.trace();
+
+                builder = builder.line().text("Failed to create share link.");
+            }
+        }
+    }
+
+    let response = builder.build();
     message_indicator
-        .edit(task.message_indicator_id, InputMessage::html(&response))
+        .edit(task.message_indicator_id, response.clone())
         .await
         .context(response)?;
 
     Ok(())
 }
 
-async fn handle_failed_task(task: tasks::Model, state: AppState) -> Result<()> {
+async fn handle_failed_task(task: tasks::Model, is_retryable: bool, state: AppState) -> Result<()> {
     let chat_bot = chat_from_hex(&task.chat_bot_hex)?;
 
     let telegram_bot = &state.telegram_bot;
@@ -276,9 +506,20 @@ async fn handle_failed_task(task: tasks::Model, state: AppState) -> Result<()> {
         .get_message(chat_bot, task.message_indicator_id)
         .await?;
 
-    let response = format!("{}\n\nFailed.", message_indicator.text());
+    let hint = if is_retryable {
+        format!("Use /retry #{} to retry.", task.code)
+    } else {
+        "It can't be retried.".to_string()
+    };
+
+    let response = MessageBuilder::new()
+        .raw(&message_indicator.formatted_text())
+        .line()
+        .line()
+        .text(&format!("Failed.\n{}", hint))
+        .build();
     message_indicator
-        .edit(task.message_id, InputMessage::html(&response))
+        .edit(task.message_id, response.clone())
         .await
         .context(response)?;
 
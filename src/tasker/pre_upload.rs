@@ -0,0 +1,127 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    local_files::LocalFiles,
+    tasks,
+    transfer::{
+        create_upload_session, download_tg_file, get_local_upload_session, get_tg_media,
+        multi_parts_uploader_from_file,
+    },
+    Progress,
+};
+use crate::{env::ENV, hooks::run_pre_upload_hook, state::AppState, utils::get_ext};
+use anyhow::{anyhow, Result};
+use std::{path::Path, sync::Arc};
+use tokio_util::sync::CancellationToken;
+
+pub fn should_run_pre_upload_hook() -> bool {
+    ENV.get().unwrap().pre_upload_hook.is_some()
+}
+
+// the whole file is downloaded for the hook, which may replace it with another file or veto it
+pub async fn hook_uploader_from_tg_file(
+    task: &tasks::Model,
+    buffer_path: Option<&Path>,
+    progress: Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: AppState,
+) -> Result<String> {
+    let command = ENV
+        .get()
+        .unwrap()
+        .pre_upload_hook
+        .as_deref()
+        .ok_or_else(|| anyhow!("pre_upload_hook not set"))?;
+
+    let ext = get_ext(&task.filename);
+    let files =
+        LocalFiles::new(task.chat_id, task.id, &ext, &ext, task.total_length as u64).await?;
+    let LocalFiles {
+        input_path,
+        output_path,
+        ..
+    } = &files;
+
+    // self-destructing media is already downloaded
+    let input_path = match buffer_path {
+        Some(buffer_path) => buffer_path,
+        None => {
+            let media = get_tg_media(task, &state).await?;
+            download_tg_file(
+                task,
+                &media,
+                input_path,
+                progress.clone(),
+                cancellation_token,
+                state.clone(),
+            )
+            .await?;
+
+            input_path
+        }
+    };
+
+    let path = run_pre_upload_hook(command, input_path, output_path, &task.filename)
+        .await?
+        .unwrap_or_else(|| input_path.to_path_buf());
+
+    let filename = get_hooked_filename(&task.filename, &path);
+
+    let upload_session = if filename == task.filename {
+        get_local_upload_session(task, &state).await?
+    } else {
+        // the upload session created when enqueueing is left to expire
+        create_upload_session(task, &filename, &state).await?
+    };
+
+    multi_parts_uploader_from_file(
+        task,
+        &filename,
+        &path,
+        &upload_session,
+        progress,
+        cancellation_token,
+        state,
+    )
+    .await
+}
+
+// the extension follows the file returned by the hook, like a.mkv transcoded into a.mp4,
+// local files are named with the lowercase extension of the task
+fn get_hooked_filename(filename: &str, path: &Path) -> String {
+    match path.extension() {
+        Some(ext) if ext.to_string_lossy().to_lowercase() != get_ext(filename) => {
+            Path::new(filename)
+                .with_extension(ext)
+                .to_string_lossy()
+                .to_string()
+        }
+        _ => filename.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_hooked_filename() {
+        assert_eq!(
+            get_hooked_filename("a.mkv", Path::new("/tmp/1/1-output.mp4")),
+            "a.mp4"
+        );
+        assert_eq!(
+            get_hooked_filename("A.MKV", Path::new("/tmp/1/1-input.mkv")),
+            "A.MKV"
+        );
+        assert_eq!(
+            get_hooked_filename("a.mkv", Path::new("/tmp/scanned")),
+            "a.mkv"
+        );
+    }
+}
@@ -5,15 +5,60 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::{TaskSession, session::ChatHex, tasks};
+use super::{
+    group::TaskGroupKey,
+    session::ChatHex,
+    tasks,
+    throughput::{Direction, RATE_WINDOW},
+    TaskSession,
+};
 use crate::{
-    client::utils::chat_from_hex,
+    client::{onedrive::THROTTLE, utils::chat_from_hex},
     error::{ErrorExt, ResultExt, ResultUnwrapExt},
+    handlers::utils::message::get_message_link,
+    message::{ChatEntity, MessageBuilder, MessagePriority},
     state::AppState,
+    utils::get_current_timestamp,
 };
-use anyhow::{Context, Result, anyhow};
+use anyhow::{anyhow, Context, Result};
 use grammers_client::InputMessage;
-use std::{collections::HashMap, time::Duration};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// streamed to the web server, untagged so that each event is a plain json object
+#[derive(Clone, Serialize)]
+#[serde(untagged)]
+pub enum ProgressEvent {
+    Task(TaskProgress),
+    Throughput(ThroughputProgress),
+}
+
+#[derive(Clone, Serialize)]
+pub struct TaskProgress {
+    pub id: i64,
+    // only sent to subscribers of the tenant
+    #[serde(skip)]
+    pub tenant_id: Option<i64>,
+    pub filename: String,
+    pub label: Option<String>,
+    pub current_length: i64,
+    pub total_length: i64,
+    pub percent: f64,
+    // bytes per second since the last event
+    pub speed: f64,
+}
+
+// sent once per event round even without running tasks, so that dashboards can chart idle time
+#[derive(Clone, Serialize)]
+pub struct ThroughputProgress {
+    pub timestamp: i64,
+    // bytes per second of all running tasks
+    pub download: f64,
+    pub upload: f64,
+}
 
 pub struct Progress {
     state: AppState,
@@ -37,6 +82,7 @@ impl Progress {
 
         let mut chat_progress_message_id = HashMap::new();
         let mut last_progress_response = String::new();
+        let mut last_lengths = HashMap::new();
 
         loop {
             self.handle_chat_tasks_progress(
@@ -46,6 +92,10 @@ impl Progress {
             .await
             .trace();
 
+            self.publish_progress_events(&mut last_lengths)
+                .await
+                .trace();
+
             tokio::time::sleep(Duration::from_secs(2)).await;
         }
     }
@@ -55,6 +105,8 @@ impl Progress {
         chat_progress_message_id: &mut HashMap<String, Option<i32>>,
         last_progress_response: &mut String,
     ) -> Result<()> {
+        self.session().prune_task_groups().await?;
+
         let chat_tasks = self.session().get_chats_current_tasks().await?;
 
         for (
@@ -96,6 +148,71 @@ impl Progress {
         Ok(())
     }
 
+    async fn publish_progress_events(
+        &self,
+        last_lengths: &mut HashMap<i64, (i64, Instant)>,
+    ) -> Result<()> {
+        let progress_sender = &self.session().progress_sender;
+
+        if progress_sender.receiver_count() == 0 {
+            last_lengths.clear();
+
+            return Ok(());
+        }
+
+        let current_tasks = self
+            .session()
+            .get_chats_current_tasks()
+            .await?
+            .into_values()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        // forget finished tasks
+        last_lengths.retain(|id, _| current_tasks.iter().any(|task| task.id == *id));
+
+        let now = Instant::now();
+
+        for task in current_tasks {
+            let speed = last_lengths
+                .get(&task.id)
+                .map_or(0.0, |(last_length, last_time)| {
+                    (task.current_length - last_length) as f64
+                        / now.duration_since(*last_time).as_secs_f64()
+                });
+
+            last_lengths.insert(task.id, (task.current_length, now));
+
+            let percent = if task.total_length > 0 {
+                task.current_length as f64 / task.total_length as f64 * 100.0
+            } else {
+                0.0
+            };
+
+            // it fails only if all subscribers are gone
+            let _ = progress_sender.send(ProgressEvent::Task(TaskProgress {
+                id: task.id,
+                tenant_id: task.tenant_id,
+                filename: task.filename,
+                label: task.label,
+                current_length: task.current_length,
+                total_length: task.total_length,
+                percent,
+                speed,
+            }));
+        }
+
+        let throughput = &self.session().throughput;
+
+        let _ = progress_sender.send(ProgressEvent::Throughput(ThroughputProgress {
+            timestamp: get_current_timestamp(),
+            download: throughput.get_rate(Direction::Download, RATE_WINDOW),
+            upload: throughput.get_rate(Direction::Upload, RATE_WINDOW),
+        }));
+
+        Ok(())
+    }
+
     async fn remove_chats_without_tasks(
         &self,
         chat_progress_message_id: &mut HashMap<String, Option<i32>>,
@@ -148,17 +265,64 @@ impl Progress {
 
         let chat = chat_from_hex(chat_bot_hex)?;
 
-        let mut response = "Progress:\n".to_string();
-
-        for task_progress in current_tasks {
-            response += &format!(
-                "\n<a href=\"https://t.me/c/{}/{}\">{}</a>: {:.2}/{:.2}MB",
-                chat.id,
-                task_progress.message_id,
-                task_progress.filename,
-                task_progress.current_length as f64 / 1024. / 1024.,
-                task_progress.total_length as f64 / 1024. / 1024.
-            );
+        let mut builder = MessageBuilder::new().text("Progress:").line();
+
+        // tasks of the same group are listed under its summary
+        let mut group_keys = Vec::new();
+        for task in &current_tasks {
+            let key = TaskGroupKey::from_task(task);
+
+            if !group_keys.contains(&key) {
+                group_keys.push(key);
+            }
+        }
+
+        for key in group_keys {
+            let group_tasks = current_tasks
+                .iter()
+                .filter(|task| TaskGroupKey::from_task(task) == key)
+                .collect::<Vec<_>>();
+
+            let group = group_tasks
+                .first()
+                .and_then(|task| self.session().task_groups.get(task.chat_id, key))
+                .filter(|group| group.items > 1);
+
+            let prefix = if let Some(group) = group {
+                let current_length = group.finished_length
+                    + group_tasks
+                        .iter()
+                        .map(|task| task.current_length as u64)
+                        .sum::<u64>();
+
+                builder = builder.line().text(&format!(
+                    "{} {}/{} finished, {:.2}/{:.2}MB",
+                    key.name(),
+                    group.finished_items,
+                    group.items,
+                    current_length as f64 / 1024. / 1024.,
+                    group.total_length as f64 / 1024. / 1024.
+                ));
+
+                "- "
+            } else {
+                ""
+            };
+
+            for task_progress in group_tasks {
+                builder = builder
+                    .line()
+                    .text(&format!("{}#{} ", prefix, task_progress.code))
+                    .link(
+                        &task_progress.filename,
+                        &get_message_link(&ChatEntity::from(chat.id), task_progress.message_id),
+                    )
+                    .text(&format!(
+                        ": {:.2}/{:.2}MB",
+                        task_progress.current_length as f64 / 1024. / 1024.,
+                        task_progress.total_length as f64 / 1024. / 1024.
+                    ));
+            }
         }
 
         let pending_tasks_number = self
@@ -167,9 +331,23 @@ impl Progress {
             .await?;
 
         if pending_tasks_number > 0 {
-            response += &format!("\n\n{} more tasks pending...", pending_tasks_number);
+            builder = builder
+                .line()
+                .line()
+                .text(&format!("{} more tasks pending...", pending_tasks_number));
+        }
+
+        // rounded up to 10 seconds, so that the message isn't edited on every update
+        if let Some(remaining) = THROTTLE.remaining() {
+            builder = builder.line().line().text(&format!(
+                "OneDrive is throttling uploads, resuming in {}s...",
+                (remaining.as_secs() + 1).div_ceil(10) * 10
+            ));
         }
 
+        let content = builder.build();
+        let response = content.to_string();
+
         let progress_message_id = chat_progress_message_id
             .get_mut(chat_bot_hex)
             .ok_or_else(|| anyhow!("chat_bot_hex not in chat_progress_message_id"))?;
@@ -188,10 +366,11 @@ impl Progress {
                 if latest_message.id() == *progress_message_id {
                     if *last_progress_response != response {
                         telegram_bot
-                            .edit_message(
+                            .edit_message_with_priority(
                                 chat,
                                 progress_message_id.to_owned(),
-                                InputMessage::html(response.as_str()),
+                                InputMessage::from(content.clone()),
+                                MessagePriority::Low,
                             )
                             .await
                             .context(response.clone())?;
@@ -202,7 +381,11 @@ impl Progress {
                         .await?;
 
                     let message = telegram_bot
-                        .send_message(chat, InputMessage::html(response.as_str()))
+                        .send_message_with_priority(
+                            chat,
+                            InputMessage::from(content.clone()),
+                            MessagePriority::Low,
+                        )
                         .await
                         .context(response.clone())?;
 
@@ -211,7 +394,11 @@ impl Progress {
             }
         } else {
             let message = telegram_bot
-                .send_message(chat, InputMessage::html(response.as_str()))
+                .send_message_with_priority(
+                    chat,
+                    InputMessage::from(content.clone()),
+                    MessagePriority::Low,
+                )
                 .await
                 .context(response.clone())?;
 
@@ -0,0 +1,94 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+const WIDTH_IN_BYTES: usize = 20;
+const WIDTH_IN_BITS: usize = WIDTH_IN_BYTES * 8;
+const SHIFT: usize = 11;
+
+// the hash onedrive reports for files in both personal and business drives,
+// each byte is xored into a 160 bit value at an offset growing by 11 bits, then the length is xored in
+#[derive(Default)]
+pub struct QuickXorHash {
+    data: [u8; WIDTH_IN_BYTES],
+    // bit offset of the next byte
+    shift: usize,
+    length: u64,
+}
+
+impl QuickXorHash {
+    // parts are fed in order
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            let index = self.shift / 8;
+            let offset = self.shift % 8;
+
+            self.data[index] ^= byte << offset;
+
+            // the bits over the byte go to the next one, wrapping around at the end
+            if offset > 0 {
+                self.data[(index + 1) % WIDTH_IN_BYTES] ^= byte >> (8 - offset);
+            }
+
+            self.shift = (self.shift + SHIFT) % WIDTH_IN_BITS;
+        }
+
+        self.length += bytes.len() as u64;
+    }
+
+    // base64, like quickXorHash in the hashes of drive items
+    pub fn finalize(&self) -> String {
+        let mut data = self.data;
+
+        for (byte, length_byte) in data[WIDTH_IN_BYTES - 8..]
+            .iter_mut()
+            .zip(self.length.to_le_bytes())
+        {
+            *byte ^= length_byte;
+        }
+
+        STANDARD.encode(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quick_xor_hash() {
+        assert_eq!(
+            QuickXorHash::default().finalize(),
+            "AAAAAAAAAAAAAAAAAAAAAAAAAAA="
+        );
+
+        let mut hash = QuickXorHash::default();
+        hash.update(&[0x01, 0xff]);
+
+        // 0x01 at bit 0, 0xff at bit 11, and the length 2 at byte 12
+        let mut expected = [0; WIDTH_IN_BYTES];
+        expected[0] = 0x01;
+        expected[1] = 0xf8;
+        expected[2] = 0x07;
+        expected[12] = 0x02;
+        assert_eq!(hash.finalize(), STANDARD.encode(expected));
+
+        // the same no matter how the data is split into parts
+        let data = (0..1000).map(|i| (i * 7 % 256) as u8).collect::<Vec<_>>();
+
+        let mut whole = QuickXorHash::default();
+        whole.update(&data);
+
+        let mut parts = QuickXorHash::default();
+        for part in data.chunks(333) {
+            parts.update(part);
+        }
+
+        assert_eq!(whole.finalize(), parts.finalize());
+    }
+}
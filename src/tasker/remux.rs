@@ -0,0 +1,218 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    local_files::LocalFiles,
+    tasks,
+    transfer::{
+        create_upload_session, download_tg_file, get_local_upload_session, get_tg_media,
+        multi_parts_uploader_from_file,
+    },
+    Progress,
+};
+use crate::{
+    env::ENV,
+    error::TaskAbortError,
+    features::Feature,
+    state::AppState,
+    utils::{get_ext, get_http_client},
+};
+use anyhow::{anyhow, Context, Result};
+use onedrive_api::UploadSession;
+use std::{path::Path, process::Stdio, sync::Arc};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+// videos to be remuxed into mp4 with faststart, returns the filename after remuxing
+pub fn get_remux_filename(filename: &str) -> Option<String> {
+    if !ENV.get().unwrap().faststart || !Feature::Ffmpeg.is_enabled() {
+        return None;
+    }
+
+    remux_filename(filename)
+}
+
+fn remux_filename(filename: &str) -> Option<String> {
+    match get_ext(filename).as_str() {
+        "mp4" | "m4v" | "mov" => Some(filename.to_string()),
+        // normalize the container so that it can be played in the onedrive web player
+        "mkv" => Some(
+            Path::new(filename)
+                .with_extension("mp4")
+                .to_string_lossy()
+                .to_string(),
+        ),
+        _ => None,
+    }
+}
+
+// the whole file is downloaded before remuxing, since the moov atom is moved to the front
+pub async fn remux_uploader_from_tg_file(
+    task: &tasks::Model,
+    remux_filename: &str,
+    progress: Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: AppState,
+) -> Result<String> {
+    let files = LocalFiles::new(
+        task.chat_id,
+        task.id,
+        &get_ext(&task.filename),
+        "mp4",
+        task.total_length as u64,
+    )
+    .await?;
+    let LocalFiles {
+        input_path,
+        output_path,
+        ..
+    } = &files;
+
+    let media = get_tg_media(task, &state).await?;
+    download_tg_file(
+        task,
+        &media,
+        input_path,
+        progress.clone(),
+        cancellation_token,
+        state.clone(),
+    )
+    .await?;
+
+    let result = tokio::select! {
+        result = remux(input_path, output_path) => result,
+        () = cancellation_token.cancelled() => return Err(TaskAbortError.into()),
+    };
+
+    let (path, filename, upload_session) = match result {
+        Ok(()) => (
+            output_path,
+            remux_filename,
+            get_remux_upload_session(task, remux_filename, &state).await?,
+        ),
+        // still upload the file as it is, such as when the codecs are not supported by mp4
+        Err(e) => {
+            tracing::warn!("failed to remux {}: {:?}", task.filename, e);
+
+            (
+                input_path,
+                task.filename.as_str(),
+                get_local_upload_session(task, &state).await?,
+            )
+        }
+    };
+
+    multi_parts_uploader_from_file(
+        task,
+        filename,
+        path,
+        &upload_session,
+        progress,
+        cancellation_token,
+        state,
+    )
+    .await
+}
+
+// the remuxed file is the same each time, so an interrupted upload is resumed in the stored session
+async fn get_remux_upload_session(
+    task: &tasks::Model,
+    remux_filename: &str,
+    state: &AppState,
+) -> Result<UploadSession> {
+    if remux_filename == task.filename && !task.upload_url.is_empty() {
+        let upload_session = UploadSession::from_upload_url(&task.upload_url);
+
+        // expired after a long interruption
+        match upload_session.get_meta(&get_http_client()?).await {
+            Ok(_) => return Ok(upload_session),
+            Err(e) => tracing::warn!("failed to get upload session status: {:?}", e),
+        }
+    }
+
+    // the upload session created when enqueueing is left to expire if the file is renamed
+    let upload_session = create_upload_session(task, remux_filename, state).await?;
+
+    state
+        .task_session
+        .restart_upload(task.id, upload_session.upload_url())
+        .await?;
+
+    // so that the stored session is reused when the task is resumed
+    if remux_filename != task.filename {
+        state
+            .task_session
+            .update_filename(task.id, remux_filename)
+            .await?;
+    }
+
+    Ok(upload_session)
+}
+
+async fn remux(input_path: &Path, output_path: &Path) -> Result<()> {
+    // subtitles are dropped since most of those in mkv can't be stored in mp4
+    run_ffmpeg(
+        input_path,
+        &[
+            "-map",
+            "0:v",
+            "-map",
+            "0:a?",
+            "-c",
+            "copy",
+            "-movflags",
+            "+faststart",
+            "-f",
+            "mp4",
+        ],
+        output_path,
+    )
+    .await
+}
+
+// the output options are put between the input and output paths
+pub async fn run_ffmpeg(
+    input_path: &Path,
+    output_options: &[&str],
+    output_path: &Path,
+) -> Result<()> {
+    let ffmpeg_path = &ENV.get().unwrap().ffmpeg_path;
+
+    let output = Command::new(ffmpeg_path)
+        .args(["-y", "-v", "error", "-i"])
+        .arg(input_path)
+        .args(output_options)
+        .arg(output_path)
+        .stdin(Stdio::null())
+        // killed when the task is aborted
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context(format!("failed to run {}", ffmpeg_path))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffmpeg exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remux_filename() {
+        assert_eq!(remux_filename("a.MP4"), Some("a.MP4".to_string()));
+        assert_eq!(remux_filename("a.b.mkv"), Some("a.b.mp4".to_string()));
+        assert_eq!(remux_filename("a.avi"), None);
+    }
+}
@@ -5,43 +5,185 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::tasks::{self, InsertTask, TaskStatus};
+use super::{
+    concurrency::Concurrency,
+    group::{get_unique_filename, TaskGroupKey, TaskGroups},
+    progress::ProgressEvent,
+    tasks::{self, InsertTask, TaskFilter, TaskStatus},
+    throughput::Throughput,
+    ttl_buffer,
+};
+use crate::{env::ENV, url_auth::seal_password};
 use anyhow::{Context, Ok, Result};
+use chrono::Utc;
+use rand::Rng;
 use sea_orm::{
-    sea_query::Expr, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
-    EntityName, EntityTrait, PaginatorTrait, QueryFilter, Schema, Set,
+    sea_query::{Expr, Index, Query, Table},
+    ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityName,
+    EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Schema, Set,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+};
+use tokio::{
+    fs,
+    sync::{broadcast, Mutex, Semaphore},
 };
-use std::{collections::HashMap, path::Path, sync::Arc};
-use tokio::{fs, sync::Mutex};
 use tokio_util::sync::CancellationToken;
 
 // (chat id, message indicator id) -> aborter
 pub type TaskAborters = Arc<Mutex<HashMap<(i64, i32), TaskAborter>>>;
 pub type BatchAborters = Arc<Mutex<HashMap<(i64, i32), BatchAborter>>>;
 
+// events are dropped if no one subscribes
+const PROGRESS_CHANNEL_CAPACITY: usize = 64;
+
+// sqlite errors of a session that can't be used as it is, other errors like a locked or unreadable file
+// are returned instead, so that the tasks aren't lost by a transient failure
+const RECREATABLE_ERRORS: [&str; 4] = [
+    // left by an older version whose tasks table doesn't match
+    "no such column",
+    "has no column named",
+    "file is not a database",
+    "database disk image is malformed",
+];
+
+const GROUP_INDEX: &str = "idx-tasks-group";
+// groups looked up in a query
+const GROUP_QUERY_SIZE: usize = 100;
+
 pub struct TaskSession {
     connection: DatabaseConnection,
     pub task_aborters: TaskAborters,
     pub batch_aborters: BatchAborters,
+    pub progress_sender: broadcast::Sender<ProgressEvent>,
+    pub concurrency: Arc<Concurrency>,
+    // one permit per task, each task uploads in its own session
+    pub upload_sessions: Arc<Semaphore>,
+    pub task_groups: TaskGroups,
+    pub throughput: Arc<Throughput>,
 }
 
 impl TaskSession {
+    // tasks left by the last run are kept, so that their transfers resume with the saved upload sessions
     pub async fn new(session_path: &str) -> Result<Self> {
-        if Path::new(session_path).exists() {
-            fs::remove_file(session_path)
-                .await
-                .context("failed to remove old task session")?;
-        }
-
-        let connection = Self::connect_db(session_path).await?;
+        let connection = match Self::connect_db(session_path).await {
+            Err(e) if is_recreatable(&e) => {
+                // kept to recover the tasks by hand
+                let backup_path = format!(
+                    "{}.{}.bak",
+                    session_path,
+                    Utc::now().format("%Y%m%d-%H%M%S")
+                );
+
+                tracing::warn!(
+                    "failed to open old task session, moved to {}: {:?}",
+                    backup_path,
+                    e
+                );
+
+                fs::rename(session_path, &backup_path)
+                    .await
+                    .context("failed to back up old task session")?;
+
+                Self::connect_db(session_path).await?
+            }
+            result => result?,
+        };
         let task_aborters = Arc::new(Mutex::new(HashMap::new()));
         let batch_aborters = Arc::new(Mutex::new(HashMap::new()));
+        let (progress_sender, _) = broadcast::channel(PROGRESS_CHANNEL_CAPACITY);
+        let concurrency = Arc::new(Concurrency::new(
+            ENV.get().unwrap().task_handler_num as usize,
+        ));
+        let upload_sessions = Arc::new(Semaphore::new(ENV.get().unwrap().upload_sessions.max(1)));
 
-        Ok(Self {
+        let session = Self {
             connection,
             task_aborters,
             batch_aborters,
-        })
+            progress_sender,
+            concurrency,
+            upload_sessions,
+            task_groups: TaskGroups::default(),
+            throughput: Arc::new(Throughput::default()),
+        };
+
+        session.restore_tasks().await?;
+
+        Ok(session)
+    }
+
+    // tasks interrupted by the last stop wait to be fetched again, and failed ones are kept for /retry
+    async fn restore_tasks(&self) -> Result<()> {
+        // finished but not deleted before the stop
+        tasks::Entity::delete_many()
+            .filter(tasks::Column::Status.eq(TaskStatus::Completed))
+            .exec(&self.connection)
+            .await
+            .context("failed to delete completed tasks")?;
+
+        let restored = tasks::Entity::update_many()
+            .filter(tasks::Column::Status.is_in([TaskStatus::Fetched, TaskStatus::Started]))
+            .col_expr(tasks::Column::Status, Expr::value(TaskStatus::Waiting))
+            .exec(&self.connection)
+            .await
+            .context("failed to restore interrupted tasks")?
+            .rows_affected;
+
+        let tasks = tasks::Entity::find()
+            .filter(tasks::Column::Status.ne(TaskStatus::Failed))
+            .all(&self.connection)
+            .await
+            .context("failed to get restored tasks")?;
+
+        // failed tasks keep their buffers for /retry
+        let buffer_paths = tasks::Entity::find()
+            .filter(tasks::Column::BufferPath.is_not_null())
+            .all(&self.connection)
+            .await
+            .context("failed to get buffered tasks")?
+            .into_iter()
+            .filter_map(|task| task.buffer_path)
+            .collect();
+
+        ttl_buffer::clear_unused_buffers(&buffer_paths).await?;
+
+        for task in &tasks {
+            self.task_groups.add(
+                task.chat_id,
+                TaskGroupKey::from_task(task),
+                task.total_length as u64,
+            );
+        }
+
+        if !tasks.is_empty() {
+            tracing::info!(
+                "restored {} tasks, {} of them interrupted",
+                tasks.len(),
+                restored
+            );
+        }
+
+        Ok(())
+    }
+
+    // tasks left by the last run, read while the bot is stopped
+    pub async fn read_tasks(session_path: &str) -> Result<Vec<tasks::Model>> {
+        if !Path::new(session_path).exists() {
+            return Ok(Vec::new());
+        }
+
+        let connection = Self::connect_db(session_path).await?;
+
+        tasks::Entity::find()
+            .filter(tasks::Column::Status.ne(TaskStatus::Failed))
+            .order_by_asc(tasks::Column::Id)
+            .all(&connection)
+            .await
+            .context("failed to read tasks")
     }
 
     async fn connect_db(path: &str) -> Result<DatabaseConnection> {
@@ -50,6 +192,14 @@ impl TaskSession {
             .context("failed to connect to task session")?;
 
         Self::create_table_if_not_exists(&connection).await?;
+        Self::create_group_index_if_not_exists(&connection).await?;
+        Self::add_buffer_path_column_if_not_exists(&connection).await?;
+
+        // all columns are selected, so that a table of an older version fails here
+        tasks::Entity::find()
+            .one(&connection)
+            .await
+            .context("failed to check tasks table")?;
 
         Ok(connection)
     }
@@ -73,6 +223,61 @@ impl TaskSession {
         Ok(())
     }
 
+    // also for sessions created before the index
+    async fn create_group_index_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        let backend = connection.get_database_backend();
+
+        let index_create_statement = Index::create()
+            .if_not_exists()
+            .name(GROUP_INDEX)
+            .table(tasks::Entity)
+            .col(tasks::Column::ChatId)
+            .col(tasks::Column::GroupId)
+            .col(tasks::Column::MessageId)
+            .to_owned();
+
+        connection
+            .execute(backend.build(&index_create_statement))
+            .await
+            .context(format!("failed to create index {}", GROUP_INDEX))?;
+
+        Ok(())
+    }
+
+    // added later, as null in tasks of older sessions
+    async fn add_buffer_path_column_if_not_exists(connection: &DatabaseConnection) -> Result<()> {
+        let backend = connection.get_database_backend();
+
+        let select_statement = Query::select()
+            .column(tasks::Column::BufferPath)
+            .from(tasks::Entity)
+            .limit(1)
+            .to_owned();
+
+        if connection
+            .query_one(backend.build(&select_statement))
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        let table_alter_statement = Table::alter()
+            .table(tasks::Entity)
+            .add_column(
+                &mut Schema::new(backend)
+                    .get_column_def::<tasks::Entity>(tasks::Column::BufferPath),
+            )
+            .to_owned();
+
+        connection
+            .execute(backend.build(&table_alter_statement))
+            .await
+            .context("failed to add column buffer_path to table tasks")?;
+
+        Ok(())
+    }
+
     async fn is_table_exists(connection: &DatabaseConnection) -> bool {
         let result = tasks::Entity::find().all(connection).await;
 
@@ -82,6 +287,8 @@ impl TaskSession {
     pub async fn fetch_task(&self) -> Result<Option<tasks::Model>> {
         let task = tasks::Entity::find()
             .filter(tasks::Column::Status.eq(TaskStatus::Waiting))
+            .order_by_desc(tasks::Column::Priority)
+            .order_by_asc(tasks::Column::Id)
             .one(&self.connection)
             .await
             .context("failed to get a task")?;
@@ -94,16 +301,89 @@ impl TaskSession {
         Ok(task)
     }
 
+    // 4 hex digits are enough since finished tasks are deleted
+    pub async fn generate_task_code(&self) -> Result<String> {
+        loop {
+            let code = format!("{:04X}", rand::thread_rng().gen::<u16>());
+
+            let exists = tasks::Entity::find()
+                .filter(tasks::Column::Code.eq(&code))
+                .count(&self.connection)
+                .await
+                .context("failed to check task code")?
+                > 0;
+
+            if !exists {
+                return Ok(code);
+            }
+        }
+    }
+
+    pub async fn get_task_from_code(
+        &self,
+        chat_id: i64,
+        tenant_id: Option<i64>,
+        code: &str,
+    ) -> Result<Option<tasks::Model>> {
+        let mut condition = Condition::all()
+            .add(tasks::Column::ChatId.eq(chat_id))
+            .add(tasks::Column::Code.eq(code));
+
+        if let Some(tenant_id) = tenant_id {
+            condition = condition.add(tasks::Column::TenantId.eq(tenant_id));
+        }
+
+        tasks::Entity::find()
+            .filter(condition)
+            .one(&self.connection)
+            .await
+            .context("failed to get task from code")
+    }
+
+    // items of a batch or album named alike, like by timestamps, are told apart before uploading
+    pub async fn get_unique_filename(
+        &self,
+        chat_id: i64,
+        group_key: TaskGroupKey,
+        filename: &str,
+    ) -> Result<String> {
+        let group_condition = match group_key {
+            TaskGroupKey::Album(group_id) => {
+                Condition::all().add(tasks::Column::GroupId.eq(group_id))
+            }
+            TaskGroupKey::Message(message_id) => Condition::all()
+                .add(tasks::Column::GroupId.is_null())
+                .add(tasks::Column::MessageId.eq(message_id)),
+        };
+
+        let taken = tasks::Entity::find()
+            .filter(tasks::Column::ChatId.eq(chat_id))
+            .filter(group_condition)
+            .all(&self.connection)
+            .await
+            .context("failed to get filenames in group")?
+            .into_iter()
+            .map(|task| task.filename)
+            .collect::<Vec<_>>();
+
+        Ok(get_unique_filename(filename, &taken))
+    }
+
     pub async fn insert_task(
         &self,
         InsertTask {
+            code,
             cmd_type,
             filename,
+            original_filename,
             root_path,
             url,
+            password,
             upload_url,
             current_length,
             total_length,
+            mime_type,
+            photo_type,
             chat_id,
             chat_bot_hex,
             chat_user_hex,
@@ -111,18 +391,30 @@ impl TaskSession {
             message_id,
             message_indicator_id,
             message_origin_id,
+            target_chat_hex,
+            group_id,
             auto_delete,
+            label,
+            sender,
+            tenant_id,
+            account,
+            buffer_path,
         }: InsertTask,
     ) -> Result<i64> {
         let insert_item = tasks::ActiveModel {
             id: ActiveValue::default(),
+            code: Set(code),
             cmd_type: Set(cmd_type),
             filename: Set(filename.to_string()),
+            original_filename: Set(original_filename),
             root_path: Set(root_path.to_string()),
             url: Set(url),
+            password: Set(password.as_deref().map(seal_password).transpose()?),
             upload_url: Set(upload_url.to_string()),
             current_length: Set(current_length as i64),
             total_length: Set(total_length as i64),
+            mime_type: Set(mime_type),
+            photo_type: Set(photo_type),
             chat_id: Set(chat_id),
             chat_bot_hex: Set(chat_bot_hex.to_string()),
             chat_user_hex: Set(chat_user_hex.to_string()),
@@ -130,8 +422,16 @@ impl TaskSession {
             message_id: Set(message_id),
             message_indicator_id: Set(message_indicator_id),
             message_origin_id: Set(message_origin_id),
+            target_chat_hex: Set(target_chat_hex),
+            group_id: Set(group_id),
             status: Set(TaskStatus::Waiting),
+            priority: Set(0),
             auto_delete: Set(auto_delete),
+            label: Set(label),
+            sender: Set(sender),
+            tenant_id: Set(tenant_id),
+            account: Set(account),
+            buffer_path: Set(buffer_path),
         };
 
         let id = tasks::Entity::insert(insert_item)
@@ -140,9 +440,28 @@ impl TaskSession {
             .context("failed to insert url task")?
             .last_insert_id;
 
+        self.task_groups.add(
+            chat_id,
+            TaskGroupKey::new(group_id, message_id),
+            total_length,
+        );
+
         Ok(id)
     }
 
+    // codes are generated again in case they are taken on this instance
+    pub async fn import_tasks(&self, tasks: Vec<InsertTask>) -> Result<usize> {
+        let count = tasks.len();
+
+        for mut task in tasks {
+            task.code = self.generate_task_code().await?;
+
+            self.insert_task(task).await?;
+        }
+
+        Ok(count)
+    }
+
     pub async fn set_task_status(&self, id: i64, status: TaskStatus) -> Result<()> {
         tasks::Entity::update_many()
             .filter(tasks::Column::Id.eq(id))
@@ -190,6 +509,80 @@ impl TaskSession {
         Ok(chats)
     }
 
+    // called every progress round, so only the groups in memory are looked up with the group index
+    pub async fn prune_task_groups(&self) -> Result<()> {
+        let keys = self.task_groups.keys();
+
+        let mut existing = HashSet::new();
+
+        // each group is a term of the condition, which sqlite limits in depth
+        for keys in keys.chunks(GROUP_QUERY_SIZE) {
+            let condition = keys
+                .iter()
+                .fold(Condition::any(), |condition, (chat_id, key)| {
+                    let group_condition = Condition::all().add(tasks::Column::ChatId.eq(*chat_id));
+
+                    condition.add(match key {
+                        TaskGroupKey::Album(group_id) => {
+                            group_condition.add(tasks::Column::GroupId.eq(*group_id))
+                        }
+                        TaskGroupKey::Message(message_id) => group_condition
+                            .add(tasks::Column::GroupId.is_null())
+                            .add(tasks::Column::MessageId.eq(*message_id)),
+                    })
+                });
+
+            let groups = tasks::Entity::find()
+                .select_only()
+                .columns([
+                    tasks::Column::ChatId,
+                    tasks::Column::GroupId,
+                    tasks::Column::MessageId,
+                ])
+                .filter(condition)
+                .distinct()
+                .into_tuple::<(i64, Option<i64>, i32)>()
+                .all(&self.connection)
+                .await
+                .context("failed to get tasks for groups")?;
+
+            existing.extend(groups.into_iter().map(|(chat_id, group_id, message_id)| {
+                (chat_id, TaskGroupKey::new(group_id, message_id))
+            }));
+        }
+
+        self.task_groups.retain(&existing);
+
+        Ok(())
+    }
+
+    pub async fn get_filtered_tasks(&self, filter: &TaskFilter) -> Result<Vec<tasks::Model>> {
+        let mut condition = Condition::all().add(tasks::Column::Status.ne(TaskStatus::Failed));
+
+        if let Some(label) = &filter.label {
+            condition = condition.add(tasks::Column::Label.eq(label));
+        }
+
+        if let Some(chat_id) = filter.chat_id {
+            condition = condition.add(tasks::Column::ChatId.eq(chat_id));
+        }
+
+        if let Some(sender) = &filter.sender {
+            condition = condition.add(tasks::Column::Sender.eq(sender));
+        }
+
+        if let Some(tenant_id) = filter.tenant_id {
+            condition = condition.add(tasks::Column::TenantId.eq(tenant_id));
+        }
+
+        tasks::Entity::find()
+            .filter(condition)
+            .order_by_asc(tasks::Column::Id)
+            .all(&self.connection)
+            .await
+            .context("failed to get filtered tasks")
+    }
+
     pub async fn get_chat_pending_tasks_number(&self, chat_bot_hex: &str) -> Result<u64> {
         tasks::Entity::find()
             .filter(
@@ -206,6 +599,53 @@ impl TaskSession {
             .context("failed to get pending tasks number")
     }
 
+    // bytes still to be uploaded by unfinished tasks, whichever account they are uploaded to
+    pub async fn get_reserved_length(&self, tenant_id: Option<i64>) -> Result<u64> {
+        let mut condition = Condition::all().add(tasks::Column::Status.is_in([
+            TaskStatus::Waiting,
+            TaskStatus::Fetched,
+            TaskStatus::Started,
+        ]));
+
+        if let Some(tenant_id) = tenant_id {
+            condition = condition.add(tasks::Column::TenantId.eq(tenant_id));
+        }
+
+        let tasks = tasks::Entity::find()
+            .filter(condition)
+            .all(&self.connection)
+            .await
+            .context("failed to get unfinished tasks")?;
+
+        let reserved_length = tasks
+            .iter()
+            .filter(|task| task.cmd_type.is_stored_in_onedrive())
+            .map(|task| (task.total_length - task.current_length).max(0))
+            .sum::<i64>();
+
+        Ok(reserved_length as u64)
+    }
+
+    // tasks already fetched by the tasker are not dropped
+    pub async fn drop_oldest_waiting_task(
+        &self,
+        chat_bot_hex: &str,
+    ) -> Result<Option<tasks::Model>> {
+        let task = tasks::Entity::find()
+            .filter(tasks::Column::ChatBotHex.eq(chat_bot_hex))
+            .filter(tasks::Column::Status.eq(TaskStatus::Waiting))
+            .order_by_asc(tasks::Column::Id)
+            .one(&self.connection)
+            .await
+            .context("failed to get oldest waiting task")?;
+
+        if let Some(task) = &task {
+            self.delete_task(task.id).await?;
+        }
+
+        Ok(task)
+    }
+
     pub async fn does_chat_has_started_tasks(&self, chat_bot_hex: &str) -> Result<bool> {
         let has_started_tasks = tasks::Entity::find()
             .filter(
@@ -232,6 +672,59 @@ impl TaskSession {
         Ok(())
     }
 
+    // put the task in front of all waiting tasks
+    pub async fn prioritize_task(&self, id: i64) -> Result<()> {
+        let max_priority = tasks::Entity::find()
+            .order_by_desc(tasks::Column::Priority)
+            .one(&self.connection)
+            .await
+            .context("failed to get max priority")?
+            .map_or(0, |task| task.priority);
+
+        tasks::Entity::update_many()
+            .filter(tasks::Column::Id.eq(id))
+            .col_expr(tasks::Column::Priority, Expr::value(max_priority + 1))
+            .exec(&self.connection)
+            .await
+            .context("failed to prioritize task")?;
+
+        Ok(())
+    }
+
+    // the failed upload session is dropped, the task restarts from the beginning
+    // tasks without an upload session keep their progress, like a partly archived sticker pack
+    pub async fn requeue_task(&self, id: i64, upload_url: Option<&str>) -> Result<()> {
+        let mut update = tasks::Entity::update_many()
+            .filter(tasks::Column::Id.eq(id))
+            .col_expr(tasks::Column::Status, Expr::value(TaskStatus::Waiting));
+
+        if let Some(upload_url) = upload_url {
+            update = update
+                .col_expr(tasks::Column::UploadUrl, Expr::value(upload_url))
+                .col_expr(tasks::Column::CurrentLength, Expr::value(0_i64));
+        }
+
+        update
+            .exec(&self.connection)
+            .await
+            .context("failed to requeue task")?;
+
+        Ok(())
+    }
+
+    // the started task uploads again from the beginning, so that it resumes from there after a restart
+    pub async fn restart_upload(&self, id: i64, upload_url: &str) -> Result<()> {
+        tasks::Entity::update_many()
+            .filter(tasks::Column::Id.eq(id))
+            .col_expr(tasks::Column::UploadUrl, Expr::value(upload_url))
+            .col_expr(tasks::Column::CurrentLength, Expr::value(0_i64))
+            .exec(&self.connection)
+            .await
+            .context("failed to restart upload")?;
+
+        Ok(())
+    }
+
     pub async fn delete_task(&self, id: i64) -> Result<()> {
         tasks::Entity::delete_by_id(id)
             .exec(&self.connection)
@@ -259,6 +752,36 @@ impl TaskSession {
         Ok(())
     }
 
+    // when the bot is removed from the chat, returns the removed tasks
+    pub async fn clear_chat(&self, chat_id: i64) -> Result<Vec<tasks::Model>> {
+        let mut aborters = self.task_aborters.lock().await;
+
+        aborters.retain(|(aborter_chat_id, _), aborter| {
+            if *aborter_chat_id == chat_id {
+                aborter.abort();
+
+                return false;
+            }
+
+            true
+        });
+        drop(aborters);
+
+        let removed_tasks = tasks::Entity::find()
+            .filter(tasks::Column::ChatId.eq(chat_id))
+            .all(&self.connection)
+            .await
+            .context("failed to get tasks of chat")?;
+
+        tasks::Entity::delete_many()
+            .filter(tasks::Column::ChatId.eq(chat_id))
+            .exec(&self.connection)
+            .await
+            .context("failed to delete tasks of chat")?;
+
+        Ok(removed_tasks)
+    }
+
     pub async fn delete_task_from_message_indicator_id_if_exists(
         &self,
         chat_id: i64,
@@ -385,3 +908,13 @@ impl BatchAborter {
         self.token.cancel();
     }
 }
+
+fn is_recreatable(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        let cause = cause.to_string();
+
+        RECREATABLE_ERRORS
+            .iter()
+            .any(|recreatable_error| cause.contains(recreatable_error))
+    })
+}
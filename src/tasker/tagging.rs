@@ -0,0 +1,394 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{
+    local_files::LocalFiles,
+    tasks,
+    transfer::{
+        download_tg_file, get_local_upload_session, get_tg_message, multi_parts_uploader_from_file,
+    },
+    Progress,
+};
+use crate::{features::Feature, state::AppState, utils::get_ext};
+use anyhow::{anyhow, Context, Result};
+use grammers_client::types::Media;
+use std::{io::SeekFrom, path::Path, sync::Arc};
+use tokio::{
+    fs,
+    io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::sync::CancellationToken;
+
+const ID3_HEADER_LEN: usize = 10;
+const ID3_FRAME_HEADER_LEN: usize = 10;
+
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct AudioTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+impl AudioTags {
+    // caption lines like title: $title, artist: $artist and album: $album
+    pub fn from_caption(caption: &str) -> Self {
+        let mut tags = Self::default();
+
+        for line in caption.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            match key.trim().to_lowercase().as_str() {
+                "title" => tags.title = Some(value.to_string()),
+                "artist" => tags.artist = Some(value.to_string()),
+                "album" => tags.album = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        tags
+    }
+
+    fn frames(&self) -> [(&[u8; 4], Option<&String>); 3] {
+        [
+            (b"TIT2", self.title.as_ref()),
+            (b"TPE1", self.artist.as_ref()),
+            (b"TALB", self.album.as_ref()),
+        ]
+    }
+}
+
+pub fn should_tag(task: &tasks::Model) -> bool {
+    Feature::AudioTags.is_active()
+        && (task.mime_type.as_deref() == Some("audio/mpeg") || get_ext(&task.filename) == "mp3")
+}
+
+// tags written by the uploader are kept, missing ones are filled from the telegram attributes,
+// and those in the caption override both
+pub async fn tagging_uploader_from_tg_file(
+    task: &tasks::Model,
+    progress: Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: AppState,
+) -> Result<String> {
+    let message = get_tg_message(task, &state).await?;
+
+    let media = message
+        .media()
+        .ok_or_else(|| anyhow!("message does not contain any media"))?;
+
+    let attribute_tags = match &media {
+        Media::Document(document) => AudioTags {
+            title: document.audio_title(),
+            artist: document.performer(),
+            album: None,
+        },
+        _ => AudioTags::default(),
+    };
+
+    // the caption of the origin message for links isn't written by the sender
+    let caption_tags = match task.cmd_type {
+        tasks::CmdType::File => AudioTags::from_caption(&message.text()),
+        _ => AudioTags::default(),
+    };
+
+    let files = LocalFiles::new(
+        task.chat_id,
+        task.id,
+        "mp3",
+        "mp3",
+        task.total_length as u64,
+    )
+    .await?;
+    let LocalFiles {
+        input_path,
+        output_path,
+        ..
+    } = &files;
+
+    download_tg_file(
+        task,
+        &media,
+        input_path,
+        progress.clone(),
+        cancellation_token,
+        state.clone(),
+    )
+    .await?;
+
+    let path = match write_tags(input_path, output_path, &attribute_tags, &caption_tags).await {
+        Ok(true) => output_path,
+        Ok(false) => input_path,
+        // still upload the file as it is, such as when the tag is in an unsupported version
+        Err(e) => {
+            tracing::warn!("failed to write tags for {}: {:?}", task.filename, e);
+
+            input_path
+        }
+    };
+
+    let upload_session = get_local_upload_session(task, &state).await?;
+
+    multi_parts_uploader_from_file(
+        task,
+        &task.filename,
+        path,
+        &upload_session,
+        progress,
+        cancellation_token,
+        state,
+    )
+    .await
+}
+
+// returns false if nothing needs to be changed
+async fn write_tags(
+    input_path: &Path,
+    output_path: &Path,
+    attribute_tags: &AudioTags,
+    caption_tags: &AudioTags,
+) -> Result<bool> {
+    let mut input = fs::File::open(input_path)
+        .await
+        .context("failed to open audio file")?;
+
+    let mut header = vec![0; ID3_HEADER_LEN];
+    let tag = match input.read_exact(&mut header).await {
+        Ok(_) if header.starts_with(b"ID3") => {
+            let mut tag = header;
+            tag.resize(ID3_HEADER_LEN + syncsafe_to_u32(&tag[6..10]) as usize, 0);
+
+            input
+                .read_exact(&mut tag[ID3_HEADER_LEN..])
+                .await
+                .context("failed to read id3 tag")?;
+
+            Some(tag)
+        }
+        _ => None,
+    };
+
+    let Some(new_tag) = build_tag(tag.as_deref(), attribute_tags, caption_tags)? else {
+        return Ok(false);
+    };
+
+    let mut output = fs::File::create(output_path)
+        .await
+        .context("failed to create tagged audio file")?;
+
+    output
+        .write_all(&new_tag)
+        .await
+        .context("failed to write id3 tag")?;
+
+    input
+        .seek(SeekFrom::Start(tag.map_or(0, |tag| tag.len() as u64)))
+        .await
+        .context("failed to seek audio file")?;
+
+    io::copy(&mut input, &mut output)
+        .await
+        .context("failed to copy audio data")?;
+
+    output
+        .flush()
+        .await
+        .context("failed to flush tagged audio file")?;
+
+    Ok(true)
+}
+
+// returns the new id3v2 tag, or none if nothing changes
+fn build_tag(
+    tag: Option<&[u8]>,
+    attribute_tags: &AudioTags,
+    caption_tags: &AudioTags,
+) -> Result<Option<Vec<u8>>> {
+    let (version, mut frames) = match tag {
+        Some(tag) => parse_tag(tag)?,
+        None => (3, Vec::new()),
+    };
+
+    let mut is_changed = false;
+
+    for ((id, attribute_value), (_, caption_value)) in attribute_tags
+        .frames()
+        .into_iter()
+        .zip(caption_tags.frames())
+    {
+        let existing_index = frames.iter().position(|(frame_id, _)| frame_id == id);
+
+        let value = match (caption_value, existing_index) {
+            (Some(value), _) => value,
+            (None, None) => match attribute_value {
+                Some(value) => value,
+                None => continue,
+            },
+            (None, Some(_)) => continue,
+        };
+
+        let frame = build_text_frame(version, id, value);
+
+        match existing_index {
+            Some(index) if frames[index].1 == frame => continue,
+            Some(index) => frames[index].1 = frame,
+            None => frames.push((*id, frame)),
+        }
+
+        is_changed = true;
+    }
+
+    if !is_changed {
+        return Ok(None);
+    }
+
+    let frames = frames
+        .into_iter()
+        .flat_map(|(_, frame)| frame)
+        .collect::<Vec<u8>>();
+
+    let mut new_tag = Vec::with_capacity(ID3_HEADER_LEN + frames.len());
+    new_tag.extend_from_slice(b"ID3");
+    new_tag.extend_from_slice(&[version, 0, 0]);
+    new_tag.extend_from_slice(&u32_to_syncsafe(frames.len() as u32));
+    new_tag.extend(frames);
+
+    Ok(Some(new_tag))
+}
+
+// returns the major version and raw frames
+fn parse_tag(tag: &[u8]) -> Result<(u8, Vec<([u8; 4], Vec<u8>)>)> {
+    let version = tag[3];
+    let flags = tag[5];
+
+    if version != 3 && version != 4 {
+        return Err(anyhow!("unsupported id3 version 2.{}", version));
+    }
+
+    // unsynchronisation and extended header
+    if flags & 0xc0 != 0 {
+        return Err(anyhow!("unsupported id3 flags {:#x}", flags));
+    }
+
+    let mut frames = Vec::new();
+    let mut offset = ID3_HEADER_LEN;
+
+    while offset + ID3_FRAME_HEADER_LEN <= tag.len() {
+        let header = &tag[offset..offset + ID3_FRAME_HEADER_LEN];
+
+        // padding
+        if header[0] == 0 {
+            break;
+        }
+
+        let size = if version == 4 {
+            syncsafe_to_u32(&header[4..8])
+        } else {
+            u32::from_be_bytes([header[4], header[5], header[6], header[7]])
+        } as usize;
+
+        let end = offset + ID3_FRAME_HEADER_LEN + size;
+        if end > tag.len() {
+            return Err(anyhow!("id3 frame exceeds the tag"));
+        }
+
+        frames.push((
+            [header[0], header[1], header[2], header[3]],
+            tag[offset..end].to_vec(),
+        ));
+
+        offset = end;
+    }
+
+    Ok((version, frames))
+}
+
+// utf-16 with bom, supported by both id3v2.3 and id3v2.4
+fn build_text_frame(version: u8, id: &[u8; 4], value: &str) -> Vec<u8> {
+    let mut body = vec![1, 0xff, 0xfe];
+    body.extend(value.encode_utf16().flat_map(u16::to_le_bytes));
+
+    let size = if version == 4 {
+        u32_to_syncsafe(body.len() as u32)
+    } else {
+        (body.len() as u32).to_be_bytes()
+    };
+
+    let mut frame = Vec::with_capacity(ID3_FRAME_HEADER_LEN + body.len());
+    frame.extend_from_slice(id);
+    frame.extend_from_slice(&size);
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend(body);
+
+    frame
+}
+
+fn syncsafe_to_u32(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 7) | u32::from(byte & 0x7f))
+}
+
+fn u32_to_syncsafe(value: u32) -> [u8; 4] {
+    [
+        ((value >> 21) & 0x7f) as u8,
+        ((value >> 14) & 0x7f) as u8,
+        ((value >> 7) & 0x7f) as u8,
+        (value & 0x7f) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_tag() {
+        let attribute_tags = AudioTags {
+            title: Some("Title".to_string()),
+            artist: Some("Artist".to_string()),
+            album: None,
+        };
+        let caption_tags = AudioTags::from_caption("-t music\nalbum: Album");
+
+        let tag = build_tag(None, &attribute_tags, &caption_tags)
+            .unwrap()
+            .unwrap();
+
+        let (version, frames) = parse_tag(&tag).unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(
+            frames.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            [b"TIT2", b"TPE1", b"TALB"]
+        );
+
+        // existing tags are kept, unless overridden by the caption
+        let attribute_tags = AudioTags {
+            title: Some("Other".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            build_tag(Some(&tag), &attribute_tags, &AudioTags::default())
+                .unwrap()
+                .is_none()
+        );
+
+        let caption_tags = AudioTags::from_caption("title: New");
+        let new_tag = build_tag(Some(&tag), &attribute_tags, &caption_tags)
+            .unwrap()
+            .unwrap();
+        let (_, frames) = parse_tag(&new_tag).unwrap();
+        assert_eq!(frames[0].1, build_text_frame(3, b"TIT2", "New"));
+        assert_eq!(frames.len(), 3);
+    }
+}
@@ -11,6 +11,7 @@ use sea_orm::{
     ActiveModelBehavior, ColIdx, ColumnType, DbErr, DerivePrimaryKey, DeriveRelation, EntityTrait,
     EnumIter, PrimaryKeyTrait, QueryResult, TryGetError, TryGetable, Value,
 };
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
 #[derive(Clone, Debug, DeriveEntityModel)]
@@ -18,15 +19,26 @@ use std::fmt::Display;
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i64,
+    // short id shown as #code in messages, unique among tasks in db
+    pub code: String,
     pub cmd_type: CmdType,
     pub filename: String,
+    // name in telegram if the file is renamed, written to the description of the uploaded file
+    pub original_filename: Option<String>,
     pub root_path: String,
     // for /url
     pub url: Option<String>,
+    // password of a protected share, set by -p, sealed with session_key if it's set
+    pub password: Option<String>,
     // onedrive upload url
     pub upload_url: String,
     pub current_length: i64,
     pub total_length: i64,
+    // resolved when enqueueing, like video/mp4
+    // for stickerpack, image/gif if animated stickers are converted by -c
+    pub mime_type: Option<String>,
+    // size type of the photo to transfer, like y, the largest if none
+    pub photo_type: Option<String>,
     pub chat_id: i64,
     // chat hex used by bot
     pub chat_bot_hex: String,
@@ -41,8 +53,25 @@ pub struct Model {
     // message id of the origin message in the origin chat
     // for link
     pub message_origin_id: Option<i32>,
+    // chat hex used by user to send the file to
+    // for copy
+    pub target_chat_hex: Option<String>,
+    // grouped id of the album the message belongs to
+    pub group_id: Option<i64>,
     pub status: TaskStatus,
+    // fetched in descending order, raised by /priority
+    pub priority: i64,
     pub auto_delete: bool,
+    // set by -t
+    pub label: Option<String>,
+    // username of the sender
+    pub sender: Option<String>,
+    // id of the sender in multi-tenant mode, whose onedrive accounts are used
+    pub tenant_id: Option<i64>,
+    // onedrive account the upload session is created with, none for the current one
+    pub account: Option<String>,
+    // self-destructing media downloaded when it was received, uploaded instead of the message
+    pub buffer_path: Option<String>,
 }
 
 #[derive(Clone, Debug, EnumIter, DeriveRelation)]
@@ -50,11 +79,28 @@ pub enum Relation {}
 
 impl ActiveModelBehavior for ActiveModel {}
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CmdType {
     File,
     Link,
     Url,
+    // the following ones don't upload through an upload session
+    Copy,
+    Fetch,
+    StickerPack,
+}
+
+impl CmdType {
+    // the upload url of the task is created when enqueueing and again on /retry
+    pub const fn has_upload_session(&self) -> bool {
+        matches!(self, Self::File | Self::Link | Self::Url)
+    }
+
+    // copied and fetched files are sent to telegram, nothing is stored in onedrive
+    pub const fn is_stored_in_onedrive(&self) -> bool {
+        !matches!(self, Self::Copy | Self::Fetch)
+    }
 }
 
 impl ValueType for CmdType {
@@ -64,6 +110,9 @@ impl ValueType for CmdType {
                 "file" => Ok(Self::File),
                 "link" => Ok(Self::Link),
                 "url" => Ok(Self::Url),
+                "copy" => Ok(Self::Copy),
+                "fetch" => Ok(Self::Fetch),
+                "stickerpack" => Ok(Self::StickerPack),
                 _ => Err(ValueTypeErr),
             },
             _ => Err(ValueTypeErr),
@@ -86,9 +135,12 @@ impl ValueType for CmdType {
 impl From<CmdType> for Value {
     fn from(value: CmdType) -> Self {
         match value {
-            CmdType::File | CmdType::Link | CmdType::Url => {
-                Self::String(Some(Box::new(value.to_string())))
-            }
+            CmdType::File
+            | CmdType::Link
+            | CmdType::Url
+            | CmdType::Copy
+            | CmdType::Fetch
+            | CmdType::StickerPack => Self::String(Some(Box::new(value.to_string()))),
         }
     }
 }
@@ -101,8 +153,11 @@ impl TryGetable for CmdType {
             "file" => Ok(Self::File),
             "link" => Ok(Self::Link),
             "url" => Ok(Self::Url),
+            "copy" => Ok(Self::Copy),
+            "fetch" => Ok(Self::Fetch),
+            "stickerpack" => Ok(Self::StickerPack),
             _ => Err(TryGetError::DbErr(DbErr::Type(format!(
-                "cmd type value should be one of file, photo, link and url: {}",
+                "cmd type value should be one of file, link, url, copy, fetch and stickerpack: {}",
                 value
             )))),
         }
@@ -115,6 +170,9 @@ impl Display for CmdType {
             Self::File => write!(f, "file"),
             Self::Link => write!(f, "link"),
             Self::Url => write!(f, "url"),
+            Self::Copy => write!(f, "copy"),
+            Self::Fetch => write!(f, "fetch"),
+            Self::StickerPack => write!(f, "stickerpack"),
         }
     }
 }
@@ -203,14 +261,23 @@ impl Display for TaskStatus {
     }
 }
 
+// also the format of exported tasks, see export.rs
+#[derive(Serialize, Deserialize)]
 pub struct InsertTask {
+    pub code: String,
     pub cmd_type: CmdType,
     pub filename: String,
+    pub original_filename: Option<String>,
     pub root_path: String,
     pub url: Option<String>,
+    // a secret, so it's not exported, a protected url has to be sent again after importing
+    #[serde(skip)]
+    pub password: Option<String>,
     pub upload_url: String,
     pub current_length: u64,
     pub total_length: u64,
+    pub mime_type: Option<String>,
+    pub photo_type: Option<String>,
     pub chat_id: i64,
     pub chat_bot_hex: String,
     pub chat_user_hex: String,
@@ -218,5 +285,89 @@ pub struct InsertTask {
     pub message_id: i32,
     pub message_indicator_id: i32,
     pub message_origin_id: Option<i32>,
+    pub target_chat_hex: Option<String>,
+    pub group_id: Option<i64>,
     pub auto_delete: bool,
+    pub label: Option<String>,
+    pub sender: Option<String>,
+    pub tenant_id: Option<i64>,
+    pub account: Option<String>,
+    // local to this instance, so it's not exported
+    #[serde(skip)]
+    pub buffer_path: Option<String>,
+}
+
+// the transferred length is kept as the offset to resume from
+impl From<Model> for InsertTask {
+    fn from(
+        Model {
+            code,
+            cmd_type,
+            filename,
+            original_filename,
+            root_path,
+            url,
+            upload_url,
+            current_length,
+            total_length,
+            mime_type,
+            photo_type,
+            chat_id,
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex,
+            message_id,
+            message_indicator_id,
+            message_origin_id,
+            target_chat_hex,
+            group_id,
+            auto_delete,
+            label,
+            sender,
+            tenant_id,
+            account,
+            buffer_path,
+            ..
+        }: Model,
+    ) -> Self {
+        Self {
+            code,
+            cmd_type,
+            filename,
+            original_filename,
+            root_path,
+            url,
+            password: None,
+            upload_url,
+            current_length: current_length as u64,
+            total_length: total_length as u64,
+            mime_type,
+            photo_type,
+            chat_id,
+            chat_bot_hex,
+            chat_user_hex,
+            chat_origin_hex,
+            message_id,
+            message_indicator_id,
+            message_origin_id,
+            target_chat_hex,
+            group_id,
+            auto_delete,
+            label,
+            sender,
+            tenant_id,
+            account,
+            buffer_path,
+        }
+    }
+}
+
+// filters for /queue, /history and /failed, all of them are optional
+#[derive(Default)]
+pub struct TaskFilter {
+    pub label: Option<String>,
+    pub chat_id: Option<i64>,
+    pub sender: Option<String>,
+    // set in multi-tenant mode, so that tenants only see their own tasks
+    pub tenant_id: Option<i64>,
 }
@@ -0,0 +1,226 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use url::Url;
+
+// chunks older than the window are dropped, so that the stats reflect the current network
+const SAMPLE_WINDOW: Duration = Duration::from_secs(10 * 60);
+const MAX_SAMPLES: usize = 1000;
+// long enough to smooth out large chunks, short enough to show a slowdown within seconds
+pub const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+// telegram files are downloaded from telegram, and urls from their hosts
+pub const TELEGRAM_HOST: &str = "telegram";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Direction {
+    Download,
+    Upload,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Download => write!(f, "download"),
+            Self::Upload => write!(f, "upload"),
+        }
+    }
+}
+
+struct Sample {
+    bytes: u64,
+    elapsed: Duration,
+    recorded_at: Instant,
+}
+
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+// of the chunks within the window
+pub struct HostStats {
+    pub direction: Direction,
+    pub host: String,
+    pub chunks: usize,
+    // in seconds
+    pub latency: Percentiles,
+    // in bytes per second, counted from the slowest chunks
+    pub speed: Percentiles,
+}
+
+// latency and speed of each chunk, to tell whether telegram, onedrive or a url host is slow
+#[derive(Default)]
+pub struct Throughput {
+    samples: Mutex<HashMap<(Direction, String), VecDeque<Sample>>>,
+}
+
+impl Throughput {
+    pub fn record(&self, direction: Direction, host: &str, bytes: u64, elapsed: Duration) {
+        let now = Instant::now();
+
+        let mut samples = self.samples.lock().unwrap();
+        let host_samples = samples.entry((direction, host.to_string())).or_default();
+
+        host_samples.push_back(Sample {
+            bytes,
+            elapsed,
+            recorded_at: now,
+        });
+
+        while host_samples.len() > MAX_SAMPLES
+            || host_samples
+                .front()
+                .is_some_and(|sample| now.duration_since(sample.recorded_at) > SAMPLE_WINDOW)
+        {
+            host_samples.pop_front();
+        }
+    }
+
+    // bytes per second of all hosts of the direction, over chunks finished within the window
+    pub fn get_rate(&self, direction: Direction, window: Duration) -> f64 {
+        let bytes = self
+            .samples
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((sample_direction, _), _)| *sample_direction == direction)
+            .flat_map(|(_, host_samples)| host_samples.iter())
+            .filter(|sample| sample.recorded_at.elapsed() <= window)
+            .map(|sample| sample.bytes)
+            .sum::<u64>();
+
+        bytes as f64 / window.as_secs_f64()
+    }
+
+    pub fn get_stats(&self) -> Vec<HostStats> {
+        let mut samples = self.samples.lock().unwrap();
+
+        samples.retain(|_, host_samples| {
+            host_samples.retain(|sample| sample.recorded_at.elapsed() <= SAMPLE_WINDOW);
+
+            !host_samples.is_empty()
+        });
+
+        let mut stats = samples
+            .iter()
+            .map(|((direction, host), host_samples)| {
+                let latencies = host_samples
+                    .iter()
+                    .map(|sample| sample.elapsed.as_secs_f64())
+                    .collect::<Vec<_>>();
+
+                let speeds = host_samples
+                    .iter()
+                    .map(|sample| sample.bytes as f64 / sample.elapsed.as_secs_f64().max(0.001))
+                    .collect::<Vec<_>>();
+
+                HostStats {
+                    direction: *direction,
+                    host: host.clone(),
+                    chunks: host_samples.len(),
+                    latency: get_percentiles(latencies, false),
+                    speed: get_percentiles(speeds, true),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        stats.sort_by(|a, b| (a.direction, &a.host).cmp(&(b.direction, &b.host)));
+
+        stats
+    }
+}
+
+pub fn get_host(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(ToString::to_string))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// for speeds, the percentiles count from the slowest, since slow chunks are what matter
+fn get_percentiles(mut values: Vec<f64>, is_descending: bool) -> Percentiles {
+    values.sort_by(f64::total_cmp);
+
+    if is_descending {
+        values.reverse();
+    }
+
+    let get = |percentile: f64| {
+        let index = ((values.len() as f64 * percentile).ceil() as usize).saturating_sub(1);
+
+        values.get(index).copied().unwrap_or_default()
+    };
+
+    Percentiles {
+        p50: get(0.5),
+        p90: get(0.9),
+        p99: get(0.99),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_percentiles() {
+        let values = (1..=100).map(f64::from).collect::<Vec<_>>();
+
+        let latency = get_percentiles(values.clone(), false);
+        assert_eq!(latency.p50 as u64, 50);
+        assert_eq!(latency.p90 as u64, 90);
+        assert_eq!(latency.p99 as u64, 99);
+
+        let speed = get_percentiles(values, true);
+        assert_eq!(speed.p50 as u64, 51);
+        assert_eq!(speed.p90 as u64, 11);
+        assert_eq!(speed.p99 as u64, 2);
+
+        assert_eq!(get_percentiles(Vec::new(), false).p50 as u64, 0);
+    }
+
+    #[test]
+    fn test_get_rate() {
+        let throughput = Throughput::default();
+        throughput.record(
+            Direction::Download,
+            TELEGRAM_HOST,
+            1000,
+            Duration::from_secs(1),
+        );
+        throughput.record(
+            Direction::Download,
+            "example.com",
+            1000,
+            Duration::from_secs(1),
+        );
+        throughput.record(
+            Direction::Upload,
+            "example.com",
+            500,
+            Duration::from_secs(1),
+        );
+
+        let window = Duration::from_secs(10);
+        assert_eq!(throughput.get_rate(Direction::Download, window) as u64, 200);
+        assert_eq!(throughput.get_rate(Direction::Upload, window) as u64, 50);
+    }
+
+    #[test]
+    fn test_get_host() {
+        assert_eq!(get_host("https://example.com:8443/a.mp4"), "example.com");
+        assert_eq!(get_host("not a url"), "unknown");
+    }
+}
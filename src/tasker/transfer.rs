@@ -5,34 +5,104 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use super::{tasks, Progress};
+use super::{
+    quick_xor::QuickXorHash,
+    tasks,
+    throughput::{get_host, Direction, TELEGRAM_HOST},
+    tuning::ChunkTuner,
+    Progress, TaskSession,
+};
 use crate::{
-    client::utils::chat_from_hex, error::TaskAbortError, state::AppState, utils::get_http_client,
+    bandwidth::AccountType,
+    client::{
+        onedrive::{GraphStatusError, THROTTLE, UPLOAD_LIMIT},
+        telegram::DownloadBuilder,
+        utils::chat_from_hex,
+        TelegramClient,
+    },
+    env::ENV,
+    error::{CorruptedUploadError, SourceDeletedError, TaskAbortError},
+    message::TelegramMessage,
+    scan::ClamdScanner,
+    settings::ConflictPolicy,
+    state::AppState,
+    url_auth::{build_url_request, open_password},
+    utils::get_http_client,
 };
 use anyhow::{anyhow, Context, Error, Result};
-use grammers_client::client::files::MAX_CHUNK_SIZE;
+use grammers_client::{
+    client::files::MAX_CHUNK_SIZE,
+    types::{photo_sizes::PhotoSize, Media},
+    InvocationError,
+};
 use onedrive_api::{resource::DriveItem, UploadSession};
-use std::{collections::VecDeque, ops::Range, sync::Arc, time::Duration};
+use path_slash::PathBufExt;
+use rand::Rng;
+use reqwest::{header, Method, StatusCode};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    ops::Range,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
 use tokio_util::sync::CancellationToken;
 
 const MAX_RETRIES: i32 = 5;
+// retries of a part wait 1s, 2s, 4s and so on, each shortened by up to half at random
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// onedrive may keep throttling for a while, each retry waits for Retry-After
+const MAX_THROTTLED_RETRIES: i32 = 30;
+
+// onedrive requires parts in multiples of 320 KiB
+const PART_UNIT: usize = 327680;
+const PART_UNITS: usize = 10;
+// about 19 MiB, well below the 60 MiB limit of onedrive
+const MAX_PART_UNITS: usize = 60;
+
+// telegram chunks downloaded in parallel and uploaded as a part
+const WORKER_COUNT: usize = 4;
+const MAX_WORKER_COUNT: usize = 16;
+
+// there's only one user client, its usage is shown in chats, so not keyed by the phone number
+const TELEGRAM_ACCOUNT: &str = "user";
 
 pub async fn multi_parts_uploader_from_url(
-    tasks::Model {
+    task: &tasks::Model,
+    progress: Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: AppState,
+) -> Result<String> {
+    let tasks::Model {
         id,
         url,
+        password,
         upload_url,
         current_length,
         total_length,
+        tenant_id,
+        account,
         ..
-    }: &tasks::Model,
-    progress: Arc<Progress>,
-) -> Result<String> {
-    const PART_SIZE: usize = 3276800;
+    } = task;
 
     let http_client = get_http_client()?;
 
+    let task_session = &state.task_session;
+    let concurrency = &task_session.concurrency;
+    let throughput = &task_session.throughput;
+
+    let mut tuner = ChunkTuner::new(PART_UNITS, 1, MAX_PART_UNITS);
+
     let url = url.clone().ok_or_else(|| anyhow!("url is none"))?;
+    let host = get_host(&url);
+
+    let password = open_password(password.as_deref())?;
 
     let upload_session = UploadSession::from_upload_url(upload_url);
 
@@ -43,36 +113,82 @@ pub async fn multi_parts_uploader_from_url(
         .set_current_length(id.to_owned(), current_length)
         .await?;
 
-    let mut response = http_client
-        .get(url)
+    let mut request = build_url_request(&http_client, Method::GET, &url, password.as_deref())?;
+
+    // resumed from an imported or interrupted task, the uploaded part is skipped
+    if current_length > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", current_length));
+    }
+
+    let mut response = request
         .send()
         .await
         .context("failed to send request for /url")?;
 
+    if current_length > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(anyhow!("the server doesn't support resuming"))
+            .context("use /retry to transfer it from the beginning");
+    }
+
+    // the part uploaded before resuming isn't hashed
+    let mut hash = (current_length == 0).then(QuickXorHash::default);
+
     let upload_response = loop {
-        let mut buffer = Vec::with_capacity(PART_SIZE);
+        // tuned by the speed of the last parts, and smaller while throttled
+        let part_size = concurrency.scale(tuner.units()) * PART_UNIT;
+
+        let mut buffer = Vec::with_capacity(part_size);
+
+        let download_start = Instant::now();
 
         while let Some(chunk) = response.chunk().await.context("failed to get chunk")? {
             buffer.extend_from_slice(&chunk);
 
-            if buffer.len() >= PART_SIZE {
+            if buffer.len() >= part_size {
                 break;
             }
         }
 
+        throughput.record(
+            Direction::Download,
+            &host,
+            buffer.len() as u64,
+            download_start.elapsed(),
+        );
+
+        if let Some(hash) = &mut hash {
+            hash.update(&buffer);
+        }
+
         tracing::debug!("downloaded chunk from url");
 
         let upload_response = upload_file(
-            &upload_session,
+            &PartUploader {
+                upload_session: &upload_session,
+                http_client: &http_client,
+                task_session,
+                cancellation_token,
+            },
             &buffer,
             current_length,
             total_length,
-            &http_client,
+            &mut tuner,
         )
         .await?;
 
+        tuner.record_part(buffer.len(), download_start.elapsed());
+
         tracing::debug!("uploaded chunk from url");
 
+        record_usage(
+            &state,
+            *tenant_id,
+            account.as_deref(),
+            false,
+            buffer.len() as u64,
+        )
+        .await?;
+
         current_length += buffer.len() as u64;
         progress
             .set_current_length(id.to_owned(), current_length)
@@ -83,10 +199,9 @@ pub async fn multi_parts_uploader_from_url(
         }
     };
 
-    let filename = upload_response
-        .ok_or_else(|| anyhow!("failed to get drive item after upload"))?
-        .name
-        .ok_or_else(|| anyhow!("drive item name not found"))?;
+    let upload_response = get_uploaded_item(upload_response, task, &task.filename, &state).await?;
+
+    let filename = verify_upload(upload_response, hash.as_ref())?;
 
     tracing::info!(
         "uploaded file from url: {} size: {}",
@@ -98,25 +213,28 @@ pub async fn multi_parts_uploader_from_url(
 }
 
 pub async fn multi_parts_uploader_from_tg_file(
-    tasks::Model {
+    task: &tasks::Model,
+    progress: Arc<Progress>,
+    cancellation_token: CancellationToken,
+    state: AppState,
+) -> Result<String> {
+    let http_client = get_http_client()?;
+
+    let tasks::Model {
         id,
-        cmd_type,
         upload_url,
         current_length,
         total_length,
-        chat_user_hex,
-        chat_origin_hex,
-        message_id,
-        message_origin_id,
         ..
-    }: &tasks::Model,
-    progress: Arc<Progress>,
-    cancellation_token: CancellationToken,
-    state: AppState,
-) -> Result<String> {
-    const WORKER_COUNT: i32 = 4;
+    } = task;
 
-    let http_client = get_http_client()?;
+    let task_session = &state.task_session;
+    let concurrency = &task_session.concurrency;
+    let throughput = &task_session.throughput;
+
+    let mut tuner = ChunkTuner::new(WORKER_COUNT, 1, MAX_WORKER_COUNT);
+    // tuned by the speed of the last parts, and fewer parallel chunks while throttled
+    let mut worker_count = concurrency.scale(tuner.units());
 
     let upload_session = UploadSession::from_upload_url(upload_url);
 
@@ -130,31 +248,8 @@ pub async fn multi_parts_uploader_from_tg_file(
     let mut upload_response = None;
 
     let telegram_user = &state.telegram_user;
-    let chat = chat_from_hex(chat_user_hex)?;
-
-    let message = match cmd_type {
-        tasks::CmdType::File => telegram_user.get_message(chat, *message_id).await?,
-        tasks::CmdType::Link => {
-            let chat = chat_from_hex(
-                chat_origin_hex
-                    .as_ref()
-                    .ok_or_else(|| anyhow!("chat_origin_hex is None"))?,
-            )?;
 
-            let message_origin_id = message_origin_id
-                .as_ref()
-                .ok_or_else(|| anyhow!("message_id_origin is None"))?;
-
-            telegram_user.get_message(chat, *message_origin_id).await?
-        }
-        tasks::CmdType::Url => return Err(anyhow!("invalid cmd type")),
-    };
-
-    let media = Arc::new(
-        message
-            .media()
-            .ok_or_else(|| anyhow!("message does not contain any media"))?,
-    );
+    let downloadable = Arc::new(get_tg_downloadable(task, &state).await?);
 
     let mut work_handles = VecDeque::new();
 
@@ -163,44 +258,78 @@ pub async fn multi_parts_uploader_from_tg_file(
     } else {
         1
     };
-    let mut current_chunk_num = 0;
+    // resumed from an imported or interrupted task, only the last chunk can be shorter than the max size
+    let mut current_chunk_num = (current_length / MAX_CHUNK_SIZE as u64) as i32;
+    // parts end in the middle of a chunk, the beginning of which is uploaded before resuming
+    let mut skipped_length = (current_length % MAX_CHUNK_SIZE as u64) as usize;
+
+    // telegram chunks don't fit in multiples of 320 KiB, so the rest is uploaded with the next part
+    let mut pending = Vec::new();
+
+    let mut part_start = Instant::now();
+
+    // the part uploaded before resuming isn't hashed
+    let mut hash = (current_length == 0).then(QuickXorHash::default);
 
     while current_chunk_num < total_chunks_num {
         let telegram_user_clone = telegram_user.clone();
-        let media_clone = media.clone();
+        let downloadable_clone = downloadable.clone();
 
         let cancellation_token_clone = cancellation_token.clone();
+        let concurrency_clone = concurrency.clone();
+        let throughput_clone = throughput.clone();
 
         // create a worker
         work_handles.push_back(tokio::spawn(async move {
-            let mut download = telegram_user_clone
-                .iter_download(media_clone.as_ref())
-                .skip_chunks(current_chunk_num);
-
-            let fut = async {
-                let mut retries = 0;
+            let mut retries = 0;
+
+            loop {
+                let download_start = Instant::now();
+
+                // only the chunk of this worker, chunks are counted in the max size
+                let result = downloadable_clone
+                    .download(&telegram_user_clone)
+                    .chunk_size(MAX_CHUNK_SIZE)
+                    .skip_chunks(current_chunk_num)
+                    .max_chunks(1)
+                    .read_ahead(1)
+                    .cancellation_token(cancellation_token_clone.clone())
+                    .stream()
+                    .next()
+                    .await;
+
+                match result {
+                    Ok(chunk) => {
+                        if let Some(chunk) = &chunk {
+                            throughput_clone.record(
+                                Direction::Download,
+                                TELEGRAM_HOST,
+                                chunk.len() as u64,
+                                download_start.elapsed(),
+                            );
+                        }
 
-                loop {
-                    match download.next().await {
-                        Ok(chunk) => break Ok(chunk),
-                        Err(e) => {
-                            if retries <= MAX_RETRIES {
-                                tokio::time::sleep(Duration::from_secs(2)).await;
+                        break Ok((chunk, retries));
+                    }
+                    Err(e) if e.is::<TaskAbortError>() => break Err(e),
+                    Err(e) => {
+                        if e.downcast_ref::<InvocationError>()
+                            .is_some_and(is_throttling_invocation_error)
+                        {
+                            concurrency_clone.record_error();
+                        }
 
-                                retries += 1;
+                        if retries <= MAX_RETRIES {
+                            tokio::time::sleep(Duration::from_secs(2)).await;
 
-                                continue;
-                            }
+                            retries += 1;
 
-                            break Err(e);
+                            continue;
                         }
+
+                        break Err(e.context("failed to get next chunk from tg file downloader"));
                     }
                 }
-            };
-
-            tokio::select! {
-                result = fut => result.context("failed to get next chunk from tg file downloader"),
-                () = cancellation_token_clone.cancelled() => Err(TaskAbortError.into())
             }
         }));
 
@@ -208,42 +337,90 @@ pub async fn multi_parts_uploader_from_tg_file(
 
         // once reached the max worker number, or the last chunk, wait for the workers to finish
         // onedrive needs the chunk to be uploaded sequentially in order
-        if current_chunk_num % WORKER_COUNT == 0 || current_chunk_num == total_chunks_num {
+        if work_handles.len() >= worker_count || current_chunk_num == total_chunks_num {
             let mut chunk = Vec::new();
+            let mut is_retried = false;
 
             while let Some(handle) = work_handles.pop_front() {
-                let mut chunk_part = handle
-                    .await
-                    .context("failed to join handle")??
+                let (chunk_part, retries) = match handle.await.context("failed to join handle")? {
+                    Ok(chunk_part) => chunk_part,
+                    Err(e) => return Err(check_source_deleted(task, &state, e).await),
+                };
+
+                let mut chunk_part = chunk_part
                     .ok_or_else(|| anyhow!("failed to get chunk from tg file downloader"))?;
 
                 chunk.append(&mut chunk_part);
+                is_retried |= retries > 0;
+            }
+
+            // once for all the workers of the part
+            if is_retried {
+                tuner.record_error();
+            }
+
+            chunk.drain(..skipped_length.min(chunk.len()));
+            skipped_length = 0;
+
+            if let Some(hash) = &mut hash {
+                hash.update(&chunk);
             }
 
             tracing::debug!("downloaded chunk from telegram");
 
+            pending.append(&mut chunk);
+
+            let part_length = if current_chunk_num == total_chunks_num {
+                pending.len()
+            } else {
+                pending.len() - pending.len() % PART_UNIT
+            };
+
+            if part_length == 0 {
+                continue;
+            }
+
+            let part = pending.drain(..part_length).collect::<Vec<_>>();
+
             upload_response = upload_file(
-                &upload_session,
-                &chunk,
+                &PartUploader {
+                    upload_session: &upload_session,
+                    http_client: &http_client,
+                    task_session,
+                    cancellation_token: &cancellation_token,
+                },
+                &part,
                 current_length,
                 total_length,
-                &http_client,
+                &mut tuner,
             )
             .await?;
 
+            tuner.record_part(part.len(), part_start.elapsed());
+            worker_count = concurrency.scale(tuner.units());
+            part_start = Instant::now();
+
             tracing::debug!("uploaded chunk from telegram");
 
-            current_length += chunk.len() as u64;
+            record_usage(
+                &state,
+                task.tenant_id,
+                task.account.as_deref(),
+                true,
+                part.len() as u64,
+            )
+            .await?;
+
+            current_length += part.len() as u64;
             progress
                 .set_current_length(id.to_owned(), current_length)
                 .await?;
         }
     }
 
-    let filename = upload_response
-        .ok_or_else(|| anyhow!("failed to get drive item after upload"))?
-        .name
-        .ok_or_else(|| anyhow!("drive item name not found"))?;
+    let upload_response = get_uploaded_item(upload_response, task, &task.filename, &state).await?;
+
+    let filename = verify_upload(upload_response, hash.as_ref())?;
 
     tracing::info!(
         "uploaded file from telegram: {} size: {}",
@@ -254,25 +431,678 @@ pub async fn multi_parts_uploader_from_tg_file(
     Ok(filename)
 }
 
-async fn upload_file(
+// small files are enqueued without an upload session, and uploaded in a single request
+pub async fn small_file_uploader_from_tg_file(
+    task: &tasks::Model,
+    progress: Arc<Progress>,
+    cancellation_token: CancellationToken,
+    state: AppState,
+) -> Result<String> {
+    let task_session = &state.task_session;
+    let throughput = &task_session.throughput;
+
+    progress.set_current_length(task.id, 0).await?;
+
+    let downloadable = get_tg_downloadable(task, &state).await?;
+
+    let download_start = Instant::now();
+
+    let content = match downloadable
+        .download(&state.telegram_user)
+        .chunk_size(MAX_CHUNK_SIZE)
+        .retries(MAX_RETRIES as u32)
+        .cancellation_token(cancellation_token.clone())
+        .stream()
+        .read_to_end()
+        .await
+    {
+        Ok(content) => content,
+        Err(e) if e.is::<TaskAbortError>() => return Err(e),
+        Err(e) => return Err(check_source_deleted(task, &state, e).await),
+    };
+
+    let length = content.len() as u64;
+
+    throughput.record(
+        Direction::Download,
+        TELEGRAM_HOST,
+        length,
+        download_start.elapsed(),
+    );
+
+    let mut hash = QuickXorHash::default();
+    hash.update(&content);
+
+    let conflict_policy = state
+        .settings_session
+        .get_conflict_policy(task.chat_id)
+        .await?;
+
+    let onedrive = state.get_onedrive(task.tenant_id).await?;
+
+    // the request goes to graph api, unlike parts sent to the upload url
+    let host = get_host(&ENV.get().unwrap().onedrive.cloud.api_url());
+
+    let mut tries = 0;
+    let mut throttled_tries = 0;
+
+    let item = loop {
+        wait_throttle(&cancellation_token).await?;
+
+        UPLOAD_LIMIT.wait(length).await;
+
+        tries += 1;
+
+        let upload_start = Instant::now();
+
+        let result = onedrive
+            .upload_small_file(
+                task.account.as_deref(),
+                &task.root_path,
+                &task.filename,
+                conflict_policy,
+                content.clone(),
+            )
+            .await;
+
+        match result {
+            Ok(item) => {
+                throughput.record(Direction::Upload, &host, length, upload_start.elapsed());
+
+                break item;
+            }
+            Err(e) => {
+                // like the parts of larger files, see upload_file
+                if let Some(GraphStatusError {
+                    status,
+                    retry_after,
+                    ..
+                }) = e.downcast_ref::<GraphStatusError>()
+                {
+                    if matches!(status.as_u16(), 408 | 429 | 503 | 504) {
+                        task_session.concurrency.record_error();
+                    }
+
+                    if matches!(status.as_u16(), 429 | 503)
+                        && throttled_tries < MAX_THROTTLED_RETRIES
+                    {
+                        THROTTLE.pause(*retry_after);
+
+                        tries -= 1;
+                        throttled_tries += 1;
+
+                        continue;
+                    }
+                }
+
+                if tries < MAX_RETRIES {
+                    let jitter = rand::thread_rng().gen_range(0.0..1.0);
+                    tokio::time::sleep(get_backoff(tries, jitter)).await;
+
+                    continue;
+                }
+
+                return Err(e);
+            }
+        }
+    };
+
+    record_usage(
+        &state,
+        task.tenant_id,
+        task.account.as_deref(),
+        true,
+        length,
+    )
+    .await?;
+
+    progress.set_current_length(task.id, length).await?;
+
+    let filename = verify_upload(Some(item), Some(&hash))?;
+
+    tracing::info!(
+        "uploaded small file from telegram: {} size: {}",
+        filename,
+        length
+    );
+
+    Ok(filename)
+}
+
+// an imported or interrupted task may have uploaded a part of the original file,
+// which can't be continued with a converted one
+pub async fn get_local_upload_session(
+    task: &tasks::Model,
+    state: &AppState,
+) -> Result<UploadSession> {
+    if task.current_length == 0 && !task.upload_url.is_empty() {
+        return Ok(UploadSession::from_upload_url(&task.upload_url));
+    }
+
+    // the upload session created when enqueueing is left to expire,
+    // and small files are enqueued without it
+    create_upload_session(task, &task.filename, state).await
+}
+
+// with the conflict policy of the chat, like for a file renamed after converting
+pub async fn create_upload_session(
+    task: &tasks::Model,
+    filename: &str,
+    state: &AppState,
+) -> Result<UploadSession> {
+    let conflict_policy = state
+        .settings_session
+        .get_conflict_policy(task.chat_id)
+        .await?;
+
+    let (upload_session, _) = state
+        .get_onedrive(task.tenant_id)
+        .await?
+        .multipart_upload_session_builder(
+            task.account.as_deref(),
+            &task.root_path,
+            filename,
+            conflict_policy,
+        )
+        .await?;
+
+    Ok(upload_session)
+}
+
+// upload a local file, such as a remuxed video, named filename in onedrive
+pub async fn multi_parts_uploader_from_file(
+    task: &tasks::Model,
+    filename: &str,
+    path: &Path,
     upload_session: &UploadSession,
+    progress: Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: AppState,
+) -> Result<String> {
+    let http_client = get_http_client()?;
+
+    let task_session = &state.task_session;
+
+    let mut tuner = ChunkTuner::new(PART_UNITS, 1, MAX_PART_UNITS);
+
+    let mut file = fs::File::open(path)
+        .await
+        .context("failed to open file to upload")?;
+
+    let total_length = file
+        .metadata()
+        .await
+        .context("failed to get metadata of file to upload")?
+        .len();
+
+    // resumed from an interrupted task, such as a remuxed video which is the same each time
+    let mut current_length =
+        get_resume_offset(upload_session, &http_client, 0, total_length as usize)
+            .await
+            .filter(|offset| (*offset as u64) < total_length)
+            .unwrap_or_default() as u64;
+
+    progress.set_current_length(task.id, current_length).await?;
+
+    let mut hash = QuickXorHash::default();
+
+    // the part uploaded before resuming is still hashed, since the whole file is here
+    let mut skipped_length = 0;
+    while skipped_length < current_length {
+        let mut buffer = Vec::with_capacity(PART_UNIT);
+
+        (&mut file)
+            .take((current_length - skipped_length).min(PART_UNIT as u64))
+            .read_to_end(&mut buffer)
+            .await
+            .context("failed to read file to upload")?;
+
+        if buffer.is_empty() {
+            return Err(anyhow!("file to upload is shorter than the uploaded part"));
+        }
+
+        hash.update(&buffer);
+        skipped_length += buffer.len() as u64;
+    }
+
+    let upload_response = loop {
+        let part_size = task_session.concurrency.scale(tuner.units()) * PART_UNIT;
+
+        let mut buffer = Vec::with_capacity(part_size);
+
+        let part_start = Instant::now();
+
+        (&mut file)
+            .take(part_size as u64)
+            .read_to_end(&mut buffer)
+            .await
+            .context("failed to read file to upload")?;
+
+        hash.update(&buffer);
+
+        let upload_response = upload_file(
+            &PartUploader {
+                upload_session,
+                http_client: &http_client,
+                task_session,
+                cancellation_token,
+            },
+            &buffer,
+            current_length,
+            total_length,
+            &mut tuner,
+        )
+        .await?;
+
+        tuner.record_part(buffer.len(), part_start.elapsed());
+
+        current_length += buffer.len() as u64;
+        progress.set_current_length(task.id, current_length).await?;
+
+        if current_length >= total_length {
+            break upload_response;
+        }
+    };
+
+    let upload_response = get_uploaded_item(upload_response, task, filename, &state).await?;
+
+    let filename = verify_upload(upload_response, Some(&hash))?;
+
+    tracing::info!(
+        "uploaded file from local: {} size: {}",
+        filename,
+        total_length
+    );
+
+    Ok(filename)
+}
+
+// the source is downloaded only to be hashed, which is cheaper than uploading it again
+pub async fn hash_tg_file(
+    task: &tasks::Model,
+    cancellation_token: &CancellationToken,
+    state: &AppState,
+) -> Result<String> {
+    let downloadable = get_tg_downloadable(task, state).await?;
+
+    let mut download = downloadable
+        .download(&state.telegram_user)
+        .chunk_size(MAX_CHUNK_SIZE)
+        .retries(MAX_RETRIES as u32)
+        .cancellation_token(cancellation_token.clone())
+        .stream();
+
+    let mut hash = QuickXorHash::default();
+
+    loop {
+        let chunk = match download.next().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => return Err(check_source_deleted(task, state, e).await),
+        };
+
+        hash.update(&chunk);
+
+        record_tg_usage(state, chunk.len() as u64).await?;
+    }
+
+    Ok(hash.finalize())
+}
+
+pub async fn hash_url(task: &tasks::Model) -> Result<String> {
+    let url = task.url.as_ref().ok_or_else(|| anyhow!("url is none"))?;
+
+    let password = open_password(task.password.as_deref())?;
+
+    let mut response =
+        build_url_request(&get_http_client()?, Method::GET, url, password.as_deref())?
+            .send()
+            .await
+            .context("failed to send request for /url")?;
+
+    let mut hash = QuickXorHash::default();
+
+    while let Some(chunk) = response.chunk().await.context("failed to get chunk")? {
+        hash.update(&chunk);
+    }
+
+    Ok(hash.finalize())
+}
+
+pub async fn hash_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .await
+        .context("failed to open file to hash")?;
+
+    let mut hash = QuickXorHash::default();
+    let mut buffer = vec![0; PART_UNIT];
+
+    loop {
+        let length = file
+            .read(&mut buffer)
+            .await
+            .context("failed to read file to hash")?;
+
+        if length == 0 {
+            break;
+        }
+
+        hash.update(&buffer[..length]);
+    }
+
+    Ok(hash.finalize())
+}
+
+// streamed to clamd as it's downloaded, returns the signature found
+pub async fn scan_tg_file(
+    task: &tasks::Model,
+    socket: &str,
+    cancellation_token: &CancellationToken,
+    state: &AppState,
+) -> Result<Option<String>> {
+    let mut scanner = ClamdScanner::connect(socket).await?;
+
+    let downloadable = get_tg_downloadable(task, state).await?;
+
+    let mut download = downloadable
+        .download(&state.telegram_user)
+        .chunk_size(MAX_CHUNK_SIZE)
+        .retries(MAX_RETRIES as u32)
+        .cancellation_token(cancellation_token.clone())
+        .stream();
+
+    loop {
+        let chunk = match download.next().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => return Err(check_source_deleted(task, state, e).await),
+        };
+
+        scanner.send(&chunk).await?;
+
+        record_tg_usage(state, chunk.len() as u64).await?;
+    }
+
+    scanner.finish().await
+}
+
+pub async fn scan_file(path: &Path, socket: &str) -> Result<Option<String>> {
+    let mut scanner = ClamdScanner::connect(socket).await?;
+
+    let mut file = fs::File::open(path)
+        .await
+        .context("failed to open file to scan")?;
+
+    let mut buffer = vec![0; PART_UNIT];
+
+    loop {
+        let length = file
+            .read(&mut buffer)
+            .await
+            .context("failed to read file to scan")?;
+
+        if length == 0 {
+            break;
+        }
+
+        scanner.send(&buffer[..length]).await?;
+    }
+
+    scanner.finish().await
+}
+
+// the origin message for links and copies
+pub async fn get_tg_message(
+    tasks::Model {
+        cmd_type,
+        chat_user_hex,
+        chat_origin_hex,
+        message_id,
+        message_origin_id,
+        ..
+    }: &tasks::Model,
+    state: &AppState,
+) -> Result<TelegramMessage> {
+    let telegram_user = &state.telegram_user;
+    let chat = chat_from_hex(chat_user_hex)?;
+
+    let message = match cmd_type {
+        tasks::CmdType::File => {
+            telegram_user
+                .get_message_if_exists(chat, *message_id)
+                .await?
+        }
+        tasks::CmdType::Link | tasks::CmdType::Copy => {
+            let chat = chat_from_hex(
+                chat_origin_hex
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("chat_origin_hex is None"))?,
+            )?;
+
+            let message_origin_id = message_origin_id
+                .as_ref()
+                .ok_or_else(|| anyhow!("message_id_origin is None"))?;
+
+            telegram_user
+                .get_message_if_exists(chat, *message_origin_id)
+                .await?
+        }
+        tasks::CmdType::Url | tasks::CmdType::Fetch | tasks::CmdType::StickerPack => {
+            return Err(anyhow!("invalid cmd type"))
+        }
+    };
+
+    message.ok_or_else(|| SourceDeletedError.into())
+}
+
+// downloading keeps going with the file location got at start even if the source is deleted,
+// until telegram refuses it, then the error is replaced if the source is gone
+async fn check_source_deleted(task: &tasks::Model, state: &AppState, e: Error) -> Error {
+    if e.downcast_ref::<TaskAbortError>().is_some() {
+        return e;
+    }
+
+    match get_tg_message(task, state).await {
+        Err(source_e) if source_e.downcast_ref::<SourceDeletedError>().is_some() => {
+            e.context(SourceDeletedError)
+        }
+        _ => e,
+    }
+}
+
+pub async fn get_tg_media(task: &tasks::Model, state: &AppState) -> Result<Media> {
+    get_tg_message(task, state)
+        .await?
+        .media()
+        .ok_or_else(|| anyhow!("message does not contain any media"))
+}
+
+// a smaller size of the photo if chosen when enqueueing
+enum TgDownloadable {
+    Media(Media),
+    PhotoSize(PhotoSize),
+}
+
+impl TgDownloadable {
+    fn download(&self, telegram_user: &TelegramClient) -> DownloadBuilder {
+        match self {
+            Self::Media(media) => telegram_user.download(media),
+            Self::PhotoSize(photo_size) => telegram_user.download(photo_size),
+        }
+    }
+}
+
+async fn get_tg_downloadable(task: &tasks::Model, state: &AppState) -> Result<TgDownloadable> {
+    let media = get_tg_media(task, state).await?;
+
+    let Some(photo_type) = &task.photo_type else {
+        return Ok(TgDownloadable::Media(media));
+    };
+
+    match media {
+        Media::Photo(file) => file
+            .thumbs()
+            .into_iter()
+            .find(|size| &size.photo_type() == photo_type)
+            .map(TgDownloadable::PhotoSize)
+            .ok_or_else(|| anyhow!("photo size {} not found", photo_type)),
+        _ => Err(anyhow!("media is not a photo")),
+    }
+}
+
+// download the whole file, for processing that can't be done while streaming
+pub async fn download_tg_file(
+    task: &tasks::Model,
+    media: &Media,
+    path: &Path,
+    progress: Arc<Progress>,
+    cancellation_token: &CancellationToken,
+    state: AppState,
+) -> Result<()> {
+    let mut file = fs::File::create(path)
+        .await
+        .context("failed to create local file")?;
+
+    let mut current_length = 0;
+    progress.set_current_length(task.id, current_length).await?;
+
+    let mut download = state
+        .telegram_user
+        .download(media)
+        .cancellation_token(cancellation_token.clone())
+        .stream();
+    loop {
+        let chunk = match download
+            .next()
+            .await
+            .context("failed to get next chunk from tg file downloader")
+        {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => return Err(check_source_deleted(task, &state, e).await),
+        };
+
+        file.write_all(&chunk)
+            .await
+            .context("failed to write local file")?;
+
+        // copied files are only sent to another chat
+        if task.cmd_type.is_stored_in_onedrive() {
+            record_usage(
+                &state,
+                task.tenant_id,
+                task.account.as_deref(),
+                true,
+                chunk.len() as u64,
+            )
+            .await?;
+        } else {
+            record_tg_usage(&state, chunk.len() as u64).await?;
+        }
+
+        current_length += chunk.len() as u64;
+        progress.set_current_length(task.id, current_length).await?;
+    }
+
+    file.flush().await.context("failed to flush local file")?;
+
+    tracing::debug!("downloaded {} to local", task.filename);
+
+    Ok(())
+}
+
+// bytes uploaded to onedrive, and downloaded from telegram if the file comes from there
+pub async fn record_usage(
+    state: &AppState,
+    tenant_id: Option<i64>,
+    account: Option<&str>,
+    is_from_telegram: bool,
+    bytes: u64,
+) -> Result<()> {
+    if is_from_telegram {
+        record_tg_usage(state, bytes).await?;
+    }
+
+    let username = match account {
+        Some(account) => Some(account.to_string()),
+        None => {
+            state
+                .get_onedrive(tenant_id)
+                .await?
+                .get_current_username()
+                .await?
+        }
+    };
+
+    if let Some(username) = username {
+        state
+            .usage_session
+            .add_usage(AccountType::OneDrive, &username, bytes)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// bytes downloaded from or sent to telegram by the user client
+pub async fn record_tg_usage(state: &AppState, bytes: u64) -> Result<()> {
+    state
+        .usage_session
+        .add_usage(AccountType::Telegram, TELEGRAM_ACCOUNT, bytes)
+        .await
+}
+
+// paused by any upload session throttled by onedrive, the pause may be long, so the task can still be cancelled
+async fn wait_throttle(cancellation_token: &CancellationToken) -> Result<()> {
+    tokio::select! {
+        () = THROTTLE.wait() => Ok(()),
+        () = cancellation_token.cancelled() => Err(TaskAbortError.into()),
+    }
+}
+
+// what the parts of an upload share
+struct PartUploader<'a> {
+    upload_session: &'a UploadSession,
+    http_client: &'a reqwest::Client,
+    task_session: &'a TaskSession,
+    cancellation_token: &'a CancellationToken,
+}
+
+async fn upload_file(
+    PartUploader {
+        upload_session,
+        http_client,
+        task_session,
+        cancellation_token,
+    }: &PartUploader<'_>,
     buffer: &[u8],
     current_length: u64,
     total_length: u64,
-    http_client: &reqwest::Client,
+    tuner: &mut ChunkTuner,
 ) -> Result<Option<DriveItem>> {
     let mut upload_response = None;
 
     let mut tries = 0;
+    let mut throttled_tries = 0;
+    // bytes of the part already received by onedrive before a retry
+    let mut offset = 0;
 
     loop {
+        let part = &buffer[offset..];
+
+        wait_throttle(cancellation_token).await?;
+
+        UPLOAD_LIMIT.wait(part.len() as u64).await;
+
         tries += 1;
 
+        let upload_start = Instant::now();
+
         let result = upload_session
             .upload_part(
-                buffer.to_owned(),
+                part.to_owned(),
                 Range {
-                    start: current_length,
+                    start: current_length + offset as u64,
                     end: current_length + buffer.len() as u64,
                 },
                 total_length,
@@ -282,6 +1112,13 @@ async fn upload_file(
 
         match result {
             Ok(response) => {
+                task_session.throughput.record(
+                    Direction::Upload,
+                    &get_host(upload_session.upload_url()),
+                    part.len() as u64,
+                    upload_start.elapsed(),
+                );
+
                 upload_response = response;
 
                 break;
@@ -303,10 +1140,45 @@ async fn upload_file(
                     if status_code.as_u16() == 416 {
                         break;
                     }
+
+                    // 429: Too Many Requests
+                    if matches!(status_code.as_u16(), 408 | 429 | 503 | 504) {
+                        task_session.concurrency.record_error();
+                    }
+
+                    // waiting for the throttling to end doesn't count as a retry
+                    if matches!(status_code.as_u16(), 429 | 503)
+                        && throttled_tries < MAX_THROTTLED_RETRIES
+                    {
+                        THROTTLE.pause(e.retry_after());
+
+                        tries -= 1;
+                        throttled_tries += 1;
+
+                        continue;
+                    }
                 }
 
+                // smaller parts for the retries and the following ones
+                tuner.record_error();
+
                 if tries < MAX_RETRIES {
-                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    let jitter = rand::thread_rng().gen_range(0.0..1.0);
+                    tokio::time::sleep(get_backoff(tries, jitter)).await;
+
+                    // a part cut off by a 5xx or a dropped connection may be partly received
+                    match get_resume_offset(
+                        upload_session,
+                        http_client,
+                        current_length,
+                        buffer.len(),
+                    )
+                    .await
+                    {
+                        Some(resume_offset) if resume_offset == buffer.len() => break,
+                        Some(resume_offset) => offset = resume_offset,
+                        None => {}
+                    }
 
                     continue;
                 }
@@ -318,3 +1190,149 @@ async fn upload_file(
 
     Ok(upload_response)
 }
+
+// the delay before a retry, doubled each time, jitter in [0, 1) keeps sessions from retrying together
+fn get_backoff(tries: i32, jitter: f64) -> Duration {
+    let backoff = BACKOFF_BASE
+        .saturating_mul(2_u32.saturating_pow(tries.max(1) as u32 - 1))
+        .min(MAX_BACKOFF);
+
+    backoff.mul_f64(1.0 - jitter / 2.0)
+}
+
+// where to resume the part from nextExpectedRanges, none to send the whole part again
+async fn get_resume_offset(
+    upload_session: &UploadSession,
+    http_client: &reqwest::Client,
+    current_length: u64,
+    length: usize,
+) -> Option<usize> {
+    let meta = match upload_session.get_meta(http_client).await {
+        Ok(meta) => meta,
+        Err(e) => {
+            tracing::warn!("failed to get upload session status: {:?}", e);
+
+            return None;
+        }
+    };
+
+    let next_expected = meta
+        .next_expected_ranges
+        .first()
+        .map_or(current_length + length as u64, |range| range.start);
+
+    get_part_offset(next_expected, current_length, length)
+}
+
+fn get_part_offset(next_expected: u64, current_length: u64, length: usize) -> Option<usize> {
+    let offset = next_expected.checked_sub(current_length)?;
+
+    Some((offset as usize).min(length))
+}
+
+// the response of the last part is lost if onedrive had received all of it before a retry,
+// then the uploaded item is got by its path, only if the upload replaces the file there,
+// since a renamed upload isn't at the path, and the existing file would be taken as corrupted
+async fn get_uploaded_item(
+    upload_response: Option<DriveItem>,
+    task: &tasks::Model,
+    filename: &str,
+    state: &AppState,
+) -> Result<Option<DriveItem>> {
+    if upload_response.is_some() {
+        return Ok(upload_response);
+    }
+
+    let conflict_policy = state
+        .settings_session
+        .get_conflict_policy(task.chat_id)
+        .await?;
+
+    if conflict_policy != ConflictPolicy::Replace {
+        return Err(anyhow!(
+            "response of last part lost, {} may have been uploaded with another name",
+            filename
+        ));
+    }
+
+    let path = Path::new(&task.root_path)
+        .join(filename)
+        .to_slash_lossy()
+        .to_string();
+
+    tracing::debug!("response of last part lost, getting uploaded item {}", path);
+
+    state
+        .get_onedrive(task.tenant_id)
+        .await?
+        .get_account_item_by_path(task.account.as_deref(), &path)
+        .await
+}
+
+// compared with the hash reported by onedrive, which some drives may not report
+fn verify_upload(
+    upload_response: Option<DriveItem>,
+    hash: Option<&QuickXorHash>,
+) -> Result<String> {
+    let item = upload_response.ok_or_else(|| anyhow!("failed to get drive item after upload"))?;
+
+    let filename = item
+        .name
+        .clone()
+        .ok_or_else(|| anyhow!("drive item name not found"))?;
+
+    let Some(hash) = hash else {
+        return Ok(filename);
+    };
+
+    let remote_hash = item
+        .file
+        .as_ref()
+        .and_then(|file| file.pointer("/hashes/quickXorHash"))
+        .and_then(Value::as_str);
+
+    match remote_hash {
+        Some(remote_hash) if remote_hash != hash.finalize() => {
+            tracing::warn!(
+                "hash of {} mismatched, local: {} remote: {}",
+                filename,
+                hash.finalize(),
+                remote_hash
+            );
+
+            Err(CorruptedUploadError { filename }.into())
+        }
+        Some(_) => Ok(filename),
+        None => {
+            tracing::debug!("no quickXorHash reported for {}", filename);
+
+            Ok(filename)
+        }
+    }
+}
+
+fn is_throttling_invocation_error(e: &InvocationError) -> bool {
+    e.is("FLOOD_WAIT") || e.is("FLOOD_PREMIUM_WAIT")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_backoff() {
+        assert_eq!(get_backoff(1, 0.0), Duration::from_secs(1));
+        assert_eq!(get_backoff(3, 0.0), Duration::from_secs(4));
+        assert_eq!(get_backoff(3, 0.5), Duration::from_secs(3));
+        assert_eq!(get_backoff(40, 0.0), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_get_part_offset() {
+        assert_eq!(get_part_offset(1000, 1000, 500), Some(0));
+        assert_eq!(get_part_offset(1200, 1000, 500), Some(200));
+        // the whole part was received
+        assert_eq!(get_part_offset(2000, 1000, 500), Some(500));
+        assert_eq!(get_part_offset(800, 1000, 500), None);
+    }
+}
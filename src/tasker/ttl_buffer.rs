@@ -0,0 +1,125 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{tasks, transfer::record_usage};
+use crate::{env::ENV, error::ResultExt, state::AppState};
+use anyhow::{Context, Result};
+use grammers_client::types::Downloadable;
+use std::{collections::HashSet, path::Path};
+use tokio::{fs, io::AsyncWriteExt};
+
+// self-destructing media may be gone before its task starts, so it's downloaded once received
+// into the session dir, the path is stored in the task so that the buffer survives restarting
+pub struct TtlBuffer {
+    pub path: String,
+    kept: bool,
+}
+
+impl TtlBuffer {
+    // the task is inserted with the path, so the buffer isn't removed when dropped
+    pub fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for TtlBuffer {
+    fn drop(&mut self) {
+        if !self.kept && Path::new(&self.path).exists() {
+            std::fs::remove_file(&self.path)
+                .context("failed to remove buffer file")
+                .trace();
+        }
+    }
+}
+
+// download the media at once, removed if the task isn't inserted
+pub async fn buffer_tg_media<D: Downloadable>(
+    chat_id: i64,
+    message_id: i32,
+    downloadable: &D,
+    tenant_id: Option<i64>,
+    account: Option<&str>,
+    state: &AppState,
+) -> Result<TtlBuffer> {
+    let dir = &ENV.get().unwrap().ttl_buffer_dir;
+
+    fs::create_dir_all(dir)
+        .await
+        .context("failed to create buffer dir")?;
+
+    let buffer = TtlBuffer {
+        path: Path::new(dir)
+            .join(format!("{}-{}.ttl", chat_id, message_id))
+            .to_string_lossy()
+            .to_string(),
+        kept: false,
+    };
+
+    let mut file = fs::File::create(&buffer.path)
+        .await
+        .context("failed to create buffer file")?;
+
+    let mut download = state.telegram_user.download_stream(downloadable);
+    while let Some(chunk) = download
+        .next()
+        .await
+        .context("failed to get next chunk of self-destructing media")?
+    {
+        file.write_all(&chunk)
+            .await
+            .context("failed to write buffer file")?;
+
+        record_usage(state, tenant_id, account, true, chunk.len() as u64).await?;
+    }
+
+    file.flush().await.context("failed to flush buffer file")?;
+
+    tracing::info!("buffered self-destructing media of message {}", message_id);
+
+    Ok(buffer)
+}
+
+// once the task is done with it, failed tasks keep it for /retry
+pub async fn remove_buffer(task: &tasks::Model) {
+    if let Some(buffer_path) = &task.buffer_path {
+        if Path::new(buffer_path).exists() {
+            fs::remove_file(buffer_path)
+                .await
+                .context("failed to remove buffer file")
+                .trace();
+        }
+    }
+}
+
+// buffers of tasks deleted without running, like cancelled ones, are removed when restarting
+pub async fn clear_unused_buffers(buffer_paths: &HashSet<String>) -> Result<()> {
+    let dir = Path::new(&ENV.get().unwrap().ttl_buffer_dir);
+
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(dir)
+        .await
+        .context("failed to read buffer dir")?;
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context("failed to read buffer dir entry")?
+    {
+        let path = entry.path();
+
+        if !buffer_paths.contains(path.to_string_lossy().as_ref()) {
+            fs::remove_file(path)
+                .await
+                .context("failed to remove unused buffer file")?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,105 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use std::time::Duration;
+
+// a part keeping at least this ratio of the best speed lets the size grow
+const GROW_RATIO: f64 = 0.9;
+// a part slower than this ratio of the best speed is treated like an error
+const SHRINK_RATIO: f64 = 0.5;
+// how fast the best speed follows a slower network
+const SPEED_DECAY: f64 = 0.9;
+
+// size of the parts of a task in units, like 320 KiB for onedrive fragments or a telegram chunk,
+// grows by one unit while the speed keeps up, and halves on errors or when the speed drops
+pub struct ChunkTuner {
+    units: usize,
+    min_units: usize,
+    max_units: usize,
+    // bytes per second
+    best_speed: f64,
+}
+
+impl ChunkTuner {
+    pub const fn new(units: usize, min_units: usize, max_units: usize) -> Self {
+        Self {
+            units,
+            min_units,
+            max_units,
+            best_speed: 0.0,
+        }
+    }
+
+    pub const fn units(&self) -> usize {
+        self.units
+    }
+
+    // a part downloaded and uploaded, retried ones are counted in the time too
+    pub fn record_part(&mut self, bytes: usize, elapsed: Duration) {
+        let speed = bytes as f64 / elapsed.as_secs_f64().max(0.001);
+
+        if speed >= self.best_speed * GROW_RATIO {
+            self.best_speed = self.best_speed.max(speed);
+            self.set_units(self.units + 1);
+        } else if speed < self.best_speed * SHRINK_RATIO {
+            self.best_speed = speed;
+            self.set_units(self.units / 2);
+        } else {
+            self.best_speed = self
+                .best_speed
+                .mul_add(SPEED_DECAY, speed * (1.0 - SPEED_DECAY));
+        }
+    }
+
+    pub fn record_error(&mut self) {
+        self.set_units(self.units / 2);
+    }
+
+    fn set_units(&mut self, units: usize) {
+        let units = units.clamp(self.min_units, self.max_units);
+
+        if units != self.units {
+            tracing::debug!("part size changed from {} to {} units", self.units, units);
+
+            self.units = units;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_tuner() {
+        let mut tuner = ChunkTuner::new(4, 1, 6);
+        let second = Duration::from_secs(1);
+
+        // grows while the speed keeps up, up to the max
+        for bytes in [100, 120, 115, 130, 140] {
+            tuner.record_part(bytes, second);
+        }
+        assert_eq!(tuner.units(), 6);
+
+        // a bit slower, kept
+        tuner.record_part(120, second);
+        assert_eq!(tuner.units(), 6);
+
+        // much slower, halved
+        tuner.record_part(50, second);
+        assert_eq!(tuner.units(), 3);
+
+        // grows again from the new speed
+        tuner.record_part(50, second);
+        assert_eq!(tuner.units(), 4);
+
+        tuner.record_error();
+        tuner.record_error();
+        tuner.record_error();
+        assert_eq!(tuner.units(), 1);
+    }
+}
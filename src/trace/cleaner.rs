@@ -5,7 +5,7 @@
 :license: MIT, see LICENSE for more details.
 */
 
-use crate::{env::LOGS_PATH, error::ResultExt};
+use crate::{env::ENV, error::ResultExt};
 use anyhow::Context;
 use chrono::{Local, NaiveDate};
 use regex::Regex;
@@ -27,8 +27,10 @@ pub fn run() {
 
         let cutoff_date = Local::now().naive_local().date() - chrono::Duration::days(7);
 
-        if Path::new(LOGS_PATH).exists() {
-            for entry in fs::read_dir(LOGS_PATH)
+        let logs_dir = &ENV.get().unwrap().logs_dir;
+
+        if Path::new(logs_dir).exists() {
+            for entry in fs::read_dir(logs_dir)
                 .context("failed to read logs dir")
                 .unwrap_or_trace()
             {
@@ -9,7 +9,7 @@ mod cleaner;
 mod formatter;
 mod visitor;
 
-use crate::env::{ENV, LOGS_PATH};
+use crate::env::ENV;
 use formatter::EventFormatter;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_log::LogTracer;
@@ -41,6 +41,6 @@ fn log_writer_builder() -> RollingFileAppender {
     RollingFileAppender::builder()
         .rotation(Rotation::DAILY)
         .filename_suffix("log")
-        .build(LOGS_PATH)
+        .build(&ENV.get().unwrap().logs_dir)
         .unwrap()
 }
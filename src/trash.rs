@@ -0,0 +1,207 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    client::OneDriveClient, env::ENV, error::ResultExt, state::AppState,
+    verify::delete_upload_records,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const TRASH_FOLDER: &str = ".trash";
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+// an item deleted by /rm, kept by the chat for /undo
+#[derive(Serialize, Deserialize)]
+pub struct TrashedItem {
+    pub path: String,
+    pub trash_path: String,
+}
+
+// items are moved into .trash/$timestamp in the directory of the account,
+// so that items with the same name don't collide, and purged after trash_days
+pub async fn trash_item(onedrive: &OneDriveClient, path: &str) -> Result<TrashedItem> {
+    let trash_root = get_trash_root(onedrive).await;
+
+    create_folders(onedrive, &trash_root).await?;
+
+    let mut timestamp = Utc::now().timestamp_millis();
+    while onedrive
+        .get_item_by_path(&format!("{}/{}", trash_root, timestamp))
+        .await?
+        .is_some()
+    {
+        timestamp += 1;
+    }
+
+    let folder_name = timestamp.to_string();
+    onedrive.create_folder(&trash_root, &folder_name).await?;
+
+    let trash_path = onedrive
+        .move_item(path, &format!("{}/{}", trash_root, folder_name))
+        .await?;
+
+    Ok(TrashedItem {
+        path: format!("/{}", path.trim_matches('/')),
+        trash_path,
+    })
+}
+
+// fails if the parent folder is gone or an item with the same name has taken its place
+pub async fn restore_item(onedrive: &OneDriveClient, item: &TrashedItem) -> Result<()> {
+    onedrive.move_item(&item.trash_path, &item.path).await?;
+
+    // the folder of the deletion is left empty
+    if let Some((folder_path, _)) = item.trash_path.rsplit_once('/') {
+        onedrive
+            .delete_item(folder_path)
+            .await
+            .context("failed to remove trash folder")
+            .trace();
+    }
+
+    Ok(())
+}
+
+// items deleted in the trash itself go to the recycle bin of onedrive
+pub async fn is_in_trash(onedrive: &OneDriveClient, path: &str) -> bool {
+    is_in_trash_root(&get_trash_root(onedrive).await, path)
+}
+
+pub async fn purge_trash(state: &AppState) {
+    let trash_days = ENV.get().unwrap().trash_days;
+
+    if trash_days == 0 {
+        return;
+    }
+
+    for (tenant_id, onedrive) in state.get_loaded_onedrives().await {
+        purge_dir(&onedrive, tenant_id, trash_days, state)
+            .await
+            .context("failed to purge trash")
+            .trace();
+    }
+}
+
+async fn purge_dir(
+    onedrive: &OneDriveClient,
+    tenant_id: Option<i64>,
+    trash_days: u64,
+    state: &AppState,
+) -> Result<()> {
+    if onedrive.get_current_username().await?.is_none() {
+        return Ok(());
+    }
+
+    let trash_root = get_trash_root(onedrive).await;
+
+    if onedrive.get_item_by_path(&trash_root).await?.is_none() {
+        return Ok(());
+    }
+
+    let now = Utc::now().timestamp_millis();
+
+    let children = onedrive.list_children(&trash_root).await?;
+
+    let mut purged = 0;
+    for item in children.iter() {
+        let Some(name) = item.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+
+        if !is_expired(name, now, trash_days) {
+            continue;
+        }
+
+        let path = format!("{}/{}", trash_root, name);
+
+        match onedrive.delete_item(&path).await {
+            Ok(()) => {
+                delete_upload_records(state, tenant_id, onedrive, &path).await;
+
+                purged += 1;
+            }
+            Err(e) => tracing::warn!("failed to purge {} in trash: {:#}", name, e),
+        }
+    }
+
+    if purged > 0 {
+        tracing::info!("purged {} items in {}", purged, trash_root);
+    }
+
+    Ok(())
+}
+
+async fn get_trash_root(onedrive: &OneDriveClient) -> String {
+    format!(
+        "{}/{}",
+        onedrive.get_account_root_path().await.trim_end_matches('/'),
+        TRASH_FOLDER
+    )
+}
+
+// the directory of the account may not exist before the first upload
+async fn create_folders(onedrive: &OneDriveClient, path: &str) -> Result<()> {
+    let mut parent_path = String::from("/");
+
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let folder_path = format!("{}/{}", parent_path.trim_end_matches('/'), name);
+
+        match onedrive.get_item_by_path(&folder_path).await? {
+            Some(item) if item.folder.is_none() => {
+                return Err(anyhow!("{} is not a folder", folder_path))
+            }
+            Some(_) => {}
+            None => {
+                onedrive.create_folder(&parent_path, name).await?;
+            }
+        }
+
+        parent_path = folder_path;
+    }
+
+    Ok(())
+}
+
+fn is_in_trash_root(trash_root: &str, path: &str) -> bool {
+    let path = format!("/{}", path.trim_matches('/'));
+
+    path == trash_root || path.starts_with(&format!("{}/", trash_root))
+}
+
+// folders are named by the time of the deletion in milliseconds, other items are left alone
+fn is_expired(name: &str, now: i64, trash_days: u64) -> bool {
+    name.parse::<i64>()
+        .is_ok_and(|timestamp| now - timestamp >= trash_days as i64 * MILLIS_PER_DAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_in_trash_root() {
+        assert!(is_in_trash_root("/TG/.trash", "/TG/.trash"));
+        assert!(is_in_trash_root(
+            "/TG/.trash",
+            "TG/.trash/1700000000000/a.mp4/"
+        ));
+        assert!(!is_in_trash_root("/TG/.trash", "/TG/.trash2/a.mp4"));
+        assert!(!is_in_trash_root("/TG/.trash", "/TG/a.mp4"));
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let now = 1_700_000_000_000;
+
+        assert!(is_expired("1699000000000", now, 10));
+        assert!(!is_expired("1699999000000", now, 10));
+        assert!(!is_expired("a.mp4", now, 10));
+    }
+}
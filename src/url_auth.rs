@@ -0,0 +1,149 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    crypto::{open_string, seal_string},
+    env::ENV,
+};
+use anyhow::{Context, Result};
+use reqwest::{Client, Method, RequestBuilder};
+use url::Url;
+
+const PASSWORD_NAME: &str = "url password";
+
+// a request to the url of a task, with the password of the share if it's protected
+pub fn build_url_request(
+    client: &Client,
+    method: Method,
+    url: &str,
+    password: Option<&str>,
+) -> Result<RequestBuilder> {
+    let Some(password) = password else {
+        return Ok(client.request(method, url));
+    };
+
+    let (url, username) = resolve_protected_url(url)?;
+
+    Ok(client
+        .request(method, url.as_str())
+        .basic_auth(username, Some(password)))
+}
+
+// stored with the task, encrypted with session_key like the onedrive refresh token if it's set
+pub fn seal_password(password: &str) -> Result<String> {
+    seal_string(
+        ENV.get().unwrap().session_key.as_deref(),
+        password,
+        PASSWORD_NAME,
+    )
+}
+
+pub fn open_password(stored: Option<&str>) -> Result<Option<String>> {
+    stored
+        .map(|stored| {
+            open_string(
+                ENV.get().unwrap().session_key.as_deref(),
+                stored,
+                PASSWORD_NAME,
+            )
+        })
+        .transpose()
+}
+
+// a nextcloud or owncloud share like https://cloud.example.com/s/$token is fetched from its public webdav
+// with the token as the username, other urls are fetched as they are with the username in the url, if any
+fn resolve_protected_url(url: &str) -> Result<(Url, String)> {
+    let mut url = Url::parse(url).context(format!("failed to parse url: {}", url))?;
+
+    let username = url.username().to_string();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+
+    let segments = url
+        .path_segments()
+        .map(|segments| {
+            segments
+                .filter(|segment| !segment.is_empty())
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    // like /s/$token, /index.php/s/$token or /s/$token/download
+    let share = segments
+        .iter()
+        .position(|segment| segment == "s")
+        .and_then(|index| {
+            let token = segments.get(index + 1)?;
+            let rest = &segments[index + 2..];
+
+            (rest.is_empty() || rest == ["download"]).then_some((index, token.clone()))
+        });
+
+    let Some((index, token)) = share else {
+        return Ok((url, username));
+    };
+
+    // the instance may be served under a path like /nextcloud
+    let prefix = segments[..index]
+        .iter()
+        .filter(|segment| *segment != "index.php")
+        .fold(String::new(), |mut prefix, segment| {
+            prefix.push('/');
+            prefix.push_str(segment);
+
+            prefix
+        });
+
+    url.set_path(&format!("{}/public.php/webdav/", prefix));
+    url.set_query(None);
+
+    Ok((url, token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_protected_url() {
+        let resolve = |url| {
+            let (url, username) = resolve_protected_url(url).unwrap();
+
+            (url.to_string(), username)
+        };
+
+        assert_eq!(
+            resolve("https://cloud.example.com/s/AbC123"),
+            (
+                "https://cloud.example.com/public.php/webdav/".to_string(),
+                "AbC123".to_string()
+            )
+        );
+        assert_eq!(
+            resolve("https://example.com/nextcloud/index.php/s/AbC123/download"),
+            (
+                "https://example.com/nextcloud/public.php/webdav/".to_string(),
+                "AbC123".to_string()
+            )
+        );
+        assert_eq!(
+            resolve("https://alice@dav.example.com/files/a.mp4"),
+            (
+                "https://dav.example.com/files/a.mp4".to_string(),
+                "alice".to_string()
+            )
+        );
+        assert_eq!(
+            resolve("https://example.com/s/AbC123/a.mp4"),
+            (
+                "https://example.com/s/AbC123/a.mp4".to_string(),
+                String::new()
+            )
+        );
+    }
+}
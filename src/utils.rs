@@ -34,6 +34,15 @@ pub fn get_http_client() -> Result<reqwest::Client> {
         .context("failed to build http client")
 }
 
+// compare secrets in constant time
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
 pub fn get_ext(filename: &str) -> String {
     filename.split('.').last().unwrap().to_lowercase()
 }
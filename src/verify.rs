@@ -0,0 +1,232 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use crate::{
+    client::OneDriveClient, env::ENV, error::ResultExt, features::Feature, history::UploadRecord,
+    listener::notify_owner, state::AppState, tasker::Task,
+};
+use anyhow::{Context, Result};
+
+// uploaded files are recorded at the path they are uploaded to with the hash reported by onedrive,
+// only if verify is active
+pub async fn record_upload(task: &Task, path: &str, state: &AppState) -> Result<()> {
+    if !Feature::Verify.is_active() {
+        return Ok(());
+    }
+
+    let (root_path, filename) = split_path(path);
+
+    let file_hash = state
+        .get_onedrive(task.tenant_id)
+        .await?
+        .get_file_hash(task.account.as_deref(), root_path, filename)
+        .await?;
+
+    // drives which don't report the hash can't be verified
+    let Some((size, Some(hash))) = file_hash else {
+        return Ok(());
+    };
+
+    state
+        .history_session
+        .insert_upload(task, path, size, &hash)
+        .await
+}
+
+// after an item is moved in the current account of the client, so that it isn't reported as missing,
+// failures are only logged since the item is moved anyway
+pub async fn move_upload_records(
+    state: &AppState,
+    tenant_id: Option<i64>,
+    onedrive: &OneDriveClient,
+    path: &str,
+    new_path: &str,
+) {
+    async {
+        let username = onedrive.get_current_username().await?;
+
+        state
+            .history_session
+            .move_uploads(
+                tenant_id,
+                username.as_deref(),
+                &normalize_path(path),
+                &normalize_path(new_path),
+            )
+            .await
+    }
+    .await
+    .context(format!("failed to move upload records of {}", path))
+    .trace();
+}
+
+// after an item is deleted in the current account of the client
+pub async fn delete_upload_records(
+    state: &AppState,
+    tenant_id: Option<i64>,
+    onedrive: &OneDriveClient,
+    path: &str,
+) {
+    async {
+        let username = onedrive.get_current_username().await?;
+
+        state
+            .history_session
+            .delete_uploads(tenant_id, username.as_deref(), &normalize_path(path))
+            .await
+    }
+    .await
+    .context(format!("failed to delete upload records of {}", path))
+    .trace();
+}
+
+// samples of uploaded files are fetched again, the owner is alerted if any of them changed or is gone
+pub async fn verify_uploads(state: &AppState) {
+    let Some(samples) = ENV.get().unwrap().verify_samples else {
+        return;
+    };
+
+    if !Feature::Verify.is_enabled() {
+        return;
+    }
+
+    verify_samples(state, samples)
+        .await
+        .context("failed to verify uploaded files")
+        .trace();
+}
+
+async fn verify_samples(state: &AppState, samples: u64) -> Result<()> {
+    let history_session = &state.history_session;
+
+    let records = history_session.sample_uploads(samples).await?;
+
+    let mut problems = Vec::new();
+    for record in &records {
+        let (root_path, filename) = split_path(&record.path);
+
+        // errors like an unlinked account or the network aren't problems of the file
+        let result = async {
+            state
+                .get_onedrive(record.tenant_id)
+                .await?
+                .get_file_hash(record.account.as_deref(), root_path, filename)
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(Some((size, hash))) => {
+                if let Some(problem) = check_drift(record, size, hash.as_deref()) {
+                    problems.push(problem);
+                }
+
+                history_session
+                    .update_upload(record.id, size, hash.as_deref().unwrap_or(&record.hash))
+                    .await?;
+            }
+            Ok(None) => {
+                problems.push(format!("Missing: {}", record.path));
+
+                history_session.delete_upload(record.id).await?;
+            }
+            Err(e) => tracing::warn!("failed to verify {}: {:#}", record.path, e),
+        }
+    }
+
+    tracing::info!(
+        "verified {} uploaded files, {} problems",
+        records.len(),
+        problems.len()
+    );
+
+    if !problems.is_empty() {
+        let response = format!(
+            "Verification of {} uploaded files found {} problems:\n{}",
+            records.len(),
+            problems.len(),
+            problems.join("\n")
+        );
+
+        notify_owner(state, response).await?;
+    }
+
+    Ok(())
+}
+
+// the hash is only compared if the drive still reports it
+fn check_drift(record: &UploadRecord, size: u64, hash: Option<&str>) -> Option<String> {
+    if size != record.size as u64 {
+        return Some(format!(
+            "Changed: {}, size {} to {}",
+            record.path, record.size, size
+        ));
+    }
+
+    match hash {
+        Some(hash) if hash != record.hash => Some(format!(
+            "Changed: {}, hash {} to {}",
+            record.path, record.hash, hash
+        )),
+        _ => None,
+    }
+}
+
+// like the paths of records, /a/b
+fn normalize_path(path: &str) -> String {
+    format!("/{}", path.trim_matches('/'))
+}
+
+// the parent and the name, files in the root have / as the parent
+pub fn split_path(path: &str) -> (&str, &str) {
+    match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => ("/", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> UploadRecord {
+        UploadRecord {
+            id: 1,
+            path: "/TG/a.mp4".to_string(),
+            size: 1024,
+            hash: "AAAA".to_string(),
+            chat_id: -100123,
+            tenant_id: None,
+            account: None,
+            uploaded_at: 0,
+            checked_at: None,
+        }
+    }
+
+    #[test]
+    fn test_check_drift() {
+        let record = record();
+
+        assert!(check_drift(&record, 1024, Some("AAAA")).is_none());
+        assert!(check_drift(&record, 1024, None).is_none());
+        assert_eq!(
+            check_drift(&record, 1024, Some("BBBB")).as_deref(),
+            Some("Changed: /TG/a.mp4, hash AAAA to BBBB")
+        );
+        assert_eq!(
+            check_drift(&record, 2048, Some("AAAA")).as_deref(),
+            Some("Changed: /TG/a.mp4, size 1024 to 2048")
+        );
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(split_path("/TG/a.mp4"), ("/TG", "a.mp4"));
+        assert_eq!(split_path("/a.mp4"), ("/", "a.mp4"));
+    }
+}
@@ -0,0 +1,148 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{verify_scoped_token, TokenParams};
+use crate::{error::HttpError, web_server::WebToken};
+use axum::{debug_handler, extract::Query, http::HeaderMap, response::Html, Extension};
+
+pub const PATH: &str = "/dashboard";
+
+// the page is static, the chart is fed by the progress websocket with the token in query
+const PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Dashboard</title>
+<style>
+body { font-family: sans-serif; margin: 16px; }
+canvas { width: 100%; height: 240px; border: 1px solid #ddd; }
+.download { color: #1e88e5; }
+.upload { color: #e53935; }
+table { border-collapse: collapse; margin-top: 16px; }
+td, th { padding: 2px 12px 2px 0; text-align: left; }
+</style>
+</head>
+<body>
+<h3>Throughput</h3>
+<p>
+<span class="download">Download <b id="download">0 B/s</b></span>
+&nbsp;
+<span class="upload">Upload <b id="upload">0 B/s</b></span>
+&nbsp;
+<span id="status">connecting</span>
+</p>
+<canvas id="chart"></canvas>
+<table>
+<thead><tr><th>Task</th><th>File</th><th>Progress</th><th>Speed</th></tr></thead>
+<tbody id="tasks"></tbody>
+</table>
+<script>
+// 10 minutes of events sent every 2 seconds
+const MAX_POINTS = 300;
+const points = [];
+const tasks = new Map();
+
+const canvas = document.getElementById("chart");
+const context = canvas.getContext("2d");
+
+function formatRate(rate) {
+    const units = ["B/s", "KB/s", "MB/s", "GB/s"];
+    let unit = 0;
+    while (rate >= 1024 && unit < units.length - 1) {
+        rate /= 1024;
+        unit++;
+    }
+    return rate.toFixed(unit ? 2 : 0) + " " + units[unit];
+}
+
+function draw() {
+    const width = canvas.width = canvas.clientWidth * devicePixelRatio;
+    const height = canvas.height = canvas.clientHeight * devicePixelRatio;
+    const max = Math.max(1, ...points.map(point => Math.max(point.download, point.upload)));
+
+    context.clearRect(0, 0, width, height);
+    context.fillStyle = "#888";
+    context.font = 12 * devicePixelRatio + "px sans-serif";
+    context.fillText(formatRate(max), 4, 14 * devicePixelRatio);
+
+    for (const [key, color] of [["download", "#1e88e5"], ["upload", "#e53935"]]) {
+        context.strokeStyle = color;
+        context.lineWidth = 2 * devicePixelRatio;
+        context.beginPath();
+        points.forEach((point, index) => {
+            const x = width - (points.length - 1 - index) * width / (MAX_POINTS - 1);
+            const y = height - point[key] / max * (height - 20 * devicePixelRatio);
+            index ? context.lineTo(x, y) : context.moveTo(x, y);
+        });
+        context.stroke();
+    }
+}
+
+function renderTasks() {
+    const rows = [...tasks.values()].map(task =>
+        "<tr><td>" + task.id + "</td><td></td><td>" + task.percent.toFixed(1) + "%</td><td>" + formatRate(task.speed) + "</td></tr>");
+    const body = document.getElementById("tasks");
+    body.innerHTML = rows.join("");
+    [...tasks.values()].forEach((task, index) => body.rows[index].cells[1].textContent = task.filename);
+}
+
+function connect() {
+    const token = new URLSearchParams(location.search).get("token") || "";
+    const url = new URL("progress?token=" + encodeURIComponent(token), location.href.replace(/\/dashboard\/?(\?.*)?$/, "/"));
+    url.protocol = location.protocol === "https:" ? "wss:" : "ws:";
+
+    const socket = new WebSocket(url);
+    socket.onopen = () => document.getElementById("status").textContent = "";
+    socket.onclose = () => {
+        document.getElementById("status").textContent = "disconnected, retrying";
+        setTimeout(connect, 5000);
+    };
+    socket.onmessage = message => {
+        const event = JSON.parse(message.data);
+
+        if (event.id === undefined) {
+            // throughput is sent after the tasks of the same round
+            points.push(event);
+            if (points.length > MAX_POINTS)
+                points.shift();
+
+            document.getElementById("download").textContent = formatRate(event.download);
+            document.getElementById("upload").textContent = formatRate(event.upload);
+
+            // finished tasks are no longer sent
+            for (const [id, task] of tasks)
+                if (task.receivedAt < Date.now() - 5000)
+                    tasks.delete(id);
+
+            renderTasks();
+            draw();
+        } else {
+            event.receivedAt = Date.now();
+            tasks.set(event.id, event);
+        }
+    };
+}
+
+window.onresize = draw;
+connect();
+</script>
+</body>
+</html>
+"##;
+
+#[debug_handler]
+pub async fn handler(
+    Extension(web_token): Extension<WebToken>,
+    Query(params): Query<TokenParams>,
+    headers: HeaderMap,
+) -> Result<Html<&'static str>, HttpError> {
+    // the page is the same for tenants, what it shows is scoped by the websocket
+    verify_scoped_token(&web_token, &headers, params)?;
+
+    Ok(Html(PAGE))
+}
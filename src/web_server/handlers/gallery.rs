@@ -0,0 +1,103 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{verify_token, TokenParams};
+use crate::{error::HttpError, state::AppState, web_server::WebToken};
+use axum::{
+    debug_handler,
+    extract::{Path, Query},
+    http::HeaderMap,
+    response::Html,
+    Extension,
+};
+
+pub const ROOT_PATH: &str = "/gallery";
+pub const PATH: &str = "/gallery/*path";
+
+#[debug_handler]
+pub async fn root_handler(
+    Extension(web_token): Extension<WebToken>,
+    Extension(state): Extension<AppState>,
+    Query(params): Query<TokenParams>,
+    headers: HeaderMap,
+) -> Result<Html<String>, HttpError> {
+    verify_token(&web_token, &headers, params)?;
+
+    render_gallery(&state, "/").await
+}
+
+#[debug_handler]
+pub async fn handler(
+    Extension(web_token): Extension<WebToken>,
+    Extension(state): Extension<AppState>,
+    Path(path): Path<String>,
+    Query(params): Query<TokenParams>,
+    headers: HeaderMap,
+) -> Result<Html<String>, HttpError> {
+    verify_token(&web_token, &headers, params)?;
+
+    render_gallery(&state, &path).await
+}
+
+async fn render_gallery(state: &AppState, path: &str) -> Result<Html<String>, HttpError> {
+    let path = format!("/{}", path.trim_matches('/'));
+
+    let thumbnails = state
+        .onedrive
+        .list_thumbnails(&path)
+        .await
+        .map_err(|e| HttpError::new(format!("{:#}", e)))?;
+
+    let mut items = String::new();
+    for thumbnail in &thumbnails {
+        items.push_str(&format!(
+            "<a href=\"{}\" title=\"{}\"><img src=\"{}\" alt=\"{}\" loading=\"lazy\"></a>\n",
+            escape_html(&thumbnail.web_url),
+            escape_html(&thumbnail.name),
+            escape_html(&thumbnail.url),
+            escape_html(&thumbnail.name)
+        ));
+    }
+
+    let title = escape_html(&path);
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="referrer" content="no-referrer">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 16px; }}
+.grid {{ display: grid; grid-template-columns: repeat(auto-fill, minmax(176px, 1fr)); gap: 8px; }}
+.grid img {{ width: 100%; aspect-ratio: 1; object-fit: cover; }}
+</style>
+</head>
+<body>
+<h3>{title}</h3>
+<p>{count} images</p>
+<div class="grid">
+{items}</div>
+</body>
+</html>
+"#,
+        title = title,
+        count = thumbnails.len(),
+        items = items
+    );
+
+    Ok(Html(html))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
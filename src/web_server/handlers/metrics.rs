@@ -0,0 +1,107 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{verify_token, TokenParams};
+use crate::{
+    error::HttpError, listener::UPDATE_STATS, state::AppState, tasker::HostStats,
+    web_server::WebToken,
+};
+use axum::{
+    debug_handler,
+    extract::Query,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    Extension,
+};
+
+pub const PATH: &str = "/metrics";
+
+// prometheus text format, chunks in the last 10 minutes as summaries
+#[debug_handler]
+pub async fn handler(
+    Extension(web_token): Extension<WebToken>,
+    Extension(state): Extension<AppState>,
+    Query(params): Query<TokenParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, HttpError> {
+    verify_token(&web_token, &headers, params)?;
+
+    let stats = state.task_session.throughput.get_stats();
+
+    let mut latency = String::from(
+        "# HELP telegram_onedrive_chunk_latency_seconds Time to transfer a chunk.\n# TYPE telegram_onedrive_chunk_latency_seconds summary\n",
+    );
+    let mut speed = String::from(
+        "# HELP telegram_onedrive_chunk_speed_bytes Bytes per second of a chunk, quantiles count from the slowest.\n# TYPE telegram_onedrive_chunk_speed_bytes summary\n",
+    );
+
+    for HostStats {
+        direction,
+        host,
+        chunks,
+        latency: latency_percentiles,
+        speed: speed_percentiles,
+    } in &stats
+    {
+        let labels = format!(
+            "direction=\"{}\",host=\"{}\"",
+            direction,
+            escape_label(host)
+        );
+
+        for (quantile, value) in [
+            ("0.5", latency_percentiles.p50),
+            ("0.9", latency_percentiles.p90),
+            ("0.99", latency_percentiles.p99),
+        ] {
+            latency.push_str(&format!(
+                "telegram_onedrive_chunk_latency_seconds{{{},quantile=\"{}\"}} {}\n",
+                labels, quantile, value
+            ));
+        }
+        latency.push_str(&format!(
+            "telegram_onedrive_chunk_latency_seconds_count{{{}}} {}\n",
+            labels, chunks
+        ));
+
+        for (quantile, value) in [
+            ("0.5", speed_percentiles.p50),
+            ("0.9", speed_percentiles.p90),
+            ("0.99", speed_percentiles.p99),
+        ] {
+            speed.push_str(&format!(
+                "telegram_onedrive_chunk_speed_bytes{{{},quantile=\"{}\"}} {}\n",
+                labels, quantile, value
+            ));
+        }
+        speed.push_str(&format!(
+            "telegram_onedrive_chunk_speed_bytes_count{{{}}} {}\n",
+            labels, chunks
+        ));
+    }
+
+    let (handled, ignored) = UPDATE_STATS.get();
+
+    let mut updates = String::from(
+        "# HELP telegram_onedrive_messages_total Messages received by the bot, ignored ones have no text or media, or are from senders not allowed, or are throttled commands.\n# TYPE telegram_onedrive_messages_total counter\n",
+    );
+    for (result, count) in [("handled", handled), ("ignored", ignored)] {
+        updates.push_str(&format!(
+            "telegram_onedrive_messages_total{{result=\"{}\"}} {}\n",
+            result, count
+        ));
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        latency + &speed + &updates,
+    ))
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,83 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+pub mod dashboard;
+pub mod gallery;
+pub mod metrics;
+pub mod progress;
+
+use super::WebToken;
+use crate::{crypto::derive_tenant_token, env::ENV, error::HttpError, utils::constant_time_eq};
+use axum::http::{header, HeaderMap, StatusCode};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct TokenParams {
+    token: Option<String>,
+}
+
+// what a token can see
+#[derive(Clone, Copy)]
+pub enum TokenScope {
+    All,
+    // a tenant token from /status in multi-tenant mode
+    Tenant(i64),
+}
+
+// browsers can't set headers for websocket and images, so the token can also be passed in query
+fn get_token(headers: &HeaderMap, TokenParams { token }: TokenParams) -> String {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(ToString::to_string);
+
+    bearer.or(token).unwrap_or_default()
+}
+
+fn invalid_token() -> HttpError {
+    tracing::warn!("web server received an invalid token");
+
+    HttpError::with_status(StatusCode::UNAUTHORIZED, "Invalid token.")
+}
+
+// only the web token, for pages showing the whole instance
+fn verify_token(
+    WebToken(expected): &WebToken,
+    headers: &HeaderMap,
+    params: TokenParams,
+) -> Result<(), HttpError> {
+    if constant_time_eq(expected, &get_token(headers, params)) {
+        Ok(())
+    } else {
+        Err(invalid_token())
+    }
+}
+
+// the web token, or a tenant token in multi-tenant mode
+fn verify_scoped_token(
+    WebToken(expected): &WebToken,
+    headers: &HeaderMap,
+    params: TokenParams,
+) -> Result<TokenScope, HttpError> {
+    let token = get_token(headers, params);
+
+    if constant_time_eq(expected, &token) {
+        return Ok(TokenScope::All);
+    }
+
+    if !ENV.get().unwrap().multi_tenant {
+        return Err(invalid_token());
+    }
+
+    token
+        .split_once('.')
+        .and_then(|(tenant_id, _)| tenant_id.parse::<i64>().ok())
+        .filter(|tenant_id| constant_time_eq(&derive_tenant_token(expected, *tenant_id), &token))
+        .map(TokenScope::Tenant)
+        .ok_or_else(invalid_token)
+}
@@ -0,0 +1,86 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+use super::{verify_scoped_token, TokenParams, TokenScope};
+use crate::{error::HttpError, tasker::ProgressEvent, web_server::WebToken};
+use axum::{
+    debug_handler,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    http::HeaderMap,
+    response::Response,
+    Extension,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+pub const PATH: &str = "/progress";
+
+#[debug_handler]
+pub async fn handler(
+    Extension(web_token): Extension<WebToken>,
+    Extension(progress_sender): Extension<broadcast::Sender<ProgressEvent>>,
+    Query(params): Query<TokenParams>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response, HttpError> {
+    let scope = verify_scoped_token(&web_token, &headers, params)?;
+
+    let receiver = progress_sender.subscribe();
+
+    Ok(ws.on_upgrade(move |socket| stream_progress(socket, receiver, scope)))
+}
+
+// tenants only get their own tasks, the throughput of the instance is for the web token
+const fn is_visible(event: &ProgressEvent, scope: TokenScope) -> bool {
+    match (event, scope) {
+        (_, TokenScope::All) => true,
+        (ProgressEvent::Task(task), TokenScope::Tenant(tenant_id)) => {
+            matches!(task.tenant_id, Some(id) if id == tenant_id)
+        }
+        (ProgressEvent::Throughput(_), TokenScope::Tenant(_)) => false,
+    }
+}
+
+async fn stream_progress(
+    mut socket: WebSocket,
+    mut receiver: broadcast::Receiver<ProgressEvent>,
+    scope: TokenScope,
+) {
+    tracing::debug!("progress subscriber connected");
+
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => {
+                    if !is_visible(&event, scope) {
+                        continue;
+                    }
+
+                    let Ok(text) = serde_json::to_string(&event) else {
+                        continue;
+                    };
+
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                // slow subscribers skip the missed events
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            // read-only, messages from the subscriber are ignored
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    tracing::debug!("progress subscriber disconnected");
+}
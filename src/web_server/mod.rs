@@ -0,0 +1,95 @@
+/*
+:project: telegram-onedrive
+:author: L-ING
+:copyright: (C) 2024 L-ING <hlf01@icloud.com>
+:license: MIT, see LICENSE for more details.
+*/
+
+mod handlers;
+
+use crate::{
+    auth_server::cert::{get_rustls_config, watch_cert},
+    env::{Env, ENV},
+    features::Feature,
+    state::AppState,
+};
+use anyhow::{Context, Result};
+use axum::{routing::get, Extension, Router};
+use handlers::{dashboard, gallery, metrics, progress};
+use std::net::SocketAddr;
+
+#[derive(Clone)]
+struct WebToken(String);
+
+// read-only pages and websocket for dashboards, runs as long as the bot
+pub async fn run(state: AppState) -> Result<()> {
+    let Env {
+        web_port,
+        web_token,
+        use_reverse_proxy,
+        ..
+    } = ENV.get().unwrap();
+
+    let Some(web_token) = web_token else {
+        tracing::debug!("web server disabled");
+
+        return Ok(());
+    };
+
+    if ![Feature::Dashboard, Feature::Gallery, Feature::Metrics]
+        .into_iter()
+        .any(Feature::is_enabled)
+    {
+        tracing::debug!("web server disabled, all its features are disabled");
+
+        return Ok(());
+    }
+
+    let mut router = Router::new();
+
+    if Feature::Dashboard.is_enabled() {
+        router = router
+            .route(progress::PATH, get(progress::handler))
+            .route(dashboard::PATH, get(dashboard::handler));
+    }
+
+    if Feature::Gallery.is_enabled() {
+        router = router
+            .route(gallery::ROOT_PATH, get(gallery::root_handler))
+            .route(gallery::PATH, get(gallery::handler));
+    }
+
+    if Feature::Metrics.is_enabled() {
+        router = router.route(metrics::PATH, get(metrics::handler));
+    }
+
+    let router = router
+        .layer(Extension(WebToken(web_token.clone())))
+        .layer(Extension(state.task_session.progress_sender.clone()))
+        .layer(Extension(state));
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], *web_port));
+
+    if use_reverse_proxy.to_owned() {
+        tracing::info!("web server listening on http://0.0.0.0:{}", web_port);
+
+        axum_server::bind(addr)
+            .serve(router.into_make_service())
+            .await
+            .context("web server failed to serve")?;
+    } else {
+        let (config, cert_paths) = get_rustls_config().await?;
+
+        tracing::info!("web server listening on https://0.0.0.0:{}", web_port);
+
+        let serve =
+            axum_server::bind_rustls(addr, config.clone()).serve(router.into_make_service());
+
+        tokio::select! {
+            result = serve => result.context("web server failed to serve")?,
+            () = watch_cert(config, cert_paths) => {}
+        }
+    }
+
+    Ok(())
+}